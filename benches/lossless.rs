@@ -0,0 +1,95 @@
+//! Times the decoding of large DefineBitsLossless bitmaps in each format, along with stripping the
+//! row padding by copying whole rows compared to copying pixel by pixel.
+//!
+//! Run with `cargo bench`. This is a plain timing loop (`harness = false`) rather than a criterion
+//! benchmark, to get by without further dependencies.
+
+
+use std::hint::black_box;
+use std::io::Write;
+use std::time::{Duration, Instant};
+
+use flate2::Compression;
+use flate2::write::ZlibEncoder;
+use swf::{BitmapFormat, DefineBitsLossless};
+use swfextract::bitmap::{strip_row_padding, Bitmap};
+use swfextract::cancel::Cancellation;
+
+
+/// An odd width, so that the rows of 8-bit and 15-bit bitmaps are padded.
+const WIDTH: u16 = 2047;
+const HEIGHT: u16 = 2048;
+
+/// How long each benchmark is repeated for.
+const DURATION: Duration = Duration::from_secs(2);
+
+
+/// Runs the function repeatedly for about [`DURATION`] and prints the median time per run.
+fn bench<T, F: FnMut() -> T>(name: &str, bytes: usize, mut f: F) {
+    let mut times = Vec::new();
+    let started = Instant::now();
+    while started.elapsed() < DURATION || times.len() < 3 {
+        let run_started = Instant::now();
+        black_box(f());
+        times.push(run_started.elapsed());
+    }
+    times.sort();
+    let median = times[times.len() / 2];
+    let mib_per_second = bytes as f64 / (1024.0 * 1024.0) / median.as_secs_f64();
+    println!("{:<40} {:>10.3} ms {:>10.1} MiB/s ({} runs)", name, median.as_secs_f64() * 1000.0, mib_per_second, times.len());
+}
+
+
+fn zlib(data: &[u8]) -> Vec<u8> {
+    let mut encoder = ZlibEncoder::new(Vec::new(), Compression::fast());
+    encoder.write_all(data).unwrap();
+    encoder.finish().unwrap()
+}
+
+
+/// Pseudo-random bytes, so that the compressed data is not trivially small.
+fn noise(len: usize) -> Vec<u8> {
+    let mut state = 0x2545_F491u32;
+    (0..len)
+        .map(|_| {
+            state ^= state << 13;
+            state ^= state >> 17;
+            state ^= state << 5;
+            state as u8
+        })
+        .collect()
+}
+
+
+/// Strips the row padding pixel by pixel, as done before copying whole rows.
+fn strip_row_padding_per_byte(padded: &[u8], row_len: usize, stride: usize) -> Vec<u8> {
+    padded.chunks_exact(stride)
+        .flat_map(|row| row.iter().take(row_len).copied())
+        .collect()
+}
+
+
+fn main() {
+    let width = usize::from(WIDTH);
+    let height = usize::from(HEIGHT);
+
+    for (name, version, format, header_len, stride) in [
+        ("colormapped", 1, BitmapFormat::ColorMap8 { num_colors: 255 }, 3 * 256, (width + 3) & !3),
+        ("colormapped with alpha", 2, BitmapFormat::ColorMap8 { num_colors: 255 }, 4 * 256, (width + 3) & !3),
+        ("rgb15", 1, BitmapFormat::Rgb15, 0, (2 * width + 3) & !3),
+        ("rgb24", 1, BitmapFormat::Rgb32, 0, 4 * width),
+        ("argb", 2, BitmapFormat::Rgb32, 0, 4 * width),
+    ] {
+        let data = zlib(&noise(header_len + stride * height));
+        let bmap = DefineBitsLossless { version, id: 1, format, width: WIDTH, height: HEIGHT, data: &data };
+        let cancellation = Cancellation::new();
+        bench(&format!("from_lossless {}", name), stride * height, || {
+            Bitmap::from_lossless(&bmap, &cancellation).unwrap()
+        });
+    }
+
+    let stride = (width + 3) & !3;
+    let padded = noise(stride * height);
+    bench("strip_row_padding rows", padded.len(), || strip_row_padding(&padded, width, stride));
+    bench("strip_row_padding per byte", padded.len(), || strip_row_padding_per_byte(&padded, width, stride));
+}
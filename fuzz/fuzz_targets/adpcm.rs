@@ -0,0 +1,15 @@
+#![no_main]
+
+use std::io::Read;
+
+use libfuzzer_sys::fuzz_target;
+use swfextract::adpcm::AdpcmDecoder;
+
+
+fuzz_target!(|data: &[u8]| {
+    // the first byte chooses between mono and stereo
+    let Some((first, adpcm_data)) = data.split_first() else { return };
+    let Ok(decoder) = AdpcmDecoder::new(adpcm_data, first & 1 != 0) else { return };
+    let mut pcm = Vec::new();
+    let _ = decoder.into_pcm_reader().read_to_end(&mut pcm);
+});
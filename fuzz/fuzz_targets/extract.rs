@@ -0,0 +1,9 @@
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+use swfextract::extract::extract_from_bytes;
+
+
+fuzz_target!(|data: &[u8]| {
+    let _ = extract_from_bytes(data);
+});
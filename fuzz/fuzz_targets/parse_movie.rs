@@ -0,0 +1,14 @@
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+use swfextract::read::{decompress_movie, parse_movie};
+
+
+/// The default of `--max-sprite-depth`.
+const MAX_SPRITE_DEPTH: usize = 64;
+
+
+fuzz_target!(|data: &[u8]| {
+    let Ok(swf_buf) = decompress_movie(data, MAX_SPRITE_DEPTH) else { return };
+    let _ = parse_movie(&swf_buf, MAX_SPRITE_DEPTH);
+});
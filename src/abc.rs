@@ -0,0 +1,419 @@
+use std::collections::{BTreeSet, HashMap};
+
+use swf::avm2::types::{
+    AbcFile, ConstantPool, Index, Method, MethodFlags, Multiname, Namespace, Trait, TraitKind,
+};
+
+use crate::embed::sniff_extension;
+use crate::json::JsonValue;
+use crate::unpack::{inflate, is_zlib};
+
+
+/// The shortest constant considered to be hidden data.
+const MIN_PAYLOAD_LEN: usize = 256;
+
+const OP_PUSHBYTE: u8 = 0x24;
+const OP_PUSHSHORT: u8 = 0x25;
+const OP_PUSHINT: u8 = 0x2D;
+const OP_NEWARRAY: u8 = 0x56;
+
+
+/// Resolves entries of the constant pool of an ABC file into readable names.
+///
+/// Index 0 of each pool is implicit and denotes the empty or any name; the swf crate does not store
+/// it, so index `i` is found at position `i - 1`.
+struct Names<'a> {
+    abc: &'a AbcFile,
+}
+impl<'a> Names<'a> {
+    fn pool(&self) -> &'a ConstantPool {
+        &self.abc.constant_pool
+    }
+
+    fn string(&self, index: &Index<String>) -> Option<&'a str> {
+        let i = usize::try_from(index.0).unwrap().checked_sub(1)?;
+        self.pool().strings.get(i).map(|s| s.as_str())
+    }
+
+    fn namespace(&self, index: &Index<Namespace>) -> Option<&'a Namespace> {
+        let i = usize::try_from(index.0).unwrap().checked_sub(1)?;
+        self.pool().namespaces.get(i)
+    }
+
+    /// Returns the name of a namespace along with its kind.
+    fn namespace_name(&self, namespace: &Namespace) -> (&'static str, &'a str) {
+        let (kind, name) = match namespace {
+            Namespace::Namespace(n) => ("namespace", n),
+            Namespace::Package(n) => ("package", n),
+            Namespace::PackageInternal(n) => ("package_internal", n),
+            Namespace::Protected(n) => ("protected", n),
+            Namespace::Explicit(n) => ("explicit", n),
+            Namespace::StaticProtected(n) => ("static_protected", n),
+            Namespace::Private(n) => ("private", n),
+        };
+        (kind, self.string(name).unwrap_or(""))
+    }
+
+    /// Returns a multiname as `package::Name`, or as `Name` if it is not in a named package.
+    fn multiname(&self, index: &Index<Multiname>) -> String {
+        let Some(i) = usize::try_from(index.0).unwrap().checked_sub(1) else { return "*".to_owned() };
+        let Some(multiname) = self.pool().multinames.get(i) else { return "*".to_owned() };
+        match multiname {
+            Multiname::QName { namespace, name }|Multiname::QNameA { namespace, name } => {
+                let name = self.string(name).unwrap_or("*");
+                let package = self.namespace(namespace)
+                    .filter(|ns| matches!(ns, Namespace::Package(_)|Namespace::PackageInternal(_)))
+                    .map(|ns| self.namespace_name(ns).1)
+                    .unwrap_or("");
+                if package.is_empty() {
+                    name.to_owned()
+                } else {
+                    format!("{}::{}", package, name)
+                }
+            },
+            Multiname::RTQName { name }|Multiname::RTQNameA { name }
+                    |Multiname::Multiname { name, .. }|Multiname::MultinameA { name, .. } => {
+                self.string(name).unwrap_or("*").to_owned()
+            },
+            Multiname::RTQNameL|Multiname::RTQNameLA
+                    |Multiname::MultinameL { .. }|Multiname::MultinameLA { .. } => "*".to_owned(),
+            Multiname::TypeName { base_type, parameters } => {
+                let parameters: Vec<String> = parameters.iter()
+                    .map(|p| self.multiname(p))
+                    .collect();
+                format!("{}.<{}>", self.multiname(base_type), parameters.join(", "))
+            },
+        }
+    }
+
+    fn method(&self, index: &Index<Method>) -> Option<&'a Method> {
+        self.abc.methods.get(usize::try_from(index.0).unwrap())
+    }
+
+    /// Returns the signature of a method in ActionScript notation, such as
+    /// `(name:String, count:int):void`.
+    fn signature(&self, index: &Index<Method>) -> String {
+        let Some(method) = self.method(index) else { return "(?)".to_owned() };
+        let mut params = Vec::new();
+        for (i, param) in method.params.iter().enumerate() {
+            let name = param.name.as_ref()
+                .and_then(|n| self.string(n))
+                .map(|n| n.to_owned())
+                .unwrap_or_else(|| format!("arg{}", i + 1));
+            let optional = if param.default_value.is_some() { " = ..." } else { "" };
+            params.push(format!("{}:{}{}", name, self.multiname(&param.kind), optional));
+        }
+        if method.flags.contains(MethodFlags::NEED_REST) {
+            params.push("...rest".to_owned());
+        }
+        format!("({}):{}", params.join(", "), self.multiname(&method.return_type))
+    }
+
+    fn trait_to_json(&self, t: &Trait) -> JsonValue {
+        let mut obj = JsonValue::new_object();
+        obj.insert("name", self.multiname(&t.name));
+        match &t.kind {
+            TraitKind::Slot { type_name, .. } => {
+                obj.insert("kind", "var");
+                obj.insert("type", self.multiname(type_name));
+            },
+            TraitKind::Const { type_name, .. } => {
+                obj.insert("kind", "const");
+                obj.insert("type", self.multiname(type_name));
+            },
+            TraitKind::Method { method, .. } => {
+                obj.insert("kind", "method");
+                obj.insert("signature", self.signature(method));
+            },
+            TraitKind::Getter { method, .. } => {
+                obj.insert("kind", "getter");
+                obj.insert("signature", self.signature(method));
+            },
+            TraitKind::Setter { method, .. } => {
+                obj.insert("kind", "setter");
+                obj.insert("signature", self.signature(method));
+            },
+            TraitKind::Function { function, .. } => {
+                obj.insert("kind", "function");
+                obj.insert("signature", self.signature(function));
+            },
+            TraitKind::Class { .. } => {
+                obj.insert("kind", "class");
+            },
+        }
+        obj
+    }
+
+    fn traits_to_json(&self, traits: &[Trait]) -> JsonValue {
+        JsonValue::Array(traits.iter().map(|t| self.trait_to_json(t)).collect())
+    }
+}
+
+
+/// Summarizes the contents of an ABC (AVM2 bytecode) file into a JSON object: its classes with
+/// their members and method signatures, top-level definitions, namespaces and string constants.
+///
+/// Returns `None` if the file cannot be decoded.
+pub(crate) fn abc_summary_to_json(abc_data: &[u8]) -> Option<JsonValue> {
+    let abc = swf::avm2::read::Reader::new(abc_data).read().ok()?;
+    let names = Names { abc: &abc };
+
+    let mut classes = JsonValue::new_array();
+    for (instance, class) in abc.instances.iter().zip(&abc.classes) {
+        let mut obj = JsonValue::new_object();
+        obj.insert("name", names.multiname(&instance.name));
+        obj.insert("super", if instance.super_name.0 == 0 { None } else { Some(names.multiname(&instance.super_name)) });
+        obj.insert("interfaces", instance.interfaces.iter().map(|i| names.multiname(i)).collect::<Vec<String>>());
+        obj.insert("is_interface", instance.is_interface);
+        obj.insert("is_final", instance.is_final);
+        obj.insert("is_sealed", instance.is_sealed);
+        obj.insert("constructor", names.signature(&instance.init_method));
+        obj.insert("instance_traits", names.traits_to_json(&instance.traits));
+        obj.insert("static_traits", names.traits_to_json(&class.traits));
+        classes.push(obj);
+    }
+
+    // the classes are also listed as traits of the scripts defining them
+    let mut script_traits = JsonValue::new_array();
+    for script in &abc.scripts {
+        for t in script.traits.iter().filter(|t| !matches!(t.kind, TraitKind::Class { .. })) {
+            script_traits.push(names.trait_to_json(t));
+        }
+    }
+
+    let namespace_set: BTreeSet<(&str, &str)> = abc.constant_pool.namespaces.iter()
+        .map(|ns| names.namespace_name(ns))
+        .collect();
+    let mut namespaces = JsonValue::new_array();
+    for (kind, name) in namespace_set {
+        let mut obj = JsonValue::new_object();
+        obj.insert("kind", kind);
+        obj.insert("name", name);
+        namespaces.push(obj);
+    }
+
+    let mut obj = JsonValue::new_object();
+    obj.insert("version", format!("{}.{}", abc.major_version, abc.minor_version));
+    obj.insert("classes", classes);
+    obj.insert("script_traits", script_traits);
+    obj.insert("namespaces", namespaces);
+    obj.insert("strings", abc.constant_pool.strings.clone());
+    obj.insert("method_count", abc.methods.len());
+    Some(obj)
+}
+
+
+/// Collects the names of the superclasses of the classes defined in an ABC file, keyed by the class
+/// name. Both are qualified in the dotted notation used by SymbolClass (`package.Name`).
+///
+/// Returns an empty map if the file cannot be decoded.
+pub(crate) fn superclass_names(abc_data: &[u8]) -> HashMap<String, String> {
+    let Ok(abc) = swf::avm2::read::Reader::new(abc_data).read() else { return HashMap::new() };
+    let names = Names { abc: &abc };
+    abc.instances.iter()
+        .filter(|instance| instance.super_name.0 != 0)
+        .map(|instance| (
+            names.multiname(&instance.name).replace("::", "."),
+            names.multiname(&instance.super_name).replace("::", "."),
+        ))
+        .collect()
+}
+
+
+/// How data has been hidden in the constants of an ABC file.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub(crate) enum PayloadEncoding {
+    /// A Base64-encoded string.
+    Base64,
+
+    /// A string of hexadecimal digits.
+    Hex,
+
+    /// A string with one character per byte.
+    Latin1,
+
+    /// An array literal of byte values, as used to fill a `ByteArray`.
+    ByteArray,
+}
+impl PayloadEncoding {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::Base64 => "base64",
+            Self::Hex => "hex",
+            Self::Latin1 => "latin1",
+            Self::ByteArray => "byte_array",
+        }
+    }
+}
+
+
+/// Data hidden in the constants of an ABC file.
+#[derive(Clone, Debug)]
+pub(crate) struct AbcPayload {
+    /// The string constant (by index into the constant pool) or method body (by position) the data
+    /// has been found in.
+    pub source: String,
+
+    pub encoding: PayloadEncoding,
+
+    /// Whether the data has been compressed with zlib; it is stored inflated.
+    pub compressed: bool,
+
+    pub data: Vec<u8>,
+}
+impl AbcPayload {
+    fn new(source: String, encoding: PayloadEncoding, data: Vec<u8>) -> Self {
+        match inflate_zlib(&data) {
+            Some(inflated) => Self { source, encoding, compressed: true, data: inflated },
+            None => Self { source, encoding, compressed: false, data },
+        }
+    }
+
+    pub fn extension(&self) -> &'static str {
+        sniff_extension(&self.data).unwrap_or("bin")
+    }
+}
+
+
+fn inflate_zlib(data: &[u8]) -> Option<Vec<u8>> {
+    if is_zlib(data) { inflate(data) } else { None }
+}
+
+
+fn decode_base64(s: &str) -> Option<Vec<u8>> {
+    let digits: Vec<u8> = s.bytes().filter(|b| !b.is_ascii_whitespace()).collect();
+    if !digits.len().is_multiple_of(4) {
+        return None;
+    }
+    let unpadded = digits.strip_suffix(b"==").or_else(|| digits.strip_suffix(b"=")).unwrap_or(&digits);
+    let mut data = Vec::with_capacity(unpadded.len() * 3 / 4);
+    let mut bits: u32 = 0;
+    let mut bit_count = 0;
+    for digit in unpadded {
+        let value = match digit {
+            b'A'..=b'Z' => digit - b'A',
+            b'a'..=b'z' => digit - b'a' + 26,
+            b'0'..=b'9' => digit - b'0' + 52,
+            b'+' => 62,
+            b'/' => 63,
+            _ => return None,
+        };
+        bits = bits << 6 | u32::from(value);
+        bit_count += 6;
+        if bit_count >= 8 {
+            bit_count -= 8;
+            data.push((bits >> bit_count) as u8);
+        }
+    }
+    Some(data)
+}
+
+
+fn decode_hex(s: &str) -> Option<Vec<u8>> {
+    if !s.len().is_multiple_of(2) || !s.bytes().all(|b| b.is_ascii_hexdigit()) {
+        return None;
+    }
+    (0..s.len()).step_by(2)
+        .map(|i| u8::from_str_radix(&s[i..i + 2], 16).ok())
+        .collect()
+}
+
+
+/// Decodes a string constant that holds binary data rather than text.
+fn decode_string_payload(s: &str) -> Option<(PayloadEncoding, Vec<u8>)> {
+    if s.len() < MIN_PAYLOAD_LEN {
+        return None;
+    }
+    // hexadecimal digits are also Base64 digits
+    if let Some(data) = decode_hex(s) {
+        return Some((PayloadEncoding::Hex, data));
+    }
+    if let Some(data) = decode_base64(s) {
+        return Some((PayloadEncoding::Base64, data));
+    }
+    let is_binary = s.chars().any(|c| c.is_control() && !c.is_whitespace());
+    if is_binary && s.chars().all(|c| u32::from(c) <= 0xFF) {
+        return Some((PayloadEncoding::Latin1, s.chars().map(|c| c as u8).collect()));
+    }
+    None
+}
+
+
+fn read_u30(code: &[u8], mut pos: usize) -> Option<(u32, usize)> {
+    let mut value: u32 = 0;
+    for shift in (0..35).step_by(7) {
+        let b = *code.get(pos)?;
+        pos += 1;
+        value |= u32::from(b & 0x7F) << shift;
+        if b & 0x80 == 0 {
+            return Some((value, pos));
+        }
+    }
+    None
+}
+
+
+/// Reads a run of instructions pushing integers, starting at `pos`, and returns their values
+/// truncated to bytes with the position after the run.
+fn read_pushed_bytes(code: &[u8], ints: &[i32], mut pos: usize) -> (Vec<u8>, usize) {
+    let mut bytes = Vec::new();
+    loop {
+        let pushed = match code.get(pos) {
+            Some(&OP_PUSHBYTE) => code.get(pos + 1).map(|b| (*b, pos + 2)),
+            Some(&OP_PUSHSHORT) => read_u30(code, pos + 1).map(|(v, next)| (v as u8, next)),
+            Some(&OP_PUSHINT) => read_u30(code, pos + 1).and_then(|(index, next)| {
+                let value = ints.get(usize::try_from(index).ok()?.checked_sub(1)?)?;
+                Some((*value as u8, next))
+            }),
+            _ => None,
+        };
+        let Some((byte, next)) = pushed else { break };
+        bytes.push(byte);
+        pos = next;
+    }
+    (bytes, pos)
+}
+
+
+/// Finds array literals of integers in the code of a method body (a run of instructions pushing
+/// integers followed by `newarray` with their count), returning their values as bytes.
+fn byte_array_literals(code: &[u8], ints: &[i32]) -> Vec<Vec<u8>> {
+    let mut literals = Vec::new();
+    let mut pos = 0;
+    while pos < code.len() {
+        let (bytes, end) = read_pushed_bytes(code, ints, pos);
+        if bytes.is_empty() {
+            pos += 1;
+            continue;
+        }
+        let is_array = code.get(end) == Some(&OP_NEWARRAY)
+            && read_u30(code, end + 1).is_some_and(|(count, _)| usize::try_from(count).unwrap() == bytes.len());
+        if is_array && bytes.len() >= MIN_PAYLOAD_LEN {
+            literals.push(bytes);
+        }
+        pos = end;
+    }
+    literals
+}
+
+
+/// Collects data hidden in the constants of an ABC file: long strings of Base64, hexadecimal digits
+/// or binary data, and long array literals of bytes. Data compressed with zlib is inflated.
+///
+/// Returns `None` if the file cannot be decoded.
+pub(crate) fn abc_payloads(abc_data: &[u8]) -> Option<Vec<AbcPayload>> {
+    let abc = swf::avm2::read::Reader::new(abc_data).read().ok()?;
+    let mut payloads = Vec::new();
+    for (i, s) in abc.constant_pool.strings.iter().enumerate() {
+        if let Some((encoding, data)) = decode_string_payload(s) {
+            // indexes of the constant pool count the implicit empty string
+            payloads.push(AbcPayload::new(format!("string {}", i + 1), encoding, data));
+        }
+    }
+    for (i, body) in abc.method_bodies.iter().enumerate() {
+        for data in byte_array_literals(&body.code, &abc.constant_pool.ints) {
+            payloads.push(AbcPayload::new(format!("method body {}", i), PayloadEncoding::ByteArray, data));
+        }
+    }
+    Some(payloads)
+}
@@ -75,18 +75,34 @@ const SAMPLE_DELTA_CALCULATOR: [fn(u16, u32) -> u16; 4] = [
     },
 ];
 
+/// An error that occurred while decoding ADPCM data.
 #[derive(Debug)]
 pub enum Error {
+    /// The data does not start with a valid ADPCM header.
     Invalid,
+
+    /// Reading the data failed.
+    Io(std::io::Error),
 }
 impl fmt::Display for Error {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         match self {
             Self::Invalid => write!(f, "Invalid ADPCM sound"),
+            Self::Io(e) => write!(f, "I/O error: {}", e),
+        }
+    }
+}
+impl std::error::Error for Error {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Self::Invalid => None,
+            Self::Io(e) => Some(e),
         }
     }
 }
-impl std::error::Error for Error {}
+impl From<std::io::Error> for Error {
+    fn from(value: std::io::Error) -> Self { Self::Io(value) }
+}
 
 #[derive(Clone, Default)]
 struct Channel {
@@ -94,15 +110,22 @@ struct Channel {
     step_index: i16,
 }
 
+/// Decodes the ADPCM data of an event sound or of a stream sound block.
+///
+/// Each item is a pair of samples (left and right); for mono sounds, both are the same. Decoding
+/// ends when the data runs out; as the data is padded to full bytes, up to a few trailing samples
+/// may be garbage.
 pub struct AdpcmDecoder<R: Read> {
     inner: BitReader<R, BigEndian>,
     bits_per_sample: usize,
     sample_num: u16,
     channels: Vec<Channel>,
     decoder: fn(u16, u32) -> u16,
+    is_finished: bool,
 }
 
 impl<R: Read> AdpcmDecoder<R> {
+    /// Creates a decoder reading ADPCM data from `inner`.
     pub fn new(inner: R, is_stereo: bool) -> Result<Self, Error> {
         let mut reader = BitReader::new(inner);
         let bits_per_sample = reader.read::<u8>(2).map_err(|_| Error::Invalid)? as usize + 2;
@@ -115,19 +138,31 @@ impl<R: Read> AdpcmDecoder<R> {
             sample_num: 0,
             channels: vec![Default::default(); num_channels],
             decoder: SAMPLE_DELTA_CALCULATOR[bits_per_sample - 2],
+            is_finished: false,
         })
     }
-}
 
-impl<R: Read> Iterator for AdpcmDecoder<R> {
-    type Item = [i16; 2];
+    /// Returns the number of channels (1 or 2) of the sound.
+    pub fn channels(&self) -> usize {
+        self.channels.len()
+    }
 
-    fn next(&mut self) -> Option<Self::Item> {
+    /// Turns the decoder into a reader of interleaved signed 16-bit little-endian PCM data.
+    pub fn into_pcm_reader(self) -> AdpcmPcmReader<R> {
+        AdpcmPcmReader {
+            decoder: self,
+            buffer: [0; 4],
+            buffer_pos: 0,
+            buffer_len: 0,
+        }
+    }
+
+    fn decode_sample(&mut self) -> Result<[i16; 2], std::io::Error> {
         if self.sample_num == 0 {
             // The initial sample values are NOT byte-aligned.
             for channel in &mut self.channels {
-                channel.sample = self.inner.read_signed(16).ok()?;
-                channel.step_index = self.inner.read::<u16>(6).ok()? as i16;
+                channel.sample = self.inner.read_signed(16)?;
+                channel.step_index = self.inner.read::<u16>(6)? as i16;
             }
         }
 
@@ -137,7 +172,7 @@ impl<R: Read> Iterator for AdpcmDecoder<R> {
             let step = STEP_TABLE[channel.step_index as usize];
 
             // `data` is sign-magnitude, NOT two's complement.
-            let data = self.inner.read::<u32>(self.bits_per_sample as u32).ok()?;
+            let data = self.inner.read::<u32>(self.bits_per_sample as u32)?;
             let sign_mask = 1 << (self.bits_per_sample - 1);
             let magnitude = data & !sign_mask;
 
@@ -156,6 +191,64 @@ impl<R: Read> Iterator for AdpcmDecoder<R> {
 
         let left = self.channels[0].sample;
         let right = self.channels.get(1).map_or(left, |c| c.sample);
-        Some([left, right])
+        Ok([left, right])
+    }
+}
+
+impl<R: Read> Iterator for AdpcmDecoder<R> {
+    type Item = Result<[i16; 2], Error>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.is_finished {
+            return None;
+        }
+        match self.decode_sample() {
+            Ok(samples) => Some(Ok(samples)),
+            Err(e) => {
+                self.is_finished = true;
+                if e.kind() == std::io::ErrorKind::UnexpectedEof {
+                    // regular end of data
+                    None
+                } else {
+                    Some(Err(e.into()))
+                }
+            },
+        }
+    }
+}
+
+
+/// Reads the output of an [`AdpcmDecoder`] as interleaved signed 16-bit little-endian PCM data.
+pub struct AdpcmPcmReader<R: Read> {
+    decoder: AdpcmDecoder<R>,
+    buffer: [u8; 4],
+    buffer_pos: usize,
+    buffer_len: usize,
+}
+
+impl<R: Read> Read for AdpcmPcmReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        let mut written = 0;
+        while written < buf.len() {
+            if self.buffer_pos == self.buffer_len {
+                let samples = match self.decoder.next() {
+                    Some(Ok(samples)) => samples,
+                    Some(Err(Error::Io(e))) => return Err(e),
+                    Some(Err(e)) => return Err(std::io::Error::new(std::io::ErrorKind::InvalidData, e)),
+                    None => break,
+                };
+                let channels = self.decoder.channels();
+                for (channel, sample) in samples.iter().take(channels).enumerate() {
+                    self.buffer[2*channel..2*channel + 2].copy_from_slice(&sample.to_le_bytes());
+                }
+                self.buffer_pos = 0;
+                self.buffer_len = 2 * channels;
+            }
+            let count = (self.buffer_len - self.buffer_pos).min(buf.len() - written);
+            buf[written..written + count].copy_from_slice(&self.buffer[self.buffer_pos..self.buffer_pos + count]);
+            self.buffer_pos += count;
+            written += count;
+        }
+        Ok(written)
     }
 }
@@ -0,0 +1,36 @@
+use swf::avm1::read::Reader;
+use swf::avm1::types::Action;
+
+
+/// Calls `visit` for every action in the given AVM1 bytecode, including the actions nested within
+/// function definitions, `try` blocks and `with` blocks.
+///
+/// Decoding stops at the first malformed action; everything decoded up to that point is still
+/// visited.
+pub(crate) fn walk_actions<'a, F: FnMut(&Action<'a>)>(action_data: &'a [u8], swf_version: u8, visit: &mut F) {
+    let mut reader = Reader::new(action_data, swf_version);
+    while !reader.get_ref().is_empty() {
+        let action = match reader.read_action() {
+            Ok(a) => a,
+            Err(_) => break,
+        };
+        visit(&action);
+
+        match &action {
+            Action::DefineFunction(df) => walk_actions(df.actions, swf_version, visit),
+            Action::DefineFunction2(df) => walk_actions(df.actions, swf_version, visit),
+            Action::Try(t) => {
+                walk_actions(t.try_body, swf_version, visit);
+                if let Some((_, catch_body)) = t.catch_body {
+                    walk_actions(catch_body, swf_version, visit);
+                }
+                if let Some(finally_body) = t.finally_body {
+                    walk_actions(finally_body, swf_version, visit);
+                }
+            },
+            Action::With(w) => walk_actions(w.actions, swf_version, visit),
+            Action::End => break,
+            _ => {},
+        }
+    }
+}
@@ -0,0 +1,296 @@
+//! Extracting a movie on a thread of its own, so that servers do not block their worker threads
+//! (or the executor of an asynchronous runtime) on large movies.
+
+
+use std::collections::VecDeque;
+use std::error;
+use std::fmt;
+use std::fs;
+use std::io;
+use std::panic::{self, AssertUnwindSafe};
+use std::path::PathBuf;
+use std::sync::{Arc, Condvar, Mutex, MutexGuard};
+use std::task::{Context, Poll, Waker};
+use std::thread;
+use std::time::Duration;
+
+use crate::cancel::Cancellation;
+use crate::extract::{Decoded, ExtractOptions, Extractor};
+use crate::read::{self, decompress_movie, patch_avc_streams};
+
+
+/// How many decoded items may wait to be taken before the extraction thread waits in turn.
+const QUEUE_LEN: usize = 8;
+
+/// How often the extraction thread checks the deadline of the cancellation while waiting for room.
+const DEADLINE_POLL_INTERVAL: Duration = Duration::from_millis(100);
+
+
+#[derive(Debug)]
+pub enum Error {
+    /// The movie file could not be read.
+    Io(io::Error),
+
+    /// The movie could not be decompressed or parsed. This is the message of the [`read::Error`],
+    /// which cannot be sent between threads.
+    Read(String),
+
+    /// The extraction thread panicked, with the given message.
+    Panicked(String),
+}
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Io(e) => write!(f, "failed to read the movie: {}", e),
+            Self::Read(message) => write!(f, "{}", message),
+            Self::Panicked(message) => write!(f, "extraction panicked: {}", message),
+        }
+    }
+}
+impl error::Error for Error {
+    fn source(&self) -> Option<&(dyn error::Error + 'static)> {
+        match self {
+            Self::Io(e) => Some(e),
+            Self::Read(_) => None,
+            Self::Panicked(_) => None,
+        }
+    }
+}
+impl From<io::Error> for Error {
+    fn from(value: io::Error) -> Self { Self::Io(value) }
+}
+impl From<read::Error> for Error {
+    fn from(value: read::Error) -> Self { Self::Read(value.to_string()) }
+}
+
+
+/// How the extraction thread ended.
+#[derive(Debug)]
+pub enum Outcome {
+    /// All characters have been decoded.
+    Finished,
+
+    /// Decoding has been stopped by the cancellation.
+    Cancelled,
+
+    /// The movie could not be read or decoding panicked; the items queued before are still
+    /// available.
+    Failed(Error),
+}
+
+
+/// The state shared between the extraction thread and the [`BackgroundExtraction`].
+#[derive(Default)]
+struct State {
+    queue: VecDeque<Decoded>,
+
+    /// Whether the extraction thread has ended.
+    ended: bool,
+
+    /// How the extraction thread ended, until the outcome is taken.
+    outcome: Option<Outcome>,
+
+    /// The task to wake once an item is queued or the extraction ends.
+    waker: Option<Waker>,
+}
+
+
+#[derive(Default)]
+struct Shared {
+    state: Mutex<State>,
+
+    /// Notified whenever an item is queued or taken and when the extraction ends.
+    changed: Condvar,
+}
+impl Shared {
+    fn lock(&self) -> MutexGuard<'_, State> {
+        // the state stays consistent even if a thread panicked while holding the lock
+        self.state.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    /// Queues an item, first waiting for room unless decoding has been cancelled.
+    fn push(&self, item: Decoded, cancellation: &Cancellation) {
+        let mut state = self.lock();
+        while state.queue.len() >= QUEUE_LEN && !cancellation.is_cancelled() {
+            // wake up now and then, as nobody notifies when the deadline passes
+            state = self.changed.wait_timeout(state, DEADLINE_POLL_INTERVAL)
+                .unwrap_or_else(|e| e.into_inner())
+                .0;
+        }
+        if cancellation.is_cancelled() {
+            return;
+        }
+        state.queue.push_back(item);
+        self.notify(state);
+    }
+
+    fn finish(&self, outcome: Outcome) {
+        let mut state = self.lock();
+        state.ended = true;
+        state.outcome = Some(outcome);
+        self.notify(state);
+    }
+
+    fn notify(&self, mut state: MutexGuard<'_, State>) {
+        let waker = state.waker.take();
+        drop(state);
+        self.changed.notify_all();
+        if let Some(waker) = waker {
+            waker.wake();
+        }
+    }
+}
+
+
+/// Decodes the bitmaps, sounds and binary data of a movie (as [`Extractor::extract`] does) on a
+/// separate thread, handing over each asset and warning as soon as it is decoded.
+///
+/// The items can be taken by blocking (as an [`Iterator`]), without blocking with
+/// [`BackgroundExtraction::try_next`], or from an asynchronous task with
+/// [`BackgroundExtraction::poll_next`], which has the signature of `futures::Stream::poll_next` and
+/// can be turned into a stream by `futures::stream::poll_fn`; no asynchronous runtime is needed.
+///
+/// The thread decodes only a few items ahead of those taken. Dropping the extraction cancels it.
+pub struct BackgroundExtraction {
+    shared: Arc<Shared>,
+    cancellation: Cancellation,
+}
+impl BackgroundExtraction {
+    /// Starts extracting the given SWF file, which is decompressed and parsed on the new thread.
+    pub fn spawn(data: Vec<u8>, options: ExtractOptions, cancellation: Cancellation) -> Self {
+        Self::spawn_with(options, cancellation, move || Ok(data))
+    }
+
+    /// Starts extracting the SWF file at the given path, which is also read on the new thread.
+    pub fn spawn_file(path: PathBuf, options: ExtractOptions, cancellation: Cancellation) -> Self {
+        Self::spawn_with(options, cancellation, move || fs::read(path))
+    }
+
+    fn spawn_with<F>(options: ExtractOptions, cancellation: Cancellation, read_movie: F) -> Self
+        where F: FnOnce() -> io::Result<Vec<u8>> + Send + 'static {
+        let shared = Arc::new(Shared::default());
+        let thread_shared = Arc::clone(&shared);
+        let thread_cancellation = cancellation.clone();
+        thread::spawn(move || {
+            // the extraction must end even if decoding panics, as the items are otherwise awaited forever
+            let result = panic::catch_unwind(AssertUnwindSafe(
+                || extract_movie(read_movie, options, &thread_shared, &thread_cancellation)
+            ));
+            let outcome = match result {
+                Ok(Ok(false)) => Outcome::Finished,
+                Ok(Ok(true)) => Outcome::Cancelled,
+                Ok(Err(e)) => Outcome::Failed(e),
+                Err(payload) => {
+                    let message = payload.downcast_ref::<&str>().map(|message| message.to_string())
+                        .or_else(|| payload.downcast_ref::<String>().cloned())
+                        .unwrap_or_default();
+                    Outcome::Failed(Error::Panicked(message))
+                },
+            };
+            thread_shared.finish(outcome);
+        });
+        Self {
+            shared,
+            cancellation,
+        }
+    }
+
+    /// Stops the extraction; items already decoded can still be taken.
+    pub fn cancel(&self) {
+        self.cancellation.cancel();
+        // the extraction thread holds the lock between checking the cancellation and waiting
+        drop(self.shared.lock());
+        self.shared.changed.notify_all();
+    }
+
+    /// Takes the next item if one has been decoded, without blocking. Returns `Poll::Ready(None)`
+    /// once the extraction has ended and all items have been taken.
+    pub fn try_next(&mut self) -> Poll<Option<Decoded>> {
+        let state = self.shared.lock();
+        self.take_queued(state)
+    }
+
+    /// Takes the next item if one has been decoded, otherwise arranging for the task of the context
+    /// to be woken once there is one or the extraction has ended.
+    pub fn poll_next(&mut self, cx: &mut Context<'_>) -> Poll<Option<Decoded>> {
+        let mut state = self.shared.lock();
+        if state.queue.is_empty() && !state.ended {
+            state.waker = Some(cx.waker().clone());
+        }
+        self.take_queued(state)
+    }
+
+    fn take_queued(&self, mut state: MutexGuard<'_, State>) -> Poll<Option<Decoded>> {
+        match state.queue.pop_front() {
+            Some(item) => {
+                drop(state);
+                self.shared.changed.notify_all();
+                Poll::Ready(Some(item))
+            },
+            None if state.ended => Poll::Ready(None),
+            None => Poll::Pending,
+        }
+    }
+
+    /// How the extraction ended, once it has (and until the outcome is taken).
+    pub fn take_outcome(&mut self) -> Option<Outcome> {
+        self.shared.lock().outcome.take()
+    }
+}
+impl Iterator for BackgroundExtraction {
+    type Item = Decoded;
+
+    /// Takes the next item, waiting for it to be decoded.
+    fn next(&mut self) -> Option<Self::Item> {
+        let mut state = self.shared.lock();
+        while state.queue.is_empty() && !state.ended {
+            state = self.shared.changed.wait(state).unwrap_or_else(|e| e.into_inner());
+        }
+        match self.take_queued(state) {
+            Poll::Ready(item) => item,
+            Poll::Pending => unreachable!("the extraction neither ended nor queued an item"),
+        }
+    }
+}
+impl Drop for BackgroundExtraction {
+    fn drop(&mut self) {
+        self.cancel();
+    }
+}
+
+
+/// Reads, parses and decodes the movie, queueing the decoded items. Returns whether decoding has
+/// been cancelled.
+fn extract_movie<F>(read_movie: F, options: ExtractOptions, shared: &Shared, cancellation: &Cancellation) -> Result<bool, Error>
+    where F: FnOnce() -> io::Result<Vec<u8>> {
+    let data = read_movie()?;
+    let mut swf_buf = decompress_movie(&data, options.max_sprite_depth)?;
+    drop(data);
+    patch_avc_streams(&mut swf_buf.data);
+    let extractor = Extractor::new(&swf_buf, options)?
+        .with_cancellation(cancellation.clone());
+    let mut decoded = extractor.decoded();
+    for item in decoded.by_ref() {
+        shared.push(item, cancellation);
+    }
+    Ok(decoded.is_cancelled())
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+
+    #[test]
+    fn panicking_extraction_ends() {
+        let mut extraction = BackgroundExtraction::spawn_with(
+            ExtractOptions::default(), Cancellation::new(), || panic!("no movie"),
+        );
+        assert!(extraction.next().is_none());
+        match extraction.take_outcome() {
+            Some(Outcome::Failed(Error::Panicked(message))) => assert_eq!(message, "no movie"),
+            other => panic!("unexpected outcome {:?}", other),
+        }
+    }
+}
@@ -1,25 +1,50 @@
 use std::fmt;
 use std::io::{Read, Write};
 
-use gif;
-use jpeg_decoder::PixelFormat;
+use clap::ValueEnum;
+use flate2::Compression;
+use flate2::write::ZlibEncoder;
 use png::{BitDepth, ColorType};
+use swf::{BitmapFormat, CharacterId, DefineBitsLossless};
+
+use crate::cancel::{Cancellation, Cancelled};
+use crate::pixels::{add_alpha_to_jpeg_pixels, jpeg_pixels_to_rgba, png_pixels_to_rgba};
+use crate::warning::{Warning, WarningCode};
 
 
 const GIF_MAGIC: &[u8] = b"\x47\x49\x46\x38\x39\x61";
 const JPEG_MAGIC: &[u8] = b"\xFF\xD8";
+const JPEG_EOI: &[u8] = b"\xFF\xD9";
+
+/// An end-of-image marker followed by a start-of-image marker, which older versions of Flash
+/// prepend to JPEG data.
+const JPEG_ERRONEOUS_HEADER: &[u8] = b"\xFF\xD9\xFF\xD8";
 const PNG_MAGIC: &[u8] = b"\x89\x50\x4E\x47\x0D\x0A\x1A\x0A";
 
+/// How many bytes of a lossless bitmap are decompressed between checks for cancellation.
+const LOSSLESS_CHUNK_LEN: u64 = 1024 * 1024;
+
+
+/// The file format in which color-mapped bitmaps without transparency are output.
+#[derive(Clone, Copy, Debug, Default, Eq, Hash, PartialEq, ValueEnum)]
+pub enum IndexedFormat {
+    #[default]
+    Png,
+
+    /// A single-frame GIF with the original palette.
+    Gif,
+}
+
 
 #[derive(Clone, Debug, Eq, Hash, Ord, PartialEq, PartialOrd)]
-pub(crate) struct RgbColor {
+pub struct RgbColor {
     pub r: u8,
     pub g: u8,
     pub b: u8,
 }
 
 #[derive(Clone, Debug, Eq, Hash, Ord, PartialEq, PartialOrd)]
-pub(crate) struct RgbaColor {
+pub struct RgbaColor {
     pub r: u8,
     pub g: u8,
     pub b: u8,
@@ -27,15 +52,19 @@ pub(crate) struct RgbaColor {
 }
 
 #[derive(Debug)]
-pub(crate) enum Error {
+pub enum Error {
     Io(std::io::Error),
     JpegDecoding(jpeg_decoder::Error),
     PngDecoding(png::DecodingError),
     PngEncoding(png::EncodingError),
     GifDecoding(gif::DecodingError),
+    GifEncoding(gif::EncodingError),
     ZlibDecoding(std::io::Error),
-    ShortRead,
-    Cmyk,
+    NoJpegScan,
+    InvalidJpeg(&'static str),
+    InvalidLossless(&'static str),
+    UnknownFormat,
+    Cancelled,
 }
 impl fmt::Display for Error {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
@@ -45,9 +74,13 @@ impl fmt::Display for Error {
             Self::PngDecoding(e) => write!(f, "PNG decoding error: {}", e),
             Self::PngEncoding(e) => write!(f, "PNG encoding error: {}", e),
             Self::GifDecoding(e) => write!(f, "GIF decoding error: {}", e),
+            Self::GifEncoding(e) => write!(f, "GIF encoding error: {}", e),
             Self::ZlibDecoding(e) => write!(f, "zlib encoding error: {}", e),
-            Self::ShortRead => write!(f, "not enough bytes available"),
-            Self::Cmyk => write!(f, "images in CMYK color are unsupported"),
+            Self::NoJpegScan => write!(f, "no start of scan in JPEG data"),
+            Self::InvalidJpeg(reason) => write!(f, "invalid JPEG data: {}", reason),
+            Self::InvalidLossless(reason) => write!(f, "invalid lossless bitmap: {}", reason),
+            Self::UnknownFormat => write!(f, "image data is neither JPEG, PNG nor GIF"),
+            Self::Cancelled => write!(f, "{}", Cancelled),
         }
     }
 }
@@ -59,9 +92,13 @@ impl std::error::Error for Error {
             Self::PngDecoding(e) => Some(e),
             Self::PngEncoding(e) => Some(e),
             Self::GifDecoding(e) => Some(e),
+            Self::GifEncoding(e) => Some(e),
             Self::ZlibDecoding(e) => Some(e),
-            Self::ShortRead => None,
-            Self::Cmyk => None,
+            Self::NoJpegScan => None,
+            Self::InvalidJpeg(_) => None,
+            Self::InvalidLossless(_) => None,
+            Self::UnknownFormat => None,
+            Self::Cancelled => None,
         }
     }
 }
@@ -80,6 +117,20 @@ impl From<png::EncodingError> for Error {
 impl From<gif::DecodingError> for Error {
     fn from(value: gif::DecodingError) -> Self { Self::GifDecoding(value) }
 }
+impl From<gif::EncodingError> for Error {
+    fn from(value: gif::EncodingError) -> Self { Self::GifEncoding(value) }
+}
+impl From<Cancelled> for Error {
+    fn from(_: Cancelled) -> Self { Self::Cancelled }
+}
+
+
+/// Makes the RGBA pixels from the given one onward fully transparent.
+fn clear_rgba_pixels(rgba: &mut [u8], first_pixel: usize) {
+    for byte in rgba.iter_mut().skip(4 * first_pixel) {
+        *byte = 0;
+    }
+}
 
 
 /// Scales a 5-bit value to an 8-bit value.
@@ -90,8 +141,10 @@ fn scale_5_to_8(value: u16) -> u8 {
 
 
 #[derive(Clone, Debug, Eq, Hash, Ord, PartialEq, PartialOrd)]
-pub(crate) struct Bitmap {
+pub struct Bitmap {
+    /// The width of the bitmap in pixels; zero (as is the height) if unknown.
     pub width: u32,
+
     pub height: u32,
     pub data: BitmapData,
 }
@@ -104,7 +157,97 @@ impl Bitmap {
         }
     }
 
-    pub fn extension(&self) -> &str {
+    /// Wraps PNG or GIF data whose header could not be read, so that it is written out unchanged.
+    pub fn pass_through(bytes: &[u8]) -> Option<Self> {
+        let data = if bytes.starts_with(GIF_MAGIC) {
+            BitmapData::Gif { gif_data: Vec::from(bytes) }
+        } else if bytes.starts_with(PNG_MAGIC) {
+            BitmapData::Png { png_data: Vec::from(bytes) }
+        } else {
+            return None;
+        };
+        Some(Self::new(0, 0, data))
+    }
+
+    /// The width and height of the bitmap, if known.
+    pub fn dimensions(&self) -> Option<(u32, u32)> {
+        if self.width == 0 && self.height == 0 {
+            None
+        } else {
+            Some((self.width, self.height))
+        }
+    }
+
+    /// Makes the rows from the given one onward transparent, for lossless bitmaps whose data ends
+    /// early. Bitmaps without an alpha channel are converted to formats with one.
+    pub fn make_rows_transparent(&mut self, first_row: u32) {
+        let width = usize::try_from(self.width).unwrap();
+        let first_pixel = width * usize::try_from(first_row).unwrap();
+        let data = std::mem::replace(&mut self.data, BitmapData::Rgba32 { image_data: Vec::new() });
+        self.data = match data {
+            BitmapData::ColorMapped { palette, image_data } => {
+                let palette = palette.into_iter()
+                    .map(|RgbColor { r, g, b }| RgbaColor { r, g, b, a: 0xFF })
+                    .collect();
+                let mut bitmap = Bitmap::new(self.width, self.height, BitmapData::ColorMappedAlpha { palette, image_data });
+                bitmap.make_rows_transparent(first_row);
+                bitmap.data
+            },
+            BitmapData::ColorMappedAlpha { mut palette, mut image_data } => {
+                // use a transparent palette entry, adding one if there is room
+                let transparent_index = match palette.iter().position(|c| c.a == 0) {
+                    Some(i) => i,
+                    None if palette.len() < 256 => {
+                        palette.push(RgbaColor { r: 0, g: 0, b: 0, a: 0 });
+                        palette.len() - 1
+                    },
+                    None => 0,
+                };
+                for pixel in image_data.iter_mut().skip(first_pixel) {
+                    *pixel = transparent_index as u8;
+                }
+                BitmapData::ColorMappedAlpha { palette, image_data }
+            },
+            BitmapData::Rgb15 { image_data } => {
+                let mut rgba = Vec::with_capacity(2 * image_data.len());
+                for pixel in image_data.chunks_exact(2) {
+                    let word = (u16::from(pixel[0]) << 8) | u16::from(pixel[1]);
+                    rgba.extend([scale_5_to_8(word >> 10), scale_5_to_8(word >> 5), scale_5_to_8(word), 0xFF]);
+                }
+                clear_rgba_pixels(&mut rgba, first_pixel);
+                BitmapData::Rgba32 { image_data: rgba }
+            },
+            BitmapData::Rgb24 { image_data } => {
+                let mut rgba = Vec::with_capacity(4 * image_data.len() / 3);
+                for pixel in image_data.chunks_exact(3) {
+                    rgba.extend([pixel[0], pixel[1], pixel[2], 0xFF]);
+                }
+                clear_rgba_pixels(&mut rgba, first_pixel);
+                BitmapData::Rgba32 { image_data: rgba }
+            },
+            BitmapData::Rgba32 { mut image_data } => {
+                clear_rgba_pixels(&mut image_data, first_pixel);
+                BitmapData::Rgba32 { image_data }
+            },
+            other => other,
+        };
+    }
+
+    /// The number of bytes taken up by the data of the bitmap.
+    pub fn data_len(&self) -> usize {
+        match &self.data {
+            BitmapData::Gif { gif_data } => gif_data.len(),
+            BitmapData::Jpeg { jpeg_data, alpha_data } => jpeg_data.len() + alpha_data.as_ref().map_or(0, |a| a.len()),
+            BitmapData::Png { png_data } => png_data.len(),
+            BitmapData::ColorMapped { palette, image_data } => palette.len() * 3 + image_data.len(),
+            BitmapData::ColorMappedAlpha { palette, image_data } => palette.len() * 4 + image_data.len(),
+            BitmapData::Rgb15 { image_data } => image_data.len(),
+            BitmapData::Rgb24 { image_data } => image_data.len(),
+            BitmapData::Rgba32 { image_data } => image_data.len(),
+        }
+    }
+
+    pub fn extension(&self, indexed_format: IndexedFormat) -> &str {
         match &self.data {
             BitmapData::Gif { .. } => "gif",
             BitmapData::Jpeg { alpha_data, .. } => {
@@ -115,7 +258,10 @@ impl Bitmap {
                 }
             },
             BitmapData::Png { .. } => "png",
-            BitmapData::ColorMapped { .. } => "png",
+            BitmapData::ColorMapped { .. } => match indexed_format {
+                IndexedFormat::Png => "png",
+                IndexedFormat::Gif => "gif",
+            },
             BitmapData::ColorMappedAlpha { .. } => "png",
             BitmapData::Rgb15 { .. } => "png",
             BitmapData::Rgb24 { .. } => "png",
@@ -123,10 +269,10 @@ impl Bitmap {
         }
     }
 
-    pub fn write<W: Write>(&self, mut write: W) -> Result<(), Error> {
+    pub fn write<W: Write>(&self, indexed_format: IndexedFormat, mut write: W) -> Result<(), Error> {
         match &self.data {
-            BitmapData::Gif { gif_data } => write.write_all(&gif_data)?,
-            BitmapData::Png { png_data } => write.write_all(&png_data)?,
+            BitmapData::Gif { gif_data } => write.write_all(gif_data)?,
+            BitmapData::Png { png_data } => write.write_all(png_data)?,
             BitmapData::Jpeg { jpeg_data, alpha_data } => {
                 if let Some(ad) = alpha_data {
                     // decode alpha data
@@ -134,96 +280,31 @@ impl Bitmap {
                     {
                         let mut decoder = flate2::read::ZlibDecoder::new(ad.as_slice());
                         decoder.read_to_end(&mut alpha_pixels)
-                            .map_err(|e| Error::ZlibDecoding(e))?;
+                            .map_err(Error::ZlibDecoding)?;
                     }
 
                     // we don't have JPEG-with-transparency; convert to PNG
-                    let (image_info, pixels) = {
+                    let (image_info, icc_profile, pixels) = {
                         let mut decoder = jpeg_decoder::Decoder::new(jpeg_data.as_slice());
-                        let image_info = decoder.info().unwrap();
-                        (image_info, decoder.decode()?)
+                        let pixels = decoder.decode()?;
+                        (decoder.info().unwrap(), decoder.icc_profile(), pixels)
                     };
 
-                    let mut pixels_iterator = pixels.iter();
-                    let mut alpha_iterator = alpha_pixels.iter();
+                    let pixel_count = usize::from(image_info.width) * usize::from(image_info.height);
+                    let png_pixels = add_alpha_to_jpeg_pixels(image_info.pixel_format, &pixels, &alpha_pixels, pixel_count);
 
                     let mut png = png::Encoder::new(
                         write,
                         image_info.width.into(),
                         image_info.height.into(),
                     );
-                    match image_info.pixel_format {
-                        PixelFormat::L8 => {
-                            png.set_color(ColorType::GrayscaleAlpha);
-                            png.set_depth(BitDepth::Eight);
-                            let mut writer = png.write_header()?;
-
-                            let mut row = Vec::new();
-                            for _ in 0..image_info.height {
-                                row.clear();
-                                for _ in 0..image_info.width {
-                                    let grayscale_value = pixels_iterator.next()
-                                        .ok_or(Error::ShortRead)?;
-                                    let alpha_value = alpha_iterator.next()
-                                        .ok_or(Error::ShortRead)?;
-                                    row.push(*grayscale_value);
-                                    row.push(*alpha_value);
-                                }
-                                writer.write_image_data(&row)?;
-                            }
-                        },
-                        PixelFormat::L16 => {
-                            png.set_color(ColorType::GrayscaleAlpha);
-                            png.set_depth(BitDepth::Sixteen);
-                            let mut writer = png.write_header()?;
-
-                            let mut row = Vec::new();
-                            for _ in 0..image_info.height {
-                                row.clear();
-                                for _ in 0..image_info.width {
-                                    let grayscale_value_msb = pixels_iterator.next()
-                                        .ok_or(Error::ShortRead)?;
-                                    let grayscale_value_lsb = pixels_iterator.next()
-                                        .ok_or(Error::ShortRead)?;
-                                    let alpha_value = alpha_iterator.next()
-                                        .ok_or(Error::ShortRead)?;
-                                    row.push(*grayscale_value_msb);
-                                    row.push(*grayscale_value_lsb);
-
-                                    // 8-bit values can be scaled to 16 bits via duplication
-                                    row.push(*alpha_value);
-                                    row.push(*alpha_value);
-                                }
-                                writer.write_image_data(&row)?;
-                            }
-                        },
-                        PixelFormat::RGB24 => {
-                            png.set_color(ColorType::Rgba);
-                            png.set_depth(BitDepth::Eight);
-                            let mut writer = png.write_header()?;
-
-                            let mut row = Vec::new();
-                            for _ in 0..image_info.height {
-                                row.clear();
-                                for _ in 0..image_info.width {
-                                    let r = pixels_iterator.next()
-                                        .ok_or(Error::ShortRead)?;
-                                    let g = pixels_iterator.next()
-                                        .ok_or(Error::ShortRead)?;
-                                    let b = pixels_iterator.next()
-                                        .ok_or(Error::ShortRead)?;
-                                    let alpha_value = alpha_iterator.next()
-                                        .ok_or(Error::ShortRead)?;
-                                    row.push(*r);
-                                    row.push(*g);
-                                    row.push(*b);
-                                    row.push(*alpha_value);
-                                }
-                                writer.write_image_data(&row)?;
-                            }
-                        },
-                        PixelFormat::CMYK32 => return Err(Error::Cmyk),
+                    png.set_color(png_pixels.color_type);
+                    png.set_depth(png_pixels.bit_depth);
+                    let mut writer = png.write_header()?;
+                    if let Some(profile) = &icc_profile {
+                        write_icc_profile(&mut writer, profile)?;
                     }
+                    writer.write_image_data(&png_pixels.data)?;
                 } else {
                     write.write_all(jpeg_data)?;
                 }
@@ -236,6 +317,16 @@ impl Bitmap {
                     palette_bytes.push(color.b);
                 }
 
+                if indexed_format == IndexedFormat::Gif {
+                    // SWF dimensions are 16-bit, just like GIF dimensions
+                    let width = u16::try_from(self.width).unwrap();
+                    let height = u16::try_from(self.height).unwrap();
+                    let mut gif = gif::Encoder::new(write, width, height, &palette_bytes)?;
+                    let frame = gif::Frame::from_indexed_pixels(width, height, image_data, None);
+                    gif.write_frame(&frame)?;
+                    return Ok(());
+                }
+
                 let mut png = png::Encoder::new(
                     write,
                     self.width,
@@ -245,7 +336,7 @@ impl Bitmap {
                 png.set_depth(BitDepth::Eight);
                 png.set_palette(&palette_bytes);
                 let mut writer = png.write_header()?;
-                writer.write_image_data(&image_data)?;
+                writer.write_image_data(image_data)?;
             },
             BitmapData::ColorMappedAlpha { palette, image_data } => {
                 let mut palette_bytes = Vec::with_capacity(3*palette.len());
@@ -267,10 +358,14 @@ impl Bitmap {
                 png.set_palette(&palette_bytes);
                 png.set_trns(&transparency_bytes);
                 let mut writer = png.write_header()?;
-                writer.write_image_data(&image_data)?;
+                writer.write_image_data(image_data)?;
             },
             BitmapData::Rgb15 { image_data } => {
-                let mut data_iter = image_data.iter();
+                let mut pixels = Vec::with_capacity(image_data.len() / 2 * 3);
+                for pixel in image_data.chunks_exact(2) {
+                    let word = (u16::from(pixel[0]) << 8) | u16::from(pixel[1]);
+                    pixels.extend([scale_5_to_8(word >> 10), scale_5_to_8(word >> 5), scale_5_to_8(word)]);
+                }
 
                 let mut png = png::Encoder::new(
                     write,
@@ -280,30 +375,9 @@ impl Bitmap {
                 png.set_color(ColorType::Rgb);
                 png.set_depth(BitDepth::Eight);
                 let mut writer = png.write_header()?;
-                let mut row = Vec::new();
-                for _ in 0..self.height {
-                    row.clear();
-                    for _ in 0..self.width {
-                        let top_byte = data_iter.next()
-                            .ok_or(Error::ShortRead)?;
-                        let bottom_byte = data_iter.next()
-                            .ok_or(Error::ShortRead)?;
-                        let word =
-                            (u16::from(*top_byte) << 8)
-                            | u16::from(*bottom_byte);
-                        let r = scale_5_to_8(word >> 10);
-                        let g = scale_5_to_8(word >>  5);
-                        let b = scale_5_to_8(word >>  0);
-                        row.push(r);
-                        row.push(g);
-                        row.push(b);
-                    }
-                    writer.write_image_data(&row)?;
-                }
+                writer.write_image_data(&pixels)?;
             },
             BitmapData::Rgb24 { image_data } => {
-                let mut data_iter = image_data.iter();
-
                 let mut png = png::Encoder::new(
                     write,
                     self.width,
@@ -312,26 +386,9 @@ impl Bitmap {
                 png.set_color(ColorType::Rgb);
                 png.set_depth(BitDepth::Eight);
                 let mut writer = png.write_header()?;
-                let mut row = Vec::new();
-                for _ in 0..self.height {
-                    row.clear();
-                    for _ in 0..self.width {
-                        let r = data_iter.next()
-                            .ok_or(Error::ShortRead)?;
-                        let g = data_iter.next()
-                            .ok_or(Error::ShortRead)?;
-                        let b = data_iter.next()
-                            .ok_or(Error::ShortRead)?;
-                        row.push(*r);
-                        row.push(*g);
-                        row.push(*b);
-                    }
-                    writer.write_image_data(&row)?;
-                }
+                writer.write_image_data(image_data)?;
             },
             BitmapData::Rgba32 { image_data } => {
-                let mut data_iter = image_data.iter();
-
                 let mut png = png::Encoder::new(
                     write,
                     self.width,
@@ -340,30 +397,85 @@ impl Bitmap {
                 png.set_color(ColorType::Rgba);
                 png.set_depth(BitDepth::Eight);
                 let mut writer = png.write_header()?;
-                let mut row = Vec::new();
-                for _ in 0..self.height {
-                    row.clear();
-                    for _ in 0..self.width {
-                        let r = data_iter.next()
-                            .ok_or(Error::ShortRead)?;
-                        let g = data_iter.next()
-                            .ok_or(Error::ShortRead)?;
-                        let b = data_iter.next()
-                            .ok_or(Error::ShortRead)?;
-                        let a = data_iter.next()
-                            .ok_or(Error::ShortRead)?;
-                        row.push(*r);
-                        row.push(*g);
-                        row.push(*b);
-                        row.push(*a);
-                    }
-                    writer.write_image_data(&row)?;
-                }
+                writer.write_image_data(image_data)?;
             },
         }
         Ok(())
     }
 
+    /// Decodes the bitmap into 8-bit RGBA pixels, returning its width, height and pixels.
+    ///
+    /// Only the first frame of a GIF image is decoded.
+    pub fn decode_rgba(&self) -> Result<(u32, u32, Vec<u8>), Error> {
+        let pixel_count = usize::try_from(self.width).unwrap() * usize::try_from(self.height).unwrap();
+        let rgba = match &self.data {
+            BitmapData::Gif { gif_data } => {
+                let mut options = gif::DecodeOptions::new();
+                options.set_color_output(gif::ColorOutput::RGBA);
+                let mut decoder = options.read_info(gif_data.as_slice())?;
+                let width = usize::from(decoder.width());
+                let height = usize::from(decoder.height());
+                let mut canvas = vec![0u8; 4 * width * height];
+                if let Some(frame) = decoder.read_next_frame()? {
+                    let (left, top) = (usize::from(frame.left), usize::from(frame.top));
+                    let frame_width = usize::from(frame.width);
+                    for (y, row) in frame.buffer.chunks_exact(4 * frame_width.max(1)).enumerate() {
+                        if top + y >= height || left >= width {
+                            break;
+                        }
+                        let visible_width = frame_width.min(width - left);
+                        let start = 4 * ((top + y) * width + left);
+                        canvas[start..start + 4*visible_width].copy_from_slice(&row[..4*visible_width]);
+                    }
+                }
+                return Ok((width as u32, height as u32, canvas));
+            },
+            BitmapData::Png { png_data } => {
+                let mut decoder = png::Decoder::new(png_data.as_slice());
+                decoder.set_transformations(png::Transformations::normalize_to_color8());
+                let mut reader = decoder.read_info()?;
+                let mut pixels = vec![0u8; reader.output_buffer_size()];
+                let info = reader.next_frame(&mut pixels)?;
+                let rgba = png_pixels_to_rgba(info.color_type, &pixels[..info.buffer_size()]);
+                return Ok((info.width, info.height, rgba));
+            },
+            BitmapData::Jpeg { jpeg_data, alpha_data } => {
+                let mut alpha_pixels = Vec::new();
+                if let Some(ad) = alpha_data {
+                    let mut decoder = flate2::read::ZlibDecoder::new(ad.as_slice());
+                    decoder.read_to_end(&mut alpha_pixels)
+                        .map_err(Error::ZlibDecoding)?;
+                }
+                let mut decoder = jpeg_decoder::Decoder::new(jpeg_data.as_slice());
+                let pixels = decoder.decode()?;
+                let image_info = decoder.info().unwrap();
+                let alpha = alpha_data.as_ref().map(|_| alpha_pixels.as_slice());
+                jpeg_pixels_to_rgba(image_info.pixel_format, &pixels, alpha, pixel_count)
+            },
+            BitmapData::ColorMapped { palette, image_data } => {
+                image_data.iter()
+                    .flat_map(|&i| palette.get(usize::from(i)).map_or([0, 0, 0, 0xFF], |c| [c.r, c.g, c.b, 0xFF]))
+                    .collect()
+            },
+            BitmapData::ColorMappedAlpha { palette, image_data } => {
+                image_data.iter()
+                    .flat_map(|&i| palette.get(usize::from(i)).map_or([0; 4], |c| [c.r, c.g, c.b, c.a]))
+                    .collect()
+            },
+            BitmapData::Rgb15 { image_data } => {
+                image_data.chunks_exact(2)
+                    .flat_map(|p| {
+                        let word = (u16::from(p[0]) << 8) | u16::from(p[1]);
+                        [scale_5_to_8(word >> 10), scale_5_to_8(word >> 5), scale_5_to_8(word), 0xFF]
+                    })
+                    .collect()
+            },
+            BitmapData::Rgb24 { image_data } => png_pixels_to_rgba(ColorType::Rgb, image_data),
+            BitmapData::Rgba32 { image_data } => image_data.clone(),
+        };
+        Ok((self.width, self.height, rgba))
+    }
+
     pub fn from_gif(gif_data: &[u8]) -> Result<Self, Error> {
         let decoder = gif::Decoder::new(gif_data)?;
         let width = decoder.width().into();
@@ -391,21 +503,11 @@ impl Bitmap {
         ))
     }
 
+    /// Reads a JPEG image, inserting the given encoding tables (as stored in a JpegTables tag) if
+    /// they are not empty.
     pub fn from_jpeg(jpeg_data: &[u8], jpeg_tables: &[u8], alpha_data: Option<&[u8]>) -> Result<Self, Error> {
-        let mut full_jpeg_data;
-        let actual_jpeg_data = if jpeg_tables.len() > 0 {
-            full_jpeg_data = Vec::with_capacity(jpeg_data.len() + jpeg_tables.len());
-            let sos_location = jpeg_data
-                .windows(2)
-                .position(|window| window == &[0xFF, 0xDA])
-                .expect("no SOS in JPEG data");
-            full_jpeg_data.extend(&jpeg_data[0..sos_location]);
-            full_jpeg_data.extend(jpeg_tables);
-            full_jpeg_data.extend(&jpeg_data[sos_location..]);
-            full_jpeg_data.as_slice()
-        } else {
-            jpeg_data
-        };
+        let full_jpeg_data = splice_jpeg(jpeg_tables, jpeg_data)?;
+        let actual_jpeg_data = full_jpeg_data.as_slice();
 
         let mut decoder = jpeg_decoder::Decoder::new(actual_jpeg_data);
         decoder.read_info()?;
@@ -417,27 +519,300 @@ impl Bitmap {
             height,
             BitmapData::Jpeg {
                 jpeg_data: Vec::from(actual_jpeg_data),
-                alpha_data: alpha_data.map(|ad| Vec::from(ad)),
+                alpha_data: alpha_data.map(Vec::from),
             },
         ))
     }
 
-    pub fn from_bytes(bytes: &[u8], alpha_bytes: Option<&[u8]>) -> Option<Self> {
+    pub fn from_bytes(bytes: &[u8], alpha_bytes: Option<&[u8]>) -> Result<Self, Error> {
         if bytes.starts_with(GIF_MAGIC) {
-            Bitmap::from_gif(bytes).ok()
+            Bitmap::from_gif(bytes)
         } else if bytes.starts_with(PNG_MAGIC) {
-            Bitmap::from_png(bytes).ok()
-        } else if bytes.starts_with(JPEG_MAGIC) {
-            Bitmap::from_jpeg(bytes, &[], alpha_bytes).ok()
+            Bitmap::from_png(bytes)
+        } else if is_jpeg(bytes) {
+            Bitmap::from_jpeg(bytes, &[], alpha_bytes)
         } else {
-            None
+            Err(Error::UnknownFormat)
         }
     }
+
+    /// Decodes the pixels of a DefineBitsLossless or DefineBitsLossless2 tag.
+    ///
+    /// If the data ends early or is corrupt, the rows decoded up to that point are kept, the others
+    /// are made transparent and a warning is returned along with the bitmap.
+    pub fn from_lossless(bmap: &DefineBitsLossless, cancellation: &Cancellation) -> Result<(Self, Option<Warning>), Error> {
+        let width = usize::from(bmap.width);
+        let (data, recovery) = match &bmap.format {
+            BitmapFormat::ColorMap8 { num_colors } => {
+                let actual_num_colors = usize::from(*num_colors) + 1;
+                let component_count = if bmap.version == 2 { 4 } else { 3 };
+                // 1 byte per pixel, padded to 4 bytes
+                let stride = (width + 3) & !3;
+                let (decompressed, recovery) = decompress_lossless(
+                    bmap.id, bmap.data, component_count*actual_num_colors, stride, bmap.height, cancellation,
+                )?;
+                let (palette_bytes, image_data_padded) = decompressed.split_at(component_count*actual_num_colors);
+
+                let image_data = strip_row_padding(image_data_padded, width, stride);
+                let data = if bmap.version == 2 {
+                    let palette = palette_bytes.chunks_exact(4)
+                        .map(|c| RgbaColor { r: c[0], g: c[1], b: c[2], a: c[3] })
+                        .collect();
+                    BitmapData::ColorMappedAlpha {
+                        palette,
+                        image_data,
+                    }
+                } else {
+                    let palette = palette_bytes.chunks_exact(3)
+                        .map(|c| RgbColor { r: c[0], g: c[1], b: c[2] })
+                        .collect();
+                    BitmapData::ColorMapped {
+                        palette,
+                        image_data,
+                    }
+                };
+                (data, recovery)
+            },
+            BitmapFormat::Rgb15 => {
+                if bmap.version == 2 {
+                    return Err(Error::InvalidLossless("DefineBitsLossless2 does not allow 15-bit RGB"));
+                }
+
+                // 2 bytes per pixel, padded to 4 bytes
+                let stride = (2*width + 3) & !3;
+                let (image_data_padded, recovery) = decompress_lossless(
+                    bmap.id, bmap.data, 0, stride, bmap.height, cancellation,
+                )?;
+
+                let image_data = strip_row_padding(&image_data_padded, 2*width, stride);
+                (BitmapData::Rgb15 { image_data }, recovery)
+            },
+            BitmapFormat::Rgb32 => {
+                // 4 bytes per pixel => no padding
+                let (image_data_argb, recovery) = decompress_lossless(
+                    bmap.id, bmap.data, 0, 4*width, bmap.height, cancellation,
+                )?;
+
+                let data = if bmap.version == 2 {
+                    // ARGB with premultiplied alpha
+                    let mut image_data = Vec::with_capacity(image_data_argb.len());
+                    for argb in image_data_argb.chunks_exact(4) {
+                        let a = argb[0];
+                        image_data.extend([
+                            unpremultiply(argb[1], a), unpremultiply(argb[2], a), unpremultiply(argb[3], a), a,
+                        ]);
+                    }
+
+                    BitmapData::Rgba32 {
+                        image_data,
+                    }
+                } else {
+                    // the first byte of each pixel is reserved
+                    let mut image_data = Vec::with_capacity(image_data_argb.len() / 4 * 3);
+                    for xrgb in image_data_argb.chunks_exact(4) {
+                        image_data.extend_from_slice(&xrgb[1..]);
+                    }
+
+                    BitmapData::Rgb24 {
+                        image_data,
+                    }
+                };
+                (data, recovery)
+            },
+        };
+
+        let mut bitmap = Bitmap::new(bmap.width.into(), bmap.height.into(), data);
+        let warning = recovery.map(|Recovery { complete_rows, warning }| {
+            bitmap.make_rows_transparent(complete_rows);
+            warning
+        });
+        Ok((bitmap, warning))
+    }
+}
+
+
+/// The rows of a lossless bitmap that have been kept although its data ends early.
+struct Recovery {
+    complete_rows: u32,
+    warning: Warning,
+}
+
+
+/// Decompresses the data of a DefineBitsLossless tag: `header_len` bytes (the palette, if any)
+/// followed by `height` rows of `stride` bytes.
+///
+/// If the data ends early, the missing bytes are filled in with zeroes and the number of complete
+/// rows is returned along with the data.
+fn decompress_lossless(
+    id: CharacterId, data: &[u8], header_len: usize, stride: usize, height: u16, cancellation: &Cancellation,
+) -> Result<(Vec<u8>, Option<Recovery>), Cancelled> {
+    let expected_len = header_len + stride * usize::from(height);
+    let mut decompressed = Vec::with_capacity(expected_len);
+    let mut decoder = flate2::read::ZlibDecoder::new(data).take(u64::try_from(expected_len).unwrap());
+    // a corrupt stream still yields the data before the corruption
+    let read_result = loop {
+        cancellation.check()?;
+        match decoder.by_ref().take(LOSSLESS_CHUNK_LEN).read_to_end(&mut decompressed) {
+            Ok(0) => break Ok(()),
+            Ok(_) => {},
+            Err(e) => break Err(e),
+        }
+    };
+    if decompressed.len() >= expected_len {
+        return Ok((decompressed, None));
+    }
+
+    let complete_rows = decompressed.len().saturating_sub(header_len) / stride.max(1);
+    let complete_rows = u32::try_from(complete_rows).unwrap();
+    let message = match read_result {
+        Err(e) => format!("bitmap {} is corrupt ({}); keeping only its first {} of {} rows", id, e, complete_rows, height),
+        Ok(_) => format!("bitmap {} is truncated; keeping only its first {} of {} rows", id, complete_rows, height),
+    };
+    decompressed.resize(expected_len, 0);
+    let warning = Warning::about(WarningCode::RecoveredBitmap, id, message);
+    Ok((decompressed, Some(Recovery { complete_rows, warning })))
+}
+
+
+/// Copies the rows of pixels out of data whose rows are `stride` bytes long, dropping the padding
+/// at the end of each row.
+pub fn strip_row_padding(padded: &[u8], row_len: usize, stride: usize) -> Vec<u8> {
+    if row_len == stride {
+        return Vec::from(padded);
+    }
+    let mut data = Vec::with_capacity(padded.len() / stride.max(1) * row_len);
+    for row in padded.chunks_exact(stride) {
+        data.extend_from_slice(&row[..row_len]);
+    }
+    data
+}
+
+
+/// Reverses the premultiplication of a color component by the alpha value.
+fn unpremultiply(component: u8, alpha: u8) -> u8 {
+    if alpha == 0 {
+        0
+    } else {
+        (u16::from(component) * 255 / u16::from(alpha)).min(255) as u8
+    }
+}
+
+
+/// Writes an ICC color profile into a PNG file; must be called before the image data is written.
+fn write_icc_profile<W: Write>(writer: &mut png::Writer<W>, profile: &[u8]) -> Result<(), Error> {
+    // profile name, its null terminator and the compression method (zlib), then the profile
+    let mut encoder = ZlibEncoder::new(b"ICC Profile\0\0".to_vec(), Compression::default());
+    encoder.write_all(profile)?;
+    let chunk_data = encoder.finish()?;
+    writer.write_chunk(png::chunk::iCCP, &chunk_data)?;
+    Ok(())
+}
+
+
+/// Whether the given image data is in JPEG format.
+pub fn is_jpeg(bytes: &[u8]) -> bool {
+    bytes.starts_with(JPEG_MAGIC) || bytes.starts_with(JPEG_ERRONEOUS_HEADER)
+}
+
+
+/// Strips the start-of-image and end-of-image markers (and the erroneous header written by older
+/// versions of Flash) from JPEG encoding tables, tolerating their absence.
+pub fn strip_jpeg_framing(jpeg_tables: &[u8]) -> &[u8] {
+    let tables = jpeg_tables.strip_prefix(JPEG_ERRONEOUS_HEADER).unwrap_or(jpeg_tables);
+    let tables = tables.strip_prefix(JPEG_MAGIC).unwrap_or(tables);
+    tables.strip_suffix(JPEG_EOI).unwrap_or(tables)
+}
+
+
+/// Whether a JPEG marker stands on its own, without a length and payload.
+fn is_standalone_jpeg_marker(marker: u8) -> bool {
+    matches!(marker, 0x01 | 0xD0..=0xD9)
+}
+
+
+/// The segments of a JPEG stream up to its first scan.
+struct JpegHeader<'a> {
+    /// The segments, each starting with its marker, without start-of-image and end-of-image markers.
+    segments: Vec<&'a [u8]>,
+
+    /// The rest of the stream from the first start-of-scan marker onward, if there is one.
+    scan: Option<&'a [u8]>,
+}
+
+
+/// Splits the header of a JPEG stream into its segments, leaving out start-of-image and
+/// end-of-image markers wherever they occur.
+fn read_jpeg_header(data: &[u8]) -> Result<JpegHeader<'_>, Error> {
+    let mut segments = Vec::new();
+    let mut pos = 0;
+    while pos < data.len() {
+        if data[pos] != 0xFF {
+            return Err(Error::InvalidJpeg("expected a marker"));
+        }
+        // markers may be preceded by any number of fill bytes
+        while data.get(pos + 1) == Some(&0xFF) {
+            pos += 1;
+        }
+        let start = pos;
+        let marker = *data.get(pos + 1)
+            .ok_or(Error::InvalidJpeg("truncated marker"))?;
+        pos += 2;
+
+        if marker == 0xDA {
+            return Ok(JpegHeader { segments, scan: Some(&data[start..]) });
+        }
+        if is_standalone_jpeg_marker(marker) {
+            if marker != 0xD8 && marker != 0xD9 {
+                segments.push(&data[start..pos]);
+            }
+            continue;
+        }
+        let length_bytes = data.get(pos..pos+2)
+            .ok_or(Error::InvalidJpeg("truncated segment length"))?;
+        let length = usize::from(u16::from_be_bytes([length_bytes[0], length_bytes[1]]));
+        if length < 2 || pos + length > data.len() {
+            return Err(Error::InvalidJpeg("truncated segment"));
+        }
+        pos += length;
+        segments.push(&data[start..pos]);
+    }
+    Ok(JpegHeader { segments, scan: None })
+}
+
+
+/// Combines JPEG encoding tables (as stored in a JpegTables tag, possibly empty) with the image
+/// data of a DefineBits tag into a single well-formed JPEG stream.
+///
+/// The start-of-image and end-of-image markers of both are dropped (including the erroneous
+/// end-of-image marker at the start of data written by older versions of Flash) and a single pair
+/// is placed around the result. Application segments such as the JFIF header are kept in front of
+/// the tables.
+pub fn splice_jpeg(jpeg_tables: &[u8], jpeg_data: &[u8]) -> Result<Vec<u8>, Error> {
+    let table_segments = read_jpeg_header(jpeg_tables)?.segments;
+    let JpegHeader { segments: image_segments, scan } = read_jpeg_header(jpeg_data)?;
+    let scan = scan.ok_or(Error::NoJpegScan)?;
+    let scan = scan.strip_suffix(JPEG_EOI).unwrap_or(scan);
+
+    let is_application_segment = |segment: &[u8]| (0xE0..=0xEF).contains(&segment[1]);
+    let mut spliced = Vec::with_capacity(jpeg_tables.len() + jpeg_data.len() + 4);
+    spliced.extend(JPEG_MAGIC);
+    for segment in image_segments.iter().filter(|s| is_application_segment(s)) {
+        spliced.extend(*segment);
+    }
+    for segment in &table_segments {
+        spliced.extend(*segment);
+    }
+    for segment in image_segments.iter().filter(|s| !is_application_segment(s)) {
+        spliced.extend(*segment);
+    }
+    spliced.extend(scan);
+    spliced.extend(JPEG_EOI);
+    Ok(spliced)
 }
 
 
 #[derive(Clone, Debug, Eq, Hash, Ord, PartialEq, PartialOrd)]
-pub(crate) enum BitmapData {
+pub enum BitmapData {
     Gif { gif_data: Vec<u8>, },
     Jpeg {
         jpeg_data: Vec<u8>,
@@ -456,34 +831,132 @@ pub(crate) enum BitmapData {
     Rgb24 { image_data: Vec<u8> },
     Rgba32 { image_data: Vec<u8> },
 }
-impl BitmapData {
-    pub fn is_gif(&self) -> bool {
-        match self {
-            Self::Gif { .. } => true,
-            _ => false,
-        }
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+
+    fn lossless(version: u8, format: BitmapFormat, width: u16, height: u16, raw: &[u8]) -> Result<(Bitmap, Option<Warning>), Error> {
+        let mut encoder = ZlibEncoder::new(Vec::new(), Compression::default());
+        encoder.write_all(raw).unwrap();
+        let data = encoder.finish().unwrap();
+        let bmap = DefineBitsLossless { version, id: 1, format, width, height, data: &data };
+        Bitmap::from_lossless(&bmap, &Cancellation::new())
     }
 
-    pub fn is_jpeg(&self) -> bool {
-        match self {
-            Self::Jpeg { .. } => true,
-            _ => false,
-        }
+
+    fn lossless_rgba(version: u8, format: BitmapFormat, width: u16, height: u16, raw: &[u8]) -> Vec<u8> {
+        let (bitmap, warning) = lossless(version, format, width, height, raw).unwrap();
+        assert_eq!(warning, None);
+        let (decoded_width, decoded_height, rgba) = bitmap.decode_rgba().unwrap();
+        assert_eq!((decoded_width, decoded_height), (u32::from(width), u32::from(height)));
+        rgba
     }
 
-    pub fn is_png(&self) -> bool {
-        match self {
-            Self::Png { .. } => true,
-            _ => false,
-        }
+
+    #[test]
+    fn rgb15() {
+        // big-endian 0RRRRRGG GGGBBBBB, rows padded to 4 bytes
+        let raw = [0x7C, 0x00, 0x03, 0xE0, 0x00, 0x1F, 0xAA, 0xAA];
+        let rgba = lossless_rgba(1, BitmapFormat::Rgb15, 3, 1, &raw);
+        assert_eq!(rgba, [255, 0, 0, 255, 0, 255, 0, 255, 0, 0, 255, 255]);
     }
 
-    pub fn is_lossless(&self) -> bool {
-        match self {
-            Self::ColorMapped { .. } => true,
-            Self::Rgb15 { .. } => true,
-            Self::Rgb24 { .. } => true,
-            _ => false,
+
+    #[test]
+    fn rgb15_is_not_allowed_with_alpha() {
+        let result = lossless(2, BitmapFormat::Rgb15, 1, 1, &[0; 4]);
+        assert!(matches!(result, Err(Error::InvalidLossless(_))));
+    }
+
+
+    #[test]
+    fn rgb24_drops_reserved_byte() {
+        let raw = [0xAA, 10, 20, 30, 0xAA, 40, 50, 60];
+        let (bitmap, _) = lossless(1, BitmapFormat::Rgb32, 2, 1, &raw).unwrap();
+        assert_eq!(bitmap.data, BitmapData::Rgb24 { image_data: vec![10, 20, 30, 40, 50, 60] });
+        let rgba = lossless_rgba(1, BitmapFormat::Rgb32, 2, 1, &raw);
+        assert_eq!(rgba, [10, 20, 30, 255, 40, 50, 60, 255]);
+    }
+
+
+    #[test]
+    fn argb_is_unpremultiplied() {
+        let raw = [255, 1, 2, 3, 128, 64, 0, 128, 0, 0, 0, 0, 51, 51, 51, 51];
+        let rgba = lossless_rgba(2, BitmapFormat::Rgb32, 4, 1, &raw);
+        assert_eq!(rgba, [1, 2, 3, 255, 127, 0, 255, 128, 0, 0, 0, 0, 255, 255, 255, 51]);
+    }
+
+
+    #[test]
+    fn premultiplied_components_above_alpha_are_clamped() {
+        assert_eq!(unpremultiply(200, 100), 255);
+        assert_eq!(unpremultiply(50, 0), 0);
+        assert_eq!(unpremultiply(50, 255), 50);
+    }
+
+
+    #[test]
+    fn colormapped_rows_are_unpadded() {
+        // two RGB colors, then rows of 3 indexes padded to 4 bytes
+        let raw = [255, 0, 0, 0, 0, 255, 0, 1, 0, 9, 1, 1, 0, 9];
+        let (bitmap, _) = lossless(1, BitmapFormat::ColorMap8 { num_colors: 1 }, 3, 2, &raw).unwrap();
+        match &bitmap.data {
+            BitmapData::ColorMapped { palette, image_data } => {
+                assert_eq!(palette.len(), 2);
+                assert_eq!(image_data, &[0, 1, 0, 1, 1, 0]);
+            },
+            other => panic!("unexpected data {:?}", other),
         }
+        let rgba = lossless_rgba(1, BitmapFormat::ColorMap8 { num_colors: 1 }, 3, 2, &raw);
+        assert_eq!(&rgba[..12], &[255, 0, 0, 255, 0, 0, 255, 255, 255, 0, 0, 255]);
+    }
+
+
+    #[test]
+    fn colormapped_with_alpha() {
+        // one RGBA color, then a row of 1 index padded to 4 bytes
+        let raw = [10, 20, 30, 40, 0, 7, 7, 7];
+        let rgba = lossless_rgba(2, BitmapFormat::ColorMap8 { num_colors: 0 }, 1, 1, &raw);
+        assert_eq!(rgba, [10, 20, 30, 40]);
+    }
+
+
+    #[test]
+    fn truncated_rows_become_transparent() {
+        // the second of two rows is missing
+        let raw = [0xFF, 1, 2, 3];
+        let (bitmap, warning) = lossless(2, BitmapFormat::Rgb32, 1, 2, &raw).unwrap();
+        let warning = warning.unwrap();
+        assert_eq!(warning.code, WarningCode::RecoveredBitmap);
+        assert_eq!(warning.character_id, Some(1));
+        let (_, _, rgba) = bitmap.decode_rgba().unwrap();
+        assert_eq!(rgba, [1, 2, 3, 255, 0, 0, 0, 0]);
+    }
+
+
+    #[test]
+    fn cancelled_decoding() {
+        let cancellation = Cancellation::new();
+        cancellation.cancel();
+        let bmap = DefineBitsLossless { version: 1, id: 1, format: BitmapFormat::Rgb32, width: 1, height: 1, data: &[] };
+        assert!(matches!(Bitmap::from_lossless(&bmap, &cancellation), Err(Error::Cancelled)));
+    }
+
+
+    #[test]
+    fn row_padding() {
+        assert_eq!(strip_row_padding(&[1, 2, 0, 0, 3, 4, 0, 0], 2, 4), [1, 2, 3, 4]);
+        assert_eq!(strip_row_padding(&[1, 2, 3, 4], 4, 4), [1, 2, 3, 4]);
+    }
+
+
+    #[test]
+    fn five_bit_scaling() {
+        assert_eq!(scale_5_to_8(0), 0);
+        assert_eq!(scale_5_to_8(0b11111), 255);
+        assert_eq!(scale_5_to_8(0b100000), 0);
     }
 }
@@ -0,0 +1,83 @@
+use std::collections::HashMap;
+
+use swf::{Button, ButtonState, CharacterId, ColorTransform, Shape};
+
+use crate::json::JsonValue;
+use crate::shape::{shape_to_path_data, tw2px};
+use crate::timeline::color_transform_to_json;
+
+
+/// Collects the characters making up the states of a button into a JSON array.
+pub(crate) fn button_records_to_json(button: &Button) -> JsonValue {
+    let mut records = JsonValue::new_array();
+    for record in &button.records {
+        let mut states = JsonValue::new_array();
+        let state_names = [
+            (ButtonState::UP, "up"),
+            (ButtonState::OVER, "over"),
+            (ButtonState::DOWN, "down"),
+            (ButtonState::HIT_TEST, "hit_test"),
+        ];
+        for (state, name) in state_names {
+            if record.states.contains(state) {
+                states.push(name);
+            }
+        }
+
+        let mut obj = JsonValue::new_object();
+        obj.insert("character", record.id);
+        obj.insert("depth", record.depth);
+        obj.insert("states", states);
+        obj.insert("color_transform", color_transform_to_json(&record.color_transform));
+        records.push(obj);
+    }
+    records
+}
+
+
+/// Replaces the color transforms of the records in a JSON array output by `button_records_to_json`
+/// with the one from a DefineButtonCxform tag, which applies to all characters of a DefineButton
+/// button.
+pub(crate) fn apply_button_color_transform(records: &mut JsonValue, ct: &ColorTransform) {
+    if let JsonValue::Array(records) = records {
+        for record in records {
+            record.insert("color_transform", color_transform_to_json(ct));
+        }
+    }
+}
+
+
+/// Collects the characters making up the hit-test state of a button into a JSON array, with the
+/// outlines of shapes as SVG path data in the coordinate space of the button.
+///
+/// Characters other than shapes are listed without outlines.
+pub(crate) fn hit_area_to_json(button: &Button, shapes: &HashMap<CharacterId, Shape>) -> JsonValue {
+    let mut hit_area = JsonValue::new_array();
+    for record in button.records.iter().filter(|r| r.states.contains(ButtonState::HIT_TEST)) {
+        let mut obj = JsonValue::new_object();
+        obj.insert("character", record.id);
+        obj.insert("depth", record.depth);
+        match shapes.get(&record.id) {
+            Some(shape) => {
+                obj.insert("path", shape_to_path_data(shape, record.matrix));
+
+                let b = &shape.shape_bounds;
+                let corners = [(b.x_min, b.y_min), (b.x_max, b.y_min), (b.x_min, b.y_max), (b.x_max, b.y_max)]
+                    .map(|(x, y)| record.matrix * (x, y))
+                    .map(|(x, y)| (tw2px(x), tw2px(y)));
+                let mut bounds = JsonValue::new_object();
+                bounds.insert("x_min", corners.iter().map(|c| c.0).fold(f64::INFINITY, f64::min));
+                bounds.insert("y_min", corners.iter().map(|c| c.1).fold(f64::INFINITY, f64::min));
+                bounds.insert("x_max", corners.iter().map(|c| c.0).fold(f64::NEG_INFINITY, f64::max));
+                bounds.insert("y_max", corners.iter().map(|c| c.1).fold(f64::NEG_INFINITY, f64::max));
+                obj.insert("bounds", bounds);
+            },
+            None => {
+                obj.insert("path", JsonValue::Null);
+                obj.insert("bounds", JsonValue::Null);
+            },
+        }
+        hit_area.push(obj);
+    }
+    hit_area
+}
@@ -0,0 +1,79 @@
+//! Stopping extraction from another thread or once a deadline has passed.
+
+
+use std::error;
+use std::fmt;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::{Duration, Instant};
+
+
+/// Tells long-running decoding to stop, either when [`Cancellation::cancel`] is called on any of
+/// its clones or once its deadline has passed.
+///
+/// Decoders check it between chunks of work, so even a single large asset can be interrupted.
+#[derive(Clone, Debug, Default)]
+pub struct Cancellation {
+    cancelled: Arc<AtomicBool>,
+    deadline: Option<Instant>,
+}
+impl Cancellation {
+    /// A cancellation without a deadline, which only takes effect when cancelled explicitly.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// A cancellation that takes effect at the given time.
+    pub fn with_deadline(deadline: Instant) -> Self {
+        Self {
+            cancelled: Arc::default(),
+            deadline: Some(deadline),
+        }
+    }
+
+    /// A cancellation that takes effect once the given time has elapsed from now. A timeout too
+    /// long to be represented never elapses.
+    pub fn with_timeout(timeout: Duration) -> Self {
+        Self {
+            cancelled: Arc::default(),
+            deadline: Instant::now().checked_add(timeout),
+        }
+    }
+
+    pub fn cancel(&self) {
+        self.cancelled.store(true, Ordering::Relaxed);
+    }
+
+    /// Whether decoding should stop, because of an explicit cancellation or the deadline.
+    pub fn is_cancelled(&self) -> bool {
+        if self.cancelled.load(Ordering::Relaxed) {
+            return true;
+        }
+        if self.deadline.is_some_and(|deadline| Instant::now() >= deadline) {
+            self.cancelled.store(true, Ordering::Relaxed);
+            return true;
+        }
+        false
+    }
+
+    /// Returns [`Cancelled`] if decoding should stop.
+    pub fn check(&self) -> Result<(), Cancelled> {
+        if self.is_cancelled() {
+            Err(Cancelled)
+        } else {
+            Ok(())
+        }
+    }
+}
+
+
+/// The error returned by decoding that has been stopped by a [`Cancellation`].
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct Cancelled;
+impl fmt::Display for Cancelled {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "decoding has been cancelled")
+    }
+}
+impl error::Error for Cancelled {
+}
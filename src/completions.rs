@@ -0,0 +1,317 @@
+use std::io::{self, Write};
+
+use clap::{Arg, Command, ValueEnum, ValueHint};
+
+
+/// The shells for which completion scripts can be generated.
+#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq, ValueEnum)]
+pub(crate) enum Shell {
+    Bash,
+    Zsh,
+    Fish,
+}
+
+
+/// Returns the first sentence of the help text of an argument or command, without the full stop.
+fn summary(help: Option<impl ToString>) -> String {
+    let help = help.map(|h| h.to_string()).unwrap_or_default();
+    let sentence = help.split(". ").next().unwrap_or("");
+    sentence.trim().trim_end_matches('.').to_owned()
+}
+
+
+/// Returns the options (not positional arguments) of a command that are shown in its help.
+fn visible_options(command: &Command) -> impl Iterator<Item = &Arg> {
+    command.get_arguments()
+        .filter(|arg| !arg.is_positional() && !arg.is_hide_set())
+}
+
+
+fn option_names(arg: &Arg) -> Vec<String> {
+    let mut names = Vec::new();
+    if let Some(short) = arg.get_short() {
+        names.push(format!("-{}", short));
+    }
+    if let Some(long) = arg.get_long() {
+        names.push(format!("--{}", long));
+    }
+    names
+}
+
+
+fn takes_value(arg: &Arg) -> bool {
+    arg.get_action().takes_values()
+}
+
+
+fn is_path(arg: &Arg) -> bool {
+    matches!(arg.get_value_hint(), ValueHint::AnyPath|ValueHint::FilePath|ValueHint::DirPath)
+}
+
+
+fn possible_values(arg: &Arg) -> Vec<String> {
+    arg.get_possible_values().iter()
+        .filter(|value| !value.is_hide_set())
+        .map(|value| value.get_name().to_owned())
+        .collect()
+}
+
+
+/// Writes a completion script for the given shell. The command must have been built, so that its
+/// global options are propagated to the subcommands.
+pub(crate) fn write_completions<W: Write>(command: &Command, shell: Shell, writer: W) -> io::Result<()> {
+    match shell {
+        Shell::Bash => write_bash(command, writer),
+        Shell::Zsh => write_zsh(command, writer),
+        Shell::Fish => write_fish(command, writer),
+    }
+}
+
+
+fn write_bash<W: Write>(command: &Command, mut writer: W) -> io::Result<()> {
+    let name = command.get_name();
+    let function = format!("_{}", name.replace('-', "_"));
+    let subcommand_names: Vec<&str> = command.get_subcommands().map(|sc| sc.get_name()).collect();
+
+    writeln!(writer, "{}() {{", function)?;
+    writeln!(writer, "    local cur=\"${{COMP_WORDS[COMP_CWORD]}}\"")?;
+    writeln!(writer, "    local prev=\"${{COMP_WORDS[COMP_CWORD-1]}}\"")?;
+    writeln!(writer, "    local subcommand=\"\" word")?;
+    writeln!(writer, "    for word in \"${{COMP_WORDS[@]:1:COMP_CWORD-1}}\"; do")?;
+    writeln!(writer, "        case \"$word\" in")?;
+    writeln!(writer, "            {})", subcommand_names.join("|"))?;
+    writeln!(writer, "                subcommand=\"$word\"")?;
+    writeln!(writer, "                break")?;
+    writeln!(writer, "                ;;")?;
+    writeln!(writer, "        esac")?;
+    writeln!(writer, "    done")?;
+    writeln!(writer)?;
+
+    // the values of options are completed regardless of the subcommand
+    writeln!(writer, "    case \"$prev\" in")?;
+    let mut seen = Vec::new();
+    for arg in std::iter::once(command).chain(command.get_subcommands()).flat_map(visible_options) {
+        if !takes_value(arg) || arg.is_require_equals_set() || seen.contains(&arg.get_id()) {
+            continue;
+        }
+        seen.push(arg.get_id());
+        let values = possible_values(arg);
+        let completion = if !values.is_empty() {
+            format!("COMPREPLY=($(compgen -W \"{}\" -- \"$cur\"))", values.join(" "))
+        } else if is_path(arg) {
+            "COMPREPLY=($(compgen -f -- \"$cur\"))".to_owned()
+        } else {
+            // free-form values cannot be completed
+            "COMPREPLY=()".to_owned()
+        };
+        writeln!(writer, "        {})", option_names(arg).join("|"))?;
+        writeln!(writer, "            {}", completion)?;
+        writeln!(writer, "            return")?;
+        writeln!(writer, "            ;;")?;
+    }
+    writeln!(writer, "    esac")?;
+    writeln!(writer)?;
+
+    // positional arguments are files unless they have a fixed set of values
+    writeln!(writer, "    local opts words=\"\" files=1")?;
+    writeln!(writer, "    case \"$subcommand\" in")?;
+    for subcommand in command.get_subcommands() {
+        let options: Vec<String> = visible_options(subcommand).flat_map(option_names).collect();
+        let values: Vec<String> = subcommand.get_positionals().flat_map(possible_values).collect();
+        writeln!(writer, "        {})", subcommand.get_name())?;
+        writeln!(writer, "            opts=\"{}\"", options.join(" "))?;
+        if !values.is_empty() {
+            writeln!(writer, "            words=\"{}\"", values.join(" "))?;
+            writeln!(writer, "            files=0")?;
+        }
+        writeln!(writer, "            ;;")?;
+    }
+    let top_level: Vec<String> = visible_options(command).flat_map(option_names)
+        .chain(subcommand_names.iter().map(|name| name.to_string()))
+        .collect();
+    writeln!(writer, "        *)")?;
+    writeln!(writer, "            opts=\"{}\"", top_level.join(" "))?;
+    writeln!(writer, "            ;;")?;
+    writeln!(writer, "    esac")?;
+    writeln!(writer, "    if [[ \"$cur\" == -* || -z \"$subcommand\" ]]; then")?;
+    writeln!(writer, "        COMPREPLY=($(compgen -W \"$opts\" -- \"$cur\"))")?;
+    writeln!(writer, "    else")?;
+    writeln!(writer, "        COMPREPLY=($(compgen -W \"$words\" -- \"$cur\"))")?;
+    writeln!(writer, "    fi")?;
+    writeln!(writer, "    if [[ \"$cur\" != -* && $files == 1 ]]; then")?;
+    writeln!(writer, "        COMPREPLY+=($(compgen -f -- \"$cur\"))")?;
+    writeln!(writer, "    fi")?;
+    writeln!(writer, "}}")?;
+    writeln!(writer, "complete -o filenames -F {} {}", function, name)?;
+    Ok(())
+}
+
+
+/// Escapes text for use within the brackets and single quotes of a zsh `_arguments` specification.
+fn zsh_escape(text: &str) -> String {
+    text.replace('\'', "'\\''")
+        .replace('[', "\\[")
+        .replace(']', "\\]")
+        .replace(':', "\\:")
+}
+
+
+fn zsh_argument_spec(arg: &Arg) -> Vec<String> {
+    let help = zsh_escape(&summary(arg.get_help()));
+    let value = if takes_value(arg) {
+        let value_name = arg.get_value_names()
+            .and_then(|names| names.first())
+            .map(|name| name.to_string())
+            .unwrap_or_else(|| arg.get_id().to_string());
+        let values = possible_values(arg);
+        let action = if !values.is_empty() {
+            format!("({})", values.join(" "))
+        } else if is_path(arg) {
+            "_files".to_owned()
+        } else {
+            " ".to_owned()
+        };
+        format!(":{}:{}", zsh_escape(&value_name.to_lowercase()), action)
+    } else {
+        String::new()
+    };
+
+    if arg.is_positional() {
+        return vec![format!("'{}'", value)];
+    }
+    let suffix = match (takes_value(arg), arg.is_require_equals_set()) {
+        (false, _) => "",
+        (true, false) => "=",
+        (true, true) => "=-",
+    };
+    let names = option_names(arg);
+    if names.len() > 1 {
+        // mutually exclusive short and long forms
+        let exclusion = names.join(" ");
+        names.iter()
+            .map(|name| {
+                let suffix = if name.starts_with("--") { suffix } else { "+" };
+                let suffix = if takes_value(arg) { suffix } else { "" };
+                format!("'({}){}{}[{}]{}'", exclusion, name, suffix, help, value)
+            })
+            .collect()
+    } else {
+        names.iter()
+            .map(|name| format!("'{}{}[{}]{}'", name, suffix, help, value))
+            .collect()
+    }
+}
+
+
+fn write_zsh<W: Write>(command: &Command, mut writer: W) -> io::Result<()> {
+    let name = command.get_name();
+    let function = format!("_{}", name.replace('-', "_"));
+
+    writeln!(writer, "#compdef {}", name)?;
+    writeln!(writer)?;
+    writeln!(writer, "{}() {{", function)?;
+    writeln!(writer, "    local line state")?;
+    writeln!(writer, "    _arguments -C \\")?;
+    for arg in visible_options(command) {
+        for spec in zsh_argument_spec(arg) {
+            writeln!(writer, "        {} \\", spec)?;
+        }
+    }
+    writeln!(writer, "        '1: :->command' \\")?;
+    writeln!(writer, "        '*:: :->arguments'")?;
+    writeln!(writer)?;
+    writeln!(writer, "    case $state in")?;
+    writeln!(writer, "        command)")?;
+    write!(writer, "            _values 'command'")?;
+    for subcommand in command.get_subcommands() {
+        write!(writer, " '{}[{}]'", subcommand.get_name(), zsh_escape(&summary(subcommand.get_about())))?;
+    }
+    writeln!(writer, " && return 0")?;
+    // a movie given without a subcommand is extracted
+    writeln!(writer, "            _files")?;
+    writeln!(writer, "            ;;")?;
+    writeln!(writer, "        arguments)")?;
+    writeln!(writer, "            case $line[1] in")?;
+    for subcommand in command.get_subcommands() {
+        let specs: Vec<String> = subcommand.get_arguments()
+            .filter(|arg| !arg.is_hide_set() && !arg.is_global_set())
+            .flat_map(zsh_argument_spec)
+            .collect();
+        writeln!(writer, "                {})", subcommand.get_name())?;
+        if specs.is_empty() {
+            writeln!(writer, "                    ;;")?;
+            continue;
+        }
+        writeln!(writer, "                    _arguments \\")?;
+        for (i, spec) in specs.iter().enumerate() {
+            let continuation = if i + 1 < specs.len() { " \\" } else { "" };
+            writeln!(writer, "                        {}{}", spec, continuation)?;
+        }
+        writeln!(writer, "                    ;;")?;
+    }
+    writeln!(writer, "            esac")?;
+    writeln!(writer, "            ;;")?;
+    writeln!(writer, "    esac")?;
+    writeln!(writer, "}}")?;
+    writeln!(writer)?;
+    writeln!(writer, "{} \"$@\"", function)?;
+    Ok(())
+}
+
+
+fn fish_escape(text: &str) -> String {
+    text.replace('\\', "\\\\").replace('\'', "\\'")
+}
+
+
+fn write_fish_option<W: Write>(writer: &mut W, name: &str, condition: &str, arg: &Arg) -> io::Result<()> {
+    write!(writer, "complete -c {} -n '{}'", name, condition)?;
+    if let Some(short) = arg.get_short() {
+        write!(writer, " -s {}", short)?;
+    }
+    if let Some(long) = arg.get_long() {
+        write!(writer, " -l {}", long)?;
+    }
+    if takes_value(arg) {
+        let values = possible_values(arg);
+        if !values.is_empty() {
+            write!(writer, " -x -a '{}'", values.join(" "))?;
+        } else if is_path(arg) {
+            write!(writer, " -r -F")?;
+        } else {
+            write!(writer, " -x")?;
+        }
+    }
+    writeln!(writer, " -d '{}'", fish_escape(&summary(arg.get_help())))
+}
+
+
+fn write_fish<W: Write>(command: &Command, mut writer: W) -> io::Result<()> {
+    let name = command.get_name();
+    let subcommand_names: Vec<&str> = command.get_subcommands().map(|sc| sc.get_name()).collect();
+
+    for arg in visible_options(command) {
+        write_fish_option(&mut writer, name, "true", arg)?;
+    }
+    for subcommand in command.get_subcommands() {
+        writeln!(
+            writer, "complete -c {} -n '__fish_use_subcommand' -f -a {} -d '{}'",
+            name, subcommand.get_name(), fish_escape(&summary(subcommand.get_about())),
+        )?;
+    }
+    for subcommand in command.get_subcommands() {
+        let condition = format!("__fish_seen_subcommand_from {}", subcommand.get_name());
+        for arg in visible_options(subcommand).filter(|arg| !arg.is_global_set()) {
+            write_fish_option(&mut writer, name, &condition, arg)?;
+        }
+        if subcommand.get_positionals().any(|arg| !is_path(arg)) {
+            writeln!(writer, "complete -c {} -n '{}' -f", name, condition)?;
+        }
+    }
+    // without a subcommand, a movie is extracted
+    writeln!(
+        writer, "complete -c {} -n 'not __fish_seen_subcommand_from {}' -F",
+        name, subcommand_names.join(" "),
+    )?;
+    Ok(())
+}
@@ -0,0 +1,191 @@
+use std::ffi::OsString;
+
+
+/// The name of the configuration file looked for in the current directory.
+pub(crate) const DEFAULT_CONFIG_FILE_NAME: &str = "swfextract.toml";
+
+
+/// Parses a single value: a string, boolean, number or array of these.
+///
+/// Returns the value as it would be passed on the command line, or `None` for `false`, along with
+/// the rest of the input.
+fn parse_value(input: &str) -> Result<(Vec<Option<String>>, &str), String> {
+    let input = input.trim_start();
+    if let Some(rest) = input.strip_prefix('"') {
+        let mut value = String::new();
+        let mut chars = rest.char_indices();
+        while let Some((i, c)) = chars.next() {
+            match c {
+                '"' => return Ok((vec![Some(value)], &rest[i+1..])),
+                '\\' => {
+                    let escaped = match chars.next() {
+                        Some((_, 'n')) => '\n',
+                        Some((_, 't')) => '\t',
+                        Some((_, '"')) => '"',
+                        Some((_, '\\')) => '\\',
+                        other => return Err(format!("unsupported escape sequence \\{}", other.map(|(_, c)| c).unwrap_or(' '))),
+                    };
+                    value.push(escaped);
+                },
+                c => value.push(c),
+            }
+        }
+        Err("unterminated string".to_owned())
+    } else if let Some(rest) = input.strip_prefix('\'') {
+        let (value, rest) = rest.split_once('\'')
+            .ok_or_else(|| "unterminated string".to_owned())?;
+        Ok((vec![Some(value.to_owned())], rest))
+    } else if let Some(mut rest) = input.strip_prefix('[') {
+        let mut values = Vec::new();
+        loop {
+            rest = rest.trim_start();
+            if let Some(after) = rest.strip_prefix(']') {
+                return Ok((values, after));
+            }
+            let (value, after) = parse_value(rest)?;
+            values.extend(value);
+            rest = after.trim_start();
+            if let Some(after) = rest.strip_prefix(',') {
+                rest = after;
+            } else if !rest.starts_with(']') {
+                return Err("expected , or ] in array".to_owned());
+            }
+        }
+    } else {
+        let end = input.find(|c: char| c == ',' || c == ']' || c.is_whitespace())
+            .unwrap_or(input.len());
+        let (token, rest) = input.split_at(end);
+        match token {
+            "true" => Ok((vec![None], rest)),
+            "false" => Ok((Vec::new(), rest)),
+            "" => Err("missing value".to_owned()),
+            t if t.parse::<f64>().is_ok() => Ok((vec![Some(t.to_owned())], rest)),
+            t => Err(format!("unsupported value {:?}", t)),
+        }
+    }
+}
+
+
+/// Removes a comment from the end of a line, taking care not to remove `#` within strings.
+fn strip_comment(line: &str) -> &str {
+    let mut quote = None;
+    let mut escaped = false;
+    for (i, c) in line.char_indices() {
+        match (quote, c) {
+            (Some('"'), '\\') if !escaped => {
+                escaped = true;
+                continue;
+            },
+            (Some(q), c) if c == q && !escaped => quote = None,
+            (None, '"'|'\'') => quote = Some(c),
+            (None, '#') => return &line[..i],
+            _ => {},
+        }
+        escaped = false;
+    }
+    line
+}
+
+
+/// Converts the contents of a configuration file into command-line arguments.
+///
+/// The file consists of `key = value` lines in TOML syntax, where each key is the name of a
+/// command-line option (with dashes or underscores). `true` passes a flag, `false` leaves it out,
+/// arrays pass the option once per element and other values are passed as the option's value.
+/// Tables are not supported.
+pub(crate) fn config_to_args(config: &str) -> Result<Vec<OsString>, String> {
+    let mut args = Vec::new();
+    for (line_index, line) in config.lines().enumerate() {
+        let line_number = line_index + 1;
+        let line = strip_comment(line).trim();
+        if line.is_empty() {
+            continue;
+        }
+        if line.starts_with('[') {
+            return Err(format!("line {}: tables are not supported", line_number));
+        }
+        let (key, value) = line.split_once('=')
+            .ok_or_else(|| format!("line {}: expected key = value", line_number))?;
+        let key = key.trim().trim_matches('"');
+        if key.is_empty() || !key.chars().all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '-') {
+            return Err(format!("line {}: invalid key {:?}", line_number, key));
+        }
+        let (values, rest) = parse_value(value)
+            .map_err(|e| format!("line {}: {}", line_number, e))?;
+        if !rest.trim().is_empty() {
+            return Err(format!("line {}: unexpected {:?} after value", line_number, rest.trim()));
+        }
+
+        let option = format!("--{}", key.replace('_', "-"));
+        for value in values {
+            match value {
+                Some(v) => args.push(OsString::from(format!("{}={}", option, v))),
+                None => args.push(OsString::from(&option)),
+            }
+        }
+    }
+    Ok(args)
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+
+    fn args(config: &str) -> Vec<String> {
+        config_to_args(config).unwrap().into_iter()
+            .map(|arg| arg.into_string().unwrap())
+            .collect()
+    }
+
+
+    #[test]
+    fn values_become_options() {
+        let config = "\
+            # extract sounds as FLAC
+            output_dir = \"out\"
+            audio-format = 'flac'
+            symbol_names = true
+            gallery = false
+            max_assets = 100
+            render_scale = 1.5
+            \"ext_map\" = [\"png=img\", 'jpeg=jpg',]
+        ";
+        assert_eq!(args(config), [
+            "--output-dir=out", "--audio-format=flac", "--symbol-names", "--max-assets=100", "--render-scale=1.5",
+            "--ext-map=png=img", "--ext-map=jpeg=jpg",
+        ]);
+        assert!(args("\n   \n# nothing\n").is_empty());
+        assert!(args("ext_map = []").is_empty());
+    }
+
+
+    #[test]
+    fn strings_and_comments() {
+        assert_eq!(args(r#"output_dir = "a\"b\\c\td" # comment"#), ["--output-dir=a\"b\\c\td"]);
+        assert_eq!(args(r#"output_dir = 'C:\out\#1' # comment"#), [r"--output-dir=C:\out\#1"]);
+        assert_eq!(args(r##"frame_label = "#intro""##), ["--frame-label=#intro"]);
+        assert_eq!(args(r##"frame_label = "\"#\"" # "quoted""##), ["--frame-label=\"#\""]);
+        assert_eq!(args(r##"frame_label = '"#'"##), ["--frame-label=\"#"]);
+    }
+
+
+    #[test]
+    fn unsupported_syntax_is_rejected() {
+        for (config, error) in [
+            ("[extract]\noutput_dir = \"out\"", "line 1: tables are not supported"),
+            ("gallery = true\noutput_dir", "line 2: expected key = value"),
+            ("output.dir = \"out\"", "line 1: invalid key \"output.dir\""),
+            ("output_dir = ", "line 1: missing value"),
+            ("output_dir = out", "line 1: unsupported value \"out\""),
+            ("output_dir = \"out", "line 1: unterminated string"),
+            ("output_dir = 'out", "line 1: unterminated string"),
+            ("output_dir = \"\\u0041\"", "line 1: unsupported escape sequence \\u"),
+            ("output_dir = \"a\" \"b\"", "line 1: unexpected \"\\\"b\\\"\" after value"),
+            ("ext_map = [\"a\" \"b\"]", "line 1: expected , or ] in array"),
+        ] {
+            assert_eq!(config_to_args(config).unwrap_err(), error, "{}", config);
+        }
+    }
+}
@@ -0,0 +1,108 @@
+use std::collections::HashMap;
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, BufRead, BufReader, Write};
+use std::path::{Path, PathBuf};
+
+use clap::ValueEnum;
+
+
+/// How to replace a file that duplicates one already in the index.
+#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq, ValueEnum)]
+pub(crate) enum LinkKind {
+    Hard,
+    Symbolic,
+}
+
+
+/// A file recorded in a duplicate index.
+#[derive(Clone, Debug)]
+pub(crate) struct IndexEntry {
+    /// The movie from which the file has been extracted.
+    pub swf: PathBuf,
+
+    /// The absolute path of the file.
+    pub file: PathBuf,
+}
+
+
+/// An index of the digests of extracted files, shared across runs to find assets that multiple
+/// movies have in common.
+///
+/// The index is stored as lines of tab-separated SHA-256 digests, movie paths and file paths. Only
+/// the first file with any given digest is recorded.
+#[derive(Clone, Debug)]
+pub(crate) struct DuplicateIndex {
+    path: PathBuf,
+    entries: HashMap<String, IndexEntry>,
+    new_entries: Vec<(String, IndexEntry)>,
+}
+impl DuplicateIndex {
+    /// Loads the index from the given file, starting with an empty index if it does not exist.
+    pub fn load(path: &Path) -> io::Result<Self> {
+        let mut entries = HashMap::new();
+        match File::open(path) {
+            Ok(f) => {
+                for line in BufReader::new(f).lines() {
+                    let line = line?;
+                    let mut pieces = line.split('\t');
+                    let (Some(digest), Some(swf), Some(file)) = (pieces.next(), pieces.next(), pieces.next()) else {
+                        continue;
+                    };
+                    entries.entry(digest.to_owned()).or_insert_with(|| IndexEntry {
+                        swf: PathBuf::from(swf),
+                        file: PathBuf::from(file),
+                    });
+                }
+            },
+            Err(e) if e.kind() == io::ErrorKind::NotFound => {},
+            Err(e) => return Err(e),
+        }
+        Ok(Self {
+            path: path.to_owned(),
+            entries,
+            new_entries: Vec::new(),
+        })
+    }
+
+    /// Returns the file recorded with the given digest, if any.
+    pub fn find(&self, digest: &str) -> Option<&IndexEntry> {
+        self.entries.get(digest)
+            .or_else(|| self.new_entries.iter().find(|(d, _)| d == digest).map(|(_, e)| e))
+    }
+
+    /// Records a file, unless a file with the same digest has already been recorded.
+    pub fn add(&mut self, digest: String, entry: IndexEntry) {
+        if self.find(&digest).is_none() {
+            self.new_entries.push((digest, entry));
+        }
+    }
+
+    /// Appends the newly recorded files to the index file.
+    pub fn save(&self) -> io::Result<()> {
+        let mut f = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&self.path)?;
+        for (digest, entry) in &self.new_entries {
+            writeln!(f, "{}\t{}\t{}", digest, entry.swf.display(), entry.file.display())?;
+        }
+        Ok(())
+    }
+}
+
+
+/// Replaces `duplicate` with a link to `original`.
+pub(crate) fn link_duplicate(original: &Path, duplicate: &Path, kind: LinkKind) -> io::Result<()> {
+    // create the link next to the duplicate first so the duplicate is kept if linking fails
+    let mut link_path = duplicate.as_os_str().to_owned();
+    link_path.push(".link");
+    let link_path = PathBuf::from(link_path);
+    match kind {
+        LinkKind::Hard => fs::hard_link(original, &link_path)?,
+        #[cfg(unix)]
+        LinkKind::Symbolic => std::os::unix::fs::symlink(original, &link_path)?,
+        #[cfg(windows)]
+        LinkKind::Symbolic => std::os::windows::fs::symlink_file(original, &link_path)?,
+    }
+    fs::rename(&link_path, duplicate)
+}
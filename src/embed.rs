@@ -0,0 +1,98 @@
+/// Guesses the file extension of binary data from its contents.
+///
+/// Returns `None` if the format is not recognized.
+pub(crate) fn sniff_extension(data: &[u8]) -> Option<&'static str> {
+    const SIGNATURES: &[(&[u8], &str)] = &[
+        (b"\x89PNG\r\n\x1A\n", "png"),
+        (b"\xFF\xD8\xFF", "jpg"),
+        (b"GIF87a", "gif"),
+        (b"GIF89a", "gif"),
+        (b"FWS", "swf"),
+        (b"CWS", "swf"),
+        (b"ZWS", "swf"),
+        (b"ID3", "mp3"),
+        (b"OggS", "ogg"),
+        (b"fLaC", "flac"),
+        (b"FLV\x01", "flv"),
+        (b"\x00\x01\x00\x00", "ttf"),
+        (b"true", "ttf"),
+        (b"OTTO", "otf"),
+        (b"wOFF", "woff"),
+        (b"wOF2", "woff2"),
+        (b"PK\x03\x04", "zip"),
+        (b"\x1F\x8B", "gz"),
+        (b"%PDF", "pdf"),
+    ];
+    if data.is_empty() {
+        return None;
+    }
+    for (signature, extension) in SIGNATURES {
+        if data.starts_with(signature) {
+            return Some(extension);
+        }
+    }
+
+    if data.len() >= 12 && data.starts_with(b"RIFF") && &data[8..12] == b"WAVE" {
+        return Some("wav");
+    }
+    if data.len() >= 2 && data[0] == 0xFF && data[1] & 0xE0 == 0xE0 {
+        // MPEG audio frame sync
+        return Some("mp3");
+    }
+
+    // text formats, possibly preceded by a byte order mark and whitespace
+    let text = std::str::from_utf8(data.strip_prefix(b"\xEF\xBB\xBF").unwrap_or(data)).ok()?;
+    let trimmed = text.trim_start();
+    if trimmed.starts_with('<') {
+        Some("xml")
+    } else if trimmed.starts_with('{') || trimmed.starts_with('[') {
+        Some("json")
+    } else if !text.chars().any(|c| c.is_control() && !c.is_whitespace()) {
+        Some("txt")
+    } else {
+        None
+    }
+}
+
+
+/// Guesses the file extension of data embedded into an ActionScript 3 class from the names of the
+/// class and its superclass, following the conventions of the Flex compiler (which derives embedded
+/// assets from `mx.core.ByteArrayAsset`, `mx.core.SoundAsset` etc.) and of common naming schemes.
+///
+/// Returns `None` if the names give no indication.
+pub(crate) fn class_extension(class_name: &str, super_name: Option<&str>) -> Option<&'static str> {
+    const SUFFIXES: &[(&str, &str)] = &[
+        ("MovieClipLoaderAsset", "swf"),
+        ("SoundAsset", "mp3"),
+        ("FontAsset", "ttf"),
+        ("BitmapAsset", "png"),
+        ("Xml", "xml"),
+        ("XML", "xml"),
+        ("Json", "json"),
+        ("JSON", "json"),
+        ("Swf", "swf"),
+        ("SWF", "swf"),
+        ("Mp3", "mp3"),
+        ("MP3", "mp3"),
+        ("Ttf", "ttf"),
+        ("TTF", "ttf"),
+        ("Otf", "otf"),
+        ("OTF", "otf"),
+        ("Png", "png"),
+        ("PNG", "png"),
+        ("Txt", "txt"),
+        ("TXT", "txt"),
+    ];
+
+    let simple_names = super_name.into_iter()
+        .chain(std::iter::once(class_name))
+        .map(|name| name.rsplit(['.', ':']).next().unwrap_or(name));
+    for name in simple_names {
+        for (suffix, extension) in SUFFIXES {
+            if name.ends_with(suffix) {
+                return Some(extension);
+            }
+        }
+    }
+    None
+}
@@ -0,0 +1,67 @@
+use clap::ValueEnum;
+use encoding_rs::Encoding;
+use swf::SwfStr;
+
+
+/// The character encoding of the strings in movies older than version 6, which is the code page of
+/// the system on which they were authored. Later versions always use UTF-8.
+#[derive(Clone, Copy, Debug, Default, Eq, Hash, PartialEq, ValueEnum)]
+pub(crate) enum SourceEncoding {
+    /// Western European, as assumed by Flash Player on most systems.
+    #[default]
+    #[value(name = "windows-1252")]
+    Windows1252,
+
+    /// Central European.
+    #[value(name = "windows-1250")]
+    Windows1250,
+
+    /// Cyrillic.
+    #[value(name = "windows-1251")]
+    Windows1251,
+
+    /// Japanese.
+    ShiftJis,
+
+    /// Simplified Chinese.
+    Gbk,
+
+    /// Traditional Chinese.
+    Big5,
+
+    /// Korean.
+    EucKr,
+
+    /// For movies produced by tools that wrote UTF-8 regardless of the version.
+    #[value(name = "utf-8")]
+    Utf8,
+}
+impl SourceEncoding {
+    fn encoding(self) -> &'static Encoding {
+        match self {
+            Self::Windows1252 => encoding_rs::WINDOWS_1252,
+            Self::Windows1250 => encoding_rs::WINDOWS_1250,
+            Self::Windows1251 => encoding_rs::WINDOWS_1251,
+            Self::ShiftJis => encoding_rs::SHIFT_JIS,
+            Self::Gbk => encoding_rs::GBK,
+            Self::Big5 => encoding_rs::BIG5,
+            Self::EucKr => encoding_rs::EUC_KR,
+            Self::Utf8 => encoding_rs::UTF_8,
+        }
+    }
+
+    /// Returns the encoding of the strings in a movie of the given version.
+    pub fn for_version(self, swf_version: u8) -> &'static Encoding {
+        if swf_version >= 6 {
+            encoding_rs::UTF_8
+        } else {
+            self.encoding()
+        }
+    }
+}
+
+
+/// Decodes a string from the movie, replacing malformed sequences with U+FFFD.
+pub(crate) fn decode_string(s: &SwfStr, encoding: &'static Encoding) -> String {
+    s.to_string_lossy(encoding)
+}
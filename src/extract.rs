@@ -0,0 +1,307 @@
+//! Decoding the bitmaps, sounds and binary data of a movie in memory, without writing any files.
+
+
+use std::collections::VecDeque;
+use std::io::Read;
+use std::slice;
+
+use swf::{AudioCompression, CharacterId, SoundFormat, Swf, SwfBuf, Tag};
+
+use crate::adpcm::AdpcmDecoder;
+use crate::bitmap::{Bitmap, Error as BitmapError};
+use crate::cancel::Cancellation;
+use crate::read::{decompress_movie, find_character, parse_movie, patch_avc_streams, Error};
+use crate::warning::{Warning, WarningCode};
+
+
+/// Limits guarding against movies that would exhaust the stack or the memory.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct ExtractOptions {
+    /// The most levels of sprites nested within each other that are accepted.
+    pub max_sprite_depth: usize,
+
+    /// The most pixels a bitmap may have to be decoded; larger bitmaps are skipped with a warning.
+    pub max_image_pixels: u64,
+}
+impl Default for ExtractOptions {
+    fn default() -> Self {
+        Self {
+            max_sprite_depth: 64,
+            max_image_pixels: 64 * 1024 * 1024,
+        }
+    }
+}
+
+
+/// A decoded character.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct Asset {
+    pub id: CharacterId,
+    pub data: AssetData,
+}
+
+
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum AssetData {
+    /// A bitmap as 8-bit RGBA pixels (not premultiplied), row by row.
+    Bitmap { width: u32, height: u32, rgba: Vec<u8> },
+
+    /// A sound. ADPCM sounds are decoded to signed 16-bit little-endian PCM if their header can be
+    /// read (their format is changed accordingly); other sounds are kept as stored.
+    Sound { format: SoundFormat, sample_count: u32, data: Vec<u8> },
+
+    /// Binary data embedded by DefineBinaryData.
+    Binary { data: Vec<u8> },
+}
+
+
+/// How many bytes of ADPCM sound data are decoded between checks for cancellation.
+const SOUND_CHUNK_LEN: u64 = 1024 * 1024;
+
+
+/// The assets decoded from a movie along with the problems encountered while decoding them.
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+pub struct Extraction {
+    pub assets: Vec<Asset>,
+    pub warnings: Vec<Warning>,
+
+    /// Whether extraction has been stopped by its [`Cancellation`], the assets being those decoded
+    /// until then.
+    pub cancelled: bool,
+}
+
+
+/// An asset or a warning, as yielded while decoding a movie.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum Decoded {
+    Asset(Asset),
+    Warning(Warning),
+}
+
+
+/// Decodes the assets of a parsed movie.
+pub struct Extractor<'a> {
+    swf: Swf<'a>,
+    options: ExtractOptions,
+    cancellation: Cancellation,
+
+    /// The contents of the JpegTables tag, if any.
+    jpeg_tables: &'a [u8],
+}
+impl<'a> Extractor<'a> {
+    /// Parses a decompressed movie (as returned by [`decompress_movie`]), whose H.264 video streams
+    /// must have been patched by [`patch_avc_streams`] for it to be parsed.
+    pub fn new(swf_buf: &'a SwfBuf, options: ExtractOptions) -> Result<Self, Error> {
+        let swf = parse_movie(swf_buf, options.max_sprite_depth)?;
+        let jpeg_tables = swf.tags.iter()
+            .find_map(|tag| match tag {
+                Tag::JpegTables(jt) => Some(*jt),
+                _ => None,
+            })
+            .unwrap_or_default();
+        Ok(Self {
+            swf,
+            options,
+            cancellation: Cancellation::new(),
+            jpeg_tables,
+        })
+    }
+
+    /// Makes decoding stop (even within a single asset) once the given cancellation takes effect.
+    pub fn with_cancellation(mut self, cancellation: Cancellation) -> Self {
+        self.cancellation = cancellation;
+        self
+    }
+
+    /// The parsed movie.
+    pub fn swf(&self) -> &Swf<'a> {
+        &self.swf
+    }
+
+    /// Decodes every bitmap, sound and binary data character of the movie. Characters that cannot
+    /// be decoded are skipped with a warning.
+    pub fn extract(&self) -> Extraction {
+        let mut extraction = Extraction::default();
+        let mut decoded = self.decoded();
+        for item in decoded.by_ref() {
+            match item {
+                Decoded::Asset(asset) => extraction.assets.push(asset),
+                Decoded::Warning(warning) => extraction.warnings.push(warning),
+            }
+        }
+        extraction.cancelled = decoded.cancelled;
+        extraction
+    }
+
+    /// Decodes the characters of the movie one after the other, yielding each asset (and each
+    /// warning) as soon as it is available, until decoding is cancelled.
+    pub fn decoded(&self) -> DecodedAssets<'_, 'a> {
+        DecodedAssets {
+            extractor: self,
+            // characters are always defined on the main timeline
+            tags: self.swf.tags.iter(),
+            pending: VecDeque::new(),
+            cancelled: false,
+        }
+    }
+
+    /// Decodes only the character with the given ID (along with the JPEG tables it may depend on),
+    /// as for previewing it. The extraction contains no asset if there is no such character, if it
+    /// is not a bitmap, sound or binary data or if it cannot be decoded (which is warned about).
+    pub fn asset_by_id(&self, id: CharacterId) -> Extraction {
+        let mut extraction = Extraction::default();
+        if let Some(definition) = find_character(&self.swf.tags, id) {
+            extraction.assets.extend(self.decode(definition.tag, &mut extraction.warnings));
+        }
+        extraction.cancelled = self.cancellation.is_cancelled();
+        extraction
+    }
+
+    /// Decodes the character defined by the tag, if it is a bitmap, sound or binary data and
+    /// decoding it has not been cancelled.
+    fn decode(&self, tag: &Tag, warnings: &mut Vec<Warning>) -> Option<Asset> {
+        let (id, bitmap) = match tag {
+            Tag::DefineBinaryData(bd) => {
+                return Some(Asset { id: bd.id, data: AssetData::Binary { data: Vec::from(bd.data) } });
+            },
+            Tag::DefineSound(snd) => {
+                let data = decode_sound(snd, &self.cancellation)?;
+                return Some(Asset { id: snd.id, data });
+            },
+            Tag::DefineBits { id, jpeg_data } => (*id, Bitmap::from_jpeg(jpeg_data, self.jpeg_tables, None)),
+            Tag::DefineBitsJpeg2 { id, jpeg_data } => (*id, Bitmap::from_bytes(jpeg_data, None)),
+            Tag::DefineBitsJpeg3(j3) => {
+                let alpha_data = Some(j3.alpha_data).filter(|a| !a.is_empty());
+                (j3.id, Bitmap::from_bytes(j3.data, alpha_data))
+            },
+            Tag::DefineBitsLossless(bmap) => {
+                // decoding alone might exhaust the memory
+                if !self.admits_image(bmap.id, bmap.width.into(), bmap.height.into(), warnings) {
+                    return None;
+                }
+                let bitmap = Bitmap::from_lossless(bmap, &self.cancellation)
+                    .map(|(bitmap, recovery)| {
+                        warnings.extend(recovery);
+                        bitmap
+                    });
+                (bmap.id, bitmap)
+            },
+            _ => return None,
+        };
+
+        let bitmap = match bitmap {
+            Ok(bitmap) => bitmap,
+            Err(BitmapError::Cancelled) => return None,
+            Err(e) => {
+                warnings.push(Warning::about(WarningCode::SkippedTag, id, format!("skipping bitmap {}: {}", id, e)));
+                return None;
+            },
+        };
+        if !self.admits_image(id, bitmap.width, bitmap.height, warnings) {
+            return None;
+        }
+        match bitmap.decode_rgba() {
+            Ok((width, height, rgba)) => Some(Asset { id, data: AssetData::Bitmap { width, height, rgba } }),
+            Err(e) => {
+                warnings.push(Warning::about(WarningCode::SkippedTag, id, format!("skipping bitmap {}: {}", id, e)));
+                None
+            },
+        }
+    }
+
+    /// Checks the dimensions of a bitmap against [`ExtractOptions::max_image_pixels`], warning if
+    /// they exceed it.
+    fn admits_image(&self, id: CharacterId, width: u32, height: u32, warnings: &mut Vec<Warning>) -> bool {
+        let pixels = u64::from(width) * u64::from(height);
+        if pixels <= self.options.max_image_pixels {
+            return true;
+        }
+        warnings.push(Warning::about(
+            WarningCode::ExceededLimit, id,
+            format!("skipping bitmap {}: {}x{} pixels are more than {}", id, width, height, self.options.max_image_pixels),
+        ));
+        false
+    }
+}
+
+
+/// The iterator returned by [`Extractor::decoded`].
+pub struct DecodedAssets<'e, 'a> {
+    extractor: &'e Extractor<'a>,
+    tags: slice::Iter<'e, Tag<'a>>,
+
+    /// Decoded items not yet returned; the warnings about an asset come before it.
+    pending: VecDeque<Decoded>,
+
+    cancelled: bool,
+}
+impl DecodedAssets<'_, '_> {
+    /// Whether decoding has been stopped by the cancellation of the extractor before all
+    /// characters were decoded.
+    pub fn is_cancelled(&self) -> bool {
+        self.cancelled
+    }
+}
+impl Iterator for DecodedAssets<'_, '_> {
+    type Item = Decoded;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            if let Some(item) = self.pending.pop_front() {
+                return Some(item);
+            }
+            if self.cancelled {
+                return None;
+            }
+            let tag = self.tags.next()?;
+            let mut warnings = Vec::new();
+            let asset = self.extractor.decode(tag, &mut warnings);
+            self.pending.extend(warnings.into_iter().map(Decoded::Warning));
+            self.pending.extend(asset.map(Decoded::Asset));
+            self.cancelled = self.extractor.cancellation.is_cancelled();
+        }
+    }
+}
+
+
+/// Decodes an ADPCM sound to PCM, keeping sounds in other formats as they are. Returns `None` if
+/// decoding has been cancelled.
+fn decode_sound(snd: &swf::Sound, cancellation: &Cancellation) -> Option<AssetData> {
+    let mut format = snd.format.clone();
+    let mut data = Vec::new();
+    let decoder = match snd.format.compression {
+        AudioCompression::Adpcm => AdpcmDecoder::new(snd.data, snd.format.is_stereo).ok(),
+        _ => None,
+    };
+    match decoder {
+        Some(decoder) => {
+            // damaged data still yields the samples before the damage
+            let mut reader = decoder.into_pcm_reader();
+            while !cancellation.is_cancelled() {
+                match reader.by_ref().take(SOUND_CHUNK_LEN).read_to_end(&mut data) {
+                    Ok(0)|Err(_) => break,
+                    Ok(_) => {},
+                }
+            }
+            if cancellation.is_cancelled() {
+                return None;
+            }
+            // the last block is padded to full length
+            let channels = if format.is_stereo { 2 } else { 1 };
+            data.truncate(usize::try_from(snd.num_samples).unwrap() * 2 * channels);
+            format.compression = AudioCompression::Uncompressed;
+            format.is_16_bit = true;
+        },
+        None => data.extend(snd.data),
+    }
+    Some(AssetData::Sound { format, sample_count: snd.num_samples, data })
+}
+
+
+/// Decompresses, parses and decodes a SWF file with the default [`ExtractOptions`].
+pub fn extract_from_bytes(data: &[u8]) -> Result<Extraction, Error> {
+    let options = ExtractOptions::default();
+    let mut swf_buf = decompress_movie(data, options.max_sprite_depth)?;
+    patch_avc_streams(&mut swf_buf.data);
+    Ok(Extractor::new(&swf_buf, options)?.extract())
+}
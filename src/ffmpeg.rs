@@ -0,0 +1,75 @@
+use std::io::{self, Write};
+#[cfg(feature = "mp4-encode")]
+use std::process::Command;
+
+
+/// Quotes a file name for an ffconcat file.
+fn quote(file_name: &str) -> String {
+    format!("'{}'", file_name.replace('\'', "'\\''"))
+}
+
+
+/// Escapes a value for an FFMETADATA file.
+fn escape_metadata(value: &str) -> String {
+    let mut escaped = String::with_capacity(value.len());
+    for c in value.chars() {
+        if matches!(c, '=' | ';' | '#' | '\\' | '\n') {
+            escaped.push('\\');
+        }
+        escaped.push(c);
+    }
+    escaped
+}
+
+
+/// Writes an ffconcat file showing each of the given images for one frame at the given frame rate.
+pub(crate) fn write_ffconcat<W: Write>(frame_files: &[String], frame_rate: f64, mut writer: W) -> io::Result<()> {
+    writeln!(writer, "ffconcat version 1.0")?;
+    for file_name in frame_files {
+        writeln!(writer, "file {}", quote(file_name))?;
+        writeln!(writer, "duration {}", 1.0 / frame_rate)?;
+    }
+    if let Some(last) = frame_files.last() {
+        // the duration of the final entry is only respected if it is followed by another one
+        writeln!(writer, "file {}", quote(last))?;
+    }
+    Ok(())
+}
+
+
+/// Writes an FFMETADATA file with the given title and chapters.
+///
+/// Chapters are given as (start, end, title) with the start and end in frames, counting from zero.
+pub(crate) fn write_ffmetadata<W: Write>(title: &str, chapters: &[(u32, u32, String)], frame_rate: f64, mut writer: W) -> io::Result<()> {
+    writeln!(writer, ";FFMETADATA1")?;
+    writeln!(writer, "title={}", escape_metadata(title))?;
+    for (start, end, chapter_title) in chapters {
+        let to_ms = |frame: u32| (f64::from(frame) * 1000.0 / frame_rate).round();
+        writeln!(writer)?;
+        writeln!(writer, "[CHAPTER]")?;
+        writeln!(writer, "TIMEBASE=1/1000")?;
+        writeln!(writer, "START={}", to_ms(*start))?;
+        writeln!(writer, "END={}", to_ms(*end))?;
+        writeln!(writer, "title={}", escape_metadata(chapter_title))?;
+    }
+    Ok(())
+}
+
+
+/// Runs ffmpeg with the given arguments, overwriting existing output files.
+#[cfg(feature = "mp4-encode")]
+pub(crate) fn run_ffmpeg(args: &[String]) -> io::Result<()> {
+    let status = Command::new("ffmpeg")
+        .args(["-y", "-loglevel", "error"])
+        .args(args)
+        .status()
+        .map_err(|e| if e.kind() == io::ErrorKind::NotFound {
+            io::Error::new(io::ErrorKind::NotFound, "encoder \"ffmpeg\" not found in PATH")
+        } else {
+            e
+        })?;
+    if !status.success() {
+        return Err(io::Error::other(format!("encoder \"ffmpeg\" failed: {}", status)));
+    }
+    Ok(())
+}
@@ -0,0 +1,177 @@
+use std::collections::{BTreeMap, HashMap, HashSet};
+
+use swf::CharacterId;
+
+
+/// The longest name produced from a single name in the movie, in bytes, which leaves room for
+/// prefixes, suffixes and extensions within the 255 bytes allowed by most file systems.
+const MAX_NAME_LENGTH: usize = 128;
+
+/// The names of devices, which Windows reserves regardless of case and extension.
+const RESERVED_NAMES: [&str; 22] = [
+    "CON", "PRN", "AUX", "NUL",
+    "COM1", "COM2", "COM3", "COM4", "COM5", "COM6", "COM7", "COM8", "COM9",
+    "LPT1", "LPT2", "LPT3", "LPT4", "LPT5", "LPT6", "LPT7", "LPT8", "LPT9",
+];
+
+/// The names (without extensions) of the files and directories written for the movie as a whole,
+/// which characters must not be named after.
+const OUTPUT_STEMS: [&str; 13] = [
+    "manifest", "report", "symbols", "index", "stream", "duplicates", "references", "urls", "strings",
+    "transcript", "movie", "timeline", "tags",
+];
+
+
+/// Turns a name from the movie into one that can be used as (part of) a file name on any common file
+/// system.
+///
+/// Characters other than letters, digits, hyphens and underscores (including path separators and
+/// dots) are replaced by underscores, overly long names are shortened, and names reserved by Windows
+/// and empty names are suffixed with an underscore.
+pub(crate) fn sanitize_file_name(name: &str) -> String {
+    let mut sanitized = String::with_capacity(name.len().min(MAX_NAME_LENGTH));
+    for c in name.chars() {
+        let c = if c.is_alphanumeric() || c == '-' || c == '_' { c } else { '_' };
+        if sanitized.len() + c.len_utf8() > MAX_NAME_LENGTH {
+            break;
+        }
+        sanitized.push(c);
+    }
+    if sanitized.is_empty() || RESERVED_NAMES.iter().any(|reserved| sanitized.eq_ignore_ascii_case(reserved)) {
+        sanitized.push('_');
+    }
+    sanitized
+}
+
+
+/// Turns a fully qualified ActionScript class name into a file name, keeping the dots between the
+/// package components.
+fn class_file_name(class_name: &str) -> String {
+    let components: Vec<String> = class_name.split('.')
+        .map(sanitize_file_name)
+        .collect();
+    components.join(".")
+}
+
+
+/// The file names (without extensions) of the characters of a movie.
+///
+/// Characters are named after their ID, followed by the name under which they are exported (or named
+/// by NameCharacter), if any, or after the ActionScript 3 class bound to them if requested. Names that
+/// would clash with the name of another character or of a file written for the movie as a whole, even
+/// on case-insensitive file systems, receive a numeric suffix.
+#[derive(Clone, Debug)]
+pub(crate) struct FileStems {
+    stems: HashMap<CharacterId, String>,
+
+    /// The names in the movie from which the file names have been derived.
+    original_names: BTreeMap<CharacterId, String>,
+}
+impl FileStems {
+    pub fn new(
+        characters: impl IntoIterator<Item = CharacterId>,
+        export_names: &HashMap<CharacterId, String>,
+        symbol_classes: &[(CharacterId, String)],
+        use_symbol_names: bool,
+    ) -> Self {
+        let mut class_names: BTreeMap<CharacterId, &str> = BTreeMap::new();
+        if use_symbol_names {
+            for (id, class_name) in symbol_classes.iter().filter(|(_, name)| !name.is_empty()) {
+                class_names.entry(*id).or_insert(class_name);
+            }
+        }
+
+        let mut stems = HashMap::new();
+        let mut original_names = BTreeMap::new();
+        let mut taken: HashSet<String> = OUTPUT_STEMS.iter()
+            .map(|stem| stem.to_string())
+            .collect();
+
+        // names starting with the ID cannot clash with each other
+        let mut characters: Vec<CharacterId> = characters.into_iter()
+            .chain(export_names.keys().copied())
+            .filter(|id| !class_names.contains_key(id))
+            .collect();
+        characters.sort_unstable();
+        characters.dedup();
+        for id in characters {
+            let stem = match export_names.get(&id).filter(|name| !name.is_empty()) {
+                Some(name) => {
+                    original_names.insert(id, name.clone());
+                    format!("{}_{}", id, sanitize_file_name(name))
+                },
+                None => id.to_string(),
+            };
+            taken.insert(stem.to_lowercase());
+            stems.insert(id, stem);
+        }
+
+        for (id, class_name) in class_names {
+            let base = class_file_name(class_name);
+            let mut stem = base.clone();
+            let mut counter = 2;
+            while !taken.insert(stem.to_lowercase()) {
+                stem = format!("{}_{}", base, counter);
+                counter += 1;
+            }
+            original_names.insert(id, class_name.to_owned());
+            stems.insert(id, stem);
+        }
+
+        Self {
+            stems,
+            original_names,
+        }
+    }
+
+    /// Returns the file name of the character with the given ID, without extension.
+    pub fn get(&self, id: CharacterId) -> String {
+        self.stems.get(&id)
+            .cloned()
+            .unwrap_or_else(|| id.to_string())
+    }
+
+    /// Returns the file name of the character with the given ID, without extension, falling back to
+    /// the ID followed by the given name (such as the name of a font) if the character is neither
+    /// exported nor bound to a class used for naming.
+    pub fn get_named(&self, id: CharacterId, name: &str) -> String {
+        if self.original_names.contains_key(&id) || name.is_empty() {
+            self.get(id)
+        } else {
+            format!("{}_{}", id, sanitize_file_name(name))
+        }
+    }
+
+    /// Returns the names in the movie from which file names have been derived, by character ID.
+    pub fn original_names(&self) -> &BTreeMap<CharacterId, String> {
+        &self.original_names
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+
+    #[test]
+    fn named_stems_prefer_export_and_class_names() {
+        let export_names = HashMap::from([(1, "logo".to_owned())]);
+        let symbol_classes = [(2, "game.Level".to_owned())];
+        let stems = FileStems::new([1, 2, 3], &export_names, &symbol_classes, true);
+        assert_eq!(stems.get_named(1, "Arial"), "1_logo");
+        assert_eq!(stems.get_named(2, "Data"), "game.Level");
+        assert_eq!(stems.get_named(3, "Arial Bold"), "3_Arial_Bold");
+        assert_eq!(stems.get_named(3, ""), "3");
+    }
+
+
+    #[test]
+    fn class_names_avoid_output_files() {
+        let symbol_classes = [(1, "manifest".to_owned()), (2, "Report".to_owned()), (3, "Manifest".to_owned())];
+        let stems = FileStems::new([1, 2, 3], &HashMap::new(), &symbol_classes, true);
+        assert_eq!(stems.get(1), "manifest_2");
+        assert_eq!(stems.get(2), "Report_2");
+        assert_eq!(stems.get(3), "Manifest_3");
+    }
+}
@@ -0,0 +1,280 @@
+use swf::{BlendMode, Color, Filter, Fixed16, GradientRecord};
+use sxd_document::dom::{Document, Element};
+
+use crate::json::JsonValue;
+
+
+fn color_to_hex(color: &Color) -> String {
+    format!("#{:02x}{:02x}{:02x}{:02x}", color.r, color.g, color.b, color.a)
+}
+
+
+fn gradient_to_json(records: &[GradientRecord]) -> JsonValue {
+    let mut stops = JsonValue::new_array();
+    for record in records {
+        let mut stop = JsonValue::new_object();
+        stop.insert("ratio", record.ratio);
+        stop.insert("color", color_to_hex(&record.color));
+        stops.push(stop);
+    }
+    stops
+}
+
+
+/// Returns the name of a blend mode as used by ActionScript.
+pub(crate) fn blend_mode_name(blend_mode: BlendMode) -> &'static str {
+    match blend_mode {
+        BlendMode::Normal => "normal",
+        BlendMode::Layer => "layer",
+        BlendMode::Multiply => "multiply",
+        BlendMode::Screen => "screen",
+        BlendMode::Lighten => "lighten",
+        BlendMode::Darken => "darken",
+        BlendMode::Difference => "difference",
+        BlendMode::Add => "add",
+        BlendMode::Subtract => "subtract",
+        BlendMode::Invert => "invert",
+        BlendMode::Alpha => "alpha",
+        BlendMode::Erase => "erase",
+        BlendMode::Overlay => "overlay",
+        BlendMode::HardLight => "hardlight",
+    }
+}
+
+
+/// Returns the CSS `mix-blend-mode` corresponding to a blend mode, or `None` if there is no
+/// equivalent or blending is not necessary.
+pub(crate) fn css_blend_mode(blend_mode: BlendMode) -> Option<&'static str> {
+    match blend_mode {
+        BlendMode::Multiply => Some("multiply"),
+        BlendMode::Screen => Some("screen"),
+        BlendMode::Lighten => Some("lighten"),
+        BlendMode::Darken => Some("darken"),
+        BlendMode::Difference => Some("difference"),
+        BlendMode::Overlay => Some("overlay"),
+        BlendMode::HardLight => Some("hard-light"),
+        _ => None,
+    }
+}
+
+
+/// Collects the type and parameters of a bitmap filter into a JSON object.
+///
+/// Blur radii and distances are in pixels, angles in radians.
+pub(crate) fn filter_to_json(filter: &Filter) -> JsonValue {
+    let mut obj = JsonValue::new_object();
+    match filter {
+        Filter::DropShadowFilter(f) => {
+            obj.insert("type", "drop_shadow");
+            obj.insert("color", color_to_hex(&f.color));
+            obj.insert("blur_x", f.blur_x.to_f64());
+            obj.insert("blur_y", f.blur_y.to_f64());
+            obj.insert("angle", f.angle.to_f64());
+            obj.insert("distance", f.distance.to_f64());
+            obj.insert("strength", f.strength.to_f64());
+            obj.insert("inner", f.is_inner);
+            obj.insert("knockout", f.is_knockout);
+            obj.insert("passes", f.num_passes);
+        },
+        Filter::BlurFilter(f) => {
+            obj.insert("type", "blur");
+            obj.insert("blur_x", f.blur_x.to_f64());
+            obj.insert("blur_y", f.blur_y.to_f64());
+            obj.insert("passes", f.num_passes);
+        },
+        Filter::GlowFilter(f) => {
+            obj.insert("type", "glow");
+            obj.insert("color", color_to_hex(&f.color));
+            obj.insert("blur_x", f.blur_x.to_f64());
+            obj.insert("blur_y", f.blur_y.to_f64());
+            obj.insert("strength", f.strength.to_f64());
+            obj.insert("inner", f.is_inner);
+            obj.insert("knockout", f.is_knockout);
+            obj.insert("passes", f.num_passes);
+        },
+        Filter::BevelFilter(f) => {
+            obj.insert("type", "bevel");
+            obj.insert("shadow_color", color_to_hex(&f.shadow_color));
+            obj.insert("highlight_color", color_to_hex(&f.highlight_color));
+            obj.insert("blur_x", f.blur_x.to_f64());
+            obj.insert("blur_y", f.blur_y.to_f64());
+            obj.insert("angle", f.angle.to_f64());
+            obj.insert("distance", f.distance.to_f64());
+            obj.insert("strength", f.strength.to_f64());
+            obj.insert("inner", f.is_inner);
+            obj.insert("knockout", f.is_knockout);
+            obj.insert("on_top", f.is_on_top);
+            obj.insert("passes", f.num_passes);
+        },
+        Filter::GradientGlowFilter(f) => {
+            obj.insert("type", "gradient_glow");
+            obj.insert("gradient", gradient_to_json(&f.colors));
+            obj.insert("blur_x", f.blur_x.to_f64());
+            obj.insert("blur_y", f.blur_y.to_f64());
+            obj.insert("angle", f.angle.to_f64());
+            obj.insert("distance", f.distance.to_f64());
+            obj.insert("strength", f.strength.to_f64());
+            obj.insert("inner", f.is_inner);
+            obj.insert("knockout", f.is_knockout);
+            obj.insert("on_top", f.is_on_top);
+            obj.insert("passes", f.num_passes);
+        },
+        Filter::ConvolutionFilter(f) => {
+            obj.insert("type", "convolution");
+            obj.insert("rows", f.num_matrix_rows);
+            obj.insert("columns", f.num_matrix_cols);
+            obj.insert("matrix", f.matrix.iter().map(|v| v.to_f64()).collect::<Vec<f64>>());
+            obj.insert("divisor", f.divisor.to_f64());
+            obj.insert("bias", f.bias.to_f64());
+            obj.insert("default_color", color_to_hex(&f.default_color));
+            obj.insert("clamp", f.is_clamped);
+            obj.insert("preserve_alpha", f.is_preserve_alpha);
+        },
+        Filter::ColorMatrixFilter(f) => {
+            obj.insert("type", "color_matrix");
+            obj.insert("matrix", f.matrix.iter().map(|v| v.to_f64()).collect::<Vec<f64>>());
+        },
+        Filter::GradientBevelFilter(f) => {
+            obj.insert("type", "gradient_bevel");
+            obj.insert("gradient", gradient_to_json(&f.colors));
+            obj.insert("blur_x", f.blur_x.to_f64());
+            obj.insert("blur_y", f.blur_y.to_f64());
+            obj.insert("angle", f.angle.to_f64());
+            obj.insert("distance", f.distance.to_f64());
+            obj.insert("strength", f.strength.to_f64());
+            obj.insert("inner", f.is_inner);
+            obj.insert("knockout", f.is_knockout);
+            obj.insert("on_top", f.is_on_top);
+            obj.insert("passes", f.num_passes);
+        },
+    }
+    obj
+}
+
+
+/// Returns the standard deviation of a Gaussian blur approximating the given number of passes of a
+/// box blur of the given width.
+fn blur_deviation(blur: Fixed16, passes: u8) -> f64 {
+    let width = blur.to_f64();
+    (f64::from(passes.max(1)) * (width * width - 1.0).max(0.0) / 12.0).sqrt()
+}
+
+
+fn append_primitive<'d>(document: Document<'d>, filter: Element<'d>, name: &str, attributes: &[(&str, String)]) -> Element<'d> {
+    let primitive = document.create_element(name);
+    for (key, value) in attributes {
+        primitive.set_attribute_value(*key, value);
+    }
+    filter.append_child(primitive);
+    primitive
+}
+
+
+/// Appends a shadow (or glow, if the distance is zero) of the input to an SVG filter and returns the
+/// name of the result.
+#[allow(clippy::too_many_arguments)]
+fn append_shadow<'d>(
+    document: Document<'d>, filter: Element<'d>, input: &str, result: &str,
+    color: &Color, blur_x: Fixed16, blur_y: Fixed16, angle: Fixed16, distance: Fixed16, strength: f64,
+    passes: u8, is_knockout: bool,
+) -> String {
+    append_primitive(document, filter, "feGaussianBlur", &[
+        ("in", input.to_owned()),
+        ("stdDeviation", format!("{} {}", blur_deviation(blur_x, passes), blur_deviation(blur_y, passes))),
+        ("result", format!("{}-blur", result)),
+    ]);
+    append_primitive(document, filter, "feOffset", &[
+        ("in", format!("{}-blur", result)),
+        ("dx", format!("{}", angle.to_f64().cos() * distance.to_f64())),
+        ("dy", format!("{}", angle.to_f64().sin() * distance.to_f64())),
+        ("result", format!("{}-offset", result)),
+    ]);
+    append_primitive(document, filter, "feFlood", &[
+        ("flood-color", format!("rgb({},{},{})", color.r, color.g, color.b)),
+        ("flood-opacity", format!("{}", f64::from(color.a) / 255.0)),
+        ("result", format!("{}-color", result)),
+    ]);
+    append_primitive(document, filter, "feComposite", &[
+        ("in", format!("{}-color", result)),
+        ("in2", format!("{}-offset", result)),
+        ("operator", "in".to_owned()),
+        ("result", format!("{}-shadow", result)),
+    ]);
+    let transfer = append_primitive(document, filter, "feComponentTransfer", &[
+        ("in", format!("{}-shadow", result)),
+        ("result", format!("{}-strong", result)),
+    ]);
+    let func_a = document.create_element("feFuncA");
+    func_a.set_attribute_value("type", "linear");
+    func_a.set_attribute_value("slope", &format!("{}", strength));
+    transfer.append_child(func_a);
+
+    if is_knockout {
+        // only the shadow remains
+        return format!("{}-strong", result);
+    }
+    let merge = append_primitive(document, filter, "feMerge", &[("result", result.to_owned())]);
+    for node_input in [format!("{}-strong", result), input.to_owned()] {
+        let node = document.create_element("feMergeNode");
+        node.set_attribute_value("in", &node_input);
+        merge.append_child(node);
+    }
+    result.to_owned()
+}
+
+
+/// Appends the SVG filter primitives corresponding to a bitmap filter to an SVG `filter` element.
+///
+/// The primitives read from the result named `input` and the name of their final result is
+/// returned. Returns `None` without appending anything if the filter has no SVG equivalent; this is
+/// the case for bevels, gradient glows and inner shadows and glows.
+pub(crate) fn append_svg_filter<'d>(document: Document<'d>, filter_elem: Element<'d>, input: &str, result: &str, filter: &Filter) -> Option<String> {
+    match filter {
+        Filter::BlurFilter(f) => {
+            append_primitive(document, filter_elem, "feGaussianBlur", &[
+                ("in", input.to_owned()),
+                ("stdDeviation", format!("{} {}", blur_deviation(f.blur_x, f.num_passes), blur_deviation(f.blur_y, f.num_passes))),
+                ("result", result.to_owned()),
+            ]);
+            Some(result.to_owned())
+        },
+        Filter::DropShadowFilter(f) if !f.is_inner => Some(append_shadow(
+            document, filter_elem, input, result,
+            &f.color, f.blur_x, f.blur_y, f.angle, f.distance, f.strength.to_f64(), f.num_passes, f.is_knockout,
+        )),
+        Filter::GlowFilter(f) if !f.is_inner => Some(append_shadow(
+            document, filter_elem, input, result,
+            &f.color, f.blur_x, f.blur_y, Fixed16::ZERO, Fixed16::ZERO, f.strength.to_f64(), f.num_passes, f.is_knockout,
+        )),
+        Filter::ColorMatrixFilter(f) => {
+            // the offsets are given in the range 0 to 255
+            let values: Vec<String> = f.matrix.iter().enumerate()
+                .map(|(i, v)| if i % 5 == 4 { v.to_f64() / 255.0 } else { v.to_f64() })
+                .map(|v| v.to_string())
+                .collect();
+            append_primitive(document, filter_elem, "feColorMatrix", &[
+                ("in", input.to_owned()),
+                ("type", "matrix".to_owned()),
+                ("values", values.join(" ")),
+                ("result", result.to_owned()),
+            ]);
+            Some(result.to_owned())
+        },
+        Filter::ConvolutionFilter(f) => {
+            let kernel: Vec<String> = f.matrix.iter().map(|v| v.to_f64().to_string()).collect();
+            let divisor = if f.divisor.to_f64() == 0.0 { 1.0 } else { f.divisor.to_f64() };
+            append_primitive(document, filter_elem, "feConvolveMatrix", &[
+                ("in", input.to_owned()),
+                ("order", format!("{} {}", f.num_matrix_cols, f.num_matrix_rows)),
+                ("kernelMatrix", kernel.join(" ")),
+                ("divisor", divisor.to_string()),
+                ("bias", (f.bias.to_f64() / 255.0).to_string()),
+                ("edgeMode", if f.is_clamped { "duplicate" } else { "none" }.to_owned()),
+                ("preserveAlpha", f.is_preserve_alpha.to_string()),
+                ("result", result.to_owned()),
+            ]);
+            Some(result.to_owned())
+        },
+        _ => None,
+    }
+}
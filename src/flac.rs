@@ -0,0 +1,636 @@
+use std::io::Write;
+
+use bitstream_io::{BigEndian, BitWrite, BitWriter};
+
+
+/// The number of samples (per channel) encoded in each frame, except possibly the last.
+const BLOCK_SIZE: usize = 4096;
+
+/// The highest Rice parameter used; 0b1111 is reserved as the escape code.
+const MAX_RICE_PARAMETER: u32 = 14;
+
+/// The highest partition order attempted when encoding residuals.
+const MAX_PARTITION_ORDER: u32 = 8;
+
+
+/// The ways in which the channels of a stereo frame can be stored.
+#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq)]
+enum ChannelAssignment {
+    Independent,
+    LeftSide,
+    SideRight,
+    MidSide,
+}
+impl ChannelAssignment {
+    fn code(&self, channels: usize) -> u32 {
+        match self {
+            Self::Independent => u32::try_from(channels).unwrap() - 1,
+            Self::LeftSide => 0b1000,
+            Self::SideRight => 0b1001,
+            Self::MidSide => 0b1010,
+        }
+    }
+}
+
+
+/// How a subframe (the samples of one channel within a frame) is encoded.
+#[derive(Clone, Debug, Eq, Hash, PartialEq)]
+enum SubframeKind {
+    Constant,
+    Verbatim,
+    Fixed { order: usize, partition_order: u32, rice_parameters: Vec<u32> },
+}
+
+
+/// Writes interleaved PCM samples as a FLAC file.
+///
+/// Only the fixed linear predictors are used, which is a fraction of the work of a full LPC
+/// encoder but still compresses typical Flash sound effects to about half their size.
+pub(crate) fn write_flac<W: Write>(
+    mut writer: W,
+    sample_rate: u32,
+    channels: usize,
+    bits_per_sample: u32,
+    samples: &[i32],
+) -> Result<(), std::io::Error> {
+    let total_samples = samples.len() / channels;
+
+    writer.write_all(b"fLaC")?;
+
+    // STREAMINFO is the only metadata block
+    let mut stream_info = BitWriter::endian(Vec::new(), BigEndian);
+    stream_info.write(16, u32::try_from(BLOCK_SIZE).unwrap())?; // minimum block size
+    stream_info.write(16, u32::try_from(BLOCK_SIZE).unwrap())?; // maximum block size
+    stream_info.write(24, 0u32)?; // minimum frame size (unknown)
+    stream_info.write(24, 0u32)?; // maximum frame size (unknown)
+    stream_info.write(20, sample_rate)?;
+    stream_info.write(3, u32::try_from(channels).unwrap() - 1)?;
+    stream_info.write(5, bits_per_sample - 1)?;
+    stream_info.write(36, total_samples as u64)?;
+    stream_info.write_bytes(&[0u8; 16])?; // MD5 of the samples (not computed)
+    let stream_info = stream_info.into_writer();
+
+    writer.write_all(&[0x80])?; // last metadata block, type STREAMINFO
+    writer.write_all(&u32::try_from(stream_info.len()).unwrap().to_be_bytes()[1..])?;
+    writer.write_all(&stream_info)?;
+
+    for (frame_number, block) in samples.chunks(BLOCK_SIZE * channels).enumerate() {
+        let frame = encode_frame(frame_number, block, channels, bits_per_sample)?;
+        writer.write_all(&frame)?;
+    }
+    Ok(())
+}
+
+
+fn encode_frame(frame_number: usize, block: &[i32], channels: usize, bits_per_sample: u32) -> Result<Vec<u8>, std::io::Error> {
+    let block_size = block.len() / channels;
+    let channel_samples: Vec<Vec<i64>> = (0..channels)
+        .map(|c| block.iter().skip(c).step_by(channels).map(|&s| i64::from(s)).collect())
+        .collect();
+    let (assignment, channel_samples, subframes) = if channels == 2 {
+        choose_stereo_encoding(channel_samples, bits_per_sample)
+    } else {
+        let subframes = channel_samples.iter()
+            .map(|samples| (plan_subframe(samples, bits_per_sample).0, bits_per_sample))
+            .collect();
+        (ChannelAssignment::Independent, channel_samples, subframes)
+    };
+
+    let mut frame = BitWriter::endian(Vec::new(), BigEndian);
+    frame.write(14, 0b11111111111110u32)?; // sync code
+    frame.write(1, 0u32)?; // reserved
+    frame.write(1, 0u32)?; // fixed block size
+    let block_size_code = if block_size == BLOCK_SIZE { 0b1100u32 } else { 0b0111 };
+    frame.write(4, block_size_code)?;
+    frame.write(4, 0b0000u32)?; // sample rate from STREAMINFO
+    frame.write(4, assignment.code(channels))?;
+    let sample_size_code = match bits_per_sample {
+        8 => 0b001u32,
+        12 => 0b010,
+        16 => 0b100,
+        20 => 0b101,
+        24 => 0b110,
+        _ => 0b000, // from STREAMINFO
+    };
+    frame.write(3, sample_size_code)?;
+    frame.write(1, 0u32)?; // reserved
+    frame.write_bytes(&utf8_number(u32::try_from(frame_number).unwrap()))?;
+    if block_size_code == 0b0111 {
+        frame.write(16, u32::try_from(block_size - 1).unwrap())?;
+    }
+    let mut frame = frame.into_writer();
+    frame.push(crc8(&frame));
+
+    let mut body = BitWriter::endian(frame, BigEndian);
+    for (samples, (kind, bits)) in channel_samples.iter().zip(&subframes) {
+        write_subframe(&mut body, samples, *bits, kind)?;
+    }
+    body.byte_align()?;
+    let mut frame = body.into_writer();
+    let crc = crc16(&frame);
+    frame.extend(crc.to_be_bytes());
+    Ok(frame)
+}
+
+
+/// Chooses how to store the channels of a stereo frame, returning the channel assignment along with
+/// the samples, encoding and sample size of each subframe.
+fn choose_stereo_encoding(mut channel_samples: Vec<Vec<i64>>, bits_per_sample: u32) -> (ChannelAssignment, Vec<Vec<i64>>, Vec<(SubframeKind, u32)>) {
+    let right = channel_samples.pop().unwrap();
+    let left = channel_samples.pop().unwrap();
+    let mid: Vec<i64> = left.iter().zip(&right).map(|(l, r)| (l + r) >> 1).collect();
+    let side: Vec<i64> = left.iter().zip(&right).map(|(l, r)| l - r).collect();
+
+    // the side channel needs an extra bit
+    let (left_kind, left_bits) = plan_subframe(&left, bits_per_sample);
+    let (right_kind, right_bits) = plan_subframe(&right, bits_per_sample);
+    let (mid_kind, mid_bits) = plan_subframe(&mid, bits_per_sample);
+    let (side_kind, side_bits) = plan_subframe(&side, bits_per_sample + 1);
+
+    let candidates = [
+        (ChannelAssignment::Independent, left_bits + right_bits),
+        (ChannelAssignment::LeftSide, left_bits + side_bits),
+        (ChannelAssignment::SideRight, side_bits + right_bits),
+        (ChannelAssignment::MidSide, mid_bits + side_bits),
+    ];
+    let assignment = candidates.iter().min_by_key(|(_, bits)| *bits).unwrap().0;
+
+    let left = (left, (left_kind, bits_per_sample));
+    let right = (right, (right_kind, bits_per_sample));
+    let mid = (mid, (mid_kind, bits_per_sample));
+    let side = (side, (side_kind, bits_per_sample + 1));
+    let (first, second) = match assignment {
+        ChannelAssignment::Independent => (left, right),
+        ChannelAssignment::LeftSide => (left, side),
+        ChannelAssignment::SideRight => (side, right),
+        ChannelAssignment::MidSide => (mid, side),
+    };
+    (assignment, vec![first.0, second.0], vec![first.1, second.1])
+}
+
+
+/// Chooses the cheapest encoding of a subframe, returning it along with its length in bits.
+fn plan_subframe(samples: &[i64], bits_per_sample: u32) -> (SubframeKind, u64) {
+    // type and wasted-bits flag
+    let header_bits = 8;
+
+    if samples.iter().all(|&s| s == samples[0]) {
+        return (SubframeKind::Constant, header_bits + u64::from(bits_per_sample));
+    }
+
+    let mut best = (
+        SubframeKind::Verbatim,
+        header_bits + u64::from(bits_per_sample) * samples.len() as u64,
+    );
+    for order in 0..=4 {
+        if samples.len() <= order {
+            break;
+        }
+        let residual = fixed_residual(samples, order);
+        let (partition_order, rice_parameters, residual_bits) = plan_residual(&residual, samples.len(), order);
+        let bits = header_bits
+            + u64::from(bits_per_sample) * order as u64 // warm-up samples
+            + residual_bits;
+        if bits < best.1 {
+            best = (SubframeKind::Fixed { order, partition_order, rice_parameters }, bits);
+        }
+    }
+    best
+}
+
+
+/// Computes the residual of the fixed predictor of the given order, skipping the warm-up samples.
+fn fixed_residual(samples: &[i64], order: usize) -> Vec<i64> {
+    samples.windows(order + 1)
+        .map(|w| match order {
+            0 => w[0],
+            1 => w[1] - w[0],
+            2 => w[2] - 2*w[1] + w[0],
+            3 => w[3] - 3*w[2] + 3*w[1] - w[0],
+            4 => w[4] - 4*w[3] + 6*w[2] - 4*w[1] + w[0],
+            _ => unreachable!(),
+        })
+        .collect()
+}
+
+
+/// Maps signed residuals onto unsigned values for Rice coding.
+fn zigzag(value: i64) -> u64 {
+    ((value << 1) ^ (value >> 63)) as u64
+}
+
+
+/// Chooses the partition order and Rice parameters for a residual, returning them along with the
+/// length of the encoded residual in bits.
+fn plan_residual(residual: &[i64], block_size: usize, predictor_order: usize) -> (u32, Vec<u32>, u64) {
+    let mut best: Option<(u32, Vec<u32>, u64)> = None;
+    for partition_order in 0..=MAX_PARTITION_ORDER {
+        let partition_count = 1usize << partition_order;
+        let partition_size = block_size >> partition_order;
+        if !block_size.is_multiple_of(partition_count) || partition_size <= predictor_order {
+            break;
+        }
+
+        // coding method and partition order
+        let mut bits = 2 + 4;
+        let mut parameters = Vec::with_capacity(partition_count);
+        let mut start = 0;
+        for partition in 0..partition_count {
+            // the first partition does not contain the warm-up samples
+            let len = if partition == 0 { partition_size - predictor_order } else { partition_size };
+            let (parameter, partition_bits) = best_rice_parameter(&residual[start..start + len]);
+            parameters.push(parameter);
+            bits += 4 + partition_bits;
+            start += len;
+        }
+
+        if best.as_ref().map(|b| bits < b.2).unwrap_or(true) {
+            best = Some((partition_order, parameters, bits));
+        }
+    }
+    best.unwrap()
+}
+
+
+/// Chooses the Rice parameter for a partition, returning it along with the encoded length of the
+/// partition in bits.
+fn best_rice_parameter(partition: &[i64]) -> (u32, u64) {
+    let cost = |k: u32| -> u64 {
+        partition.iter()
+            .map(|&r| (zigzag(r) >> k) + 1 + u64::from(k))
+            .sum()
+    };
+
+    if partition.is_empty() {
+        return (0, 0);
+    }
+
+    // the mean of the values is a good estimate; check its neighbors as well
+    let sum: u64 = partition.iter().map(|&r| zigzag(r)).sum();
+    let mean = sum / partition.len() as u64;
+    let estimate = (64 - mean.leading_zeros()).min(MAX_RICE_PARAMETER);
+    (estimate.saturating_sub(1)..=(estimate + 1).min(MAX_RICE_PARAMETER))
+        .map(|k| (k, cost(k)))
+        .min_by_key(|(_, bits)| *bits)
+        .unwrap()
+}
+
+
+fn write_subframe<W: Write>(writer: &mut BitWriter<W, BigEndian>, samples: &[i64], bits_per_sample: u32, kind: &SubframeKind) -> Result<(), std::io::Error> {
+    writer.write(1, 0u32)?; // padding
+    match kind {
+        SubframeKind::Constant => {
+            writer.write(6, 0b000000u32)?;
+            writer.write(1, 0u32)?; // no wasted bits
+            writer.write_signed(bits_per_sample, samples[0])?;
+        },
+        SubframeKind::Verbatim => {
+            writer.write(6, 0b000001u32)?;
+            writer.write(1, 0u32)?; // no wasted bits
+            for &sample in samples {
+                writer.write_signed(bits_per_sample, sample)?;
+            }
+        },
+        SubframeKind::Fixed { order, partition_order, rice_parameters } => {
+            writer.write(6, 0b001000u32 | u32::try_from(*order).unwrap())?;
+            writer.write(1, 0u32)?; // no wasted bits
+            for &sample in &samples[..*order] {
+                writer.write_signed(bits_per_sample, sample)?;
+            }
+
+            writer.write(2, 0b00u32)?; // Rice coding with 4-bit parameters
+            writer.write(4, *partition_order)?;
+            let residual = fixed_residual(samples, *order);
+            let partition_size = samples.len() >> partition_order;
+            let mut start = 0;
+            for (partition, &parameter) in rice_parameters.iter().enumerate() {
+                let len = if partition == 0 { partition_size - order } else { partition_size };
+                writer.write(4, parameter)?;
+                for &r in &residual[start..start + len] {
+                    let value = zigzag(r);
+                    writer.write_unary1(u32::try_from(value >> parameter).unwrap())?;
+                    if parameter > 0 {
+                        writer.write(parameter, value & ((1 << parameter) - 1))?;
+                    }
+                }
+                start += len;
+            }
+        },
+    }
+    Ok(())
+}
+
+
+/// Encodes a frame number in the UTF-8-like variable-length encoding used by FLAC.
+fn utf8_number(value: u32) -> Vec<u8> {
+    if value < 0x80 {
+        return vec![value as u8];
+    }
+    let byte_count = match value {
+        0..=0x7FF => 2,
+        0x800..=0xFFFF => 3,
+        0x1_0000..=0x1F_FFFF => 4,
+        0x20_0000..=0x3FF_FFFF => 5,
+        _ => 6,
+    };
+    let mut bytes = Vec::with_capacity(byte_count);
+    let lead_marker = (0xFF00u32 >> byte_count) as u8;
+    bytes.push(lead_marker | (value >> (6 * (byte_count - 1))) as u8);
+    for i in (0..byte_count - 1).rev() {
+        bytes.push(0x80 | ((value >> (6 * i)) & 0x3F) as u8);
+    }
+    bytes
+}
+
+
+/// CRC-8 with polynomial x^8 + x^2 + x + 1, as used in frame headers.
+fn crc8(data: &[u8]) -> u8 {
+    let mut crc = 0u8;
+    for &b in data {
+        crc ^= b;
+        for _ in 0..8 {
+            crc = if crc & 0x80 != 0 { (crc << 1) ^ 0x07 } else { crc << 1 };
+        }
+    }
+    crc
+}
+
+
+/// CRC-16 with polynomial x^16 + x^15 + x^2 + 1, as used at the end of frames.
+fn crc16(data: &[u8]) -> u16 {
+    let mut crc = 0u16;
+    for &b in data {
+        crc ^= u16::from(b) << 8;
+        for _ in 0..8 {
+            crc = if crc & 0x8000 != 0 { (crc << 1) ^ 0x8005 } else { crc << 1 };
+        }
+    }
+    crc
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+
+    /// Reads the bits of a FLAC stream, most significant bit first.
+    struct BitCursor<'a> {
+        data: &'a [u8],
+        bit: usize,
+    }
+    impl BitCursor<'_> {
+        fn read(&mut self, count: u32) -> u64 {
+            let mut value = 0;
+            for _ in 0..count {
+                let byte = self.data[self.bit / 8];
+                value = (value << 1) | u64::from((byte >> (7 - self.bit % 8)) & 1);
+                self.bit += 1;
+            }
+            value
+        }
+
+        fn read_signed(&mut self, count: u32) -> i64 {
+            let value = self.read(count) as i64;
+            if value >> (count - 1) & 1 != 0 { value - (1 << count) } else { value }
+        }
+
+        fn read_unary(&mut self) -> u64 {
+            let mut value = 0;
+            while self.read(1) == 0 {
+                value += 1;
+            }
+            value
+        }
+
+        fn byte_pos(&self) -> usize {
+            self.bit.div_ceil(8)
+        }
+    }
+
+
+    /// Decodes a number written by `utf8_number`, returning it along with its length in bytes.
+    fn decode_utf8_number(bytes: &[u8]) -> (u32, usize) {
+        if bytes[0] < 0x80 {
+            return (u32::from(bytes[0]), 1);
+        }
+        let byte_count = bytes[0].leading_ones() as usize;
+        let mut value = u32::from(bytes[0] & (0xFF >> (byte_count + 1)));
+        for &byte in &bytes[1..byte_count] {
+            assert_eq!(byte & 0xC0, 0x80);
+            value = (value << 6) | u32::from(byte & 0x3F);
+        }
+        (value, byte_count)
+    }
+
+
+    struct Decoded {
+        sample_rate: u32,
+        channels: usize,
+        bits_per_sample: u32,
+        samples: Vec<i32>,
+
+        /// The channel assignment of each frame.
+        assignments: Vec<u64>,
+
+        /// The type code of each subframe.
+        subframe_types: Vec<u64>,
+    }
+
+
+    /// Decodes a FLAC file as written by `write_flac`, checking the CRCs and frame numbers.
+    fn decode(flac: &[u8]) -> Decoded {
+        assert_eq!(&flac[..5], b"fLaC\x80");
+        let mut reader = BitCursor { data: flac, bit: 8 * 8 };
+        reader.read(16 + 16 + 24 + 24);
+        let sample_rate = reader.read(20) as u32;
+        let channels = reader.read(3) as usize + 1;
+        let bits_per_sample = reader.read(5) as u32 + 1;
+        let total_samples = reader.read(36) as usize;
+        reader.bit += 16 * 8;
+
+        let mut decoded = Decoded {
+            sample_rate, channels, bits_per_sample,
+            samples: Vec::new(), assignments: Vec::new(), subframe_types: Vec::new(),
+        };
+        let mut frame_number = 0;
+        while reader.byte_pos() < flac.len() {
+            let frame_start = reader.byte_pos();
+            assert_eq!(reader.read(14), 0b11111111111110);
+            assert_eq!(reader.read(2), 0);
+            let block_size_code = reader.read(4);
+            assert_eq!(reader.read(4), 0);
+            let assignment = reader.read(4);
+            reader.read(4);
+            let (number, number_len) = decode_utf8_number(&flac[reader.byte_pos()..]);
+            assert_eq!(number, frame_number);
+            reader.bit += 8 * number_len;
+            let block_size = match block_size_code {
+                0b1100 => BLOCK_SIZE,
+                0b0111 => reader.read(16) as usize + 1,
+                other => panic!("unexpected block size code {:#b}", other),
+            };
+            assert_eq!(u64::from(crc8(&flac[frame_start..reader.byte_pos()])), reader.read(8));
+
+            let mut channel_samples = Vec::new();
+            for channel in 0..channels {
+                let is_side = matches!((assignment, channel), (0b1000, 1) | (0b1001, 0) | (0b1010, 1));
+                let bits = bits_per_sample + u32::from(is_side);
+                assert_eq!(reader.read(1), 0);
+                let subframe_type = reader.read(6);
+                assert_eq!(reader.read(1), 0);
+                decoded.subframe_types.push(subframe_type);
+                let samples: Vec<i64> = match subframe_type {
+                    0 => vec![reader.read_signed(bits); block_size],
+                    1 => (0..block_size).map(|_| reader.read_signed(bits)).collect(),
+                    0b001000..=0b001100 => {
+                        let order = (subframe_type & 0b111) as usize;
+                        let mut samples: Vec<i64> = (0..order).map(|_| reader.read_signed(bits)).collect();
+                        assert_eq!(reader.read(2), 0);
+                        let partition_order = reader.read(4);
+                        for partition in 0..1 << partition_order {
+                            let parameter = reader.read(4) as u32;
+                            let len = (block_size >> partition_order) - if partition == 0 { order } else { 0 };
+                            for _ in 0..len {
+                                let value = (reader.read_unary() << parameter) | reader.read(parameter);
+                                let residual = (value >> 1) as i64 ^ -((value & 1) as i64);
+                                let s = |back: usize| samples[samples.len() - back];
+                                let prediction = match order {
+                                    0 => 0,
+                                    1 => s(1),
+                                    2 => 2*s(1) - s(2),
+                                    3 => 3*s(1) - 3*s(2) + s(3),
+                                    _ => 4*s(1) - 6*s(2) + 4*s(3) - s(4),
+                                };
+                                samples.push(prediction + residual);
+                            }
+                        }
+                        samples
+                    },
+                    other => panic!("unexpected subframe type {:#b}", other),
+                };
+                channel_samples.push(samples);
+            }
+            reader.bit = reader.byte_pos() * 8;
+            assert_eq!(u64::from(crc16(&flac[frame_start..reader.byte_pos()])), reader.read(16));
+
+            if channels == 2 {
+                let (first, second) = (&channel_samples[0], &channel_samples[1]);
+                let (left, right): (Vec<i64>, Vec<i64>) = match assignment {
+                    0b0001 => (first.clone(), second.clone()),
+                    0b1000 => (first.clone(), first.iter().zip(second).map(|(l, s)| l - s).collect()),
+                    0b1001 => (first.iter().zip(second).map(|(s, r)| s + r).collect(), second.clone()),
+                    0b1010 => first.iter().zip(second)
+                        .map(|(m, s)| {
+                            let mid = (m << 1) | (s & 1);
+                            ((mid + s) >> 1, (mid - s) >> 1)
+                        })
+                        .unzip(),
+                    other => panic!("unexpected channel assignment {:#b}", other),
+                };
+                channel_samples = vec![left, right];
+            }
+            for i in 0..block_size {
+                decoded.samples.extend(channel_samples.iter().map(|c| i32::try_from(c[i]).unwrap()));
+            }
+            decoded.assignments.push(assignment);
+            frame_number += 1;
+        }
+        assert_eq!(decoded.samples.len(), total_samples * channels);
+        decoded
+    }
+
+
+    fn encode(sample_rate: u32, channels: usize, bits_per_sample: u32, samples: &[i32]) -> Decoded {
+        let mut flac = Vec::new();
+        write_flac(&mut flac, sample_rate, channels, bits_per_sample, samples).unwrap();
+        let decoded = decode(&flac);
+        assert_eq!((decoded.sample_rate, decoded.channels, decoded.bits_per_sample), (sample_rate, channels, bits_per_sample));
+        assert_eq!(decoded.samples, samples);
+        decoded
+    }
+
+
+    /// Pseudo-random 16-bit samples, which no predictor helps with.
+    fn noise(len: usize) -> Vec<i32> {
+        let mut state = 0x2545_F491u32;
+        (0..len)
+            .map(|_| {
+                state ^= state << 13;
+                state ^= state >> 17;
+                state ^= state << 5;
+                i32::from(state as i16)
+            })
+            .collect()
+    }
+
+
+    #[test]
+    fn crc_check_values() {
+        assert_eq!(crc8(b"123456789"), 0xF4);
+        assert_eq!(crc16(b"123456789"), 0xFEE8);
+        assert_eq!(crc8(b""), 0);
+        assert_eq!(crc16(b""), 0);
+    }
+
+
+    #[test]
+    fn frame_numbers() {
+        for (value, bytes) in [
+            (0, &[0x00][..]),
+            (0x7F, &[0x7F]),
+            (0x80, &[0xC2, 0x80]),
+            (0x7FF, &[0xDF, 0xBF]),
+            (0x800, &[0xE0, 0xA0, 0x80]),
+            (0xFFFF, &[0xEF, 0xBF, 0xBF]),
+            (0x1_0000, &[0xF0, 0x90, 0x80, 0x80]),
+            (0x20_0000, &[0xF8, 0x88, 0x80, 0x80, 0x80]),
+            (0x7FFF_FFFF, &[0xFD, 0xBF, 0xBF, 0xBF, 0xBF, 0xBF]),
+        ] {
+            assert_eq!(utf8_number(value), bytes, "{:#x}", value);
+            assert_eq!(decode_utf8_number(bytes), (value, bytes.len()));
+        }
+    }
+
+
+    #[test]
+    fn constant_and_verbatim_subframes() {
+        let decoded = encode(22050, 1, 16, &[-1234; 100]);
+        assert_eq!(decoded.subframe_types, [0b000000]);
+
+        let decoded = encode(22050, 1, 16, &noise(100));
+        assert_eq!(decoded.subframe_types, [0b000001]);
+
+        let decoded = encode(11025, 1, 8, &[-128, 127, 0, -1, 5, -100, 64, 3]);
+        assert_eq!(decoded.subframe_types, [0b000001]);
+    }
+
+
+    #[test]
+    fn fixed_subframes_across_frames() {
+        // a triangle wave, long enough for a full and a partial block
+        let left: Vec<i32> = (0..5000)
+            .map(|i| if i % 400 < 200 { i % 400 } else { 400 - i % 400 } * 50 - 5000)
+            .collect();
+        let mono = encode(44100, 1, 16, &left);
+        assert_eq!(mono.assignments, [0b0000, 0b0000]);
+        assert!(mono.subframe_types.iter().all(|t| (0b001000..=0b001100).contains(t)), "{:?}", mono.subframe_types);
+
+        // a right channel close to the left one is best stored as a side channel
+        let stereo: Vec<i32> = left.iter()
+            .zip(noise(5000))
+            .flat_map(|(&l, n)| [l, l + n % 4])
+            .collect();
+        let decoded = encode(44100, 2, 16, &stereo);
+        assert_eq!(decoded.assignments.len(), 2);
+        assert!(decoded.assignments.iter().all(|&a| a != 0b0001), "{:?}", decoded.assignments);
+
+        // unrelated channels are stored independently
+        let stereo: Vec<i32> = left.iter()
+            .zip(noise(5000))
+            .flat_map(|(&l, n)| [l, n])
+            .collect();
+        let decoded = encode(44100, 2, 16, &stereo);
+        assert_eq!(decoded.subframe_types[1], 0b000001);
+    }
+}
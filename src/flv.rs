@@ -0,0 +1,195 @@
+use std::io::Write;
+
+use swf::{Fixed8, VideoCodec};
+
+
+/// The FLV tag type of video data.
+const FLV_TAG_VIDEO: u8 = 9;
+
+/// The FLV frame type of keyframes.
+const FLV_FRAME_KEY: u8 = 1;
+
+/// The FLV frame type of interframes.
+const FLV_FRAME_INTER: u8 = 2;
+
+
+#[derive(Clone, Debug)]
+struct VideoPacket {
+    frame_num: u16,
+    data: Vec<u8>,
+}
+
+
+/// Collects the frames of an H.263 or VP6 video stream and packages them into an FLV file.
+///
+/// The packets within VideoFrame tags are the same as within FLV files, except that VP6 packets
+/// lack the byte specifying how much to crop from the decoded image.
+#[derive(Clone, Debug)]
+pub(crate) struct FlvVideoStream {
+    codec: VideoCodec,
+    width: u16,
+    height: u16,
+    packets: Vec<VideoPacket>,
+}
+impl FlvVideoStream {
+    pub fn new(codec: VideoCodec, width: u16, height: u16) -> Self {
+        Self {
+            codec,
+            width,
+            height,
+            packets: Vec::new(),
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.packets.is_empty()
+    }
+
+    /// Adds the payload of a VideoFrame tag to the stream, returning whether it is a keyframe.
+    pub fn push_frame(&mut self, frame_num: u16, data: &[u8]) -> bool {
+        self.packets.push(VideoPacket {
+            frame_num,
+            data: data.to_vec(),
+        });
+        self.is_keyframe(data)
+    }
+
+    /// Splits a VP6 stream with alpha channel into two VP6 streams, one containing the color
+    /// planes and the other containing the alpha plane (encoded as luma).
+    ///
+    /// Returns `None` if this stream has no alpha channel.
+    pub fn split_alpha(&self) -> Option<(FlvVideoStream, FlvVideoStream)> {
+        if self.codec != VideoCodec::Vp6WithAlpha {
+            return None;
+        }
+        let mut color = FlvVideoStream::new(VideoCodec::Vp6, self.width, self.height);
+        let mut alpha = FlvVideoStream::new(VideoCodec::Vp6, self.width, self.height);
+        for packet in &self.packets {
+            if let Some((color_data, alpha_data)) = split_vp6_alpha_packet(&packet.data) {
+                color.push_frame(packet.frame_num, color_data);
+                alpha.push_frame(packet.frame_num, alpha_data);
+            }
+        }
+        Some((color, alpha))
+    }
+
+    /// Returns whether the given packet can be decoded without reference to previous frames.
+    fn is_keyframe(&self, data: &[u8]) -> bool {
+        match self.codec {
+            VideoCodec::H263 => {
+                // 17 bits start code, 5 bits version, 8 bits temporal reference, 3 bits size
+                let Some(&size_byte) = data.get(4) else { return false };
+                let picture_size = ((u32::from(data[3]) << 8 | u32::from(size_byte)) >> 7) & 0b111;
+                let size_bits = match picture_size {
+                    0 => 16,
+                    1 => 32,
+                    _ => 0,
+                };
+                let bit_offset = 33 + size_bits;
+                let Some(&type_byte) = data.get(bit_offset / 8) else { return false };
+                let next_byte = data.get(bit_offset / 8 + 1).copied().unwrap_or(0);
+                let picture_type = ((u16::from(type_byte) << 8 | u16::from(next_byte)) >> (14 - bit_offset % 8)) & 0b11;
+                picture_type == 0
+            },
+            VideoCodec::Vp6 => {
+                // the topmost bit is zero for intra frames
+                data.first().map(|b| b & 0x80 == 0).unwrap_or(false)
+            },
+            VideoCodec::Vp6WithAlpha => {
+                split_vp6_alpha_packet(data)
+                    .and_then(|(color, _)| color.first())
+                    .map(|b| b & 0x80 == 0)
+                    .unwrap_or(false)
+            },
+            _ => false,
+        }
+    }
+
+    /// Returns the byte specifying how many pixels to crop from the decoded VP6 image.
+    ///
+    /// VP6 encodes whole macroblocks of 16x16 pixels; the dimensions in macroblocks are stored
+    /// in the header of intra frames.
+    fn vp6_adjustment(&self) -> u8 {
+        for packet in &self.packets {
+            let data = if self.codec == VideoCodec::Vp6WithAlpha {
+                match split_vp6_alpha_packet(&packet.data) {
+                    Some((color, _)) => color,
+                    None => continue,
+                }
+            } else {
+                &packet.data[..]
+            };
+            if data.len() < 2 || data[0] & 0x80 != 0 {
+                continue;
+            }
+            let has_marker = data[0] & 0x01 != 0;
+            let version2 = (data[1] >> 1) & 0b11;
+            let dimensions_offset = if has_marker || version2 == 0 { 4 } else { 2 };
+            let (Some(&rows), Some(&columns)) = (data.get(dimensions_offset), data.get(dimensions_offset + 1)) else {
+                continue;
+            };
+            let horizontal = (u16::from(columns) * 16).saturating_sub(self.width).min(15) as u8;
+            let vertical = (u16::from(rows) * 16).saturating_sub(self.height).min(15) as u8;
+            return horizontal << 4 | vertical;
+        }
+        0
+    }
+
+    /// Writes the stream as an FLV file, deriving the timestamps from the given frame rate.
+    pub fn write_flv<W: Write>(&self, frame_rate: Fixed8, mut writer: W) -> Result<(), std::io::Error> {
+        writer.write_all(b"FLV")?;
+        writer.write_all(&[
+            0x01, // version
+            0x01, // flags: has video
+        ])?;
+        writer.write_all(&9u32.to_be_bytes())?; // header length
+        writer.write_all(&0u32.to_be_bytes())?; // size of the previous tag
+
+        let is_vp6 = matches!(self.codec, VideoCodec::Vp6 | VideoCodec::Vp6WithAlpha);
+        let adjustment = if is_vp6 { self.vp6_adjustment() } else { 0 };
+        let frame_rate = frame_rate.to_f64();
+        let first_frame = self.packets.first().map(|p| p.frame_num).unwrap_or(0);
+
+        let mut tag_data = Vec::new();
+        for packet in &self.packets {
+            let frame_type = if self.is_keyframe(&packet.data) { FLV_FRAME_KEY } else { FLV_FRAME_INTER };
+            tag_data.clear();
+            tag_data.push(frame_type << 4 | (self.codec as u8));
+            if is_vp6 {
+                tag_data.push(adjustment);
+            }
+            tag_data.extend(&packet.data);
+
+            let frame_index = f64::from(packet.frame_num.wrapping_sub(first_frame));
+            let timestamp_ms = if frame_rate > 0.0 { (frame_index * 1000.0 / frame_rate) as u32 } else { 0 };
+            let data_size = u32::try_from(tag_data.len()).unwrap();
+            let data_size_bytes = data_size.to_be_bytes();
+            let timestamp_bytes = timestamp_ms.to_be_bytes();
+
+            writer.write_all(&[
+                FLV_TAG_VIDEO,
+                data_size_bytes[1], data_size_bytes[2], data_size_bytes[3],
+                // lower 24 bits of the timestamp, then the upper 8 bits
+                timestamp_bytes[1], timestamp_bytes[2], timestamp_bytes[3], timestamp_bytes[0],
+                0x00, 0x00, 0x00, // stream ID
+            ])?;
+            writer.write_all(&tag_data)?;
+            writer.write_all(&(11 + data_size).to_be_bytes())?;
+        }
+        Ok(())
+    }
+}
+
+
+/// Splits a VP6 packet with alpha channel into its color and alpha data.
+fn split_vp6_alpha_packet(data: &[u8]) -> Option<(&[u8], &[u8])> {
+    if data.len() < 3 {
+        return None;
+    }
+    let offset_to_alpha = usize::from(data[0]) << 16 | usize::from(data[1]) << 8 | usize::from(data[2]);
+    let rest = &data[3..];
+    if rest.len() < offset_to_alpha {
+        return None;
+    }
+    Some(rest.split_at(offset_to_alpha))
+}
@@ -0,0 +1,540 @@
+use std::collections::{BTreeSet, HashMap};
+use std::fmt::Write;
+use std::rc::Rc;
+
+use encoding_rs::Encoding;
+use swf::{
+    CharacterId, Font, FontAlignZone, FontFlag, FontInfo, FontInfoFlag, FontThickness, FontV1, Glyph, Language, Rectangle,
+    ShapeRecord, SwfStr, Tag,
+};
+use sxd_document::Package;
+
+use crate::encoding::decode_string;
+use crate::json::JsonValue;
+use crate::truetype::TrueTypeFont;
+
+
+/// The size of the EM square of DefineFont2 fonts, in font units.
+const EM_SQUARE_SIZE_V2: i64 = 1024;
+
+/// The size of the EM square of DefineFont3 fonts, in font units.
+///
+/// DefineFont3 glyphs are stored at twenty times the resolution of DefineFont2 glyphs.
+const EM_SQUARE_SIZE_V3: i64 = 20 * EM_SQUARE_SIZE_V2;
+
+
+fn rectangle_to_json(rect: &Rectangle) -> JsonValue {
+    let mut obj = JsonValue::new_object();
+    obj.insert("x_min", rect.x_min.get());
+    obj.insert("y_min", rect.y_min.get());
+    obj.insert("x_max", rect.x_max.get());
+    obj.insert("y_max", rect.y_max.get());
+    obj
+}
+
+
+/// Decodes an IEEE 754 half-precision floating-point number.
+fn f16_to_f64(bits: u16) -> f64 {
+    let sign = if bits & 0x8000 != 0 { -1.0 } else { 1.0 };
+    let exponent = i32::from((bits >> 10) & 0x1F);
+    let mantissa = f64::from(bits & 0x3FF);
+    let magnitude = match exponent {
+        0 => mantissa * 2f64.powi(-24), // subnormal
+        0x1F => if mantissa == 0.0 { f64::INFINITY } else { f64::NAN },
+        _ => (1.0 + mantissa / 1024.0) * 2f64.powi(exponent - 15),
+    };
+    sign * magnitude
+}
+
+
+/// Collects the alignment zones of a font (from DefineFontAlignZones) into a JSON object.
+///
+/// There is one zone per glyph, in glyph order. The coordinates are given in EM squares.
+pub(crate) fn align_zones_to_json(thickness: FontThickness, zones: &[FontAlignZone]) -> JsonValue {
+    let mut zones_json = JsonValue::new_array();
+    for zone in zones {
+        let mut obj = JsonValue::new_object();
+        // the swf crate reads the half-precision values as integers
+        obj.insert("left", f16_to_f64(zone.left as u16));
+        obj.insert("width", f16_to_f64(zone.width as u16));
+        obj.insert("bottom", f16_to_f64(zone.bottom as u16));
+        obj.insert("height", f16_to_f64(zone.height as u16));
+        zones_json.push(obj);
+    }
+
+    let mut obj = JsonValue::new_object();
+    obj.insert("thickness", format!("{:?}", thickness).to_lowercase());
+    obj.insert("zones", zones_json);
+    obj
+}
+
+
+/// Returns the full name of the font with the given ID as specified by a DefineFontName tag.
+pub(crate) fn find_font_name(tags: &[Tag], font_id: CharacterId, encoding: &'static Encoding) -> Option<String> {
+    tags.iter()
+        .find_map(|tag| match tag {
+            Tag::DefineFontName { id, name, .. } if *id == font_id => {
+                Some(decode_string(name, encoding))
+            },
+            _ => None,
+        })
+}
+
+/// Returns the font information given by a DefineFontInfo or DefineFontInfo2 tag for the font with
+/// the given ID.
+pub(crate) fn find_font_info<'a>(tags: &'a [Tag<'a>], font_id: CharacterId) -> Option<&'a FontInfo<'a>> {
+    tags.iter()
+        .find_map(|tag| match tag {
+            Tag::DefineFontInfo(fi) if fi.id == font_id => Some(fi.as_ref()),
+            _ => None,
+        })
+}
+
+/// Maps the code table of a DefineFontInfo tag to Unicode.
+///
+/// Movies older than version 6 give the codes in the encoding of the font: Shift-JIS if it is
+/// flagged as such, the encoding of the movie's strings otherwise. Codes that cannot be mapped are
+/// kept as they are.
+pub(crate) fn font_info_unicode_codes(info: &FontInfo, swf_version: u8, encoding: &'static Encoding) -> Vec<u16> {
+    if swf_version >= 6 {
+        return info.code_table.clone();
+    }
+    let encoding = if info.flags.contains(FontInfoFlag::IS_SHIFT_JIS) {
+        encoding_rs::SHIFT_JIS
+    } else {
+        encoding
+    };
+    info.code_table.iter()
+        .map(|&code| {
+            if code < 0x80 {
+                return code;
+            }
+            let [high, low] = code.to_be_bytes();
+            let bytes = if high == 0 { &[low][..] } else { &[high, low][..] };
+            let (decoded, had_errors) = encoding.decode_without_bom_handling(bytes);
+            let mut chars = decoded.chars();
+            match (chars.next(), chars.next()) {
+                (Some(c), None) if !had_errors => u16::try_from(u32::from(c)).unwrap_or(code),
+                _ => code,
+            }
+        })
+        .collect()
+}
+
+/// Combines the glyph outlines of a DefineFont font with the name, style and (Unicode) character
+/// codes from its DefineFontInfo tag into a font that can be output like a DefineFont2 font.
+///
+/// Without font information, the glyphs are left without character codes.
+pub(crate) fn font_v1_to_font<'a>(font: &FontV1, info: Option<&FontInfo<'a>>, codes: &[u16]) -> Font<'a> {
+    let mut flags = FontFlag::empty();
+    if let Some(info) = info {
+        flags.set(FontFlag::IS_BOLD, info.flags.contains(FontInfoFlag::IS_BOLD));
+        flags.set(FontFlag::IS_ITALIC, info.flags.contains(FontInfoFlag::IS_ITALIC));
+        flags.set(FontFlag::IS_ANSI, info.flags.contains(FontInfoFlag::IS_ANSI));
+        flags.set(FontFlag::IS_SHIFT_JIS, info.flags.contains(FontInfoFlag::IS_SHIFT_JIS));
+        flags.set(FontFlag::IS_SMALL_TEXT, info.flags.contains(FontInfoFlag::IS_SMALL_TEXT));
+    }
+    let glyphs = font.glyphs.iter()
+        .enumerate()
+        .map(|(i, shape_records)| Glyph {
+            shape_records: shape_records.clone(),
+            code: codes.get(i).copied().unwrap_or(0),
+            advance: 0,
+            bounds: None,
+        })
+        .collect();
+    Font {
+        version: 1,
+        id: font.id,
+        name: info.map_or(SwfStr::from_bytes(b""), |info| info.name),
+        language: info.map_or(Language::Unknown, |info| info.language),
+        layout: None,
+        glyphs,
+        flags,
+    }
+}
+
+/// Returns the size of the font's EM square in font units.
+pub(crate) fn em_square_size(font: &Font) -> i64 {
+    if font.version >= 3 {
+        EM_SQUARE_SIZE_V3
+    } else {
+        EM_SQUARE_SIZE_V2
+    }
+}
+
+/// Collects the metrics of a DefineFont2/DefineFont3 font (or one converted by `font_v1_to_font`)
+/// into a JSON object.
+///
+/// All lengths are given in font units; see `em_square_size` for their scale.
+pub(crate) fn font_to_json(font: &Font, encoding: &'static Encoding) -> JsonValue {
+    let mut obj = JsonValue::new_object();
+    obj.insert("id", font.id);
+    obj.insert("version", font.version);
+    obj.insert("name", decode_string(font.name, encoding));
+    obj.insert("language", format!("{:?}", font.language));
+    obj.insert("is_bold", font.flags.contains(FontFlag::IS_BOLD));
+    obj.insert("is_italic", font.flags.contains(FontFlag::IS_ITALIC));
+    obj.insert("is_small_text", font.flags.contains(FontFlag::IS_SMALL_TEXT));
+    obj.insert("is_shift_jis", font.flags.contains(FontFlag::IS_SHIFT_JIS));
+    obj.insert("is_ansi", font.flags.contains(FontFlag::IS_ANSI));
+    obj.insert("em_square_size", em_square_size(font));
+
+    let code_table: Vec<u16> = font.glyphs.iter()
+        .map(|g| g.code)
+        .collect();
+    obj.insert("code_table", code_table);
+
+    if let Some(layout) = &font.layout {
+        obj.insert("ascent", layout.ascent);
+        obj.insert("descent", layout.descent);
+        obj.insert("leading", layout.leading);
+
+        let advance_table: Vec<i16> = font.glyphs.iter()
+            .map(|g| g.advance)
+            .collect();
+        obj.insert("advance_table", advance_table);
+
+        let mut bounds_table = JsonValue::new_array();
+        for glyph in &font.glyphs {
+            bounds_table.push(glyph.bounds.as_ref().map(rectangle_to_json));
+        }
+        obj.insert("bounds_table", bounds_table);
+
+        let mut kerning = JsonValue::new_array();
+        for record in &layout.kerning {
+            let mut pair = JsonValue::new_object();
+            pair.insert("left_code", record.left_code);
+            pair.insert("right_code", record.right_code);
+            pair.insert("adjustment", record.adjustment.get());
+            kerning.push(pair);
+        }
+        obj.insert("kerning", kerning);
+    } else {
+        // fonts without layout information are only usable for device text and edit fields
+        obj.insert("ascent", JsonValue::Null);
+        obj.insert("descent", JsonValue::Null);
+        obj.insert("leading", JsonValue::Null);
+        obj.insert("advance_table", JsonValue::Null);
+        obj.insert("bounds_table", JsonValue::Null);
+        obj.insert("kerning", JsonValue::new_array());
+    }
+
+    obj
+}
+
+
+/// Tracks which glyphs of which fonts are referenced by texts in the movie.
+#[derive(Clone, Debug, Default)]
+pub(crate) struct GlyphUsage {
+    /// Glyph indices referenced directly, e.g. by DefineText records.
+    glyph_indices: HashMap<CharacterId, BTreeSet<usize>>,
+
+    /// Character codes referenced indirectly, e.g. by the initial text of DefineEditText.
+    codes: HashMap<CharacterId, BTreeSet<u16>>,
+}
+impl GlyphUsage {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Collects glyph usage from the given tags, descending into sprites.
+    ///
+    /// Only statically known text can be taken into account; text assigned to edit fields at
+    /// runtime is invisible to this scan.
+    pub fn scan(&mut self, tags: &[Tag], encoding: &'static Encoding) {
+        for tag in tags {
+            match tag {
+                Tag::DefineText(text) => {
+                    let mut current_font = None;
+                    for record in &text.records {
+                        if record.font_id.is_some() {
+                            current_font = record.font_id;
+                        }
+                        let Some(font_id) = current_font else { continue };
+                        let indices = self.glyph_indices.entry(font_id).or_default();
+                        for glyph in &record.glyphs {
+                            indices.insert(glyph.index as usize);
+                        }
+                    }
+                },
+                Tag::DefineEditText(et) => {
+                    let (Some(font_id), Some(initial_text)) = (et.font_id, et.initial_text) else { continue };
+                    let text = decode_string(initial_text, encoding);
+                    let codes = self.codes.entry(font_id).or_default();
+                    let mut in_markup = false;
+                    for c in text.chars() {
+                        if et.is_html {
+                            // skip over tags; entities are kept as-is, which merely over-approximates
+                            if c == '<' {
+                                in_markup = true;
+                            } else if c == '>' {
+                                in_markup = false;
+                                continue;
+                            }
+                            if in_markup {
+                                continue;
+                            }
+                        }
+                        let mut buf = [0u16; 2];
+                        codes.extend(c.encode_utf16(&mut buf).iter());
+                    }
+                },
+                Tag::DefineSprite(sprite) => {
+                    self.scan(&sprite.tags, encoding);
+                },
+                _ => {},
+            }
+        }
+    }
+
+    /// Returns whether the glyph at the given index of the given font is referenced.
+    pub fn is_used(&self, font: &Font, glyph_index: usize) -> bool {
+        let used_by_index = self.glyph_indices.get(&font.id)
+            .map(|indices| indices.contains(&glyph_index))
+            .unwrap_or(false);
+        let used_by_code = self.codes.get(&font.id)
+            .and_then(|codes| font.glyphs.get(glyph_index).map(|g| codes.contains(&g.code)))
+            .unwrap_or(false);
+        used_by_index || used_by_code
+    }
+}
+
+
+/// Converts glyph outlines to SVG path data.
+///
+/// SWF glyphs are defined with the y axis pointing down while SVG fonts expect it to point up,
+/// so the outline is mirrored vertically unless it is going to be drawn as a path.
+fn glyph_to_path_data(records: &[ShapeRecord], mirror: bool) -> String {
+    let y_sign = if mirror { -1 } else { 1 };
+    let mut path_data = String::new();
+    let (mut x, mut y) = (0i32, 0i32);
+    for record in records {
+        if !path_data.is_empty() {
+            path_data.push(' ');
+        }
+        match record {
+            ShapeRecord::StyleChange(sc) => {
+                if let Some((move_x, move_y)) = sc.move_to {
+                    x = move_x.get();
+                    y = move_y.get();
+                }
+                write!(path_data, "M {} {}", x, y_sign * y).unwrap();
+            },
+            ShapeRecord::StraightEdge { delta_x, delta_y } => {
+                x += delta_x.get();
+                y += delta_y.get();
+                write!(path_data, "L {} {}", x, y_sign * y).unwrap();
+            },
+            ShapeRecord::CurvedEdge { control_delta_x, control_delta_y, anchor_delta_x, anchor_delta_y } => {
+                let cx = x + control_delta_x.get();
+                let cy = y + control_delta_y.get();
+                x = cx + anchor_delta_x.get();
+                y = cy + anchor_delta_y.get();
+                write!(path_data, "Q {} {} {} {}", cx, y_sign * cy, x, y_sign * y).unwrap();
+            },
+        }
+    }
+    path_data
+}
+
+/// Returns whether any glyph of the font has an outline; fonts without are device fonts, whose
+/// glyphs are supplied by the system.
+pub(crate) fn has_outlines(font: &Font) -> bool {
+    font.glyphs.iter()
+        .flat_map(|glyph| &glyph.shape_records)
+        .any(|record| !matches!(record, ShapeRecord::StyleChange(_)))
+}
+
+
+/// The outlines and metrics of the glyphs of a font, as needed to draw texts: those embedded into the
+/// movie or, for a device font, those of a TrueType font standing in for it.
+#[derive(Clone, Debug)]
+pub(crate) struct FontOutlines {
+    /// The size of the EM square in font units.
+    pub em_size: i64,
+
+    /// The height of the font above and below the baseline in font units, if known.
+    pub ascent: Option<i32>,
+    pub descent: Option<i32>,
+
+    /// The SVG path data of each glyph, in font units with the y axis pointing down.
+    pub glyph_paths: Vec<String>,
+
+    codes: Vec<u16>,
+
+    /// The advance of each glyph in font units; only known for fonts with layout information.
+    advances: Option<Vec<i32>>,
+
+    /// The adjustments of the advances between pairs of character codes, in font units.
+    kerning: HashMap<(u16, u16), i32>,
+
+    /// The font from which the glyphs of a device font are taken.
+    device_font: Option<Rc<TrueTypeFont>>,
+}
+impl FontOutlines {
+    pub fn new(font: &Font) -> Self {
+        let kerning = font.layout.iter()
+            .flat_map(|layout| &layout.kerning)
+            .map(|record| ((record.left_code, record.right_code), record.adjustment.get()))
+            .collect();
+        Self {
+            em_size: em_square_size(font),
+            ascent: font.layout.as_ref().map(|layout| i32::from(layout.ascent)),
+            descent: font.layout.as_ref().map(|layout| i32::from(layout.descent)),
+            glyph_paths: font.glyphs.iter()
+                .map(|glyph| glyph_to_path_data(&glyph.shape_records, false))
+                .collect(),
+            codes: font.glyphs.iter().map(|glyph| glyph.code).collect(),
+            advances: font.layout.as_ref()
+                .map(|_| font.glyphs.iter().map(|glyph| i32::from(glyph.advance)).collect()),
+            kerning,
+            device_font: None,
+        }
+    }
+
+    /// Takes the glyphs of a device font from the given TrueType font, keeping the glyph indices of
+    /// the movie (those of its code table).
+    pub fn from_device_font(font: &Font, device_font: Rc<TrueTypeFont>) -> Self {
+        let mut outlines = Self {
+            em_size: i64::from(device_font.units_per_em),
+            ascent: Some(i32::from(device_font.ascent)),
+            descent: Some(-i32::from(device_font.descent)),
+            glyph_paths: Vec::with_capacity(font.glyphs.len()),
+            codes: Vec::with_capacity(font.glyphs.len()),
+            advances: Some(Vec::with_capacity(font.glyphs.len())),
+            kerning: HashMap::new(),
+            device_font: Some(device_font),
+        };
+        for glyph in &font.glyphs {
+            outlines.push_device_glyph(glyph.code);
+        }
+        outlines
+    }
+
+    fn push_device_glyph(&mut self, code: u16) {
+        let Some(device_font) = &self.device_font else { return };
+        let device_glyph = char::from_u32(code.into()).and_then(|c| device_font.glyph_index(c));
+        let path = device_glyph.and_then(|g| device_font.glyph_path(g)).unwrap_or_default();
+        let advance = device_glyph.and_then(|g| device_font.advance(g)).unwrap_or(0);
+        self.glyph_paths.push(path);
+        self.codes.push(code);
+        if let Some(advances) = &mut self.advances {
+            advances.push(i32::from(advance));
+        }
+    }
+
+    /// Returns the index of the glyph for the given character, taking it from the TrueType font
+    /// standing in for a device font if the movie does not list it.
+    pub fn glyph_for_char(&mut self, c: char) -> Option<usize> {
+        let code = u16::try_from(u32::from(c)).ok()?;
+        if let Some(index) = self.codes.iter().position(|&glyph_code| glyph_code == code) {
+            return Some(index);
+        }
+        self.device_font.as_ref()?;
+        self.push_device_glyph(code);
+        Some(self.codes.len() - 1)
+    }
+
+    /// Returns the advance of the glyph with the given index followed by the glyph with the index
+    /// `next`, in font units, adjusted by the kerning between the two. Returns `None` if the font has
+    /// no layout information.
+    pub fn advance(&self, index: usize, next: Option<usize>) -> Option<i32> {
+        let advance = *self.advances.as_ref()?.get(index)?;
+        let adjustment = next
+            .and_then(|next| Some((*self.codes.get(index)?, *self.codes.get(next)?)))
+            .and_then(|pair| self.kerning.get(&pair))
+            .copied()
+            .unwrap_or(0);
+        Some(advance + adjustment)
+    }
+}
+
+
+/// Converts a DefineFont2/DefineFont3 font (or one converted by `font_v1_to_font`) to an SVG font.
+///
+/// Only the glyphs for which `include_glyph` returns `true` are output.
+pub(crate) fn font_to_svg<F: Fn(usize) -> bool>(
+    font: &Font,
+    encoding: &'static Encoding,
+    include_glyph: F,
+) -> String {
+    let em_size = em_square_size(font);
+
+    let svg_package = Package::new();
+    let svg_document = svg_package.as_document();
+
+    let svg = svg_document.create_element("svg");
+    svg_document.root().append_child(svg);
+    svg.set_default_namespace_uri(Some("http://www.w3.org/2000/svg"));
+
+    let defs = svg_document.create_element("defs");
+    svg.append_child(defs);
+
+    let font_elem = svg_document.create_element("font");
+    defs.append_child(font_elem);
+    font_elem.set_attribute_value("id", &format!("font{}", font.id));
+    font_elem.set_attribute_value("horiz-adv-x", &em_size.to_string());
+
+    let font_face = svg_document.create_element("font-face");
+    font_elem.append_child(font_face);
+    font_face.set_attribute_value("font-family", &decode_string(font.name, encoding));
+    font_face.set_attribute_value("units-per-em", &em_size.to_string());
+    if font.flags.contains(FontFlag::IS_BOLD) {
+        font_face.set_attribute_value("font-weight", "bold");
+    }
+    if font.flags.contains(FontFlag::IS_ITALIC) {
+        font_face.set_attribute_value("font-style", "italic");
+    }
+    if let Some(layout) = &font.layout {
+        font_face.set_attribute_value("ascent", &layout.ascent.to_string());
+        font_face.set_attribute_value("descent", &(-i32::from(layout.descent)).to_string());
+    }
+
+    let missing_glyph = svg_document.create_element("missing-glyph");
+    font_elem.append_child(missing_glyph);
+
+    let mut included_codes = BTreeSet::new();
+    for (i, glyph) in font.glyphs.iter().enumerate() {
+        if !include_glyph(i) {
+            continue;
+        }
+
+        let glyph_elem = svg_document.create_element("glyph");
+        font_elem.append_child(glyph_elem);
+        glyph_elem.set_attribute_value("glyph-name", &format!("g{}", i));
+        // code 0 marks glyphs of DefineFont fonts without a code table
+        if let Some(c) = char::from_u32(glyph.code.into()).filter(|&c| c != '\0') {
+            glyph_elem.set_attribute_value("unicode", &c.to_string());
+            included_codes.insert(glyph.code);
+        }
+        if font.layout.is_some() {
+            glyph_elem.set_attribute_value("horiz-adv-x", &glyph.advance.to_string());
+        }
+        glyph_elem.set_attribute_value("d", &glyph_to_path_data(&glyph.shape_records, true));
+    }
+
+    if let Some(layout) = &font.layout {
+        for record in &layout.kerning {
+            if !included_codes.contains(&record.left_code) || !included_codes.contains(&record.right_code) {
+                continue;
+            }
+            let (Some(left), Some(right)) = (char::from_u32(record.left_code.into()), char::from_u32(record.right_code.into())) else {
+                continue;
+            };
+
+            let hkern = svg_document.create_element("hkern");
+            font_elem.append_child(hkern);
+            hkern.set_attribute_value("u1", &left.to_string());
+            hkern.set_attribute_value("u2", &right.to_string());
+            // SVG kerning values are subtracted from the advance
+            hkern.set_attribute_value("k", &(-record.adjustment.get()).to_string());
+        }
+    }
+
+    let mut buf = Vec::new();
+    sxd_document::writer::format_document(&svg_document, &mut buf)
+        .expect("failed to write SVG");
+    String::from_utf8(buf)
+        .expect("written SVG is not UTF-8?!")
+}
@@ -0,0 +1,173 @@
+use std::collections::BTreeMap;
+use std::fs::File;
+use std::io::{self, Read, Write};
+
+use swf::CharacterId;
+
+use crate::html::{escape_html, escape_url_path};
+
+
+/// An extracted asset as shown in the gallery.
+#[derive(Clone, Debug)]
+pub(crate) struct GalleryAsset {
+    pub id: CharacterId,
+    pub kind: String,
+
+    /// The path of the character through the sprites defining it.
+    pub path: String,
+
+    /// The name under which the character is exported or the class bound to it, if any.
+    pub name: Option<String>,
+
+    pub files: Vec<String>,
+
+    /// The thumbnail previewing the first image among the files, if one has been written.
+    pub thumbnail: Option<String>,
+}
+
+
+/// How a file is shown in the gallery.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+enum Preview {
+    /// A PNG, JPEG, GIF or SVG image.
+    Image,
+
+    /// A sound a browser can play.
+    Audio,
+
+    /// An MP4 video.
+    Video,
+
+    /// Any other file, which is only linked to.
+    Link,
+}
+impl Preview {
+    /// Determines the preview of a file from its first bytes, as its extension may have been
+    /// changed by `--ext-map`.
+    fn of_file(file_name: &str) -> Self {
+        let mut start = Vec::new();
+        let read_result = File::open(file_name)
+            .and_then(|f| f.take(512).read_to_end(&mut start));
+        if read_result.is_err() {
+            return Self::Link;
+        }
+
+        let is_svg = (start.starts_with(b"<?xml") || start.starts_with(b"<svg"))
+            && start.windows(4).any(|w| w == b"<svg");
+        let is_mp3 = start.starts_with(b"ID3")
+            || (start.len() >= 2 && start[0] == 0xFF && start[1] & 0xE0 == 0xE0);
+        if start.starts_with(b"\x89PNG") || start.starts_with(b"GIF8") || start.starts_with(b"\xFF\xD8") || is_svg {
+            Self::Image
+        } else if (start.starts_with(b"RIFF") && start.get(8..12) == Some(b"WAVE")) || start.starts_with(b"fLaC")
+                || start.starts_with(b"OggS") || is_mp3 {
+            Self::Audio
+        } else if start.get(4..8) == Some(b"ftyp") {
+            Self::Video
+        } else {
+            Self::Link
+        }
+    }
+}
+
+
+/// Writes a page linking to all extracted assets grouped by type, showing images (or their
+/// thumbnails), sound players and video players inline, followed by the stream sounds.
+///
+/// SVG documents are shown as images instead of being inlined, as the IDs within them clash.
+pub(crate) fn write_gallery<W: Write>(movie_name: &str, assets: &[GalleryAsset], streams: &[String], mut write: W) -> io::Result<()> {
+    let mut kind_to_assets: BTreeMap<&str, Vec<&GalleryAsset>> = BTreeMap::new();
+    for asset in assets {
+        if !asset.files.is_empty() {
+            kind_to_assets.entry(asset.kind.as_str()).or_default().push(asset);
+        }
+    }
+
+    writeln!(write, "<!DOCTYPE html>")?;
+    writeln!(write, "<html>")?;
+    writeln!(write, "<head>")?;
+    writeln!(write, "<meta charset=\"utf-8\">")?;
+    writeln!(write, "<title>{}</title>", escape_html(movie_name))?;
+    writeln!(write, "<style>")?;
+    writeln!(write, "body {{ font-family: sans-serif; }}")?;
+    writeln!(write, "section.asset {{ display: inline-block; margin: 8px; padding: 8px; border: 1px solid #ccc; vertical-align: top; }}")?;
+    writeln!(write, "section.asset img, section.asset video {{ max-width: 320px; max-height: 320px; }}")?;
+    writeln!(write, "section.asset ul {{ padding-left: 16px; font-size: small; }}")?;
+    writeln!(write, "</style>")?;
+    writeln!(write, "</head>")?;
+    writeln!(write, "<body>")?;
+    writeln!(write, "<h1>{}</h1>", escape_html(movie_name))?;
+    writeln!(write, "<nav>")?;
+    for (kind, kind_assets) in &kind_to_assets {
+        writeln!(write, "<a href=\"#{}\">{} ({})</a>", escape_html(kind), escape_html(kind), kind_assets.len())?;
+    }
+    if !streams.is_empty() {
+        writeln!(write, "<a href=\"#streams\">streams ({})</a>", streams.len())?;
+    }
+    writeln!(write, "</nav>")?;
+
+    for (kind, kind_assets) in &kind_to_assets {
+        writeln!(write, "<h2 id=\"{}\">{}</h2>", escape_html(kind), escape_html(kind))?;
+        for asset in kind_assets {
+            writeln!(write, "<section class=\"asset\">")?;
+            write!(write, "<h3>{}", escape_html(&asset.path))?;
+            if let Some(name) = &asset.name {
+                write!(write, " <small>{}</small>", escape_html(name))?;
+            }
+            writeln!(write, "</h3>")?;
+            write_previews(&mut write, asset)?;
+            writeln!(write, "<ul>")?;
+            for file in &asset.files {
+                writeln!(write, "<li><a href=\"{}\">{}</a></li>", escape_url_path(file), escape_html(file))?;
+            }
+            writeln!(write, "</ul>")?;
+            writeln!(write, "</section>")?;
+        }
+    }
+
+    if !streams.is_empty() {
+        writeln!(write, "<h2 id=\"streams\">streams</h2>")?;
+        for stream in streams {
+            writeln!(write, "<section class=\"asset\">")?;
+            writeln!(write, "<h3>{}</h3>", escape_html(stream))?;
+            if Preview::of_file(stream) == Preview::Audio {
+                writeln!(write, "<audio controls preload=\"none\" src=\"{}\"></audio>", escape_url_path(stream))?;
+            }
+            writeln!(write, "<ul><li><a href=\"{}\">{}</a></li></ul>", escape_url_path(stream), escape_html(stream))?;
+            writeln!(write, "</section>")?;
+        }
+    }
+    writeln!(write, "</body>")?;
+    writeln!(write, "</html>")?;
+    Ok(())
+}
+
+
+/// Writes the inline previews of the files of an asset: the first image (by way of its thumbnail if
+/// there is one) and every sound and video.
+fn write_previews<W: Write>(write: &mut W, asset: &GalleryAsset) -> io::Result<()> {
+    // SVG fonts show nothing as images
+    let mut has_image = asset.kind == "font";
+    for file in &asset.files {
+        let url = escape_url_path(file);
+        match Preview::of_file(file) {
+            Preview::Image if !has_image => {
+                has_image = true;
+                let src = asset.thumbnail.as_deref()
+                    .map(escape_url_path)
+                    .unwrap_or_else(|| url.clone());
+                writeln!(
+                    write, "<a href=\"{}\"><img src=\"{}\" alt=\"{} {}\" loading=\"lazy\"></a>",
+                    url, src, escape_html(&asset.kind), asset.id,
+                )?;
+            },
+            Preview::Audio => {
+                writeln!(write, "<audio controls preload=\"none\" src=\"{}\"></audio>", url)?;
+            },
+            Preview::Video => {
+                writeln!(write, "<video controls preload=\"metadata\" src=\"{}\"></video>", url)?;
+            },
+            Preview::Image|Preview::Link => {},
+        }
+    }
+    Ok(())
+}
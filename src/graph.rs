@@ -0,0 +1,332 @@
+use std::collections::{BTreeMap, BTreeSet, HashSet};
+use std::io::Write;
+
+use clap::ValueEnum;
+use encoding_rs::Encoding;
+use swf::{CharacterId, FillStyle, LineStyle, PlaceObjectAction, ShapeRecord, ShapeStyles, Tag};
+
+use crate::encoding::decode_string;
+use crate::json::JsonValue;
+
+
+#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq, ValueEnum)]
+pub(crate) enum GraphFormat {
+    Dot,
+    Json,
+}
+
+
+/// The characters of a movie and the references between them.
+///
+/// References from the main timeline have `None` as their source.
+#[derive(Clone, Debug, Default)]
+pub(crate) struct ReferenceGraph {
+    /// The kind of each defined character.
+    kinds: BTreeMap<CharacterId, &'static str>,
+
+    /// The names under which characters are exported.
+    export_names: BTreeMap<CharacterId, String>,
+
+    /// References as (source, target, relation) triples.
+    references: BTreeSet<(Option<CharacterId>, CharacterId, &'static str)>,
+
+    /// The timelines on which each character is placed, with the frames at which it is placed.
+    placements: BTreeMap<CharacterId, Vec<(Option<CharacterId>, u32)>>,
+
+    /// The sprites within which characters are defined; characters defined on the main timeline are
+    /// not listed.
+    definers: BTreeMap<CharacterId, CharacterId>,
+
+    /// The most sprites followed through each other when looking for the timeline position of a
+    /// character.
+    max_depth: usize,
+}
+impl ReferenceGraph {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Builds the reference graph of a movie from its top-level tags. Characters shown only through
+    /// more than `max_depth` sprites placing each other are considered not to be shown at all.
+    pub fn build(tags: &[Tag], encoding: &'static Encoding, max_depth: usize) -> Self {
+        let mut graph = Self::new();
+        graph.max_depth = max_depth;
+        graph.scan(None, tags, encoding);
+        graph
+    }
+
+    fn scan(&mut self, timeline: Option<CharacterId>, tags: &[Tag], encoding: &'static Encoding) {
+        let mut frame: u32 = 1;
+        for tag in tags {
+            match tag {
+                Tag::DefineBinaryData(bd) => self.define(timeline, bd.id, "binary"),
+                Tag::DefineBits { id, .. }|Tag::DefineBitsJpeg2 { id, .. } => self.define(timeline, *id, "bitmap"),
+                Tag::DefineBitsJpeg3(j3) => self.define(timeline, j3.id, "bitmap"),
+                Tag::DefineBitsLossless(bmap) => self.define(timeline, bmap.id, "bitmap"),
+                Tag::DefineButton(btn)|Tag::DefineButton2(btn) => {
+                    self.define(timeline, btn.id, "button");
+                    for record in &btn.records {
+                        self.reference(Some(btn.id), record.id, "displays");
+                    }
+                },
+                Tag::DefineButtonSound(bs) => {
+                    let sounds = [
+                        &bs.over_to_up_sound, &bs.up_to_over_sound, &bs.over_to_down_sound, &bs.down_to_over_sound,
+                    ];
+                    for (sound_id, _) in sounds.into_iter().flatten() {
+                        if *sound_id != 0 {
+                            self.reference(Some(bs.id), *sound_id, "plays");
+                        }
+                    }
+                },
+                Tag::DefineEditText(et) => {
+                    self.define(timeline, et.id, "edit_text");
+                    if let Some(font_id) = et.font_id {
+                        self.reference(Some(et.id), font_id, "uses_font");
+                    }
+                },
+                Tag::DefineFont(font) => self.define(timeline, font.id, "font"),
+                Tag::DefineFont2(font) => self.define(timeline, font.id, "font"),
+                Tag::DefineFont4(font) => self.define(timeline, font.id, "font"),
+                Tag::DefineMorphShape(ms) => {
+                    self.define(timeline, ms.id, "morph_shape");
+                    for morph_shape in [&ms.start, &ms.end] {
+                        self.reference_fills(ms.id, &morph_shape.fill_styles, &morph_shape.line_styles);
+                        self.reference_shape_records(ms.id, &morph_shape.shape);
+                    }
+                },
+                Tag::DefineShape(sh) => {
+                    self.define(timeline, sh.id, "shape");
+                    self.reference_fills(sh.id, &sh.styles.fill_styles, &sh.styles.line_styles);
+                    self.reference_shape_records(sh.id, &sh.shape);
+                },
+                Tag::DefineSound(snd) => self.define(timeline, snd.id, "sound"),
+                Tag::DefineSprite(ds) => {
+                    self.define(timeline, ds.id, "sprite");
+                    self.scan(Some(ds.id), &ds.tags, encoding);
+                },
+                Tag::DefineText(text) => {
+                    self.define(timeline, text.id, "text");
+                    for record in &text.records {
+                        if let Some(font_id) = record.font_id {
+                            self.reference(Some(text.id), font_id, "uses_font");
+                        }
+                    }
+                },
+                Tag::DefineVideoStream(dvs) => self.define(timeline, dvs.id, "video"),
+                Tag::ExportAssets(assets) => {
+                    for asset in assets {
+                        self.export_names.insert(asset.id, decode_string(asset.name, encoding));
+                    }
+                },
+                Tag::SymbolClass(links) => {
+                    // ActionScript 3 classes can instantiate linked characters just like exported ones
+                    for link in links.iter().filter(|link| link.id != 0) {
+                        self.export_names.insert(link.id, decode_string(link.class_name, encoding));
+                    }
+                },
+                Tag::PlaceObject(po) => {
+                    match po.action {
+                        PlaceObjectAction::Place(id)|PlaceObjectAction::Replace(id) => {
+                            self.reference(timeline, id, "places");
+                            self.placements.entry(id).or_default().push((timeline, frame));
+                        },
+                        PlaceObjectAction::Modify => {},
+                    }
+                },
+                Tag::ShowFrame => frame += 1,
+                Tag::StartSound(ss) => self.reference(timeline, ss.id, "plays"),
+                _ => {},
+            }
+        }
+    }
+
+    fn define(&mut self, timeline: Option<CharacterId>, id: CharacterId, kind: &'static str) {
+        self.kinds.insert(id, kind);
+        if let Some(sprite_id) = timeline {
+            self.definers.insert(id, sprite_id);
+        }
+    }
+
+    fn reference(&mut self, source: Option<CharacterId>, target: CharacterId, relation: &'static str) {
+        self.references.insert((source, target, relation));
+    }
+
+    fn reference_fills(&mut self, id: CharacterId, fill_styles: &[FillStyle], line_styles: &[LineStyle]) {
+        let line_fills = line_styles.iter().map(|ls| ls.fill_style());
+        for fill_style in fill_styles.iter().chain(line_fills) {
+            if let FillStyle::Bitmap { id: bitmap_id, .. } = fill_style {
+                // 0xFFFF denotes a missing bitmap
+                if *bitmap_id != 0xFFFF {
+                    self.reference(Some(id), *bitmap_id, "fills_with");
+                }
+            }
+        }
+    }
+
+    fn reference_shape_records(&mut self, id: CharacterId, records: &[ShapeRecord]) {
+        for record in records {
+            if let ShapeRecord::StyleChange(change) = record {
+                if let Some(ShapeStyles { fill_styles, line_styles }) = &change.new_styles {
+                    self.reference_fills(id, fill_styles, line_styles);
+                }
+            }
+        }
+    }
+
+    /// Returns the kind of the character with the given ID, or `None` if no such character is defined.
+    pub fn kind(&self, id: CharacterId) -> Option<&'static str> {
+        self.kinds.get(&id).copied()
+    }
+
+    /// Returns the path identifying the character with the given ID across the movie: its kind and
+    /// ID, preceded by those of the sprites within which it is defined (such as `sprite/12/shape/40`),
+    /// or just its ID if no such character is defined.
+    pub fn asset_path(&self, id: CharacterId) -> String {
+        let Some(kind) = self.kind(id) else { return id.to_string() };
+        let mut path = format!("{}/{}", kind, id);
+        let mut current = id;
+        // sprites defined within themselves must not hang the lookup
+        let mut seen = HashSet::from([id]);
+        while let Some(&sprite_id) = self.definers.get(&current) {
+            if !seen.insert(sprite_id) {
+                break;
+            }
+            path = format!("sprite/{}/{}", sprite_id, path);
+            current = sprite_id;
+        }
+        path
+    }
+
+    /// Returns all defined characters along with their kinds, ordered by ID.
+    pub fn characters(&self) -> Vec<(CharacterId, &'static str)> {
+        self.kinds.iter()
+            .map(|(id, kind)| (*id, *kind))
+            .collect()
+    }
+
+    /// Returns the characters that can be shown or played: those reachable from the main timeline
+    /// or from an exported character.
+    pub fn used_characters(&self) -> HashSet<CharacterId> {
+        let mut used: HashSet<CharacterId> = self.export_names.keys().copied().collect();
+        let mut pending: Vec<Option<CharacterId>> = used.iter().map(|id| Some(*id)).collect();
+        pending.push(None);
+
+        while let Some(source) = pending.pop() {
+            let targets = self.references.iter()
+                .filter(|(s, _, _)| *s == source)
+                .map(|(_, t, _)| *t);
+            for target in targets {
+                if used.insert(target) {
+                    pending.push(Some(target));
+                }
+            }
+        }
+        used
+    }
+
+    /// Returns the characters that are defined but never used, along with their kinds.
+    pub fn unused_characters(&self) -> Vec<(CharacterId, &'static str)> {
+        let used = self.used_characters();
+        self.kinds.iter()
+            .filter(|(id, _)| !used.contains(id))
+            .map(|(id, kind)| (*id, *kind))
+            .collect()
+    }
+
+    /// Returns the sprites through which the character is first shown, starting with a sprite placed
+    /// on the main timeline and ending with the character itself, along with the frame of the main
+    /// timeline at which it is first shown. Returns `None` if the character is never shown.
+    ///
+    /// Sprites are assumed to start playing from their first frame when they are placed.
+    pub fn timeline_position(&self, id: CharacterId) -> Option<(Vec<CharacterId>, u32)> {
+        self.timeline_position_avoiding(id, &mut Vec::new())
+    }
+
+    fn timeline_position_avoiding(&self, id: CharacterId, visiting: &mut Vec<CharacterId>) -> Option<(Vec<CharacterId>, u32)> {
+        if visiting.contains(&id) || visiting.len() > self.max_depth {
+            // sprites placing each other, or too many sprites to follow
+            return None;
+        }
+        visiting.push(id);
+
+        let mut best: Option<(Vec<CharacterId>, u32)> = None;
+        for (timeline, frame) in self.placements.get(&id).into_iter().flatten() {
+            let candidate = match timeline {
+                None => Some((vec![id], *frame)),
+                Some(parent) => self.timeline_position_avoiding(*parent, visiting)
+                    .map(|(mut path, parent_frame)| {
+                        path.push(id);
+                        (path, parent_frame + frame - 1)
+                    }),
+            };
+            if let Some(c) = candidate {
+                if best.as_ref().is_none_or(|b| c.1 < b.1) {
+                    best = Some(c);
+                }
+            }
+        }
+
+        visiting.pop();
+        best
+    }
+
+    fn node_name(id: Option<CharacterId>) -> String {
+        match id {
+            Some(i) => format!("c{}", i),
+            None => "movie".to_owned(),
+        }
+    }
+
+    fn write_dot<W: Write>(&self, mut writer: W) -> Result<(), std::io::Error> {
+        writeln!(writer, "digraph references {{")?;
+        writeln!(writer, "  movie [shape=doublecircle, label=\"movie\"];")?;
+        for (id, kind) in &self.kinds {
+            let mut label = format!("{} {}", kind, id);
+            if let Some(name) = self.export_names.get(id) {
+                label.push_str("\\n");
+                label.push_str(&name.replace('\\', "\\\\").replace('"', "\\\""));
+            }
+            writeln!(writer, "  {} [shape=box, label=\"{}\"];", Self::node_name(Some(*id)), label)?;
+        }
+        for (source, target, relation) in &self.references {
+            writeln!(
+                writer, "  {} -> {} [label=\"{}\"];",
+                Self::node_name(*source), Self::node_name(Some(*target)), relation,
+            )?;
+        }
+        writeln!(writer, "}}")
+    }
+
+    fn to_json(&self) -> JsonValue {
+        let mut nodes = JsonValue::new_array();
+        for (id, kind) in &self.kinds {
+            let mut node = JsonValue::new_object();
+            node.insert("id", *id);
+            node.insert("type", *kind);
+            node.insert("export_name", self.export_names.get(id).map(|name| name.as_str()));
+            nodes.push(node);
+        }
+
+        let mut edges = JsonValue::new_array();
+        for (source, target, relation) in &self.references {
+            let mut edge = JsonValue::new_object();
+            edge.insert("from", *source);
+            edge.insert("to", *target);
+            edge.insert("relation", *relation);
+            edges.push(edge);
+        }
+
+        let mut obj = JsonValue::new_object();
+        obj.insert("nodes", nodes);
+        obj.insert("edges", edges);
+        obj
+    }
+
+    pub fn write<W: Write>(&self, format: GraphFormat, writer: W) -> Result<(), std::io::Error> {
+        match format {
+            GraphFormat::Dot => self.write_dot(writer),
+            GraphFormat::Json => self.to_json().write(writer),
+        }
+    }
+}
@@ -0,0 +1,53 @@
+/// Collects text information frames and outputs them as an ID3v2.3 tag.
+#[derive(Clone, Debug, Default)]
+pub(crate) struct Id3Tag {
+    frames: Vec<([u8; 4], String)>,
+}
+impl Id3Tag {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Adds a text information frame (such as `TIT2` for the title).
+    pub fn add_text(&mut self, frame_id: &[u8; 4], text: &str) {
+        self.frames.push((*frame_id, text.to_owned()));
+    }
+
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut frames = Vec::new();
+        for (frame_id, text) in &self.frames {
+            let mut body = Vec::new();
+            if text.chars().all(|c| u32::from(c) < 0x100) {
+                body.push(0x00); // ISO-8859-1
+                body.extend(text.chars().map(|c| u32::from(c) as u8));
+            } else {
+                body.push(0x01); // UTF-16 with byte order mark
+                body.extend([0xFF, 0xFE]);
+                for unit in text.encode_utf16() {
+                    body.extend(unit.to_le_bytes());
+                }
+            }
+
+            frames.extend(frame_id);
+            frames.extend(u32::try_from(body.len()).unwrap().to_be_bytes());
+            frames.extend([0x00, 0x00]); // flags
+            frames.extend(body);
+        }
+
+        let mut tag = Vec::with_capacity(10 + frames.len());
+        tag.extend(b"ID3");
+        tag.extend([0x03, 0x00]); // version 2.3.0
+        tag.push(0x00); // flags
+
+        // the size is stored in 7 bits per byte
+        let size = u32::try_from(frames.len()).unwrap();
+        tag.extend([
+            ((size >> 21) & 0x7F) as u8,
+            ((size >> 14) & 0x7F) as u8,
+            ((size >> 7) & 0x7F) as u8,
+            (size & 0x7F) as u8,
+        ]);
+        tag.extend(frames);
+        tag
+    }
+}
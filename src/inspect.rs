@@ -0,0 +1,239 @@
+use std::collections::HashMap;
+use std::io::{self, Write};
+
+use swf::{CharacterId, Compression, Swf};
+
+use crate::graph::ReferenceGraph;
+use crate::movie::{background_color, ScriptKind};
+use crate::tags::{RawTag, TagLocation};
+
+
+const BOLD: &str = "\x1b[1m";
+const DIM: &str = "\x1b[2m";
+const RESET: &str = "\x1b[0m";
+
+
+/// How the `list` and `info` subcommands format their output.
+#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq)]
+pub(crate) enum ListingStyle {
+    /// Tab-separated fields without a header, with sizes in bytes, for processing by scripts.
+    Plain,
+
+    /// Aligned columns with a header and human-readable sizes, optionally colorized with ANSI escape
+    /// sequences.
+    Table { colored: bool },
+}
+
+
+/// A field of a listing.
+struct Cell {
+    /// The text when the listing is plain.
+    plain: String,
+
+    /// The text when the listing is a table.
+    text: String,
+
+    /// The ANSI escape sequence with which to color the text, if any.
+    color: &'static str,
+
+    right_aligned: bool,
+}
+impl Cell {
+    fn new(text: impl Into<String>) -> Self {
+        let text = text.into();
+        Self {
+            plain: text.clone(),
+            text,
+            color: "",
+            right_aligned: false,
+        }
+    }
+
+    fn size(bytes: usize) -> Self {
+        Self {
+            plain: bytes.to_string(),
+            text: human_size(bytes),
+            color: "",
+            right_aligned: true,
+        }
+    }
+
+    fn colored(mut self, color: &'static str) -> Self {
+        self.color = color;
+        self
+    }
+}
+
+
+/// Formats a number of bytes with a binary unit, such as `1.5 KiB`.
+pub(crate) fn human_size(bytes: usize) -> String {
+    const UNITS: [&str; 4] = ["KiB", "MiB", "GiB", "TiB"];
+    if bytes < 1024 {
+        return format!("{} B", bytes);
+    }
+    let mut value = bytes as f64 / 1024.0;
+    let mut unit = 0;
+    while value >= 1024.0 && unit + 1 < UNITS.len() {
+        value /= 1024.0;
+        unit += 1;
+    }
+    format!("{:.1} {}", value, UNITS[unit])
+}
+
+
+/// The color in which characters of the given kind are listed.
+fn kind_color(kind: &str) -> &'static str {
+    match kind {
+        "bitmap" => "\x1b[36m",
+        "shape"|"morph_shape" => "\x1b[32m",
+        "sprite"|"button" => "\x1b[35m",
+        "sound" => "\x1b[33m",
+        "video" => "\x1b[31m",
+        "font"|"text"|"edit_text" => "\x1b[34m",
+        _ => "",
+    }
+}
+
+
+/// Writes rows of cells in the given style. The header, if any, is only written for tables.
+fn write_listing<W: Write>(header: &[&str], rows: &[Vec<Cell>], style: ListingStyle, mut writer: W) -> io::Result<()> {
+    let colored = match style {
+        ListingStyle::Plain => {
+            for row in rows {
+                let fields: Vec<&str> = row.iter().map(|cell| cell.plain.as_str()).collect();
+                writeln!(writer, "{}", fields.join("\t"))?;
+            }
+            return Ok(());
+        },
+        ListingStyle::Table { colored } => colored,
+    };
+
+    // headings are aligned like the fields below them
+    let header_cells: Vec<Cell> = header.iter().enumerate()
+        .map(|(i, h)| Cell {
+            right_aligned: rows.first().and_then(|row| row.get(i)).is_some_and(|cell| cell.right_aligned),
+            ..Cell::new(*h).colored(BOLD)
+        })
+        .collect();
+    let header_row = if header_cells.is_empty() { None } else { Some(&header_cells) };
+    let column_count = rows.iter().map(|row| row.len()).max().unwrap_or(0).max(header.len());
+    let mut widths = vec![0; column_count];
+    for row in header_row.into_iter().chain(rows) {
+        for (width, cell) in widths.iter_mut().zip(row) {
+            *width = (*width).max(cell.text.chars().count());
+        }
+    }
+
+    for row in header_row.into_iter().chain(rows) {
+        let mut line = String::new();
+        for (i, (cell, width)) in row.iter().zip(&widths).enumerate() {
+            if i > 0 {
+                line.push_str("  ");
+            }
+            let padding = " ".repeat(width - cell.text.chars().count());
+            let is_last = i + 1 == row.len();
+            if cell.right_aligned {
+                line.push_str(&padding);
+            }
+            if colored && !cell.color.is_empty() {
+                line.push_str(cell.color);
+                line.push_str(&cell.text);
+                line.push_str(RESET);
+            } else {
+                line.push_str(&cell.text);
+            }
+            if !cell.right_aligned && !is_last {
+                line.push_str(&padding);
+            }
+        }
+        writeln!(writer, "{}", line)?;
+    }
+    Ok(())
+}
+
+
+/// Writes a row for each character, ordered by ID, giving its ID, kind, defining tag, the length of
+/// that tag's body and whether the character is used.
+pub(crate) fn write_character_list<W: Write>(
+    graph: &ReferenceGraph,
+    tag_locations: &HashMap<CharacterId, TagLocation>,
+    style: ListingStyle,
+    writer: W,
+) -> io::Result<()> {
+    let used = graph.used_characters();
+    let mut rows = Vec::new();
+    for (id, kind) in graph.characters() {
+        let (tag_name, size) = match tag_locations.get(&id) {
+            Some(location) => (Cell::new(location.name.as_str()), Cell::size(location.length)),
+            None => (Cell::new("?"), Cell { right_aligned: true, ..Cell::new("?") }),
+        };
+        let usage = if used.contains(&id) {
+            Cell::new("used")
+        } else {
+            Cell::new("unused").colored(DIM)
+        };
+        rows.push(vec![
+            Cell { right_aligned: true, ..Cell::new(id.to_string()) },
+            Cell::new(kind).colored(kind_color(kind)),
+            tag_name,
+            size,
+            usage,
+        ]);
+    }
+    write_listing(&["ID", "TYPE", "TAG", "SIZE", "USAGE"], &rows, style, writer)
+}
+
+
+/// Writes a summary of the header and contents of the movie.
+pub(crate) fn write_movie_info<W: Write>(swf: &Swf, raw_tags: &[RawTag], style: ListingStyle, writer: W) -> io::Result<()> {
+    let header = &swf.header;
+    let compression = match header.compression() {
+        Compression::None => "none",
+        Compression::Zlib => "zlib",
+        Compression::Lzma => "LZMA",
+    };
+    let stage = header.stage_size();
+    let script_kind = match ScriptKind::classify(&swf.tags) {
+        ScriptKind::None => "none",
+        ScriptKind::ActionScript2 => "ActionScript 1/2",
+        ScriptKind::ActionScript3 => "ActionScript 3",
+    };
+    let frame_rate = header.frame_rate().to_f64();
+    let length = usize::try_from(header.uncompressed_len()).unwrap();
+
+    let mut stage_cell = Cell::new(format!(
+        "{}x{} px at ({}, {})",
+        (stage.x_max - stage.x_min).to_pixels(), (stage.y_max - stage.y_min).to_pixels(),
+        stage.x_min.to_pixels(), stage.y_min.to_pixels(),
+    ));
+    stage_cell.plain = format!(
+        "{},{},{},{}",
+        stage.x_min.to_pixels(), stage.y_min.to_pixels(), stage.x_max.to_pixels(), stage.y_max.to_pixels(),
+    );
+    let mut frame_rate_cell = Cell::new(format!("{} fps", frame_rate));
+    frame_rate_cell.plain = frame_rate.to_string();
+    let background = background_color(&swf.tags)
+        .map(|c| format!("#{:02x}{:02x}{:02x}", c.r, c.g, c.b))
+        .unwrap_or_else(|| "none".to_owned());
+
+    let fields = [
+        ("version", Cell::new(header.version().to_string())),
+        ("compression", Cell::new(compression)),
+        ("uncompressed_length", Cell { right_aligned: false, ..Cell::size(length) }),
+        ("stage", stage_cell),
+        ("frame_rate", frame_rate_cell),
+        ("frames", Cell::new(header.num_frames().to_string())),
+        ("background", Cell::new(background)),
+        ("scripts", Cell::new(script_kind)),
+        ("tags", Cell::new(raw_tags.len().to_string())),
+        ("characters", Cell::new(raw_tags.iter().filter(|t| t.defines_character()).count().to_string())),
+    ];
+    let rows: Vec<Vec<Cell>> = fields.into_iter()
+        .map(|(key, value)| {
+            let mut key_cell = Cell::new(key.replace('_', " ")).colored(BOLD);
+            key_cell.plain = key.to_owned();
+            vec![key_cell, value]
+        })
+        .collect();
+    write_listing(&[], &rows, style, writer)
+}
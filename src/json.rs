@@ -0,0 +1,199 @@
+use std::fmt;
+use std::io::Write;
+
+
+/// A JSON value, as written into sidecar files.
+///
+/// Objects retain the order in which their keys were inserted.
+#[derive(Clone, Debug, PartialEq)]
+pub(crate) enum JsonValue {
+    Null,
+    Bool(bool),
+    Integer(i64),
+    Float(f64),
+    String(String),
+    Array(Vec<JsonValue>),
+    Object(Vec<(String, JsonValue)>),
+}
+impl JsonValue {
+    pub fn new_object() -> Self {
+        Self::Object(Vec::new())
+    }
+
+    pub fn new_array() -> Self {
+        Self::Array(Vec::new())
+    }
+
+    /// Sets the value of the given key in this object, replacing any previous value.
+    ///
+    /// Panics if this value is not an object.
+    pub fn insert<K: Into<String>, V: Into<JsonValue>>(&mut self, key: K, value: V) {
+        let key = key.into();
+        let value = value.into();
+        match self {
+            Self::Object(entries) => {
+                if let Some(entry) = entries.iter_mut().find(|(k, _)| k == &key) {
+                    entry.1 = value;
+                } else {
+                    entries.push((key, value));
+                }
+            },
+            other => panic!("cannot insert a key into non-object JSON value {:?}", other),
+        }
+    }
+
+    /// Returns the value of the given key if this is an object containing it.
+    pub fn get(&self, key: &str) -> Option<&JsonValue> {
+        match self {
+            Self::Object(entries) => entries.iter()
+                .find(|(k, _)| k == key)
+                .map(|(_, v)| v),
+            _ => None,
+        }
+    }
+
+    /// Returns a mutable reference to the value of the given key if this is an object containing
+    /// it.
+    pub fn get_mut(&mut self, key: &str) -> Option<&mut JsonValue> {
+        match self {
+            Self::Object(entries) => entries.iter_mut()
+                .find(|(k, _)| k == key)
+                .map(|(_, v)| v),
+            _ => None,
+        }
+    }
+
+    /// Appends a value to this array.
+    ///
+    /// Panics if this value is not an array.
+    pub fn push<V: Into<JsonValue>>(&mut self, value: V) {
+        match self {
+            Self::Array(values) => values.push(value.into()),
+            other => panic!("cannot push a value onto non-array JSON value {:?}", other),
+        }
+    }
+
+    pub fn write<W: Write>(&self, mut writer: W) -> Result<(), std::io::Error> {
+        writeln!(writer, "{}", self)
+    }
+
+    fn fmt_indented(&self, f: &mut fmt::Formatter<'_>, indent: usize) -> fmt::Result {
+        match self {
+            Self::Null => write!(f, "null"),
+            Self::Bool(b) => write!(f, "{}", b),
+            Self::Integer(i) => write!(f, "{}", i),
+            Self::Float(v) => {
+                if v.is_finite() {
+                    write!(f, "{}", v)
+                } else {
+                    // JSON has no representation for these
+                    write!(f, "null")
+                }
+            },
+            Self::String(s) => write_json_string(f, s),
+            Self::Array(values) => {
+                if values.is_empty() {
+                    return write!(f, "[]");
+                }
+                writeln!(f, "[")?;
+                for (i, value) in values.iter().enumerate() {
+                    write!(f, "{:1$}", "", indent + 2)?;
+                    value.fmt_indented(f, indent + 2)?;
+                    if i < values.len() - 1 {
+                        write!(f, ",")?;
+                    }
+                    writeln!(f)?;
+                }
+                write!(f, "{:1$}]", "", indent)
+            },
+            Self::Object(entries) => {
+                if entries.is_empty() {
+                    return write!(f, "{{}}");
+                }
+                writeln!(f, "{{")?;
+                for (i, (key, value)) in entries.iter().enumerate() {
+                    write!(f, "{:1$}", "", indent + 2)?;
+                    write_json_string(f, key)?;
+                    write!(f, ": ")?;
+                    value.fmt_indented(f, indent + 2)?;
+                    if i < entries.len() - 1 {
+                        write!(f, ",")?;
+                    }
+                    writeln!(f)?;
+                }
+                write!(f, "{:1$}}}", "", indent)
+            },
+        }
+    }
+}
+impl fmt::Display for JsonValue {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.fmt_indented(f, 0)
+    }
+}
+
+fn write_json_string(f: &mut fmt::Formatter<'_>, s: &str) -> fmt::Result {
+    write!(f, "\"")?;
+    for c in s.chars() {
+        match c {
+            '"' => write!(f, "\\\"")?,
+            '\\' => write!(f, "\\\\")?,
+            '\n' => write!(f, "\\n")?,
+            '\r' => write!(f, "\\r")?,
+            '\t' => write!(f, "\\t")?,
+            c if (c as u32) < 0x20 => write!(f, "\\u{:04x}", c as u32)?,
+            c => write!(f, "{}", c)?,
+        }
+    }
+    write!(f, "\"")
+}
+
+impl From<bool> for JsonValue {
+    fn from(value: bool) -> Self { Self::Bool(value) }
+}
+impl From<String> for JsonValue {
+    fn from(value: String) -> Self { Self::String(value) }
+}
+impl From<&str> for JsonValue {
+    fn from(value: &str) -> Self { Self::String(String::from(value)) }
+}
+impl From<f32> for JsonValue {
+    fn from(value: f32) -> Self { Self::Float(value.into()) }
+}
+impl From<f64> for JsonValue {
+    fn from(value: f64) -> Self { Self::Float(value) }
+}
+impl<T: Into<JsonValue>> From<Option<T>> for JsonValue {
+    fn from(value: Option<T>) -> Self {
+        match value {
+            Some(v) => v.into(),
+            None => Self::Null,
+        }
+    }
+}
+impl<T: Into<JsonValue>> From<Vec<T>> for JsonValue {
+    fn from(value: Vec<T>) -> Self {
+        Self::Array(value.into_iter().map(|v| v.into()).collect())
+    }
+}
+macro_rules! impl_from_integer {
+    ($($t:ty),*) => {
+        $(
+            impl From<$t> for JsonValue {
+                fn from(value: $t) -> Self { Self::Integer(value.into()) }
+            }
+        )*
+    };
+}
+impl_from_integer!(u8, u16, u32, i8, i16, i32, i64);
+impl From<u64> for JsonValue {
+    fn from(value: u64) -> Self {
+        match i64::try_from(value) {
+            Ok(i) => Self::Integer(i),
+            Err(_) => Self::Float(value as f64),
+        }
+    }
+}
+impl From<usize> for JsonValue {
+    fn from(value: usize) -> Self { (value as u64).into() }
+}
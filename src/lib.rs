@@ -0,0 +1,11 @@
+//! Components of swfextract that are useful to other Flash tooling.
+
+
+pub mod adpcm;
+pub mod background;
+pub mod bitmap;
+pub mod cancel;
+pub mod extract;
+pub mod pixels;
+pub mod read;
+pub mod warning;
@@ -0,0 +1,201 @@
+use std::collections::HashSet;
+use std::io::Write;
+
+use clap::ValueEnum;
+use encoding_rs::Encoding;
+use swf::avm1::types::{Action, Value};
+
+use crate::avm1::walk_actions;
+use crate::encoding::decode_string;
+
+
+#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq, ValueEnum)]
+pub(crate) enum LocalizationFormat {
+    Csv,
+    Po,
+}
+
+
+#[derive(Clone, Debug)]
+struct LocalizationEntry {
+    key: String,
+    location: String,
+    text: String,
+}
+
+
+/// Returns whether a string constant from a script is most likely a name (of a variable, method,
+/// path, etc.) and not text intended for display.
+///
+/// The heuristic errs on the side of caution, so some names will still be included.
+fn is_probably_identifier(s: &str) -> bool {
+    let Some(first) = s.chars().next() else { return true };
+    let is_name_like = s.chars()
+        .all(|c| c.is_ascii_alphanumeric() || "_$.:/".contains(c));
+    let looks_like_member = first.is_ascii_lowercase() || first == '_' || first == '$'
+        || s.contains(|c| "_.:/".contains(c));
+    is_name_like && looks_like_member
+}
+
+fn csv_escape(s: &str) -> String {
+    if s.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", s.replace('"', "\"\""))
+    } else {
+        s.to_owned()
+    }
+}
+
+fn po_escape(s: &str) -> String {
+    let mut ret = String::with_capacity(s.len() + 2);
+    ret.push('"');
+    for c in s.chars() {
+        match c {
+            '"' => ret.push_str("\\\""),
+            '\\' => ret.push_str("\\\\"),
+            '\n' => ret.push_str("\\n"),
+            '\r' => ret.push_str("\\r"),
+            '\t' => ret.push_str("\\t"),
+            c => ret.push(c),
+        }
+    }
+    ret.push('"');
+    ret
+}
+
+
+/// Collects user-visible strings for translation.
+///
+/// Each string is keyed by the character ID or the script location it stems from so that
+/// translations can be matched up with the original strings.
+#[derive(Clone, Debug, Default)]
+pub(crate) struct LocalizationExport {
+    entries: Vec<LocalizationEntry>,
+
+    /// The key prefixes and texts of the script strings added so far.
+    script_strings: HashSet<(String, String)>,
+}
+impl LocalizationExport {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn add(&mut self, key: String, location: String, text: String) {
+        if text.trim().is_empty() {
+            return;
+        }
+        self.entries.push(LocalizationEntry {
+            key,
+            location,
+            text,
+        });
+    }
+
+    /// Adds the string constants pushed or pooled by AVM1 bytecode.
+    pub fn add_avm1_strings(
+        &mut self,
+        key_prefix: &str,
+        location: &str,
+        action_data: &[u8],
+        swf_version: u8,
+        encoding: &'static Encoding,
+    ) {
+        let mut strings = Vec::new();
+        walk_actions(action_data, swf_version, &mut |action| {
+            match action {
+                Action::ConstantPool(cp) => {
+                    strings.extend(cp.strings.iter().map(|s| decode_string(s, encoding)));
+                },
+                Action::Push(push) => {
+                    for value in &push.values {
+                        if let Value::Str(s) = value {
+                            strings.push(decode_string(s, encoding));
+                        }
+                    }
+                },
+                _ => {},
+            }
+        });
+        self.add_script_strings(key_prefix, location, strings);
+    }
+
+    /// Adds the strings from the constant pool of an ABC (AVM2 bytecode) file.
+    pub fn add_abc_strings(&mut self, key_prefix: &str, location: &str, abc_data: &[u8]) {
+        let abc = match swf::avm2::read::Reader::new(abc_data).read() {
+            Ok(a) => a,
+            Err(_) => return,
+        };
+        self.add_script_strings(key_prefix, location, abc.constant_pool.strings);
+    }
+
+    /// Adds the strings of a script, keyed by the given prefix (which must be unique to the script)
+    /// and their index.
+    fn add_script_strings(&mut self, key_prefix: &str, location: &str, strings: Vec<String>) {
+        let mut index = 0;
+        for string in strings {
+            if is_probably_identifier(&string) {
+                continue;
+            }
+            // constant pools and pushes often repeat each other
+            if !self.script_strings.insert((key_prefix.to_owned(), string.clone())) {
+                continue;
+            }
+            self.add(format!("{}:{}", key_prefix, index), location.to_owned(), string);
+            index += 1;
+        }
+    }
+
+    pub fn write<W: Write>(&self, format: LocalizationFormat, mut writer: W) -> Result<(), std::io::Error> {
+        match format {
+            LocalizationFormat::Csv => {
+                writeln!(writer, "key,location,text")?;
+                for entry in &self.entries {
+                    writeln!(
+                        writer, "{},{},{}",
+                        csv_escape(&entry.key), csv_escape(&entry.location), csv_escape(&entry.text),
+                    )?;
+                }
+            },
+            LocalizationFormat::Po => {
+                writeln!(writer, "msgid \"\"")?;
+                writeln!(writer, "msgstr \"\"")?;
+                writeln!(writer, "\"Content-Type: text/plain; charset=UTF-8\\n\"")?;
+                for entry in &self.entries {
+                    writeln!(writer)?;
+                    writeln!(writer, "#: {}", entry.location)?;
+                    writeln!(writer, "msgctxt {}", po_escape(&entry.key))?;
+                    writeln!(writer, "msgid {}", po_escape(&entry.text))?;
+                    writeln!(writer, "msgstr \"\"")?;
+                }
+            },
+        }
+        Ok(())
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+
+    fn keys_and_texts(export: &LocalizationExport) -> Vec<(&str, &str)> {
+        export.entries.iter()
+            .map(|e| (e.key.as_str(), e.text.as_str()))
+            .collect()
+    }
+
+
+    #[test]
+    fn script_strings_are_keyed_by_script() {
+        let mut export = LocalizationExport::new();
+        let strings = |texts: &[&str]| texts.iter().map(|t| (*t).to_owned()).collect();
+        export.add_script_strings("script:main:frame1_action1", "frame 1", strings(&["Hello", "gotoAndPlay", "Hello", "Bye"]));
+        export.add_script_strings("script:main:frame1_action2", "frame 1", strings(&["Hello", "Again"]));
+        assert_eq!(keys_and_texts(&export), [
+            ("script:main:frame1_action1:0", "Hello"),
+            ("script:main:frame1_action1:1", "Bye"),
+            ("script:main:frame1_action2:0", "Hello"),
+            ("script:main:frame1_action2:1", "Again"),
+        ]);
+    }
+}
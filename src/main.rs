@@ -1,356 +1,3388 @@
-mod adpcm;
-mod bitmap;
+mod abc;
+mod avm1;
+mod button;
+mod completions;
+mod config;
+mod duplicates;
+mod embed;
+mod encoding;
+mod flac;
+mod flv;
+mod ffmpeg;
+mod filenames;
+mod filter;
+mod font;
+mod gallery;
+mod graph;
+mod html;
+mod id3;
+mod inspect;
+mod json;
+mod localization;
+mod man;
+mod manifest;
+mod movie;
+mod mp3;
+mod mp4;
+mod profile;
+mod render;
+mod repack;
+mod report;
+mod serve;
 mod shape;
+mod sha256;
 mod sound;
+mod swc;
+mod sync;
+mod tags;
+mod text;
+mod thumbnail;
+mod timeline;
+mod transcript;
+mod truetype;
+mod unpack;
+mod urls;
+mod video;
+mod zip;
 
 
-use std::collections::HashMap;
-use std::fs::File;
-use std::io::{Write, Read};
-use std::path::PathBuf;
+use std::collections::{HashMap, HashSet};
+use std::ffi::OsString;
+use std::fs::{create_dir_all, File};
+use std::io::{IsTerminal, Write};
+use std::ops::Range;
+use std::panic::{self, AssertUnwindSafe};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::{Duration, Instant, SystemTime};
 
-use clap::Parser;
-use swf::{BitmapFormat, Tag};
+use clap::{Args, CommandFactory, FromArgMatches, Parser, Subcommand, ValueEnum};
+use clap::error::ErrorKind;
+use encoding_rs::Encoding;
+use swf::{
+    AudioCompression, CharacterId, Color, Fixed8, Font, FontFlag, FontInfoFlag, Matrix, PlaceObjectAction, Rectangle,
+    Shape, SwfBuf, Tag, Twips, VideoCodec,
+};
+use swfextract::bitmap::{
+    is_jpeg, splice_jpeg, strip_jpeg_framing, Bitmap, BitmapData, Error as BitmapError, IndexedFormat,
+};
+use swfextract::cancel::Cancellation;
+use swfextract::pixels::{resize_rgba, ImageScale};
+use swfextract::read::{decompress_movie, parse_movie, patch_avc_streams, Error as ReadError};
+use swfextract::warning::{Warning, WarningCode};
 
-use crate::bitmap::{Bitmap, BitmapData, RgbaColor, RgbColor};
-use crate::shape::shape_to_svg;
-use crate::sound::Sound;
+use crate::abc::{abc_payloads, abc_summary_to_json, superclass_names};
+use crate::duplicates::{link_duplicate, DuplicateIndex, IndexEntry, LinkKind};
+use crate::config::{config_to_args, DEFAULT_CONFIG_FILE_NAME};
+use crate::button::{apply_button_color_transform, button_records_to_json, hit_area_to_json};
+use crate::completions::{write_completions, Shell};
+use crate::embed::{class_extension, sniff_extension};
+use crate::encoding::{decode_string, SourceEncoding};
+#[cfg(feature = "mp4-encode")]
+use crate::ffmpeg::run_ffmpeg;
+use crate::ffmpeg::{write_ffconcat, write_ffmetadata};
+use crate::filenames::{sanitize_file_name, FileStems};
+use crate::flv::FlvVideoStream;
+use crate::font::{
+    align_zones_to_json, find_font_info, find_font_name, font_info_unicode_codes, font_to_json, font_to_svg, font_v1_to_font,
+    has_outlines, FontOutlines, GlyphUsage,
+};
+use crate::id3::Id3Tag;
+use crate::inspect::{write_character_list, write_movie_info, ListingStyle};
+use crate::gallery::{write_gallery, GalleryAsset};
+use crate::graph::{GraphFormat, ReferenceGraph};
+use crate::json::JsonValue;
+use crate::localization::{LocalizationExport, LocalizationFormat};
+use crate::man::write_man_page;
+use crate::manifest::Manifest;
+use crate::movie::{
+    background_color, debug_id, file_attributes_to_json, product_info_to_json, protection_to_json, script_limits_to_json,
+    ScriptKind,
+};
+use crate::mp4::AvcStream;
+use crate::render::{text_to_svg, FrameRenderer};
+use crate::repack::{repack, RepackCompression};
+use crate::profile::{profiled_character, Profile, Stage};
+use crate::report::{AssetOutcome, AssetStatus, RunReport};
+use crate::serve::{serve, ServeConfig, DEFAULT_EXTRACTION_TIMEOUT, TIMED_OUT_EXIT_CODE};
+use crate::sha256::sha256_hex;
+use crate::shape::{bitmap_fills_to_json, shape_to_svg};
+use crate::sound::{is_program_available, sound_info_to_json, AudioFormat, AudioOptions, PcmEndian, Provenance, Sound};
+use crate::swc::{SwcCatalog, CATALOG_PATH};
+use crate::sync::{StreamPosition, TimelineSync};
+use crate::tags::{
+    character_tag_locations, dump_raw_tags, legacy_tag_name, read_raw_tags,
+    TagLocation,
+};
+use crate::thumbnail::{bitmap_thumbnail, svg_thumbnail, write_thumbnail_index, Thumbnail, THUMBNAIL_DIR};
+use crate::text::{
+    csm_text_settings_to_json, edit_text_to_html, edit_text_to_json, edit_text_to_string, layout_edit_text, layout_static_text,
+    static_text_to_string, TextDrawing,
+};
+use crate::timeline::{color_transform_to_json, FrameLabels, Timeline};
+use crate::transcript::{Transcript, TranscriptFormat};
+use crate::truetype::FontDirectory;
+use crate::unpack::{LoaderAnalysis, MAX_UNPACK_DEPTH};
+use crate::urls::UrlReport;
+use crate::video::{video_stream_to_json, Error as VideoError, ScreenVideoDecoder};
+use crate::zip::{is_zip, ZipReader};
 
 
+/// Whether `--quiet` has been passed.
+static QUIET: AtomicBool = AtomicBool::new(false);
+
+
+/// Outputs a line about the progress or findings of the run, unless `--quiet` has been passed.
+macro_rules! status {
+    ($($arg:tt)*) => {
+        if !QUIET.load(Ordering::Relaxed) {
+            println!($($arg)*);
+        }
+    };
+}
+
+
+/// Extracts the sounds, bitmaps, shapes, fonts, texts, videos and scripts of Flash movies.
 #[derive(Parser)]
-struct Opts {
+#[command(args_override_self = true)]
+struct Cli {
+    /// Output a man page describing all commands and options instead of running a command.
+    #[arg(long)]
+    man: bool,
+
+    #[command(flatten)]
+    global: GlobalOpts,
+
+    #[command(subcommand)]
+    command: Option<Command>,
+}
+
+
+/// The options shared by all subcommands.
+#[derive(Args)]
+struct GlobalOpts {
+    /// Read default options from the given configuration file instead of `swfextract.toml` in the
+    /// current directory. Options given on the command line take precedence.
+    #[arg(long, global = true)]
+    config: Option<PathBuf>,
+
+    /// Write the output files into the given directory, creating it if necessary, instead of the
+    /// current directory.
+    #[arg(long, global = true)]
+    output_dir: Option<PathBuf>,
+
+    /// Only output errors, not the progress and findings of the run.
+    #[arg(short, long, global = true)]
+    quiet: bool,
+
+    /// Write out decoded bitmaps as soon as the ones held in memory take up more than the given
+    /// number of bytes (such as `512M` or `1G`) instead of once their timeline has been processed.
+    /// The movie itself is always held in memory completely.
+    #[arg(long, global = true, value_parser = parse_byte_size)]
+    max_memory: Option<u64>,
+
+    /// Stop once the given time (in seconds with an optional unit such as `30s` or `2min`) has
+    /// passed since the run started. The files written up to then are kept and listed in the
+    /// manifest, and the run is reported as failed.
+    #[arg(long, global = true, value_parser = parse_duration)]
+    timeout: Option<f64>,
+
+    /// Refuse movies nesting sprite definitions more than the given number of levels deep, and draw
+    /// no more than that many sprites within each other when rendering frames. Guards against
+    /// malformed movies that would otherwise exhaust the stack.
+    #[arg(long, global = true, default_value_t = 64)]
+    max_sprite_depth: usize,
+}
+
+
+#[derive(Subcommand)]
+enum Command {
+    /// Extract the assets of a movie (the default if no subcommand is given).
+    Extract(Box<ExtractOpts>),
+
+    /// List the characters of a movie with their types and sizes.
+    List(InspectArgs),
+
+    /// Output the header and summary information of a movie.
+    Info(InspectArgs),
+
+    /// Render the frames of the timelines of a movie without extracting its assets.
+    Render(RenderOpts),
+
+    /// Write a movie back out with different compression.
+    Repack(RepackOpts),
+
+    /// Serve an HTTP API that extracts the SWF files posted to it.
+    Serve(ServeOpts),
+
+    /// Output a script that lets the given shell complete the commands and options.
+    Completions(CompletionsArgs),
+}
+impl Command {
+    const NAMES: [&'static str; 7] = ["extract", "list", "info", "render", "repack", "serve", "completions"];
+}
+
+
+#[derive(Args)]
+struct CompletionsArgs {
+    #[arg(value_enum)]
+    shell: Shell,
+}
+
+
+#[derive(Args)]
+struct InspectArgs {
+    /// Output tab-separated fields without a header and with exact sizes, for processing by
+    /// scripts.
+    #[arg(long)]
+    plain: bool,
+
+    /// Do not colorize the output, which is otherwise colorized if it goes to a terminal and the
+    /// `NO_COLOR` environment variable is not set.
+    #[arg(long)]
+    no_color: bool,
+
+    swf_path: PathBuf,
+}
+impl InspectArgs {
+    fn listing_style(&self) -> ListingStyle {
+        if self.plain {
+            ListingStyle::Plain
+        } else {
+            let colored = !self.no_color
+                && std::env::var_os("NO_COLOR").is_none_or(|value| value.is_empty())
+                && std::io::stdout().is_terminal();
+            ListingStyle::Table { colored }
+        }
+    }
+}
+
+
+/// The options controlling how frames are rendered and which frames are output.
+#[derive(Args)]
+struct RenderingOpts {
+    /// Render the frames of the main timeline and write `movie.ffconcat` and `movie.ffmetadata`,
+    /// which let ffmpeg combine the frames and the stream sound into a video with a chapter per
+    /// frame label.
+    #[arg(long)]
+    export_movie: bool,
+
+    /// Run ffmpeg to encode the exported movie into `movie.mp4`.
+    #[cfg(feature = "mp4-encode")]
+    #[arg(long, requires = "export_movie")]
+    encode_mp4: bool,
+
+    /// Apply the color transforms of placed characters (tints, transparency) when rendering frames.
+    #[arg(long)]
+    bake_color_transforms: bool,
+
+    /// Leave the background of rendered frames transparent instead of filling it with the background
+    /// color of the movie.
+    #[arg(long)]
+    transparent_bg: bool,
+
+    /// Multiply the pixel dimensions of rendered frames by the given factor, keeping the visible area
+    /// unchanged. Rasterizing such a frame and scaling the result down again reduces aliasing.
+    #[arg(long, default_value_t = 1, value_parser = clap::value_parser!(u32).range(1..))]
+    render_scale: u32,
+
+    /// Restrict the stream sound, timeline file and rendered frames of the main timeline to a range
+    /// of frames, given as `start..end` with labels or frame numbers. The end is exclusive; either
+    /// may be left out.
+    #[arg(long, conflicts_with = "frame_label")]
+    frames: Option<String>,
+
+    /// Restrict the stream sound, timeline file and rendered frames of the main timeline to the
+    /// frames from the given label up to the next label.
+    #[arg(long)]
+    frame_label: Option<String>,
+
+    /// Draw texts in device fonts, whose glyphs are not embedded into the movie, with the TrueType
+    /// fonts of the same name found in the given directory (`_sans`, `_serif` and `_typewriter` are
+    /// looked up as Arial, Times New Roman and Courier New or their free lookalikes). Texts in device
+    /// fonts are otherwise left out of rendered frames and text images.
+    #[arg(long, value_name = "DIR")]
+    font_dir: Option<PathBuf>,
+}
+impl RenderingOpts {
+    /// Whether any option that only affects rendering differs from its default.
+    fn is_customized(&self) -> bool {
+        self.bake_color_transforms || self.transparent_bg || self.render_scale != 1
+    }
+}
+
+
+#[derive(Args)]
+struct RenderOpts {
+    #[command(flatten)]
+    rendering: RenderingOpts,
+
+    /// The character encoding of the strings (texts, names and frame labels) in movies older than
+    /// version 6; later versions always use UTF-8.
+    #[arg(long, value_enum, default_value_t)]
+    source_encoding: SourceEncoding,
+
+    swf_path: PathBuf,
+}
+
+
+#[derive(Args)]
+struct RepackOpts {
+    /// The compression of the repacked movie, which is written as `<movie>.repacked.swf`.
+    #[arg(long, value_enum, default_value_t)]
+    compression: RepackCompression,
+
+    swf_path: PathBuf,
+}
+
+
+#[derive(Args)]
+struct ServeOpts {
+    /// Handle at most the given number of requests at once; further connections wait until one of
+    /// them has been answered.
+    #[arg(long, default_value_t = 4, value_parser = clap::value_parser!(u16).range(1..))]
+    max_connections: u16,
+
+    /// Close connections on which nothing could be received or sent for the given time (in seconds
+    /// with an optional unit such as `30s` or `2min`).
+    #[arg(long, default_value = "30s", value_parser = parse_duration)]
+    socket_timeout: f64,
+
+    /// The address to listen on, such as `127.0.0.1:8080`.
+    address: String,
+
+    /// The options with which each posted SWF file is extracted, after `--` (such as
+    /// `serve 127.0.0.1:8080 -- --gallery`). Extraction is stopped after five minutes unless the
+    /// global `--timeout` is given.
+    #[arg(last = true)]
+    extract_args: Vec<OsString>,
+}
+
+
+#[derive(Args)]
+struct ExtractOpts {
+    /// Only output the glyphs of embedded fonts that are referenced by texts in the movie.
+    #[arg(long)]
+    subset_fonts: bool,
+
+    /// Collect all text shown in the movie, in timeline order, into a single transcript file.
+    #[arg(long, value_enum, num_args = 0..=1, require_equals = true, default_missing_value = "text")]
+    transcript: Option<TranscriptFormat>,
+
+    /// Insert silence into PCM and ADPCM stream sounds for frames without sound data, keeping the
+    /// output aligned with the timeline.
+    #[arg(long)]
+    pad_stream_silence: bool,
+
+    /// Also mix the PCM and ADPCM stream sounds of sprites into the stream sound of the main
+    /// timeline, starting at the frame at which each sprite is first shown.
+    #[arg(long)]
+    mix_sprite_streams: bool,
+
+    /// The format in which to output uncompressed and ADPCM sounds. The lossy formats require
+    /// `oggenc` or `opusenc` to be installed.
+    #[arg(long, value_enum, default_value_t)]
+    audio_format: AudioFormat,
+
+    /// The bit rate in kbit/s when re-encoding sounds into a lossy format.
+    #[arg(long, default_value_t = 128)]
+    audio_bitrate: u32,
+
+    /// The byte order of 16-bit uncompressed sounds that do not declare it.
+    #[arg(long, value_enum, default_value_t)]
+    pcm_endian: PcmEndian,
+
+    /// Output ADPCM sounds undecoded, along with a JSON file describing their format.
+    #[arg(long)]
+    raw_audio: bool,
+
+    /// Mix stereo uncompressed and ADPCM sounds down to mono.
+    #[arg(long)]
+    audio_mono: bool,
+
+    /// Resample uncompressed and ADPCM sounds to the given sample rate.
+    #[arg(long, value_parser = clap::value_parser!(u16).range(1..))]
+    audio_rate: Option<u16>,
+
+    /// Drop data between MP3 frames that is not part of a valid frame.
+    #[arg(long)]
+    mp3_resync: bool,
+
+    /// Prepend an Xing/Info header to MP3 stream sounds, allowing players to determine their
+    /// duration and to seek.
+    #[arg(long)]
+    xing_header: bool,
+
+    /// Record the origin of sounds in LIST/INFO and bext chunks of WAV files.
+    #[arg(long)]
+    wav_metadata: bool,
+
+    /// Prepend ID3 tags (title, movie name and track number) to MP3 sounds.
+    #[arg(long)]
+    id3: bool,
+
+    /// Do not skip the latency seek samples at the start of MP3 stream sounds.
+    #[arg(long)]
+    ignore_latency_seek: bool,
+
+    /// Collect all user-visible strings into a file for translation.
+    #[arg(long, value_enum)]
+    localization: Option<LocalizationFormat>,
+
+    /// The character encoding of the strings (texts, names and frame labels) in movies older than
+    /// version 6; later versions always use UTF-8.
+    #[arg(long, value_enum, default_value_t)]
+    source_encoding: SourceEncoding,
+
+    /// Only extract characters that are placed on a timeline or exported, directly or through other
+    /// characters.
+    #[arg(long, conflicts_with = "only_unused")]
+    only_used: bool,
+
+    /// Only extract characters that are neither placed on a timeline nor exported.
+    #[arg(long)]
+    only_unused: bool,
+
+    /// Write a graph of the references between characters (shapes to bitmaps, buttons and sprites
+    /// to the characters they display, texts to fonts) into `references.dot` or `references.json`.
+    #[arg(long, value_enum)]
+    reference_graph: Option<GraphFormat>,
+
+    /// Summarize each ActionScript 3 (DoABC) block into a JSON file listing its classes with their
+    /// members and method signatures, namespaces and string constants.
+    #[arg(long)]
+    abc_summary: bool,
+
+    /// Write data hidden in the constants of each ActionScript 3 (DoABC) block into files, named
+    /// after the block and given extensions by their contents: long strings of Base64, hexadecimal
+    /// digits or binary data and long array literals of bytes (such as those filling a
+    /// `ByteArray`), inflated if compressed with zlib.
+    #[arg(long)]
+    abc_payloads: bool,
+
+    /// Name the files of characters bound to ActionScript 3 classes by SymbolClass after the fully
+    /// qualified class name (such as `assets.sounds.BossTheme.mp3`) instead of the character ID.
+    /// Always done for SWC component libraries.
+    #[arg(long)]
+    symbol_names: bool,
+
+    /// If the movie is a loader that stores the actual movie in binary data (as is, compressed with
+    /// zlib or LZMA, or XORed with a single byte), extract from the actual movie instead. Packed
+    /// movies are always detected and noted in the manifest.
+    #[arg(long)]
+    unpack: bool,
+
+    /// Write a report of the external resources referenced by the movie (imported movies and the
+    /// constant URLs passed to `getURL`, `loadMovie` and the like, or found in scripts) into
+    /// `urls.json`.
+    #[arg(long)]
+    url_report: bool,
+
+    /// Write a machine-readable report of the run into `report.json`: whether each character has
+    /// been written, skipped or failed and why, the duration of each phase and totals. The report is
+    /// also written if extraction is aborted by an error.
+    #[arg(long)]
+    report: bool,
+
+    /// Measure the time spent on each phase of the run and on decoding, encoding and writing each
+    /// asset, and print a summary listing the given number of slowest assets.
+    #[arg(long, value_name = "COUNT", num_args = 0..=1, require_equals = true, default_missing_value = "10")]
+    profile: Option<usize>,
+
+    /// The format in which to output color-mapped bitmaps without transparency.
+    #[arg(long, value_enum, default_value_t)]
+    indexed_format: IndexedFormat,
+
+    /// Resize bitmaps using a Lanczos filter, either by a factor (`2x`), a percentage (`50%`) or
+    /// to the given dimensions (`640x480`). Resized bitmaps are written as PNG files.
+    #[arg(long, value_parser = parse_image_scale)]
+    image_scale: Option<ImageScale>,
+
+    /// Write bitmaps as headerless 8-bit RGBA pixels (`.raw`) instead of PNG, JPEG or GIF files,
+    /// each described by a `.raw.json` file giving its width, height and stride.
+    #[arg(long)]
+    raw_pixels: bool,
+
+    /// Also write previews fitting into a square of the given size in pixels into `thumbnails/`:
+    /// PNG images for bitmaps and scaled-down SVG documents for shapes, texts and rendered sprites,
+    /// along with `thumbnails/index.html` showing them.
+    #[arg(long, value_name = "SIZE", value_parser = clap::value_parser!(u32).range(1..))]
+    thumbnails: Option<u32>,
+
+    /// Write `index.html`, a page linking to the extracted assets grouped by type, which shows
+    /// images (or their thumbnails, see `--thumbnails`), shapes and texts inline and plays sounds
+    /// and videos.
+    #[arg(long)]
+    gallery: bool,
+
+    /// Skip bitmaps narrower or lower than the given size, given as `WIDTHxHEIGHT` in pixels.
+    #[arg(long, value_parser = parse_dimensions)]
+    min_image_size: Option<(u32, u32)>,
+
+    /// Skip sounds shorter than the given duration, given in seconds with an optional unit such as
+    /// `2s` or `500ms`.
+    #[arg(long, value_parser = parse_duration)]
+    min_sound_duration: Option<f64>,
+
+    /// Skip bitmaps, sounds and binary data stored in fewer than the given number of bytes, with an
+    /// optional binary unit such as `10k` or `1M`.
+    #[arg(long, value_parser = parse_byte_size)]
+    min_bytes: Option<u64>,
+
+    /// Skip bitmaps with more than the given number of pixels, warning about each; lossless bitmaps
+    /// exceeding it are not even decoded.
+    #[arg(long)]
+    max_image_pixels: Option<u64>,
+
+    /// Skip sounds longer than the given number of minutes, warning about each.
+    #[arg(long)]
+    max_audio_minutes: Option<f64>,
+
+    /// Write the files of at most the given number of characters, skipping the ones defined later
+    /// with a warning each.
+    #[arg(long)]
+    max_assets: Option<usize>,
+
+    /// Record the SHA-256 digests of the written files in the given index file, which is shared
+    /// across runs, and list the files identical to ones recorded before (from this or earlier
+    /// movies) in `duplicates.json`.
+    #[arg(long)]
+    duplicate_index: Option<PathBuf>,
+
+    /// Replace files identical to ones recorded in the duplicate index by links to them.
+    #[arg(long, value_enum, requires = "duplicate_index")]
+    link_duplicates: Option<LinkKind>,
+
+    /// Replace the extensions of output sounds, bitmaps, SVG files and binary data, given as a
+    /// comma-separated list of `original=replacement` pairs such as `mp3=mpga,svg=xml`.
+    #[arg(long, value_delimiter = ',', value_parser = parse_extension_mapping)]
+    ext_map: Vec<(String, String)>,
+
+    /// Write the undecoded body of every top-level tag into the `tags` directory, along with an
+    /// index of their offsets and lengths.
+    #[arg(long)]
+    dump_tags: bool,
+
+    /// Write the contents of every frame of every timeline (placed characters with their
+    /// transformation matrices and color transforms) into `timeline.json` and
+    /// `<sprite>.timeline.json`.
+    #[arg(long)]
+    timeline: bool,
+
+    /// Compose every frame of every timeline from its shapes and sprites into an SVG file.
+    #[arg(long)]
+    render_frames: bool,
+
+    #[command(flatten)]
+    rendering: RenderingOpts,
+
     swf_path: PathBuf,
+
+    /// Only render frames, as the `render` subcommand does, without writing the assets.
+    #[arg(skip)]
+    render_only: bool,
+}
+
+
+/// Parses an `original=replacement` pair of extensions.
+fn parse_extension_mapping(s: &str) -> Result<(String, String), String> {
+    let (original, replacement) = s.split_once('=')
+        .ok_or_else(|| format!("expected original=replacement, got {:?}", s))?;
+    let original = original.trim_start_matches('.');
+    let replacement = replacement.trim_start_matches('.');
+    if original.is_empty() || replacement.is_empty() {
+        return Err(format!("empty extension in {:?}", s));
+    }
+    if replacement.contains(['/', '\\']) {
+        return Err(format!("extension {:?} contains a path separator", replacement));
+    }
+    Ok((original.to_owned(), replacement.to_owned()))
+}
+
+
+/// Parses dimensions of the form `WIDTHxHEIGHT`.
+fn parse_dimensions(s: &str) -> Result<(u32, u32), String> {
+    let (width, height) = s.split_once(['x', 'X'])
+        .ok_or_else(|| format!("expected WIDTHxHEIGHT, got {:?}", s))?;
+    let width = width.trim().parse().map_err(|e| format!("invalid width {:?}: {}", width, e))?;
+    let height = height.trim().parse().map_err(|e| format!("invalid height {:?}: {}", height, e))?;
+    Ok((width, height))
+}
+
+/// Parses a scale of the form `FACTORx`, `PERCENT%` or `WIDTHxHEIGHT`.
+fn parse_image_scale(s: &str) -> Result<ImageScale, String> {
+    let s = s.trim();
+    let factor = if let Some(factor) = s.strip_suffix(['x', 'X']) {
+        factor.trim().parse::<f64>().map_err(|e| format!("invalid factor {:?}: {}", s, e))?
+    } else if let Some(percentage) = s.strip_suffix('%') {
+        percentage.trim().parse::<f64>().map_err(|e| format!("invalid percentage {:?}: {}", s, e))? / 100.0
+    } else {
+        let (width, height) = parse_dimensions(s)?;
+        if width == 0 || height == 0 {
+            return Err(format!("invalid dimensions {:?}", s));
+        }
+        return Ok(ImageScale::Size(width, height));
+    };
+    if !factor.is_finite() || factor <= 0.0 {
+        return Err(format!("invalid scale {:?}", s));
+    }
+    Ok(ImageScale::Factor(factor))
+}
+
+/// Parses a duration in seconds, optionally suffixed by `s`, `ms` or `min`.
+fn parse_duration(s: &str) -> Result<f64, String> {
+    let s = s.trim();
+    let (number, factor) = if let Some(n) = s.strip_suffix("ms") {
+        (n, 0.001)
+    } else if let Some(n) = s.strip_suffix("min") {
+        (n, 60.0)
+    } else {
+        (s.strip_suffix('s').unwrap_or(s), 1.0)
+    };
+    let value: f64 = number.trim().parse().map_err(|e| format!("invalid duration {:?}: {}", s, e))?;
+    if !value.is_finite() || value < 0.0 {
+        return Err(format!("invalid duration {:?}", s));
+    }
+    Ok(value * factor)
+}
+
+/// Parses a number of bytes, optionally suffixed by a binary unit (`k`, `M` or `G`, with or without a
+/// trailing `B` or `iB`).
+fn parse_byte_size(s: &str) -> Result<u64, String> {
+    let s = s.trim();
+    let number_end = s.find(|c: char| !c.is_ascii_digit()).unwrap_or(s.len());
+    let (number, unit) = s.split_at(number_end);
+    let value: u64 = number.parse().map_err(|e| format!("invalid size {:?}: {}", s, e))?;
+    let unit = unit.trim();
+    let unit = unit.strip_suffix("iB").or_else(|| unit.strip_suffix('B')).unwrap_or(unit);
+    let factor: u64 = match unit {
+        "" => 1,
+        "k"|"K" => 1 << 10,
+        "m"|"M" => 1 << 20,
+        "g"|"G" => 1 << 30,
+        other => return Err(format!("unknown unit {:?} in {:?}", other, s)),
+    };
+    value.checked_mul(factor).ok_or_else(|| format!("size {:?} is too large", s))
+}
+
+
+/// State shared across all timelines of the movie being extracted.
+struct Context {
+    swf_version: u8,
+
+    /// The encoding of the strings in the movie.
+    string_encoding: &'static Encoding,
+
+    frame_rate: Fixed8,
+    script_kind: ScriptKind,
+    pad_stream_silence: bool,
+    mix_sprite_streams: bool,
+    apply_latency_seek: bool,
+    mp3_resync: bool,
+    xing_header: bool,
+    audio_options: AudioOptions,
+
+    /// Glyphs referenced by texts; only set if fonts are to be subset.
+    glyph_usage: Option<GlyphUsage>,
+
+    /// Names of the fonts encountered so far.
+    font_names: HashMap<CharacterId, String>,
+
+    /// Code tables (glyph index to UCS-2 code) of the fonts encountered so far.
+    font_code_tables: HashMap<CharacterId, Vec<u16>>,
+
+    /// Texts shown in the movie; only set if a transcript has been requested.
+    transcript: Option<Transcript>,
+
+    /// Strings to translate; only set if a localization export has been requested.
+    localization: Option<LocalizationExport>,
+
+    /// The names under which characters are exported, or, failing that, the names given to them
+    /// by NameCharacter tags.
+    export_names: HashMap<CharacterId, String>,
+
+    /// The ActionScript 3 classes bound to characters by SymbolClass tags, in the order in which
+    /// they are bound. The class bound to ID 0 is the document class of the main timeline.
+    symbol_classes: Vec<(CharacterId, String)>,
+
+    /// The superclasses of the ActionScript 3 classes defined by the movie; only collected if
+    /// classes are bound to characters.
+    superclasses: HashMap<String, String>,
+
+    /// The names of the components of a SWC component library, by class name.
+    component_names: HashMap<String, String>,
+
+    /// The names of the files of the characters.
+    file_stems: FileStems,
+
+    /// Replacements for the extensions of output files, from `--ext-map`.
+    extension_map: HashMap<String, String>,
+
+    /// The references between characters and where they are placed.
+    reference_graph: ReferenceGraph,
+
+    /// Characters reachable from the main timeline or from an exported character.
+    used_characters: HashSet<CharacterId>,
+
+    extract_used: bool,
+    extract_unused: bool,
+
+    /// Only the timelines are rendered, as by the `render` subcommand; the files of the characters
+    /// are not written.
+    render_only: bool,
+
+    /// Whether the main timeline is exported as a movie, which requires its stream sound.
+    export_movie: bool,
+
+    abc_summary: bool,
+    abc_payloads: bool,
+    write_timelines: bool,
+    render_frames: bool,
+    bake_color_transforms: bool,
+
+    /// The color with which to fill the background of rendered frames, if any.
+    background_color: Option<Color>,
+
+    render_scale: u32,
+
+    /// The most sprites drawn within each other when rendering frames.
+    max_sprite_depth: usize,
+
+    #[cfg(feature = "mp4-encode")]
+    encode_mp4: bool,
+
+    /// The rendered frames of the main timeline, in order.
+    main_frame_files: Vec<String>,
+
+    /// The file name and start frame of the first stream sound of the main timeline, if any.
+    main_stream: Option<(String, u32)>,
+
+    /// The frames of the main timeline to which its output is restricted, if any.
+    frame_range: Option<Range<u32>>,
+
+    /// The area of the main timeline, to which rendered frames of it are cropped.
+    stage: Rectangle,
+
+    /// The shapes encountered so far.
+    shapes: HashMap<CharacterId, Shape>,
+
+    /// The texts encountered so far, laid out for drawing.
+    texts: HashMap<CharacterId, TextDrawing>,
+
+    /// The glyphs of the fonts encountered so far, with which texts are drawn.
+    fonts: HashMap<CharacterId, FontOutlines>,
+
+    /// The fonts standing in for device fonts, from `--font-dir`.
+    font_dir: Option<FontDirectory>,
+
+    /// The timelines of the sprites encountered so far; only collected if frames are to be rendered.
+    timelines: HashMap<CharacterId, Timeline>,
+
+    /// The areas covered by the shapes and sprites encountered so far, relative to their
+    /// registration points.
+    character_bounds: HashMap<CharacterId, Rectangle>,
+
+    /// The file name of the movie and the time extraction started; only set if WAV metadata has
+    /// been requested.
+    wav_provenance: Option<(String, SystemTime)>,
+
+    /// The album name to put into ID3 tags; only set if ID3 tags have been requested.
+    id3_album: Option<String>,
+
+    /// The number of MP3 sounds tagged so far.
+    id3_track: u32,
+
+    /// Stream sounds of sprites yet to be mixed into the main timeline's stream sound, with the frame
+    /// of the main timeline at which they start; only collected if mixing has been requested.
+    sprite_streams: Vec<(CharacterId, u32, Sound)>,
+
+    /// The ways in which each sound is played, collected from StartSound and DefineButtonSound.
+    sound_usages: HashMap<CharacterId, Vec<JsonValue>>,
+
+    /// Decoders for the Screen Video streams encountered so far.
+    screen_video_decoders: HashMap<CharacterId, ScreenVideoDecoder>,
+
+    /// IDs of the video streams declaring the H.264 codec.
+    avc_stream_ids: HashSet<CharacterId>,
+
+    /// The H.264 video streams encountered so far, muxed once extraction has finished.
+    avc_streams: HashMap<CharacterId, AvcStream>,
+
+    /// The H.263 and VP6 video streams encountered so far, packaged once extraction has finished.
+    flv_streams: HashMap<CharacterId, FlvVideoStream>,
+
+    manifest: Manifest,
+
+    /// The timing of the phases of the run and the characters that failed to be extracted.
+    report: RunReport,
+
+    /// The time spent on each asset.
+    profile: Profile,
+
+    /// The locations of the tags defining each character.
+    tag_locations: HashMap<CharacterId, TagLocation>,
+
+    /// The format in which color-mapped bitmaps without transparency are output.
+    indexed_format: IndexedFormat,
+
+    /// How to resize bitmaps.
+    image_scale: Option<ImageScale>,
+
+    /// Whether bitmaps are written as raw RGBA pixels.
+    raw_pixels: bool,
+
+    /// The minimum width and height of bitmaps to extract.
+    min_image_size: Option<(u32, u32)>,
+
+    /// The minimum duration of sounds to extract, in seconds.
+    min_sound_duration: Option<f64>,
+
+    /// The minimum length of the tags defining bitmaps, sounds and binary data to extract.
+    min_bytes: Option<u64>,
+
+    /// The maximum number of pixels of bitmaps to extract.
+    max_image_pixels: Option<u64>,
+
+    /// The maximum duration of sounds to extract, in minutes.
+    max_audio_minutes: Option<f64>,
+
+    /// The maximum number of characters whose files are written.
+    max_assets: Option<usize>,
+
+    /// Whether each character checked against the resource limits has been found within them.
+    asset_admissions: HashMap<CharacterId, bool>,
+
+    /// The number of characters found within the resource limits.
+    admitted_count: usize,
+
+    /// The number of bytes of decoded bitmaps beyond which they are written out early.
+    max_memory: Option<u64>,
+
+    /// The JPEG encoding tables shared by all DefineBits tags, without their start-of-image and
+    /// end-of-image markers; empty if the movie has none.
+    jpeg_tables: Vec<u8>,
+
+    /// Stops extraction once `--timeout` has elapsed, including the decoding of single assets.
+    cancellation: Cancellation,
+
+    /// Whether extraction has been stopped because the deadline has passed.
+    timed_out: bool,
+}
+
+
+/// Describes a frame on a timeline in a human-readable fashion.
+pub(crate) fn describe_location(sprite_id: Option<CharacterId>, frame: u32) -> String {
+    match sprite_id {
+        Some(id) => format!("sprite {}, frame {}", id, frame),
+        None => format!("main timeline, frame {}", frame),
+    }
+}
+
+/// Returns the current position within the stream sound, if any.
+fn stream_position(stream_sound: Option<&Sound>) -> StreamPosition {
+    match stream_sound {
+        Some(snd) => {
+            let (sample_offset, byte_offset) = snd.padded_length();
+            StreamPosition { sample_offset, byte_offset }
+        },
+        None => StreamPosition::default(),
+    }
+}
+
+/// Returns a short description of a timeline, suitable as part of a key.
+fn timeline_key(sprite_id: Option<CharacterId>) -> String {
+    match sprite_id {
+        Some(id) => format!("sprite{}", id),
+        None => "main".to_owned(),
+    }
+}
+
+
+/// Returns an ID3 tag to prepend to the given sound, or an empty vector if ID3 tags have not been
+/// requested or the sound is not output as MP3.
+fn id3_tag(context: &mut Context, sound: &Sound, title: &str) -> Vec<u8> {
+    let Some(album) = context.id3_album.clone() else { return Vec::new() };
+    if sound.format.compression != AudioCompression::Mp3 {
+        return Vec::new();
+    }
+    context.id3_track += 1;
+    let mut tag = Id3Tag::new();
+    tag.add_text(b"TIT2", title);
+    tag.add_text(b"TALB", &album);
+    tag.add_text(b"TRCK", &context.id3_track.to_string());
+    tag.to_bytes()
+}
+
+/// Returns the provenance to record in the given sound, if WAV metadata has been requested.
+fn provenance(context: &Context, title: &str, character_id: Option<CharacterId>) -> Option<Provenance> {
+    let (source, extraction_time) = context.wav_provenance.clone()?;
+    Some(Provenance {
+        source,
+        title: title.to_owned(),
+        character_id,
+        extraction_time,
+    })
+}
+
+/// Collects the names under which characters are exported.
+fn collect_export_names(tags: &[Tag], encoding: &'static Encoding) -> HashMap<CharacterId, String> {
+    let mut export_names = HashMap::new();
+    for tag in tags {
+        match tag {
+            Tag::ExportAssets(assets) => {
+                for asset in assets {
+                    export_names.insert(asset.id, decode_string(asset.name, encoding));
+                }
+            },
+            Tag::NameCharacter(nc) => {
+                // only used by very old movies; exported names take precedence
+                export_names.entry(nc.id)
+                    .or_insert_with(|| decode_string(nc.name, encoding));
+            },
+            _ => {},
+        }
+    }
+    export_names
+}
+
+/// Collects the ActionScript 3 classes bound to characters.
+fn collect_symbol_classes(tags: &[Tag], encoding: &'static Encoding) -> Vec<(CharacterId, String)> {
+    let mut symbol_classes = Vec::new();
+    for tag in tags {
+        if let Tag::SymbolClass(links) = tag {
+            for link in links {
+                symbol_classes.push((link.id, decode_string(link.class_name, encoding)));
+            }
+        }
+    }
+    symbol_classes
+}
+
+/// Collects the superclasses of the ActionScript 3 classes defined in the DoABC blocks of the main
+/// timeline.
+fn collect_superclasses(tags: &[Tag]) -> HashMap<String, String> {
+    let mut superclasses = HashMap::new();
+    for tag in tags {
+        if let Tag::DoAbc(abc) = tag {
+            superclasses.extend(superclass_names(abc.data));
+        }
+    }
+    superclasses
+}
+
+/// Whether the files of the character with the given ID are to be written, according to whether it
+/// is used and which of `--only-used` and `--only-unused` has been passed.
+fn is_extracted(context: &Context, id: CharacterId) -> bool {
+    if context.used_characters.contains(&id) {
+        context.extract_used
+    } else {
+        context.extract_unused
+    }
+}
+
+/// Finds the JPEG encoding tables of the movie, skipping JpegTables tags that contain no tables.
+fn collect_jpeg_tables(tags: &[Tag]) -> Vec<u8> {
+    for tag in tags {
+        if let Tag::JpegTables(jt) = tag {
+            let tables = strip_jpeg_framing(jt);
+            if !tables.is_empty() {
+                return Vec::from(tables);
+            }
+        }
+    }
+    Vec::new()
+}
+
+/// Checks whether the deadline has passed, announcing it the first time it is found to have.
+fn is_past_deadline(context: &mut Context) -> bool {
+    if context.timed_out {
+        return true;
+    }
+    if context.cancellation.is_cancelled() {
+        status!("timed out; stopping extraction");
+        context.timed_out = true;
+    }
+    context.timed_out
+}
+
+/// Checks a bitmap, sound or binary data against the size filters, reporting it as skipped if it
+/// does not pass them.
+///
+/// `dimensions` is given for bitmaps and `duration` (in seconds) for sounds.
+fn passes_size_filters(context: &mut Context, id: CharacterId, dimensions: Option<(u32, u32)>, duration: Option<f64>) -> bool {
+    let mut reason = None;
+    let length = context.tag_locations.get(&id).map(|l| l.length);
+    if let (Some(min_bytes), Some(length)) = (context.min_bytes, length) {
+        if (length as u64) < min_bytes {
+            reason = Some(format!("{} bytes is less than {} bytes", length, min_bytes));
+        }
+    }
+    if let (Some((min_width, min_height)), Some((width, height))) = (context.min_image_size, dimensions) {
+        if width < min_width || height < min_height {
+            reason = Some(format!("{}x{} is smaller than {}x{}", width, height, min_width, min_height));
+        }
+    }
+    if let (Some(min_duration), Some(duration)) = (context.min_sound_duration, duration) {
+        if duration < min_duration {
+            reason = Some(format!("{:.3} s is shorter than {} s", duration, min_duration));
+        }
+    }
+
+    let Some(reason) = reason else { return within_limits(context, id, dimensions, duration) };
+    status!("skipping {}: {}", context.reference_graph.asset_path(id), reason);
+    context.report.skip(id, reason);
+    false
+}
+
+/// Checks the given bitmap dimensions or sound duration (in seconds) against the resource limits,
+/// returning the reason if they exceed them.
+fn exceeded_limit(context: &Context, dimensions: Option<(u32, u32)>, duration: Option<f64>) -> Option<String> {
+    if let (Some(max_pixels), Some((width, height))) = (context.max_image_pixels, dimensions) {
+        if u64::from(width) * u64::from(height) > max_pixels {
+            return Some(format!("{}x{} is more than {} pixels", width, height, max_pixels));
+        }
+    }
+    if let (Some(max_minutes), Some(duration)) = (context.max_audio_minutes, duration) {
+        if duration / 60.0 > max_minutes {
+            return Some(format!("{:.1} min is longer than {} min", duration / 60.0, max_minutes));
+        }
+    }
+    None
+}
+
+/// Reports a character as skipped for exceeding a resource limit, warning about it.
+fn skip_over_limit(context: &mut Context, id: CharacterId, reason: String) {
+    let message = format!("skipping {}: {}", context.reference_graph.asset_path(id), reason);
+    warn(context, Warning::about(WarningCode::ExceededLimit, id, message));
+    context.report.skip(id, reason);
+}
+
+/// Checks a character whose files are about to be written against the resource limits, reporting
+/// it as skipped with a warning if it exceeds them. Characters within them count towards
+/// `--max-assets`; each character is only checked once.
+fn within_limits(context: &mut Context, id: CharacterId, dimensions: Option<(u32, u32)>, duration: Option<f64>) -> bool {
+    if let Some(admitted) = context.asset_admissions.get(&id) {
+        return *admitted;
+    }
+    let reason = exceeded_limit(context, dimensions, duration)
+        .or_else(|| context.max_assets
+            .filter(|max_assets| context.admitted_count >= *max_assets)
+            .map(|max_assets| format!("the limit of {} assets has been reached", max_assets))
+        );
+    context.asset_admissions.insert(id, reason.is_none());
+    let Some(reason) = reason else {
+        context.admitted_count += 1;
+        return true;
+    };
+    skip_over_limit(context, id, reason);
+    false
+}
+
+/// Checks whether a bitmap, sound or binary data is a placeholder without any data, as emitted by
+/// some exporters. Placeholders are noted in the manifest and reported as skipped instead of being
+/// written out as empty or undecodable files.
+fn is_placeholder(context: &mut Context, id: CharacterId, kind: &str, data: &[u8]) -> bool {
+    if !data.is_empty() {
+        return false;
+    }
+    if is_extracted(context, id) {
+        status!("skipping {}: placeholder without data", context.reference_graph.asset_path(id));
+        context.manifest.asset(id, kind).insert("placeholder", true);
+        context.report.skip(id, "placeholder without data");
+    }
+    true
+}
+
+/// Outputs a problem that did not prevent extraction and records it for the summary and the report.
+fn warn(context: &mut Context, warning: Warning) {
+    let path = warning.character_id.map(|id| context.reference_graph.asset_path(id));
+    status!("{}", warning);
+    context.report.warn(warning, path);
+}
+
+/// Returns the name of a file of the character with the given ID without extension, including the
+/// name under which the character is exported (or named by NameCharacter) or the class bound to it,
+/// as assigned by [`FileStems`].
+fn character_file_stem(context: &Context, filename_prefix: &str, id: CharacterId) -> String {
+    format!("{}{}", filename_prefix, context.file_stems.get(id))
+}
+
+/// Returns the name of a file of the character with the given ID as [`character_file_stem`] does,
+/// but including the given name in place of the ID alone.
+fn named_character_file_stem(context: &Context, filename_prefix: &str, id: CharacterId, name: &str) -> String {
+    format!("{}{}", filename_prefix, context.file_stems.get_named(id, name))
+}
+
+
+/// Checks the result of writing a listing to standard output. The reader (such as `head`) going
+/// away before the listing has been written in full is no failure.
+fn end_listing(result: std::io::Result<()>, failure: &str) {
+    if let Err(e) = result {
+        if e.kind() != std::io::ErrorKind::BrokenPipe {
+            panic!("{}: {}", failure, e);
+        }
+    }
+}
+
+
+/// Returns the extension to give an output file in place of the given one, honoring `--ext-map`.
+fn output_extension<'a>(context: &'a Context, extension: &'a str) -> &'a str {
+    context.extension_map.get(extension)
+        .map(|e| e.as_str())
+        .unwrap_or(extension)
+}
+
+
+/// Returns the name of a stream sound file without extension.
+///
+/// Streams are only numbered if the timeline contains more than one.
+fn stream_file_stem(filename_prefix: &str, stream_index: usize, multiple_streams: bool) -> String {
+    if multiple_streams {
+        format!("{}stream-{}", filename_prefix, stream_index)
+    } else {
+        format!("{}stream", filename_prefix)
+    }
+}
+
+/// Writes a stream sound along with its cue and sync files.
+fn write_stream(context: &mut Context, sprite_id: Option<CharacterId>, stream_stem: &str, mut ssnd: Sound, sync: &TimelineSync) {
+    if ssnd.data.is_empty() {
+        return;
+    }
+
+    let position = sprite_id.and_then(|id| context.reference_graph.timeline_position(id));
+    if let (Some(id), Some((_, first_shown)), true) = (sprite_id, &position, context.mix_sprite_streams) {
+        let start_frame = first_shown + sync.first_frame() - 1;
+        context.sprite_streams.push((id, start_frame, ssnd.clone()));
+    }
+    let mut mixed_sprites = Vec::new();
+    if sprite_id.is_none() {
+        let mut i = 0;
+        while i < context.sprite_streams.len() {
+            let (id, start_frame, sprite_stream) = &context.sprite_streams[i];
+            let mixed = match sync.frame_start_sample(*start_frame) {
+                Some(offset) => ssnd.mix_in(sprite_stream, offset, context.audio_options.pcm_endian),
+                None => false,
+            };
+            if mixed {
+                status!("mixed stream sound of sprite {} into {}", id, stream_stem);
+                mixed_sprites.push(*id);
+                context.sprite_streams.remove(i);
+            } else {
+                i += 1;
+            }
+        }
+    }
+
+    let mut sync = sync.clone();
+    let removed = if context.mp3_resync { ssnd.resync_mp3() } else { Vec::new() };
+    sync.remove_byte_ranges(&removed);
+    let xing_len = if context.xing_header { ssnd.prepend_xing_frame() } else { 0 };
+
+    let title = sprite_id
+        .and_then(|id| context.export_names.get(&id).cloned())
+        .unwrap_or_else(|| stream_stem.to_owned());
+    let tag = id3_tag(context, &ssnd, &title);
+    ssnd.provenance = provenance(context, &title, None);
+
+    let file_name = format!("{}.{}", stream_stem, output_extension(context, ssnd.extension(context.audio_options.format)));
+    let mut f = File::create(&file_name)
+        .expect("failed to open stream file");
+    f.write_all(&tag)
+        .expect("failed to write stream file");
+    ssnd.write(&context.audio_options, f)
+        .expect("failed to write stream file");
+    if sprite_id.is_none() && context.main_stream.is_none() {
+        context.main_stream = Some((file_name.clone(), sync.first_frame()));
+    }
+    let discarded: usize = removed.iter().map(|(_, len)| len).sum();
+    if discarded > 0 {
+        warn(context, Warning::new(
+            WarningCode::TrimmedAudio, format!("{}: discarded {} bytes of invalid MP3 data", file_name, discarded),
+        ));
+    }
+    let stream_entry = context.manifest.add_stream(sprite_id, &file_name);
+    stream_entry.insert("start_frame", sync.first_frame());
+    if let Some((path, first_shown)) = &position {
+        let parent = if path.len() > 1 { Some(path[path.len() - 2]) } else { None };
+        stream_entry.insert("parent_sprite", parent);
+        stream_entry.insert("sprite_path", path.clone());
+        stream_entry.insert("main_timeline_frame", first_shown + sync.first_frame() - 1);
+    } else if sprite_id.is_none() && context.mix_sprite_streams {
+        stream_entry.insert("mixed_sprites", mixed_sprites);
+    }
+    if context.mp3_resync {
+        stream_entry.insert("discarded_bytes", discarded);
+    }
+
+    // the cues refer to the samples of the output file
+    let sample_rate = ssnd.output_sample_rate(&context.audio_options);
+    sync.resample(ssnd.format.sample_rate, sample_rate);
+
+    if let Some(descriptor) = ssnd.raw_adpcm_descriptor() {
+        let descriptor_file_name = format!("{}.adpcm.json", stream_stem);
+        let f = File::create(&descriptor_file_name)
+            .expect("failed to open stream descriptor file");
+        descriptor.write(f)
+            .expect("failed to write stream descriptor file");
+        stream_entry.insert("descriptor", descriptor_file_name);
+    }
+
+    let cue_file_name = format!("{}.cue.json", stream_stem);
+    let f = File::create(&cue_file_name)
+        .expect("failed to open cue file");
+    let data_offset = ssnd.data_offset(&context.audio_options).map(|o| o + tag.len() + xing_len);
+    sync.to_cue_json(sample_rate, data_offset).write(f)
+        .expect("failed to write cue file");
+    stream_entry.insert("cues", cue_file_name);
+
+    let file_type = if ssnd.extension(context.audio_options.format) == "mp3" { "MP3" } else { "WAVE" };
+    if let Some(cue_sheet) = sync.to_cue_sheet(&file_name, file_type, sample_rate) {
+        let cue_sheet_file_name = format!("{}.cue", stream_stem);
+        let mut f = File::create(&cue_sheet_file_name)
+            .expect("failed to open cue sheet file");
+        f.write_all(cue_sheet.as_bytes())
+            .expect("failed to write cue sheet file");
+        stream_entry.insert("cue_sheet", cue_sheet_file_name);
+    }
+
+    if sync.has_video() {
+        let sync_file_name = format!("{}.sync.json", stream_stem);
+        let f = File::create(&sync_file_name)
+            .expect("failed to open sync file");
+        sync.to_json(context.frame_rate, &file_name, sample_rate).write(f)
+            .expect("failed to write sync file");
+        stream_entry.insert("sync", sync_file_name);
+    }
+}
+
+/// Writes the metrics of a font into a JSON file and its glyphs into an SVG font.
+fn extract_font(context: &mut Context, tags: &[Tag], font: &Font, filename_prefix: &str) {
+    // prefer the full name from DefineFontName, if available
+    let font_name = find_font_name(tags, font.id, context.string_encoding)
+        .unwrap_or_else(|| decode_string(font.name, context.string_encoding));
+    let file_stem = named_character_file_stem(context, filename_prefix, font.id, &font_name);
+    context.font_names.insert(font.id, font_name.clone());
+    context.font_code_tables.insert(font.id, font.glyphs.iter().map(|g| g.code).collect());
+    let device_font = match &mut context.font_dir {
+        Some(font_dir) if !has_outlines(font) => {
+            let bold = font.flags.contains(FontFlag::IS_BOLD);
+            let italic = font.flags.contains(FontFlag::IS_ITALIC);
+            let device_font = font_dir.find(&font_name, bold, italic);
+            if device_font.is_none() {
+                warn(context, Warning::about(
+                    WarningCode::MissingFont, font.id,
+                    format!("no font named {:?} in the font directory; texts in it are not drawn", font_name),
+                ));
+            }
+            device_font
+        },
+        _ => None,
+    };
+    let outlines = match device_font {
+        Some(device_font) => FontOutlines::from_device_font(font, device_font),
+        None => FontOutlines::new(font),
+    };
+    context.fonts.insert(font.id, outlines);
+    let font_entry = context.manifest.asset(font.id, "font");
+    font_entry.insert("name", font_name.as_str());
+    font_entry.insert("language", format!("{:?}", font.language));
+    if !is_extracted(context, font.id) || !within_limits(context, font.id, None, None) {
+        return;
+    }
+
+    let filename = format!("{}.json", file_stem);
+    let f = File::create(&filename)
+        .expect("failed to open font metrics file");
+    font_to_json(font, context.string_encoding).write(f)
+        .expect("failed to write font metrics file");
+    context.manifest.add_file(font.id, "font", &filename);
+
+    let font_data = match &context.glyph_usage {
+        Some(usage) => font_to_svg(font, context.string_encoding, |i| usage.is_used(font, i)),
+        None => font_to_svg(font, context.string_encoding, |_| true),
+    };
+    let filename = format!("{}.{}", file_stem, output_extension(context, "svg"));
+    let mut f = File::create(&filename)
+        .expect("failed to open SVG font file");
+    f.write_all(font_data.as_bytes())
+        .expect("failed to write SVG font file");
+    context.manifest.add_file(font.id, "font", &filename);
+}
+
+
+fn process_tags(context: &mut Context, sprite_id: Option<CharacterId>, tags: &[Tag]) {
+    let filename_prefix = match sprite_id {
+        Some(id) => format!("{}-", id),
+        None => String::new(),
+    };
+    // label stream sounds with the sprites through which they are shown
+    let stream_prefix = match sprite_id.and_then(|id| context.reference_graph.timeline_position(id)) {
+        Some((path, _)) => path.iter().map(|id| format!("{}-", id)).collect(),
+        None => filename_prefix.clone(),
+    };
+    let mut stream_sound: Option<Sound> = None;
+    let mut id_to_bitmap = HeldBitmaps::default();
+    let mut frame: u32 = 1;
+    let mut sync = TimelineSync::new(frame);
+    let mut stream_index: usize = 0;
+    let mut stream_samples_per_block: u16 = 0;
+    let mut stream_block_in_frame = false;
+    let mut timeline = Timeline::new();
+    let mut abc_index: usize = 0;
+    // the number of DoAction tags in the current frame
+    let mut frame_action_count: usize = 0;
+    // only the main timeline is restricted to a range of frames
+    let frame_range = if sprite_id.is_none() { context.frame_range.clone() } else { None };
+    let is_in_range = |frame: u32| frame_range.as_ref().is_none_or(|r| r.contains(&frame));
+    let multiple_streams = tags.iter()
+        .filter(|t| matches!(t, Tag::SoundStreamHead(_)|Tag::SoundStreamHead2(_)))
+        .count() > 1;
+    context.profile.begin_timeline();
+    for tag in tags {
+        context.profile.end_asset();
+        // keep what has been extracted so far; the held sounds and bitmaps are written out below
+        if is_past_deadline(context) {
+            break;
+        }
+        if let Some(max_memory) = context.max_memory {
+            if id_to_bitmap.data_len as u64 > max_memory {
+                write_bitmaps(context, &filename_prefix, &mut id_to_bitmap);
+            }
+        }
+        if let Some(id) = profiled_character(tag) {
+            let kind = context.reference_graph.kind(id).unwrap_or("unknown");
+            context.profile.begin_asset(id, kind);
+        }
+
+        match tag {
+            Tag::DefineSound(snd) => {
+                if !is_extracted(context, snd.id) || is_placeholder(context, snd.id, "sound", snd.data) {
+                    continue;
+                }
+                let mut sound = Sound::from_define_sound(snd, context.audio_options.raw_adpcm);
+                let duration = sound.sample_count as f64 / f64::from(sound.format.sample_rate);
+                if !passes_size_filters(context, snd.id, None, Some(duration)) {
+                    continue;
+                }
+                let discarded: usize = if context.mp3_resync {
+                    sound.resync_mp3().iter().map(|(_, len)| len).sum()
+                } else {
+                    0
+                };
+                let title = context.export_names.get(&snd.id)
+                    .cloned()
+                    .unwrap_or_else(|| format!("sound {}", snd.id));
+                let tag = id3_tag(context, &sound, &title);
+                sound.provenance = provenance(context, &title, Some(snd.id));
+
+                let extension = output_extension(context, sound.extension(context.audio_options.format));
+                let file_name = format!("{}.{}", character_file_stem(context, &filename_prefix, snd.id), extension);
+                let started = Instant::now();
+                let mut encoded = tag;
+                sound.write(&context.audio_options, &mut encoded)
+                    .expect("failed to encode sound");
+                context.profile.record_stage(Stage::Encode, started);
+                let started = Instant::now();
+                std::fs::write(&file_name, &encoded)
+                    .expect("failed to write sound file");
+                context.profile.record_stage(Stage::Write, started);
+                context.manifest.add_file(snd.id, "sound", &file_name);
+                if discarded > 0 {
+                    warn(context, Warning::about(
+                        WarningCode::TrimmedAudio, snd.id,
+                        format!("{}: discarded {} bytes of invalid MP3 data", file_name, discarded),
+                    ));
+                    context.manifest.asset(snd.id, "sound").insert("discarded_bytes", discarded);
+                }
+
+                if let Some(descriptor) = sound.raw_adpcm_descriptor() {
+                    let descriptor_file_name = format!("{}.adpcm.json", character_file_stem(context, &filename_prefix, snd.id));
+                    let f = File::create(&descriptor_file_name)
+                        .expect("failed to open sound descriptor file");
+                    descriptor.write(f)
+                        .expect("failed to write sound descriptor file");
+                    context.manifest.add_file(snd.id, "sound", &descriptor_file_name);
+                }
+            },
+            Tag::CsmTextSettings(settings) => {
+                let kind = context.reference_graph.kind(settings.id).unwrap_or("text");
+                context.manifest.asset(settings.id, kind)
+                    .insert("csm_text_settings", csm_text_settings_to_json(settings));
+            },
+            Tag::DebugId(_) => {},
+            Tag::DefineBinaryData(bd) => {
+                if !is_extracted(context, bd.id) || is_placeholder(context, bd.id, "binary", bd.data) {
+                    continue;
+                }
+                if !passes_size_filters(context, bd.id, None, None) {
+                    continue;
+                }
+
+                // data embedded into an ActionScript 3 class is named after the class
+                let class_name = context.symbol_classes.iter()
+                    .find(|(id, name)| *id == bd.id && !name.is_empty())
+                    .map(|(_, name)| name.clone());
+                let super_name = class_name.as_ref()
+                    .and_then(|name| context.superclasses.get(name))
+                    .cloned();
+                let file_stem = named_character_file_stem(
+                    context, &filename_prefix, bd.id, class_name.as_deref().unwrap_or(""),
+                );
+                let extension = sniff_extension(bd.data)
+                    .or_else(|| class_name.as_ref().and_then(|name| class_extension(name, super_name.as_deref())))
+                    .unwrap_or("bin");
+
+                let file_name = format!("{}.{}", file_stem, output_extension(context, extension));
+                let mut bin = File::create(&file_name)
+                    .expect("failed to open binary file");
+                bin.write_all(bd.data)
+                    .expect("failed to write binary data");
+                context.manifest.add_file(bd.id, "binary", &file_name);
+                if let Some(class_name) = class_name {
+                    let mut embed = JsonValue::new_object();
+                    embed.insert("class", class_name);
+                    embed.insert("super", super_name);
+                    context.manifest.asset(bd.id, "binary")
+                        .insert("embed", embed);
+                }
+            },
+            Tag::DefineSprite(ds) => {
+                // process subtags
+                process_tags(context, Some(ds.id), &ds.tags);
+            },
+            Tag::ExportAssets(ass) => {
+                status!("exporting assets: {:?}", ass);
+            },
+            Tag::DefineBits { id, jpeg_data } => {
+                if is_placeholder(context, *id, "bitmap", jpeg_data) {
+                    continue;
+                }
+                status!("Bits {}", id);
+                if context.jpeg_tables.is_empty() {
+                    warn(context, Warning::about(
+                        WarningCode::MissingJpegTables, *id, format!("no JPEG tables for bits {}; decoding them on their own", id),
+                    ));
+                }
+                match Bitmap::from_jpeg(jpeg_data, &context.jpeg_tables, None) {
+                    Ok(bmp) => {
+                        id_to_bitmap.insert(*id, bmp);
+                    },
+                    Err(e) => {
+                        let raw_data = splice_jpeg(&context.jpeg_tables, jpeg_data)
+                            .unwrap_or_else(|_| Vec::from(*jpeg_data));
+                        write_damaged_jpeg(context, &filename_prefix, *id, &raw_data, &e);
+                    },
+                }
+            },
+            Tag::DefineBitsJpeg2 { id, jpeg_data } => {
+                if is_placeholder(context, *id, "bitmap", jpeg_data) {
+                    continue;
+                }
+                status!("J2 {}", id);
+                // Jpeg2 may also be PNG or GIF
+                let bitmap = match Bitmap::from_bytes(jpeg_data, None) {
+                    Ok(bmp) => Some(bmp),
+                    Err(e) => recover_image(context, &filename_prefix, *id, jpeg_data, &e),
+                };
+                if let Some(bmp) = bitmap {
+                    id_to_bitmap.insert(*id, bmp);
+                }
+            },
+            Tag::DefineBitsJpeg3(j3) => {
+                if is_placeholder(context, j3.id, "bitmap", j3.data) {
+                    continue;
+                }
+                status!("J3 {}", j3.id);
+                // Jpeg3 may also be PNG or GIF
+                let alpha_data = if !j3.alpha_data.is_empty() {
+                    Some(j3.alpha_data)
+                } else {
+                    None
+                };
+                let bitmap = match Bitmap::from_bytes(j3.data, alpha_data) {
+                    Ok(bmp) => Some(bmp),
+                    Err(e) => recover_image(context, &filename_prefix, j3.id, j3.data, &e),
+                };
+                if let Some(bmp) = bitmap {
+                    id_to_bitmap.insert(j3.id, bmp);
+                }
+                if j3.version >= 4 && is_extracted(context, j3.id) {
+                    // the strength of the deblocking filter Flash applied (DefineBitsJPEG4)
+                    context.manifest.asset(j3.id, "bitmap").insert("deblocking", j3.deblocking.to_f32());
+                }
+            },
+            Tag::DefineBitsLossless(bmap) => {
+                if is_placeholder(context, bmap.id, "bitmap", bmap.data) {
+                    continue;
+                }
+                if let Some(reason) = exceeded_limit(context, Some((bmap.width.into(), bmap.height.into())), None) {
+                    // decoding alone might exhaust the memory
+                    skip_over_limit(context, bmap.id, reason);
+                    continue;
+                }
+                let started = Instant::now();
+                match Bitmap::from_lossless(bmap, &context.cancellation) {
+                    Ok((bitmap, recovery)) => {
+                        if let Some(warning) = recovery {
+                            warn(context, warning);
+                        }
+                        id_to_bitmap.insert(bmap.id, bitmap);
+                    },
+                    // announced when the next tag is reached
+                    Err(BitmapError::Cancelled) => {},
+                    Err(e) => warn(context, Warning::about(
+                        WarningCode::SkippedTag, bmap.id, format!("skipping bitmap {}: {}", bmap.id, e),
+                    )),
+                }
+                context.profile.record_stage(Stage::Decode, started);
+            },
+            Tag::DefineButton(btn)|Tag::DefineButton2(btn) => {
+                context.manifest.asset(btn.id, "button")
+                    .insert("records", button_records_to_json(btn));
+                context.manifest.asset(btn.id, "button")
+                    .insert("hit_area", hit_area_to_json(btn, &context.shapes));
+                if let Some(transcript) = &mut context.transcript {
+                    let character_ids = btn.records.iter()
+                        .map(|r| r.id)
+                        .collect();
+                    transcript.define_button(btn.id, character_ids);
+                }
+            },
+            Tag::DefineButtonColorTransform(bct) => {
+                let button_entry = context.manifest.asset(bct.id, "button");
+                if let Some(ct) = bct.color_transforms.first() {
+                    button_entry.insert("color_transform", color_transform_to_json(ct));
+                    if let Some(records) = button_entry.get_mut("records") {
+                        apply_button_color_transform(records, ct);
+                    }
+                }
+            },
+            Tag::DefineButtonSound(bs) => {
+                let transitions = [
+                    ("over_to_up", &bs.over_to_up_sound),
+                    ("up_to_over", &bs.up_to_over_sound),
+                    ("over_to_down", &bs.over_to_down_sound),
+                    ("down_to_over", &bs.down_to_over_sound),
+                ];
+                for (transition, button_sound) in transitions {
+                    let Some((sound_id, sound_info)) = button_sound else { continue };
+                    if *sound_id == 0 {
+                        // no sound for this transition
+                        continue;
+                    }
+                    let mut usage = sound_info_to_json(sound_info);
+                    usage.insert("button", bs.id);
+                    usage.insert("transition", transition);
+                    context.sound_usages.entry(*sound_id).or_default().push(usage);
+                }
+            },
+            Tag::DefineEditText(et) => {
+                if let Some(text) = edit_text_to_string(et, context.string_encoding) {
+                    if let Some((font_id, font)) = et.font_id.and_then(|id| context.fonts.get_mut(&id).map(|font| (id, font))) {
+                        let drawing = TextDrawing {
+                            bounds: et.bounds.clone(),
+                            matrix: Matrix::IDENTITY,
+                            glyphs: layout_edit_text(et, &text, font_id, font),
+                        };
+                        context.texts.insert(et.id, drawing);
+                        context.character_bounds.insert(et.id, et.bounds.clone());
+                    }
+                    if let Some(localization) = &mut context.localization {
+                        localization.add(
+                            format!("edit_text:{}", et.id),
+                            describe_location(sprite_id, frame),
+                            text.clone(),
+                        );
+                    }
+                    if let Some(transcript) = &mut context.transcript {
+                        transcript.define_text(et.id, "edit_text", text);
+                    }
+                }
+
+                let is_written = is_extracted(context, et.id) && within_limits(context, et.id, None, None);
+                let font_name = et.font_id
+                    .and_then(|fid| context.font_names.get(&fid))
+                    .map(|n| n.as_str());
+                context.manifest.asset(et.id, "edit_text")
+                    .insert("edit_text", edit_text_to_json(et, font_name, context.string_encoding));
+
+                if !is_written {
+                    // only the text itself is of interest
+                } else if et.is_html {
+                    // keep the markup and reproduce the field's styling
+                    let html = edit_text_to_html(et, font_name, context.string_encoding);
+                    let filename = format!("{}.html", character_file_stem(context, &filename_prefix, et.id));
+                    let mut f = File::create(&filename)
+                        .expect("failed to open HTML file");
+                    f.write_all(html.as_bytes())
+                        .expect("failed to write HTML file");
+                    context.manifest.add_file(et.id, "edit_text", &filename);
+                } else if let Some(it) = et.initial_text {
+                    let filename = format!("{}.txt", character_file_stem(context, &filename_prefix, et.id));
+                    let mut f = File::create(&filename)
+                        .expect("failed to open text file");
+                    f.write_all(decode_string(it, context.string_encoding).as_bytes())
+                        .expect("failed to write text file");
+                    context.manifest.add_file(et.id, "edit_text", &filename);
+                }
+            },
+            Tag::DefineFont(font) => {
+                // the glyphs are only mapped to characters by DefineFontInfo
+                let info = find_font_info(tags, font.id);
+                let codes = info
+                    .map(|fi| font_info_unicode_codes(fi, context.swf_version, context.string_encoding))
+                    .unwrap_or_default();
+                let font = font_v1_to_font(font, info, &codes);
+                extract_font(context, tags, &font, &filename_prefix);
+            },
+            Tag::DefineFont2(font) => {
+                extract_font(context, tags, font, &filename_prefix);
+            },
+            Tag::DefineFont4(font) => {
+                let font_entry = context.manifest.asset(font.id, "font");
+                font_entry.insert("name", decode_string(font.name, context.string_encoding));
+                font_entry.insert("bold", font.is_bold);
+                font_entry.insert("italic", font.is_italic);
+                // device fonts carry no data
+                let Some(data) = font.data else { continue };
+                if !is_extracted(context, font.id) || !within_limits(context, font.id, None, None) {
+                    continue;
+                }
+
+                // the data is a complete OpenType font with CFF outlines
+                let file_name = format!("{}.{}", character_file_stem(context, &filename_prefix, font.id), output_extension(context, "otf"));
+                std::fs::write(&file_name, data)
+                    .expect("failed to write OpenType font file");
+                context.manifest.add_file(font.id, "font", &file_name);
+            },
+            Tag::DefineFontAlignZones { id, thickness, zones } => {
+                context.manifest.asset(*id, "font")
+                    .insert("align_zones", align_zones_to_json(*thickness, zones));
+            },
+            Tag::DefineFontInfo(fi) => {
+                context.font_names.insert(fi.id, decode_string(fi.name, context.string_encoding));
+                context.font_code_tables.insert(fi.id, font_info_unicode_codes(fi, context.swf_version, context.string_encoding));
+                let font_entry = context.manifest.asset(fi.id, "font");
+                font_entry.insert("name", decode_string(fi.name, context.string_encoding));
+                font_entry.insert("language", format!("{:?}", fi.language));
+                font_entry.insert("is_bold", fi.flags.contains(FontInfoFlag::IS_BOLD));
+                font_entry.insert("is_italic", fi.flags.contains(FontInfoFlag::IS_ITALIC));
+            },
+            Tag::DefineScalingGrid { id, splitter_rect } => {
+                let kind = context.reference_graph.kind(*id).unwrap_or("character");
+                context.manifest.set_scaling_grid(*id, kind, splitter_rect);
+            },
+            Tag::DefineFontName { id, name, copyright_info } => {
+                context.font_names.insert(*id, decode_string(name, context.string_encoding));
+                let font_entry = context.manifest.asset(*id, "font");
+                font_entry.insert("name", decode_string(name, context.string_encoding));
+                font_entry.insert("copyright", decode_string(copyright_info, context.string_encoding));
+            },
+            Tag::DefineMorphShape(_) => {},
+            Tag::DefineSceneAndFrameLabelData(_) => {},
+            Tag::DefineShape(sh) => {
+                context.shapes.insert(sh.id, sh.clone());
+                context.character_bounds.insert(sh.id, sh.shape_bounds.clone());
+                if !is_extracted(context, sh.id) || !within_limits(context, sh.id, None, None) {
+                    continue;
+                }
+                let started = Instant::now();
+                let shape_data = shape_to_svg(sh);
+                context.profile.record_stage(Stage::Encode, started);
+                let filename = format!("{}.{}", character_file_stem(context, &filename_prefix, sh.id), output_extension(context, "svg"));
+                let started = Instant::now();
+                let mut f = File::create(&filename)
+                    .expect("failed to open SVG file");
+                f.write_all(shape_data.as_bytes())
+                    .expect("failed to write SVG file");
+                context.profile.record_stage(Stage::Write, started);
+                context.manifest.add_file(sh.id, "shape", &filename);
+                context.manifest.set_bounds(sh.id, "shape", &sh.shape_bounds);
+                let bitmap_fills = bitmap_fills_to_json(sh);
+                if bitmap_fills != JsonValue::new_array() {
+                    context.manifest.asset(sh.id, "shape").insert("bitmap_fills", bitmap_fills);
+                }
+            },
+            Tag::DefineText(text) => {
+                let text_string = static_text_to_string(text, &context.font_code_tables);
+                if let Some(localization) = &mut context.localization {
+                    localization.add(
+                        format!("text:{}", text.id),
+                        describe_location(sprite_id, frame),
+                        text_string.clone(),
+                    );
+                }
+                if let Some(transcript) = &mut context.transcript {
+                    transcript.define_text(text.id, "text", text_string);
+                }
+
+                let drawing = TextDrawing {
+                    bounds: text.bounds.clone(),
+                    matrix: text.matrix,
+                    glyphs: layout_static_text(text, &context.fonts),
+                };
+                context.character_bounds.insert(text.id, text.bounds.clone());
+                if !is_extracted(context, text.id) || !within_limits(context, text.id, None, None) {
+                    context.texts.insert(text.id, drawing);
+                    continue;
+                }
+                let started = Instant::now();
+                let text_data = text_to_svg(text.id, &drawing, &context.fonts);
+                context.texts.insert(text.id, drawing);
+                context.profile.record_stage(Stage::Encode, started);
+                let filename = format!("{}.{}", character_file_stem(context, &filename_prefix, text.id), output_extension(context, "svg"));
+                let started = Instant::now();
+                let mut f = File::create(&filename)
+                    .expect("failed to open SVG file");
+                f.write_all(text_data.as_bytes())
+                    .expect("failed to write SVG file");
+                context.profile.record_stage(Stage::Write, started);
+                context.manifest.add_file(text.id, "text", &filename);
+                context.manifest.set_bounds(text.id, "text", &text.bounds);
+            },
+            Tag::DoAbc(abc) => {
+                if !context.script_kind.runs_avm2() || context.render_only {
+                    continue;
+                }
+                abc_index += 1;
+                let abc_name = decode_string(abc.name, context.string_encoding);
+                let file_stem = if abc_name.is_empty() {
+                    format!("{}frame{:05}_abc{}", filename_prefix, frame, abc_index)
+                } else {
+                    format!("{}frame{:05}_abc{}_{}", filename_prefix, frame, abc_index, sanitize_file_name(&abc_name))
+                };
+                let file_name = format!("{}.abc", file_stem);
+                let mut f = File::create(&file_name)
+                    .expect("failed to open ABC file");
+                f.write_all(abc.data)
+                    .expect("failed to write ABC file");
+
+                let summary = if context.abc_summary { abc_summary_to_json(abc.data) } else { None };
+                if context.abc_summary && summary.is_none() {
+                    warn(context, Warning::new(WarningCode::InvalidAbc, format!("{}: failed to decode ABC data", file_name)));
+                }
+                let payloads = if context.abc_payloads { abc_payloads(abc.data) } else { None };
+                if context.abc_payloads && payloads.is_none() {
+                    warn(context, Warning::new(WarningCode::InvalidAbc, format!("{}: failed to decode ABC data", file_name)));
+                }
+                let mut payloads_json = JsonValue::new_array();
+                for (i, payload) in payloads.unwrap_or_default().into_iter().enumerate() {
+                    let payload_file_name = format!(
+                        "{}_payload{}.{}", file_stem, i + 1, output_extension(context, payload.extension()),
+                    );
+                    let mut f = File::create(&payload_file_name)
+                        .expect("failed to open ABC payload file");
+                    f.write_all(&payload.data)
+                        .expect("failed to write ABC payload file");
+                    status!("{}: {} bytes hidden in {}", file_name, payload.data.len(), payload.source);
+
+                    let mut obj = JsonValue::new_object();
+                    obj.insert("file", payload_file_name);
+                    obj.insert("source", payload.source);
+                    obj.insert("encoding", payload.encoding.as_str());
+                    obj.insert("compressed", payload.compressed);
+                    obj.insert("length", payload.data.len());
+                    payloads_json.push(obj);
+                }
+
+                let script_entry = context.manifest.add_script(sprite_id, frame, &abc_name, &file_name);
+                script_entry.insert("lazy_initialize", abc.is_lazy_initialize);
+                if context.abc_payloads {
+                    script_entry.insert("payloads", payloads_json);
+                }
+                if let Some(summary) = summary {
+                    let summary_file_name = format!("{}.abc.json", file_stem);
+                    let f = File::create(&summary_file_name)
+                        .expect("failed to open ABC summary file");
+                    summary.write(f)
+                        .expect("failed to write ABC summary file");
+                    script_entry.insert("summary", summary_file_name);
+                }
+
+                if let Some(localization) = &mut context.localization {
+                    let key_prefix = if abc_name.is_empty() {
+                        format!("abc:{}:abc{}", timeline_key(sprite_id), abc_index)
+                    } else {
+                        format!("abc:{}:abc{}_{}", timeline_key(sprite_id), abc_index, abc_name)
+                    };
+                    localization.add_abc_strings(
+                        &key_prefix,
+                        &describe_location(sprite_id, frame),
+                        abc.data,
+                    );
+                }
+            },
+            Tag::DoAction(action_data) => {
+                frame_action_count += 1;
+                if !context.script_kind.runs_avm1() {
+                    continue;
+                }
+                if let Some(localization) = &mut context.localization {
+                    localization.add_avm1_strings(
+                        &format!("script:{}:frame{}_action{}", timeline_key(sprite_id), frame, frame_action_count),
+                        &describe_location(sprite_id, frame),
+                        action_data,
+                        context.swf_version,
+                        context.string_encoding,
+                    );
+                }
+            },
+            Tag::DoInitAction { id, action_data } => {
+                if !context.script_kind.runs_avm1() {
+                    continue;
+                }
+                if let Some(localization) = &mut context.localization {
+                    localization.add_avm1_strings(
+                        &format!("init_script:{}", id),
+                        &describe_location(sprite_id, frame),
+                        action_data,
+                        context.swf_version,
+                        context.string_encoding,
+                    );
+                }
+            },
+            Tag::EnableDebugger(_) => {},
+            Tag::EnableTelemetry { .. } => {},
+            Tag::FileAttributes(_) => {},
+            Tag::FrameLabel(fl) => {
+                if !is_in_range(frame) {
+                    continue;
+                }
+                sync.add_label(frame, decode_string(fl.label, context.string_encoding));
+            },
+            Tag::ImportAssets { url, imports } => {
+                status!("importing {} assets from {}", imports.len(), decode_string(url, context.string_encoding));
+            },
+            Tag::JpegTables(_) => {
+                // collected before processing, as DefineBits may precede them
+            },
+            Tag::Metadata(_) => {},
+            Tag::NameCharacter(nc) => {
+                let kind = context.reference_graph.kind(nc.id).unwrap_or("character");
+                context.manifest.asset(nc.id, kind)
+                    .insert("name", decode_string(nc.name, context.string_encoding));
+            },
+            Tag::PlaceObject(po) => {
+                timeline.place(po, context.string_encoding);
+                if let Some(transcript) = &mut context.transcript {
+                    if let PlaceObjectAction::Place(id)|PlaceObjectAction::Replace(id) = po.action {
+                        transcript.place(sprite_id, frame, id);
+                    }
+                }
+            },
+            Tag::ProductInfo(_) => {},
+            Tag::Protect(_) => {},
+            Tag::RemoveObject(ro) => timeline.remove(ro.depth),
+            Tag::ScriptLimits { .. } => {},
+            Tag::SetBackgroundColor(_) => {},
+            Tag::ShowFrame => {
+                timeline.show_frame();
+                frame_action_count = 0;
+                if !is_in_range(frame) {
+                    frame += 1;
+                    if frame_range.as_ref().is_some_and(|r| r.start == frame) {
+                        // the stream sound is output from the start of the range
+                        sync = TimelineSync::new(frame);
+                    }
+                    continue;
+                }
+                if context.pad_stream_silence && !stream_block_in_frame {
+                    if let Some(snd) = &mut stream_sound {
+                        // only inserted once the stream continues, so no silence is appended at the end
+                        snd.defer_silence(stream_samples_per_block.into());
+                    }
+                }
+                stream_block_in_frame = false;
+                sync.end_frame(stream_position(stream_sound.as_ref()));
+                frame += 1;
+            },
+            Tag::SoundStreamBlock(ssb) => {
+                if !is_in_range(frame) {
+                    continue;
+                }
+                if let Some(snd) = &mut stream_sound {
+                    snd.append_data(ssb);
+                }
+                stream_block_in_frame = true;
+            },
+            Tag::SoundStreamHead(ssh)|Tag::SoundStreamHead2(ssh) => {
+                // a new head ends the previous stream
+                if let Some(ssnd) = stream_sound.take() {
+                    let stream_stem = stream_file_stem(&stream_prefix, stream_index, multiple_streams);
+                    write_stream(context, sprite_id, &stream_stem, ssnd, &sync);
+                }
+                stream_index += 1;
+                sync = TimelineSync::new(frame);
+                let mut ssnd = Sound::new_stream(ssh.stream_format.clone(), context.audio_options.raw_adpcm);
+                if context.apply_latency_seek && ssh.stream_format.compression == AudioCompression::Mp3 {
+                    ssnd.pending_seek = ssh.latency_seek.into();
+                }
+                stream_sound = Some(ssnd);
+                stream_samples_per_block = ssh.num_samples_per_block;
+            },
+            Tag::SetTabIndex { .. } => {},
+            Tag::StartSound2 { class_name, sound_info } => {
+                // the sound is referred to by the class bound to it
+                let class_name = decode_string(class_name, context.string_encoding);
+                let id = context.symbol_classes.iter()
+                    .find(|(_, name)| *name == class_name)
+                    .map(|(id, _)| *id);
+                if let Some(id) = id {
+                    let mut usage = sound_info_to_json(sound_info);
+                    usage.insert("sprite", sprite_id);
+                    usage.insert("frame", frame);
+                    context.sound_usages.entry(id).or_default().push(usage);
+                }
+            },
+            Tag::SymbolClass(_) => {},
+            Tag::StartSound(ss) => {
+                let mut usage = sound_info_to_json(&ss.sound_info);
+                usage.insert("sprite", sprite_id);
+                usage.insert("frame", frame);
+                context.sound_usages.entry(ss.id).or_default().push(usage);
+            },
+            Tag::DefineVideoStream(dvs) => {
+                let is_avc = context.avc_stream_ids.contains(&dvs.id);
+                context.manifest.asset(dvs.id, "video")
+                    .insert("video", video_stream_to_json(dvs, is_avc));
+                if !is_extracted(context, dvs.id) || !within_limits(context, dvs.id, None, None) {
+                    // without a decoder or stream, the frames are skipped too
+                    continue;
+                }
+                if is_avc {
+                    context.avc_streams.insert(dvs.id, AvcStream::new(dvs.width, dvs.height));
+                    continue;
+                }
+                match dvs.codec {
+                    VideoCodec::ScreenVideo|VideoCodec::ScreenVideoV2 => {
+                        let is_v2 = dvs.codec == VideoCodec::ScreenVideoV2;
+                        context.screen_video_decoders.insert(
+                            dvs.id,
+                            ScreenVideoDecoder::new(dvs.width, dvs.height, is_v2),
+                        );
+                    },
+                    VideoCodec::H263|VideoCodec::Vp6|VideoCodec::Vp6WithAlpha => {
+                        context.flv_streams.insert(
+                            dvs.id,
+                            FlvVideoStream::new(dvs.codec, dvs.width, dvs.height),
+                        );
+                    },
+                }
+            },
+            Tag::VideoFrame(vf) => {
+                if is_in_range(frame) {
+                    sync.add_video_frame(frame, vf.stream_id, vf.frame_num);
+                }
+                let file_stem = character_file_stem(context, "", vf.stream_id);
+                let mut is_keyframe = false;
+                if let Some(avc_stream) = context.avc_streams.get_mut(&vf.stream_id) {
+                    match avc_stream.push_packet(vf.frame_num, vf.data) {
+                        Ok(keyframe) => is_keyframe = keyframe,
+                        Err(e) => {
+                            // an MP4 file missing packets is of no use
+                            context.avc_streams.remove(&vf.stream_id);
+                            warn(context, Warning::about(
+                                WarningCode::UndecodableVideo, vf.stream_id,
+                                format!("skipping H.264 video: failed to read frame {}: {}", vf.frame_num, e),
+                            ));
+                        },
+                    }
+                } else if let Some(flv_stream) = context.flv_streams.get_mut(&vf.stream_id) {
+                    is_keyframe = flv_stream.push_frame(vf.frame_num, vf.data);
+                } else if let Some(decoder) = context.screen_video_decoders.get_mut(&vf.stream_id) {
+                    // frames build upon each other; output the full image after every packet
+                    let started = Instant::now();
+                    match decoder.decode_frame(vf.data, &context.cancellation) {
+                        Ok(keyframe) => is_keyframe = keyframe,
+                        Err(VideoError::Cancelled) => continue,
+                        Err(e) => {
+                            // later frames only update the image decoded so far
+                            context.screen_video_decoders.remove(&vf.stream_id);
+                            warn(context, Warning::about(
+                                WarningCode::UndecodableVideo, vf.stream_id,
+                                format!("skipping Screen Video frames from {} on: {}", vf.frame_num, e),
+                            ));
+                            continue;
+                        },
+                    }
+                    context.profile.record_stage(Stage::Decode, started);
+                    let file_name = format!("{}_frame{:05}.png", file_stem, vf.frame_num);
+                    let started = Instant::now();
+                    let mut encoded = Vec::new();
+                    if let Err(e) = decoder.write_png(&mut encoded) {
+                        context.screen_video_decoders.remove(&vf.stream_id);
+                        warn(context, Warning::about(
+                            WarningCode::UndecodableVideo, vf.stream_id,
+                            format!("skipping Screen Video frames from {} on: {}", vf.frame_num, e),
+                        ));
+                        continue;
+                    }
+                    context.profile.record_stage(Stage::Encode, started);
+                    let started = Instant::now();
+                    std::fs::write(&file_name, &encoded)
+                        .expect("failed to write video frame file");
+                    context.profile.record_stage(Stage::Write, started);
+                    context.manifest.add_file(vf.stream_id, "video", &file_name);
+                }
+
+                if is_keyframe {
+                    let keyframes = context.manifest.asset(vf.stream_id, "video")
+                        .get_mut("video")
+                        .and_then(|v| v.get_mut("keyframes"));
+                    if let Some(kf) = keyframes {
+                        kf.push(vf.frame_num);
+                    }
+                }
+            },
+            Tag::Unknown { tag_code, data } => {
+                let name = legacy_tag_name(*tag_code).unwrap_or("unknown");
+                warn(context, Warning::new(
+                    WarningCode::SkippedTag, format!("skipping {} tag (code {}, {} bytes)", name, tag_code, data.len()),
+                ));
+            },
+            other => {
+                let name = format!("{:?}", other);
+                let name = name.split(|c: char| !c.is_alphanumeric()).next().unwrap_or("unknown");
+                warn(context, Warning::new(WarningCode::SkippedTag, format!("skipping unsupported {} tag", name)));
+            },
+        }
+    }
+    context.profile.end_asset();
+    // stream sounds belong to their timeline; the main timeline is always used
+    let is_timeline_extracted = match sprite_id {
+        Some(id) => is_extracted(context, id),
+        None => context.extract_used,
+    };
+    let is_stream_written = if context.render_only {
+        sprite_id.is_none() && context.export_movie
+    } else {
+        is_timeline_extracted
+    };
+    if let (Some(ssnd), true) = (stream_sound, is_stream_written) {
+        let stream_stem = stream_file_stem(&stream_prefix, stream_index, multiple_streams);
+        write_stream(context, sprite_id, &stream_stem, ssnd, &sync);
+    }
+    write_bitmaps(context, &filename_prefix, &mut id_to_bitmap);
+
+    if is_timeline_extracted || context.render_only {
+        write_timeline(context, sprite_id, &timeline);
+    }
+    if let Some(id) = sprite_id {
+        // sprites can only show characters defined before them
+        if let Some(bounds) = timeline.bounds(&context.character_bounds) {
+            if is_timeline_extracted {
+                context.manifest.set_bounds(id, "sprite", &bounds);
+            }
+            context.character_bounds.insert(id, bounds);
+        }
+    }
+    if let (Some(id), true) = (sprite_id, context.render_frames) {
+        context.timelines.insert(id, timeline);
+    }
+    context.profile.end_timeline();
+}
+
+
+/// Decoded bitmaps waiting to be written at the end of their timeline, or earlier once they take up
+/// more memory than `--max-memory` allows.
+#[derive(Default)]
+struct HeldBitmaps {
+    bitmaps: HashMap<CharacterId, Bitmap>,
+
+    /// The total size of the pixel data of the bitmaps, in bytes.
+    data_len: usize,
+}
+impl HeldBitmaps {
+    fn insert(&mut self, id: CharacterId, bitmap: Bitmap) {
+        self.data_len += bitmap.data_len();
+        if let Some(replaced) = self.bitmaps.insert(id, bitmap) {
+            self.data_len -= replaced.data_len();
+        }
+    }
+
+    fn drain(&mut self) -> std::collections::hash_map::Drain<'_, CharacterId, Bitmap> {
+        self.data_len = 0;
+        self.bitmaps.drain()
+    }
+}
+
+
+/// Writes the given bitmaps and removes them.
+fn write_bitmaps(context: &mut Context, filename_prefix: &str, id_to_bitmap: &mut HeldBitmaps) {
+    for (i, bitmap) in id_to_bitmap.drain() {
+        context.profile.begin_asset(i, "bitmap");
+        if !is_extracted(context, i) {
+            continue;
+        }
+        if !passes_size_filters(context, i, bitmap.dimensions(), None) {
+            continue;
+        }
+        let original_dimensions = bitmap.dimensions();
+        let started = Instant::now();
+        let bitmap = match context.image_scale {
+            Some(scale) => match scale_bitmap(&bitmap, scale) {
+                Ok(scaled) => scaled,
+                Err(e) => {
+                    warn(context, Warning::about(
+                        WarningCode::UnconvertedBitmap, i, format!("failed to scale bitmap {}: {}; writing it unscaled", i, e),
+                    ));
+                    bitmap
+                },
+            },
+            None => bitmap,
+        };
+        context.profile.record_stage(Stage::Encode, started);
+        if context.raw_pixels {
+            let started = Instant::now();
+            let decoded = bitmap.decode_rgba();
+            context.profile.record_stage(Stage::Decode, started);
+            match decoded {
+                Ok((width, height, pixels)) => {
+                    let started = Instant::now();
+                    write_raw_pixels(context, filename_prefix, i, width, height, &pixels);
+                    context.profile.record_stage(Stage::Write, started);
+                    if let Some((width, height)) = original_dimensions {
+                        context.manifest.set_bounds(i, "bitmap", &bitmap_bounds(width, height));
+                    }
+                    continue;
+                },
+                Err(e) => warn(context, Warning::about(
+                    WarningCode::UnconvertedBitmap, i,
+                    format!("failed to decode bitmap {} into raw pixels: {}; writing it as is", i, e),
+                )),
+            }
+        }
+        let file_name = format!("{}.{}", character_file_stem(context, filename_prefix, i), output_extension(context, bitmap.extension(context.indexed_format)));
+        let started = Instant::now();
+        let mut encoded = Vec::new();
+        let written = bitmap.write(context.indexed_format, &mut encoded);
+        context.profile.record_stage(Stage::Encode, started);
+        match written {
+            Ok(()) => {
+                let started = Instant::now();
+                std::fs::write(&file_name, &encoded)
+                    .expect("failed to write bitmap file");
+                context.profile.record_stage(Stage::Write, started);
+                context.manifest.add_file(i, "bitmap", &file_name);
+                let entry = context.manifest.asset(i, "bitmap");
+                entry.insert("width", bitmap.dimensions().map(|(w, _)| w));
+                entry.insert("height", bitmap.dimensions().map(|(_, h)| h));
+                if let Some((width, height)) = original_dimensions {
+                    context.manifest.set_bounds(i, "bitmap", &bitmap_bounds(width, height));
+                }
+            },
+            Err(e) => {
+                if let BitmapData::Jpeg { jpeg_data, .. } = &bitmap.data {
+                    write_damaged_jpeg(context, filename_prefix, i, jpeg_data, &e);
+                } else {
+                    status!("failed to write bitmap {}: {}", i, e);
+                    context.report.fail(i, e.to_string());
+                }
+            },
+        }
+    }
+    context.profile.end_asset();
+}
+
+
+/// Returns the area covered by a bitmap with the given dimensions, whose registration point is its
+/// top left corner.
+fn bitmap_bounds(width: u32, height: u32) -> Rectangle {
+    Rectangle {
+        x_min: Twips::ZERO,
+        x_max: Twips::from_pixels(f64::from(width)),
+        y_min: Twips::ZERO,
+        y_max: Twips::from_pixels(f64::from(height)),
+    }
+}
+
+
+/// Resizes a bitmap, returning it as RGBA pixels.
+fn scale_bitmap(bitmap: &Bitmap, scale: ImageScale) -> Result<Bitmap, BitmapError> {
+    let (width, height, pixels) = bitmap.decode_rgba()?;
+    let (new_width, new_height) = scale.scaled_dimensions(width, height);
+    let image_data = resize_rgba(&pixels, width, height, new_width, new_height);
+    Ok(Bitmap::new(new_width, new_height, BitmapData::Rgba32 { image_data }))
+}
+
+
+/// Writes the RGBA pixels of a bitmap into a `.raw` file, described by a `.raw.json` file.
+fn write_raw_pixels(context: &mut Context, filename_prefix: &str, id: CharacterId, width: u32, height: u32, pixels: &[u8]) {
+    let file_stem = character_file_stem(context, filename_prefix, id);
+    let file_name = format!("{}.{}", file_stem, output_extension(context, "raw"));
+    let mut f = File::create(&file_name)
+        .expect("failed to open raw pixel file");
+    f.write_all(pixels)
+        .expect("failed to write raw pixel file");
+    context.manifest.add_file(id, "bitmap", &file_name);
+
+    let mut descriptor = JsonValue::new_object();
+    descriptor.insert("file", file_name.as_str());
+    descriptor.insert("width", width);
+    descriptor.insert("height", height);
+    descriptor.insert("stride", u64::from(width) * 4);
+    descriptor.insert("format", "rgba8");
+    let descriptor_file_name = format!("{}.raw.json", file_stem);
+    let f = File::create(&descriptor_file_name)
+        .expect("failed to open raw pixel descriptor file");
+    descriptor.write(f)
+        .expect("failed to write raw pixel descriptor file");
+    context.manifest.add_file(id, "bitmap", &descriptor_file_name);
+
+    let entry = context.manifest.asset(id, "bitmap");
+    entry.insert("width", width);
+    entry.insert("height", height);
+}
+
+
+/// Salvages image data that could not be read: JPEG data is written out as damaged, while PNG and
+/// GIF data is returned to be written out unchanged, with unknown dimensions.
+fn recover_image(context: &mut Context, filename_prefix: &str, id: CharacterId, data: &[u8], error: &BitmapError) -> Option<Bitmap> {
+    if is_jpeg(data) {
+        write_damaged_jpeg(context, filename_prefix, id, data, error);
+        return None;
+    }
+    let bitmap = Bitmap::pass_through(data);
+    if bitmap.is_some() {
+        warn(context, Warning::about(
+            WarningCode::RecoveredBitmap, id, format!("failed to read header of image {}: {}; writing its data unchanged", id, error),
+        ));
+    } else {
+        status!("failed to decode image {}: {}", id, error);
+        context.report.fail(id, error.to_string());
+    }
+    bitmap
+}
+
+
+/// Writes the raw data of a JPEG image that could not be decoded into a `.jpeg.damaged` file and
+/// records the failure.
+fn write_damaged_jpeg(context: &mut Context, filename_prefix: &str, id: CharacterId, jpeg_data: &[u8], error: &BitmapError) {
+    status!("failed to process JPEG image {}: {}; writing its raw data", id, error);
+    context.report.fail(id, error.to_string());
+    if !is_extracted(context, id) {
+        return;
+    }
+    let file_name = format!("{}.jpeg.damaged", character_file_stem(context, filename_prefix, id));
+    let mut f = File::create(&file_name)
+        .expect("failed to open damaged JPEG file");
+    f.write_all(jpeg_data)
+        .expect("failed to write damaged JPEG file");
+    context.manifest.add_file(id, "bitmap", &file_name);
+}
+
+
+/// Writes the contents of the frames of a timeline and renders them, if requested.
+fn write_timeline(context: &mut Context, sprite_id: Option<CharacterId>, timeline: &Timeline) {
+    let file_stem = match sprite_id {
+        Some(id) => format!("{}_", character_file_stem(context, "", id)),
+        None => String::new(),
+    };
+    let mut file_names = Vec::new();
+    let frame_range = if sprite_id.is_none() { context.frame_range.clone() } else { None };
+
+    if context.write_timelines {
+        let file_name = match sprite_id {
+            Some(id) => format!("{}.timeline.json", character_file_stem(context, "", id)),
+            None => "timeline.json".to_owned(),
+        };
+        let f = File::create(&file_name)
+            .expect("failed to open timeline file");
+        timeline.to_json(frame_range.as_ref(), &context.reference_graph).write(f)
+            .expect("failed to write timeline file");
+        file_names.push(file_name);
+    }
+
+    if context.render_frames {
+        let renderer = FrameRenderer {
+            shapes: &context.shapes,
+            texts: &context.texts,
+            fonts: &context.fonts,
+            timelines: &context.timelines,
+            bake_color_transforms: context.bake_color_transforms,
+            background_color: context.background_color.as_ref(),
+            scale: context.render_scale,
+            max_sprite_depth: context.max_sprite_depth,
+        };
+        // sprites have no stage of their own
+        let stage = if sprite_id.is_none() { Some(&context.stage) } else { None };
+        for frame in 0..timeline.frame_count() {
+            let frame_number = u32::try_from(frame).unwrap() + 1;
+            if frame_range.as_ref().is_some_and(|r| !r.contains(&frame_number)) {
+                continue;
+            }
+            let file_name = format!("{}frame{:05}.svg", file_stem, frame + 1);
+            let mut f = File::create(&file_name)
+                .expect("failed to open frame file");
+            f.write_all(renderer.render(timeline, frame, stage).as_bytes())
+                .expect("failed to write frame file");
+            file_names.push(file_name);
+        }
+        if sprite_id.is_none() {
+            context.main_frame_files = file_names.iter()
+                .filter(|name| name.ends_with(".svg"))
+                .cloned()
+                .collect();
+        }
+    }
+
+    if let Some(id) = sprite_id {
+        for file_name in &file_names {
+            context.manifest.add_file(id, "sprite", file_name);
+        }
+    }
 }
 
 
-fn process_tags(filename_prefix: &str, tags: &[Tag]) {
-    let mut stream_sound: Option<Sound> = None;
-    let mut id_to_bitmap: HashMap<u16, Bitmap> = HashMap::new();
-    let mut jpeg_tables = Vec::new();
-    for tag in tags {
-        match tag {
-            Tag::DefineSound(snd) => {
-                let sound = Sound {
-                    format: snd.format.clone(),
-                    data: Vec::from(snd.data),
-                };
-                let file_name = format!("{}{}.{}", filename_prefix, snd.id, sound.extension());
-                let output = File::create(file_name)
-                    .expect("failed to open sound file");
-                sound.write(output)
-                    .expect("failed to write sound file");
-            },
-            Tag::DefineBinaryData(bd) => {
-                let file_name = format!("{}{}.bin", filename_prefix, bd.id);
-                let mut bin = File::create(file_name)
-                    .expect("failed to open binary file");
-                bin.write_all(bd.data)
-                    .expect("failed to write binary data");
-            },
-            Tag::DefineSprite(ds) => {
-                // process subtags
-                let filename_prefix = format!("{}-", ds.id);
-                process_tags(&filename_prefix, &ds.tags);
-            },
-            Tag::ExportAssets(ass) => {
-                println!("exporting assets: {:?}", ass);
-            },
-            Tag::DefineBits { id, jpeg_data } => {
-                println!("Bits {}", id);
-                id_to_bitmap.insert(
-                    *id,
-                    Bitmap::from_jpeg(jpeg_data, &jpeg_tables, None).unwrap(),
-                );
+/// Writes the files with which ffmpeg can assemble the rendered frames of the main timeline and its
+/// stream sound into a video.
+fn export_movie(context: &mut Context, frame_labels: &FrameLabels, title: &str) {
+    let frame_rate = context.frame_rate.to_f64();
+    let range = context.frame_range.clone().unwrap_or(1..frame_labels.frame_count() + 1);
+
+    let f = File::create("movie.ffconcat")
+        .expect("failed to open concat file");
+    write_ffconcat(&context.main_frame_files, frame_rate, f)
+        .expect("failed to write concat file");
+
+    // chapters count from the first exported frame
+    let labels: Vec<&(u32, String)> = frame_labels.labels().iter()
+        .filter(|(frame, _)| range.contains(frame))
+        .collect();
+    let mut chapters = Vec::new();
+    for (i, (frame, label)) in labels.iter().enumerate() {
+        let end = labels.get(i + 1).map(|(next, _)| *next).unwrap_or(range.end);
+        if end > *frame {
+            chapters.push((frame - range.start, end - range.start, label.clone()));
+        }
+    }
+    let f = File::create("movie.ffmetadata")
+        .expect("failed to open metadata file");
+    write_ffmetadata(title, &chapters, frame_rate, f)
+        .expect("failed to write metadata file");
+
+    let mut args: Vec<String> = ["-f", "concat", "-i", "movie.ffconcat"].map(String::from).to_vec();
+    let mut audio_offset = None;
+    if let Some((stream_file, start_frame)) = &context.main_stream {
+        let offset = f64::from(start_frame.saturating_sub(range.start)) / frame_rate;
+        args.extend(["-itsoffset".to_owned(), offset.to_string(), "-i".to_owned(), stream_file.clone()]);
+        audio_offset = Some(offset);
+    }
+    let metadata_input = if audio_offset.is_some() { 2 } else { 1 };
+    args.extend(["-i".to_owned(), "movie.ffmetadata".to_owned(), "-map_metadata".to_owned(), metadata_input.to_string()]);
+    args.extend(["-map", "0:v"].map(String::from));
+    if audio_offset.is_some() {
+        args.extend(["-map", "1:a"].map(String::from));
+    }
+    args.extend(["-c:v", "libx264", "-pix_fmt", "yuv420p", "movie.mp4"].map(String::from));
+    let command = format!("ffmpeg {}", args.join(" "));
+    status!("to encode the movie (requires ffmpeg with SVG support): {}", command);
+
+    let mut export = JsonValue::new_object();
+    export.insert("frames", "movie.ffconcat");
+    export.insert("metadata", "movie.ffmetadata");
+    export.insert("audio", context.main_stream.as_ref().map(|(file_name, _)| file_name.as_str()));
+    export.insert("audio_offset", audio_offset);
+    export.insert("ffmpeg_command", command);
+    context.manifest.movie.insert("movie_export", export);
+
+    #[cfg(feature = "mp4-encode")]
+    if context.encode_mp4 {
+        // the frames and the command remain for encoding by hand
+        match run_ffmpeg(&args) {
+            Ok(()) => {
+                context.manifest.movie.get_mut("movie_export").unwrap()
+                    .insert("video", "movie.mp4");
             },
-            Tag::DefineBitsJpeg2 { id, jpeg_data } => {
-                println!("J2 {}", id);
-                // Jpeg2 may also be PNG or GIF
-                if let Some(bmp) = Bitmap::from_bytes(jpeg_data, None) {
-                    id_to_bitmap.insert(
-                        *id,
-                        bmp,
-                    );
+            Err(e) => warn(context, Warning::new(
+                WarningCode::FailedEncoding, format!("failed to encode the movie: {}", e),
+            )),
+        }
+    }
+}
+
+/// Collects the bindings of ActionScript 3 classes to characters along with the files written for
+/// each character.
+fn symbols_to_json(context: &Context) -> JsonValue {
+    let mut symbols = JsonValue::new_array();
+    let mut document_class = None;
+    for (id, class_name) in &context.symbol_classes {
+        if *id == 0 {
+            document_class = Some(class_name.as_str());
+            continue;
+        }
+        let asset = context.manifest.find_asset(*id);
+        let mut obj = JsonValue::new_object();
+        obj.insert("class", class_name.as_str());
+        obj.insert("component", context.component_names.get(class_name).map(|name| name.as_str()));
+        obj.insert("id", *id);
+        obj.insert("type", context.reference_graph.kind(*id));
+        obj.insert("files", asset.and_then(|a| a.get("files")).cloned().unwrap_or_else(JsonValue::new_array));
+        symbols.push(obj);
+    }
+
+    let mut obj = JsonValue::new_object();
+    obj.insert("document_class", document_class);
+    obj.insert("symbols", symbols);
+    obj
+}
+
+
+/// Determines what became of each character of the movie.
+fn asset_outcomes(context: &Context) -> Vec<AssetOutcome> {
+    let mut outcomes = Vec::new();
+    for (id, kind) in context.reference_graph.characters() {
+        let files: Vec<String> = match context.manifest.find_asset(id).and_then(|a| a.get("files")) {
+            Some(JsonValue::Array(files)) => files.iter()
+                .filter_map(|f| if let JsonValue::String(s) = f { Some(s.clone()) } else { None })
+                .collect(),
+            _ => Vec::new(),
+        };
+        let (status, message) = if let Some(failure) = context.report.failure(id) {
+            (AssetStatus::Failed, Some(failure.to_owned()))
+        } else if let Some(reason) = context.report.skip_reason(id) {
+            (AssetStatus::Skipped, Some(reason.to_owned()))
+        } else if !files.is_empty() {
+            (AssetStatus::Written, None)
+        } else if !is_extracted(context, id) {
+            let reason = if context.used_characters.contains(&id) { "excluded by --only-unused" } else { "excluded by --only-used" };
+            (AssetStatus::Skipped, Some(reason.to_owned()))
+        } else {
+            (AssetStatus::Skipped, Some("nothing is output for this character with the given options".to_owned()))
+        };
+        let path = context.reference_graph.asset_path(id);
+        outcomes.push(AssetOutcome { id, kind, path, status, message, files });
+    }
+    outcomes
+}
+
+fn write_report(context: &Context) {
+    let f = File::create("report.json")
+        .expect("failed to open report file");
+    context.report.write(&asset_outcomes(context), f)
+        .expect("failed to write report file");
+}
+
+
+/// Looks up the written files in the duplicate index, records the ones not found there and lists the
+/// others in `duplicates.json`, replacing them by links if requested.
+fn find_duplicates(context: &mut Context, index_path: &Path, swf_path: &Path, link_kind: Option<LinkKind>) {
+    let mut index = DuplicateIndex::load(index_path)
+        .expect("failed to read duplicate index");
+    let mut duplicates = JsonValue::new_array();
+    for (id, file_name) in context.manifest.asset_files() {
+        let data = std::fs::read(&file_name)
+            .expect("failed to read written file");
+        let digest = sha256_hex(&data);
+        let file_path = std::path::absolute(&file_name)
+            .expect("failed to resolve path of written file");
+
+        let Some(original) = index.find(&digest).filter(|e| e.file != file_path).cloned() else {
+            index.add(digest, IndexEntry { swf: swf_path.to_owned(), file: file_path });
+            continue;
+        };
+        if let Some(kind) = link_kind {
+            link_duplicate(&original.file, Path::new(&file_name), kind)
+                .expect("failed to link duplicate file");
+        }
+
+        let mut entry = JsonValue::new_object();
+        entry.insert("id", id);
+        entry.insert("file", file_name.as_str());
+        entry.insert("sha256", digest);
+        entry.insert("original_swf", original.swf.to_string_lossy().into_owned());
+        entry.insert("original_file", original.file.to_string_lossy().into_owned());
+        entry.insert("linked", link_kind.is_some());
+        duplicates.push(entry);
+    }
+    index.save()
+        .expect("failed to write duplicate index");
+
+    if let JsonValue::Array(entries) = &duplicates {
+        status!("{} written files duplicate previously indexed files", entries.len());
+    }
+    let f = File::create("duplicates.json")
+        .expect("failed to open duplicate report file");
+    duplicates.write(f)
+        .expect("failed to write duplicate report file");
+}
+
+
+/// Inserts `extract` into the command line unless it already names a subcommand, keeping
+/// `swfextract [OPTIONS] movie.swf` working. Returns the position of the subcommand, or `None` if
+/// help or the man page has been requested instead.
+fn insert_default_subcommand(command: &clap::Command, args: &mut Vec<OsString>) -> Option<usize> {
+    let mut index = 1;
+    while let Some(arg) = args.get(index).and_then(|a| a.to_str()) {
+        if Command::NAMES.contains(&arg) {
+            return Some(index);
+        }
+        if arg == "help" {
+            return None;
+        }
+        if arg == "--" || !arg.starts_with('-') {
+            break;
+        }
+        let option = command.get_arguments().find(|a| {
+            let long = a.get_long().map(|long| format!("--{}", long));
+            let short = a.get_short().map(|short| format!("-{}", short));
+            long.as_deref() == Some(arg) || short.as_deref() == Some(arg)
+        });
+        match option {
+            // options of the command itself, such as --help and --man, stand in for a subcommand
+            Some(option) if !option.is_global_set() => return None,
+            // skip the values of the global options given before the subcommand
+            Some(option) if option.get_action().takes_values() => index += 2,
+            _ => index += 1,
+        }
+    }
+    args.insert(1, OsString::from("extract"));
+    Some(1)
+}
+
+
+/// Parses the command line, with the options from the configuration file if there is one inserted
+/// after the subcommand. Options from the configuration file that the subcommand does not accept are
+/// ignored.
+fn parse_opts() -> Cli {
+    let mut args: Vec<OsString> = std::env::args_os().collect();
+    let mut command = Cli::command();
+    command.build();
+    let subcommand_index = insert_default_subcommand(&command, &mut args);
+
+    // the configuration file must be known before the command line can be parsed
+    let mut config_path = None;
+    let mut arg_iter = args.iter().skip(1);
+    while let Some(arg) = arg_iter.next() {
+        if arg == "--" {
+            break;
+        } else if arg == "--config" {
+            config_path = arg_iter.next().map(PathBuf::from);
+        } else if let Some(path) = arg.to_str().and_then(|a| a.strip_prefix("--config=")) {
+            config_path = Some(PathBuf::from(path));
+        }
+    }
+    let is_explicit = config_path.is_some();
+    let config_path = config_path.unwrap_or_else(|| PathBuf::from(DEFAULT_CONFIG_FILE_NAME));
+
+    let Some(subcommand_index) = subcommand_index else {
+        return Cli::parse_from(args);
+    };
+    let subcommand = args[subcommand_index].to_str()
+        .and_then(|name| command.find_subcommand(name))
+        .unwrap();
+    let config_args = match std::fs::read_to_string(&config_path) {
+        Ok(config) => {
+            config_to_args(&config).unwrap_or_else(|e| Cli::command().error(
+                ErrorKind::InvalidValue, format!("invalid configuration file {}: {}", config_path.display(), e),
+            ).exit())
+        },
+        Err(e) if is_explicit || e.kind() != std::io::ErrorKind::NotFound => {
+            Cli::command().error(
+                ErrorKind::Io, format!("failed to read configuration file {}: {}", config_path.display(), e),
+            ).exit();
+        },
+        Err(_) => Vec::new(),
+    };
+    let config_args: Vec<OsString> = config_args.into_iter()
+        .filter(|arg| {
+            let name = arg.to_str()
+                .and_then(|a| a.strip_prefix("--"))
+                .map(|a| a.split_once('=').map_or(a, |(name, _)| name));
+            subcommand.get_arguments().any(|a| a.get_long().is_some() && a.get_long() == name)
+        })
+        .collect();
+    let mut full_args = args[..=subcommand_index].to_vec();
+    full_args.extend(config_args);
+    full_args.extend(args[subcommand_index+1..].iter().cloned());
+    Cli::parse_from(full_args)
+}
+
+
+/// Returns the command line (without the program and the movie) with which the `serve` subcommand
+/// runs `extract`: the global options given, a timeout if none was given, and the extraction
+/// options.
+fn extraction_args(global: &GlobalOpts, extract_args: Vec<OsString>) -> Vec<OsString> {
+    let mut args = vec![OsString::from("extract")];
+    if let Some(config) = &global.config {
+        args.push(OsString::from("--config"));
+        args.push(config.as_os_str().to_owned());
+    }
+    if let Some(max_memory) = global.max_memory {
+        args.push(OsString::from(format!("--max-memory={}", max_memory)));
+    }
+    let timeout = global.timeout.unwrap_or(DEFAULT_EXTRACTION_TIMEOUT.as_secs_f64());
+    args.push(OsString::from(format!("--timeout={}", timeout)));
+    args.push(OsString::from(format!("--max-sprite-depth={}", global.max_sprite_depth)));
+    args.extend(extract_args);
+    args
+}
+
+
+/// Returns the options with which `extract` would be run on the given SWF file if no other options
+/// were given.
+fn default_extract_opts(swf_path: &Path) -> ExtractOpts {
+    let matches = ExtractOpts::augment_args(clap::Command::new("extract"))
+        .get_matches_from([OsString::from("extract"), swf_path.as_os_str().to_owned()]);
+    ExtractOpts::from_arg_matches(&matches)
+        .expect("failed to determine default options")
+}
+
+
+/// Where a movie has been read from: a SWF file or a SWF file within a ZIP archive.
+struct MovieSource {
+    /// The path of the SWF file or of the archive.
+    path: PathBuf,
+
+    /// The path of the SWF file within the archive.
+    member: Option<String>,
+
+    /// The catalog of the archive if it is a SWC component library.
+    catalog: Option<SwcCatalog>,
+}
+impl MovieSource {
+    /// Returns the path from which the name of the movie is taken: that of the SWF file (within the
+    /// archive if it is in one) or, as their SWF files are all named alike, of a SWC file.
+    fn swf_path(&self) -> &Path {
+        match &self.member {
+            Some(member) if self.catalog.is_none() => Path::new(member),
+            _ => &self.path,
+        }
+    }
+
+    /// Returns the absolute path of the SWF file, as `archive.zip!path/in/archive.swf` if it is in
+    /// an archive.
+    fn absolute(&self) -> PathBuf {
+        let mut path = std::path::absolute(&self.path)
+            .expect("failed to resolve path of SWF file")
+            .into_os_string();
+        if let Some(member) = &self.member {
+            path.push("!");
+            path.push(member);
+        }
+        PathBuf::from(path)
+    }
+}
+
+
+/// Splits a path of the form `archive.zip!path/in/archive.swf` into the path of the archive and the
+/// path of the file within it. Paths of existing files are left alone.
+fn split_archive_path(path: &Path) -> (PathBuf, Option<String>) {
+    if !path.is_file() {
+        if let Some(path_str) = path.to_str() {
+            for (index, _) in path_str.match_indices('!') {
+                let archive_path = Path::new(&path_str[..index]);
+                if archive_path.is_file() {
+                    return (archive_path.to_owned(), Some(path_str[index+1..].to_owned()));
                 }
+            }
+        }
+    }
+    (path.to_owned(), None)
+}
+
+
+/// Reads a SWF file, which may also be given as a file within a ZIP archive (such as a JAR) as
+/// `archive.zip!path/in/archive.swf`. An archive given without a path must contain a single SWF
+/// file, unless it is a SWC component library, whose (first) library SWF file is read.
+fn read_swf(swf_path: &Path, max_sprite_depth: usize) -> (SwfBuf, MovieSource) {
+    let (path, member) = split_archive_path(swf_path);
+    let data = std::fs::read(&path)
+        .expect("failed to read SWF file");
+    if member.is_none() && !is_zip(&data) {
+        let swf_buf = decompress_swf(&data, max_sprite_depth);
+        return (swf_buf, MovieSource { path, member, catalog: None });
+    }
+
+    let archive = ZipReader::new(&data)
+        .expect("failed to read ZIP archive");
+    let is_swc = path.extension().is_some_and(|ext| ext.eq_ignore_ascii_case("swc"))
+        || archive.file_names().any(|name| name == CATALOG_PATH);
+    let catalog = if is_swc {
+        let catalog_data = archive.read_file(CATALOG_PATH)
+            .expect("failed to read SWC catalog");
+        let catalog = SwcCatalog::parse(&String::from_utf8_lossy(&catalog_data))
+            .expect("failed to parse SWC catalog");
+        Some(catalog)
+    } else {
+        None
+    };
+    let library = catalog.as_ref().and_then(|c| c.libraries.first().cloned());
+    let member = member.or(library).unwrap_or_else(|| {
+        let swf_names: Vec<&str> = archive.file_names()
+            .filter(|name| name.to_lowercase().ends_with(".swf"))
+            .collect();
+        match swf_names[..] {
+            [name] => name.to_owned(),
+            [] => Cli::command().error(
+                ErrorKind::InvalidValue, format!("{} contains no SWF file", path.display()),
+            ).exit(),
+            _ => Cli::command().error(
+                ErrorKind::InvalidValue,
+                format!(
+                    "{} contains several SWF files; choose one as {}!<path>: {}",
+                    path.display(), path.display(), swf_names.join(", "),
+                ),
+            ).exit(),
+        }
+    });
+    let swf_data = archive.read_file(&member)
+        .expect("failed to read SWF file from ZIP archive");
+    let swf_buf = decompress_swf(&swf_data, max_sprite_depth);
+    (swf_buf, MovieSource { path, member: Some(member), catalog })
+}
+
+
+/// Parses a movie, refusing to if it nests sprites more than `max_sprite_depth` levels deep, as
+/// parsing them (and every pass over the parsed tags) recurses into each level.
+fn parse_swf(swf_buf: &SwfBuf, max_sprite_depth: usize) -> swf::Swf<'_> {
+    parse_movie(swf_buf, max_sprite_depth)
+        .unwrap_or_else(|e| refuse_movie(e, "failed to parse SWF file"))
+}
+
+
+/// Decompresses a SWF file, refusing to if its first tags (which are parsed along with the header)
+/// nest sprites more than `max_sprite_depth` levels deep.
+fn decompress_swf(data: &[u8], max_sprite_depth: usize) -> SwfBuf {
+    decompress_movie(data, max_sprite_depth)
+        .unwrap_or_else(|e| refuse_movie(e, "failed to decompress SWF file"))
+}
+
+
+fn refuse_movie(error: ReadError, failure: &str) -> ! {
+    match error {
+        ReadError::NestedTooDeeply { limit } => Cli::command().error(
+            ErrorKind::InvalidValue,
+            format!("the movie nests sprites more than {} levels deep (see --max-sprite-depth)", limit),
+        ).exit(),
+        e @ (ReadError::TooLong { .. }|ReadError::Swf(_)) => {
+            Cli::command().error(ErrorKind::InvalidValue, format!("{}: {}", failure, e)).exit()
+        },
+    }
+}
+
+
+/// Writes a thumbnail of each bitmap, shape, text and rendered sprite into the thumbnail directory,
+/// along with a page showing them, and records the thumbnails in the manifest.
+fn write_thumbnails(context: &mut Context, size: u32, movie_name: &str) {
+    create_dir_all(THUMBNAIL_DIR)
+        .expect("failed to create thumbnail directory");
+    let mut thumbnails: Vec<Thumbnail> = Vec::new();
+    for (id, asset_file) in context.manifest.asset_files() {
+        // the first file that can be previewed, such as the first frame of a sprite
+        if thumbnails.iter().any(|t| t.id == id) {
+            continue;
+        }
+        let Some(JsonValue::String(kind)) = context.manifest.find_asset(id).and_then(|a| a.get("type")) else { continue };
+        let kind = kind.clone();
+        if !["bitmap", "shape", "text", "sprite"].contains(&kind.as_str()) {
+            continue;
+        }
+        let Ok(data) = std::fs::read(&asset_file) else { continue };
+        let file_stem = Path::new(&asset_file).file_stem()
+            .map(|stem| stem.to_string_lossy().into_owned())
+            .unwrap_or_else(|| id.to_string());
+        let (file_name, thumbnail_data) = if kind == "bitmap" {
+            let Ok(bitmap) = bitmap_thumbnail(&data, size) else { continue };
+            let mut png_data = Vec::new();
+            bitmap.write(context.indexed_format, &mut png_data)
+                .expect("failed to encode thumbnail");
+            (format!("{}.png", file_stem), png_data)
+        } else {
+            let Some(svg) = svg_thumbnail(&String::from_utf8_lossy(&data), size) else { continue };
+            (format!("{}.svg", file_stem), svg.into_bytes())
+        };
+
+        let path = format!("{}/{}", THUMBNAIL_DIR, file_name);
+        let mut f = File::create(&path)
+            .expect("failed to open thumbnail file");
+        f.write_all(&thumbnail_data)
+            .expect("failed to write thumbnail file");
+        if let Some(asset) = context.manifest.find_asset_mut(id) {
+            asset.insert("thumbnail", path);
+        }
+        thumbnails.push(Thumbnail {
+            id,
+            kind,
+            path: context.reference_graph.asset_path(id),
+            asset_file,
+            file_name,
+        });
+    }
+
+    let f = File::create(format!("{}/index.html", THUMBNAIL_DIR))
+        .expect("failed to open thumbnail index");
+    write_thumbnail_index(&thumbnails, movie_name, f)
+        .expect("failed to write thumbnail index");
+    status!("written: {} thumbnails", thumbnails.len());
+}
+
+
+/// Writes `index.html`, showing the files of the assets recorded in the manifest and the stream
+/// sounds.
+fn write_gallery_page(context: &Context, movie_name: &str) {
+    let mut assets: Vec<GalleryAsset> = Vec::new();
+    let mut id_to_index: HashMap<CharacterId, usize> = HashMap::new();
+    for (id, file) in context.manifest.asset_files() {
+        if let Some(index) = id_to_index.get(&id) {
+            assets[*index].files.push(file);
+            continue;
+        }
+        let entry = context.manifest.find_asset(id);
+        let string = |key| match entry.and_then(|e| e.get(key)) {
+            Some(JsonValue::String(value)) => Some(value.clone()),
+            _ => None,
+        };
+        id_to_index.insert(id, assets.len());
+        assets.push(GalleryAsset {
+            id,
+            kind: string("type").unwrap_or_default(),
+            path: context.reference_graph.asset_path(id),
+            name: string("name"),
+            files: vec![file],
+            thumbnail: string("thumbnail"),
+        });
+    }
+
+    let f = File::create("index.html")
+        .expect("failed to open gallery file");
+    write_gallery(movie_name, &assets, &context.manifest.stream_files(), f)
+        .expect("failed to write gallery file");
+}
+
+
+/// Changes into the output directory if one has been given, creating it if necessary.
+fn enter_output_dir(global: &GlobalOpts) {
+    if let Some(output_dir) = &global.output_dir {
+        create_dir_all(output_dir)
+            .expect("failed to create output directory");
+        std::env::set_current_dir(output_dir)
+            .expect("failed to change into output directory");
+    }
+}
+
+
+fn main() {
+    let cli = parse_opts();
+    QUIET.store(cli.global.quiet, Ordering::Relaxed);
+    if cli.man {
+        let mut command = Cli::command();
+        command.build();
+        write_man_page(&command, std::io::stdout().lock())
+            .expect("failed to write man page");
+        return;
+    }
+    let Some(command) = cli.command else {
+        Cli::command().error(ErrorKind::MissingSubcommand, "no command given").exit();
+    };
+    match command {
+        Command::Extract(opts) => {
+            if !(opts.render_frames || opts.rendering.export_movie) && opts.rendering.is_customized() {
+                Cli::command().error(
+                    ErrorKind::MissingRequiredArgument,
+                    "--bake-color-transforms, --transparent-bg and --render-scale require --render-frames or --export-movie",
+                ).exit();
+            }
+            if let Some(encoder) = opts.audio_format.encoder().filter(|encoder| !is_program_available(encoder)) {
+                Cli::command().error(
+                    ErrorKind::InvalidValue,
+                    format!("--audio-format {} requires {:?}, which was not found in PATH", opts.audio_format.to_possible_value().unwrap().get_name(), encoder),
+                ).exit();
+            }
+            run_extraction(&cli.global, &opts);
+        },
+        Command::List(args) => {
+            let (swf_buf, _) = read_swf(&args.swf_path, cli.global.max_sprite_depth);
+            let raw_tags = read_raw_tags(&swf_buf.data, 0);
+            let swf = parse_swf(&swf_buf, cli.global.max_sprite_depth);
+            let result = write_character_list(
+                &ReferenceGraph::build(
+                    &swf.tags, SourceEncoding::default().for_version(swf.header.version()), cli.global.max_sprite_depth,
+                ),
+                &character_tag_locations(&raw_tags),
+                args.listing_style(),
+                std::io::stdout().lock(),
+            );
+            end_listing(result, "failed to write character list");
+        },
+        Command::Info(args) => {
+            let (swf_buf, _) = read_swf(&args.swf_path, cli.global.max_sprite_depth);
+            let raw_tags = read_raw_tags(&swf_buf.data, 0);
+            let swf = parse_swf(&swf_buf, cli.global.max_sprite_depth);
+            let result = write_movie_info(&swf, &raw_tags, args.listing_style(), std::io::stdout().lock());
+            end_listing(result, "failed to write movie information");
+        },
+        Command::Render(render) => {
+            let mut opts = default_extract_opts(&render.swf_path);
+            opts.rendering = render.rendering;
+            opts.source_encoding = render.source_encoding;
+            opts.render_frames = true;
+            opts.render_only = true;
+            run_extraction(&cli.global, &opts);
+        },
+        Command::Repack(repack_opts) => {
+            let (swf_buf, source) = read_swf(&repack_opts.swf_path, cli.global.max_sprite_depth);
+            let movie_name = source.swf_path().file_stem()
+                .map(|stem| stem.to_string_lossy().into_owned())
+                .unwrap_or_default();
+            enter_output_dir(&cli.global);
+            let file_name = format!("{}.repacked.swf", movie_name);
+            let f = File::create(&file_name)
+                .expect("failed to open repacked SWF file");
+            repack(&swf_buf, repack_opts.compression, f)
+                .expect("failed to write repacked SWF file");
+            status!("repacked movie into {}", file_name);
+        },
+        Command::Serve(serve_opts) => {
+            let config = ServeConfig {
+                max_connections: serve_opts.max_connections.into(),
+                socket_timeout: Duration::try_from_secs_f64(serve_opts.socket_timeout).unwrap_or(Duration::MAX),
+                extraction_args: extraction_args(&cli.global, serve_opts.extract_args),
+            };
+            serve(&serve_opts.address, config)
+                .expect("failed to serve HTTP API");
+        },
+        Command::Completions(args) => {
+            let mut command = Cli::command();
+            command.build();
+            write_completions(&command, args.shell, std::io::stdout().lock())
+                .expect("failed to write completion script");
+        },
+    }
+}
+
+
+/// Extracts the assets of a movie (or, for the `render` subcommand, only renders its frames).
+fn run_extraction(global: &GlobalOpts, opts: &ExtractOpts) {
+    // a timeout too long to be represented never elapses
+    let cancellation = match global.timeout {
+        Some(seconds) => Cancellation::with_timeout(Duration::try_from_secs_f64(seconds).unwrap_or(Duration::MAX)),
+        None => Cancellation::new(),
+    };
+    let mut report = RunReport::new();
+    report.begin_phase("reading");
+
+    let (mut swf_buf, source) = read_swf(&opts.swf_path, global.max_sprite_depth);
+    // the loaders the movie has been unpacked from, outermost first
+    let mut unpacked_from = JsonValue::new_array();
+    let mut unpack_count = 0;
+    while opts.unpack && unpack_count < MAX_UNPACK_DEPTH {
+        let loader = parse_swf(&swf_buf, global.max_sprite_depth);
+        let analysis = LoaderAnalysis::analyze(&loader.tags);
+        let movie_len = usize::try_from(swf_buf.header.uncompressed_len()).unwrap();
+        let Some(payload) = analysis.payload.as_ref().filter(|_| analysis.is_loader(movie_len)) else { break };
+        match decompress_movie(&payload.data, global.max_sprite_depth) {
+            Ok(inner) => {
+                status!("unpacked the movie from binary data {} ({})", payload.id, payload.method.as_str());
+                unpacked_from.push(analysis.to_json());
+                unpack_count += 1;
+                swf_buf = inner;
             },
-            Tag::DefineBitsJpeg3(j3) => {
-                println!("J3 {}", j3.id);
-                // Jpeg3 may also be PNG or GIF
-                let alpha_data = if j3.alpha_data.len() > 0 {
-                    Some(j3.alpha_data)
-                } else {
-                    None
+            Err(e) => {
+                let message = match e {
+                    ReadError::NestedTooDeeply { .. } => format!("the movie in binary data {} nests sprites too deeply to be read", payload.id),
+                    e => format!("failed to decompress the movie in binary data {}: {}", payload.id, e),
                 };
-                id_to_bitmap.insert(
-                    j3.id,
-                    Bitmap::from_bytes(j3.data, alpha_data).unwrap(),
+                report.warn(
+                    Warning::about(WarningCode::FailedUnpacking, payload.id, message),
+                    Some(format!("binary/{}", payload.id)),
                 );
+                break;
             },
-            Tag::DefineBitsLossless(bmap) => {
-                match &bmap.format {
-                    BitmapFormat::ColorMap8 { num_colors } => {
-                        let actual_num_colors = usize::from(*num_colors) + 1;
-                        let component_count = if bmap.version == 2 { 4 } else { 3 };
-                        let mut palette_bytes = vec![0u8; component_count*actual_num_colors];
-                        let mut image_data_padded = Vec::new();
-                        let mut decoder = flate2::read::ZlibDecoder::new(bmap.data);
-                        decoder.read_exact(&mut palette_bytes)
-                            .expect("failed to read palette");
-                        decoder.read_to_end(&mut image_data_padded)
-                            .expect("failed to read image data");
-
-                        let data = if bmap.version == 2 {
-                            let mut palette = Vec::with_capacity(actual_num_colors);
-                            let mut palette_iter = palette_bytes.iter();
-                            for _ in 0..actual_num_colors {
-                                let r = *palette_iter.next().unwrap();
-                                let g = *palette_iter.next().unwrap();
-                                let b = *palette_iter.next().unwrap();
-                                let a = *palette_iter.next().unwrap();
-                                palette.push(RgbaColor { r, g, b, a });
-                            }
-
-                            let mut image_data = Vec::with_capacity(image_data_padded.len());
-                            let mut data_iter = image_data_padded.iter();
-                            for _ in 0..bmap.height {
-                                for _ in 0..bmap.width {
-                                    let pixel = *data_iter.next().unwrap();
-                                    image_data.push(pixel);
-                                }
-
-                                // 1 byte per pixel, padded to 4 bytes
-                                if (1 * bmap.width) % 4 != 0 {
-                                    let padding_count = 4 - ((1 * bmap.width) % 4);
-                                    for _ in 0..padding_count {
-                                        data_iter.next().unwrap();
-                                    }
-                                }
-                            }
-
-                            BitmapData::ColorMappedAlpha {
-                                palette,
-                                image_data,
-                            }
-                        } else {
-                            let mut palette = Vec::with_capacity(actual_num_colors);
-                            let mut palette_iter = palette_bytes.iter();
-                            for _ in 0..actual_num_colors {
-                                let r = *palette_iter.next().unwrap();
-                                let g = *palette_iter.next().unwrap();
-                                let b = *palette_iter.next().unwrap();
-                                palette.push(RgbColor { r, g, b });
-                            }
-
-                            let mut image_data = Vec::with_capacity(image_data_padded.len());
-                            let mut data_iter = image_data_padded.iter();
-                            for _ in 0..bmap.height {
-                                for _ in 0..bmap.width {
-                                    let pixel = *data_iter.next().unwrap();
-                                    image_data.push(pixel);
-                                }
-
-                                // 1 byte per pixel, padded to 4 bytes
-                                if (1 * bmap.width) % 4 != 0 {
-                                    let padding_count = 4 - ((1 * bmap.width) % 4);
-                                    for _ in 0..padding_count {
-                                        data_iter.next().unwrap();
-                                    }
-                                }
-                            }
-
-                            BitmapData::ColorMapped {
-                                palette,
-                                image_data,
-                            }
-                        };
+        }
+    }
+    // other paths given on the command line are relative to the original working directory
+    let swf_path = source.absolute();
+    let duplicate_index_path = opts.duplicate_index.as_ref().map(|path| std::path::absolute(path)
+        .expect("failed to resolve path of duplicate index"));
+    let font_dir = opts.rendering.font_dir.as_ref().map(|dir| FontDirectory::scan(dir)
+        .expect("failed to read font directory"));
+    // the SWF file has been read; all other paths are relative to the output directory
+    enter_output_dir(global);
+    let tag_locations = {
+        // the header (signature, length, stage size, frame rate and frame count) precedes the tags
+        let stream_offset = usize::try_from(swf_buf.header.uncompressed_len()).unwrap()
+            .saturating_sub(swf_buf.data.len());
+        let raw_tags = read_raw_tags(&swf_buf.data, stream_offset);
+        if opts.dump_tags {
+            dump_raw_tags(&raw_tags, Path::new("tags"))
+                .expect("failed to dump tags");
+        }
+        character_tag_locations(&raw_tags)
+    };
+    let avc_stream_ids = patch_avc_streams(&mut swf_buf.data);
+    report.begin_phase("parsing");
+    let swf = parse_swf(&swf_buf, global.max_sprite_depth);
+    report.begin_phase("analysis");
 
-                        id_to_bitmap.insert(
-                            bmap.id,
-                            Bitmap::new(
-                                bmap.width.into(),
-                                bmap.height.into(),
-                                data,
-                            )
-                        );
-                    },
-                    BitmapFormat::Rgb15 => {
-                        if bmap.version == 2 {
-                            panic!("forbidden combo of version 2 with format Rgb15");
-                        }
+    let movie_name = source.swf_path().file_stem()
+        .map(|stem| stem.to_string_lossy().into_owned())
+        .unwrap_or_default();
+    let movie_file_name = source.swf_path().file_name()
+        .map(|name| name.to_string_lossy().into_owned())
+        .unwrap_or_default();
 
-                        let mut image_data_padded = Vec::new();
-                        let mut decoder = flate2::read::ZlibDecoder::new(bmap.data);
-                        decoder.read_to_end(&mut image_data_padded)
-                            .expect("failed to read image data");
-
-                        let mut image_data = Vec::with_capacity(image_data_padded.len());
-                        let mut data_iter = image_data_padded.iter();
-                        for _ in 0..bmap.height {
-                            for _ in 0..bmap.width {
-                                let pixel = *data_iter.next().unwrap();
-                                image_data.push(pixel);
-                            }
-
-                            // 2 bytes per pixel, padded to 4 bytes
-                            if (2 * bmap.width) % 4 != 0 {
-                                let padding_count = 4 - ((2 * bmap.width) % 4);
-                                for _ in 0..padding_count {
-                                    data_iter.next().unwrap();
-                                }
-                            }
-                        }
+    let string_encoding = opts.source_encoding.for_version(swf.header.version());
+    let glyph_usage = if opts.subset_fonts {
+        let mut usage = GlyphUsage::new();
+        usage.scan(&swf.tags, string_encoding);
+        Some(usage)
+    } else {
+        None
+    };
+    let frame_labels = FrameLabels::scan(&swf.tags, string_encoding);
+    let frame_range = if let Some(spec) = &opts.rendering.frames {
+        let range = frame_labels.select(spec).unwrap_or_else(|| Cli::command().error(
+            ErrorKind::InvalidValue, format!("no frames match {:?}", spec),
+        ).exit());
+        Some(range)
+    } else if let Some(label) = &opts.rendering.frame_label {
+        let range = frame_labels.section(label).unwrap_or_else(|| Cli::command().error(
+            ErrorKind::InvalidValue, format!("no frame is labeled {:?}", label),
+        ).exit());
+        Some(range)
+    } else {
+        None
+    };
+    if let Some(range) = &frame_range {
+        status!("restricting the main timeline to frames {} to {}", range.start, range.end - 1);
+    }
 
-                        id_to_bitmap.insert(
-                            bmap.id,
-                            Bitmap::new(
-                                bmap.width.into(),
-                                bmap.height.into(),
-                                BitmapData::Rgb15 {
-                                    image_data,
-                                },
-                            )
-                        );
-                    },
-                    BitmapFormat::Rgb32 => {
-                        let data = if bmap.version == 2 {
-                            // 4 bytes per pixel => no padding
-
-                            let mut image_data = Vec::new();
-                            let mut decoder = flate2::read::ZlibDecoder::new(bmap.data);
-                            decoder.read_to_end(&mut image_data)
-                                .expect("failed to read image data");
-
-                            BitmapData::Rgba32 {
-                                image_data,
-                            }
-                        } else {
-                            let mut image_data_padded = Vec::new();
-                            let mut decoder = flate2::read::ZlibDecoder::new(bmap.data);
-                            decoder.read_to_end(&mut image_data_padded)
-                                .expect("failed to read image data");
-
-                            let mut image_data = Vec::with_capacity(image_data_padded.len());
-                            let mut data_iter = image_data_padded.iter();
-                            for _ in 0..bmap.height {
-                                for _ in 0..bmap.width {
-                                    let pixel = *data_iter.next().unwrap();
-                                    image_data.push(pixel);
-                                }
-
-                                // 3 bytes per pixel, padded to 4 bytes
-                                if (3 * bmap.width) % 4 != 0 {
-                                    let padding_count = 4 - ((3 * bmap.width) % 4);
-                                    for _ in 0..padding_count {
-                                        data_iter.next().unwrap();
-                                    }
-                                }
-                            }
-
-                            BitmapData::Rgb24 {
-                                image_data,
-                            }
-                        };
-                        id_to_bitmap.insert(
-                            bmap.id,
-                            Bitmap::new(
-                                bmap.width.into(),
-                                bmap.height.into(),
-                                data,
-                            )
-                        );
-                    },
-                }
-            },
-            Tag::DefineButton2(_) => {},
-            Tag::DefineButtonSound(_) => {},
-            Tag::DefineEditText(et) => {
-                if let Some(it) = et.initial_text {
-                    let filename = format!("{}{}.txt", filename_prefix, et.id);
-                    let mut f = File::create(&filename)
-                        .expect("failed to open text file");
-                    f.write_all(it.as_bytes())
-                        .expect("failed to write text file");
-                }
-            },
-            Tag::DefineFont(_) => {},
-            Tag::DefineFont2(_) => {},
-            Tag::DefineFontInfo(_) => {},
-            Tag::DefineMorphShape(_) => {},
-            Tag::DefineShape(sh) => {
-                let shape_data = shape_to_svg(sh);
-                let filename = format!("{}{}.svg", filename_prefix, sh.id);
-                let mut f = File::create(&filename)
-                    .expect("failed to open SVG file");
-                f.write_all(shape_data.as_bytes())
-                    .expect("failed to write SVG file");
-            },
-            Tag::DefineText(_) => {},
-            Tag::DoAction(_) => {},
-            Tag::FrameLabel(_) => {},
-            Tag::JpegTables(jt) => {
-                if let Some(jt_no_prefix) = jt.strip_prefix(&[0xFF, 0xD8]) {
-                    if let Some(jt_stripped) = jt_no_prefix.strip_suffix(&[0xFF, 0xD9]) {
-                        jpeg_tables = Vec::from(jt_stripped);
-                    }
-                }
-            },
-            Tag::PlaceObject(_) => {},
-            Tag::Protect(_) => {},
-            Tag::RemoveObject(_) => {},
-            Tag::SetBackgroundColor(_) => {},
-            Tag::ShowFrame => {},
-            Tag::SoundStreamBlock(ssb) => {
-                if let Some(snd) = &mut stream_sound {
-                    snd.append_data(ssb);
-                }
-            },
-            Tag::SoundStreamHead(ssh) => {
-                stream_sound = Some(Sound {
-                    format: ssh.stream_format.clone(),
-                    data: Vec::new(),
-                });
-            },
-            Tag::SoundStreamHead2(ssh) => {
-                stream_sound = Some(Sound {
-                    format: ssh.stream_format.clone(),
-                    data: Vec::new(),
-                });
-            },
-            Tag::StartSound(_) => {},
-            other => {
-                panic!("unhandled block: {:?}", other);
-            },
+    let reference_graph = ReferenceGraph::build(&swf.tags, string_encoding, global.max_sprite_depth);
+    let symbol_classes = collect_symbol_classes(&swf.tags, string_encoding);
+    let export_names = collect_export_names(&swf.tags, string_encoding);
+    // the classes of a component library are what its users refer to its symbols by
+    let use_symbol_names = opts.symbol_names || source.catalog.is_some();
+    let file_stems = FileStems::new(
+        reference_graph.characters().into_iter().map(|(id, _)| id), &export_names, &symbol_classes, use_symbol_names,
+    );
+    let mut context = Context {
+        swf_version: swf.header.version(),
+        string_encoding,
+        frame_rate: swf.header.frame_rate(),
+        script_kind: ScriptKind::classify(&swf.tags),
+        pad_stream_silence: opts.pad_stream_silence,
+        mix_sprite_streams: opts.mix_sprite_streams,
+        apply_latency_seek: !opts.ignore_latency_seek,
+        mp3_resync: opts.mp3_resync,
+        xing_header: opts.xing_header,
+        audio_options: AudioOptions {
+            format: opts.audio_format,
+            bit_rate: opts.audio_bitrate,
+            pcm_endian: opts.pcm_endian,
+            raw_adpcm: opts.raw_audio,
+            mono: opts.audio_mono,
+            sample_rate: opts.audio_rate,
+        },
+        glyph_usage,
+        font_names: HashMap::new(),
+        font_code_tables: HashMap::new(),
+        transcript: opts.transcript.map(|_| Transcript::new()),
+        localization: opts.localization.map(|_| LocalizationExport::new()),
+        export_names,
+        superclasses: if symbol_classes.is_empty() { HashMap::new() } else { collect_superclasses(&swf.tags) },
+        symbol_classes,
+        component_names: source.catalog.map(|catalog| catalog.component_names).unwrap_or_default(),
+        file_stems,
+        extension_map: opts.ext_map.iter().cloned().collect(),
+        used_characters: reference_graph.used_characters(),
+        reference_graph,
+        // rendering alone writes none of the characters' own files
+        extract_used: !opts.only_unused && !opts.render_only,
+        extract_unused: !opts.only_used && !opts.render_only,
+        render_only: opts.render_only,
+        export_movie: opts.rendering.export_movie,
+        abc_summary: opts.abc_summary,
+        abc_payloads: opts.abc_payloads,
+        write_timelines: opts.timeline,
+        render_frames: opts.render_frames || opts.rendering.export_movie,
+        bake_color_transforms: opts.rendering.bake_color_transforms,
+        background_color: if opts.rendering.transparent_bg { None } else { background_color(&swf.tags) },
+        render_scale: opts.rendering.render_scale,
+        max_sprite_depth: global.max_sprite_depth,
+        #[cfg(feature = "mp4-encode")]
+        encode_mp4: opts.rendering.encode_mp4,
+        main_frame_files: Vec::new(),
+        main_stream: None,
+        frame_range,
+        stage: swf.header.stage_size().clone(),
+        shapes: HashMap::new(),
+        texts: HashMap::new(),
+        fonts: HashMap::new(),
+        font_dir,
+        timelines: HashMap::new(),
+        character_bounds: HashMap::new(),
+        wav_provenance: if opts.wav_metadata { Some((movie_file_name, SystemTime::now())) } else { None },
+        id3_album: if opts.id3 { Some(movie_name.clone()) } else { None },
+        id3_track: 0,
+        sprite_streams: Vec::new(),
+        sound_usages: HashMap::new(),
+        screen_video_decoders: HashMap::new(),
+        avc_stream_ids,
+        avc_streams: HashMap::new(),
+        flv_streams: HashMap::new(),
+        manifest: Manifest::new(),
+        report,
+        profile: Profile::new(),
+        tag_locations,
+        indexed_format: opts.indexed_format,
+        image_scale: opts.image_scale,
+        raw_pixels: opts.raw_pixels,
+        min_image_size: opts.min_image_size,
+        min_sound_duration: opts.min_sound_duration,
+        min_bytes: opts.min_bytes,
+        max_image_pixels: opts.max_image_pixels,
+        max_audio_minutes: opts.max_audio_minutes,
+        max_assets: opts.max_assets,
+        asset_admissions: HashMap::new(),
+        admitted_count: 0,
+        max_memory: global.max_memory,
+        jpeg_tables: collect_jpeg_tables(&swf.tags),
+        cancellation,
+        timed_out: false,
+    };
+
+    let movie_len = usize::try_from(swf.header.uncompressed_len()).unwrap();
+    let loader = LoaderAnalysis::analyze(&swf.tags);
+    if loader.is_loader(movie_len) {
+        match &loader.payload {
+            Some(payload) => status!(
+                "movie appears to be a loader of the movie in binary data {} ({}); extract that with --unpack",
+                payload.id, payload.method.as_str(),
+            ),
+            None => status!("movie appears to be a loader of encrypted binary data"),
+        }
+        context.manifest.movie.insert("loader", loader.to_json());
+    }
+    if opts.unpack {
+        context.manifest.movie.insert("unpacked_from", unpacked_from);
+    }
+
+    let protection = protection_to_json(&swf.tags);
+    if protection.get("protected") == Some(&JsonValue::Bool(true)) {
+        status!("movie is protected against importing");
+    }
+    if protection.get("debugger_enabled") == Some(&JsonValue::Bool(true)) {
+        status!("movie allows remote debugging");
+    }
+    context.manifest.movie.insert("protection", protection);
+
+    context.manifest.movie.insert("file_attributes", file_attributes_to_json(&swf.tags));
+    match context.script_kind {
+        ScriptKind::None => status!("movie contains no scripts"),
+        ScriptKind::ActionScript2 => status!("movie uses ActionScript 1/2"),
+        ScriptKind::ActionScript3 => status!("movie uses ActionScript 3"),
+    }
+    context.manifest.movie.insert("script_kind", context.script_kind.as_str());
+    let script_limits = script_limits_to_json(&swf.tags);
+    if let (Some(depth), Some(timeout)) = (script_limits.get("max_recursion_depth"), script_limits.get("timeout_seconds")) {
+        status!("script limits: recursion depth {}, timeout {} s", depth, timeout);
+    }
+    context.manifest.movie.insert("script_limits", script_limits);
+
+    let product_info = product_info_to_json(&swf.tags);
+    if let (Some(JsonValue::String(version)), Some(JsonValue::String(date))) = (product_info.get("version"), product_info.get("compilation_date")) {
+        status!("compiled with version {} on {}", version, date);
+    }
+    context.manifest.movie.insert("product_info", product_info);
+    context.manifest.movie.insert("debug_id", debug_id(&swf.tags));
+    context.manifest.movie.insert("background_color", background_color(&swf.tags).map(|c| format!(
+        "#{:02x}{:02x}{:02x}", c.r, c.g, c.b,
+    )));
+
+    let mut unused = JsonValue::new_array();
+    for (id, kind) in context.reference_graph.unused_characters() {
+        let path = context.reference_graph.asset_path(id);
+        status!("{} is never used", path);
+        let mut entry = JsonValue::new_object();
+        entry.insert("id", id);
+        entry.insert("type", kind);
+        entry.insert("path", path);
+        unused.push(entry);
+    }
+    context.manifest.movie.insert("unused_characters", unused);
+
+    context.report.begin_phase("extraction");
+    let outcome = panic::catch_unwind(AssertUnwindSafe(|| process_tags(&mut context, None, &swf.tags)));
+    if let Err(payload) = outcome {
+        // the message has already been output; record it and carry on failing
+        let message = payload.downcast_ref::<String>().map(|s| s.as_str())
+            .or_else(|| payload.downcast_ref::<&str>().copied())
+            .unwrap_or("unknown error");
+        context.report.abort(message);
+        if opts.report {
+            write_report(&context);
+        }
+        panic::resume_unwind(payload);
+    }
+    if context.timed_out {
+        context.report.abort(format!("timed out after {} s", global.timeout.unwrap_or_default()));
+    }
+    context.report.begin_phase("finishing");
+    let unmixed: Vec<CharacterId> = context.sprite_streams.iter().map(|(id, _, _)| *id).collect();
+    for id in unmixed {
+        warn(&mut context, Warning::about(
+            WarningCode::UnmixedStream, id, format!("stream sound of sprite {} could not be mixed into the main timeline", id),
+        ));
+    }
+    if opts.rendering.export_movie && !context.timed_out {
+        export_movie(&mut context, &frame_labels, &movie_name);
+    }
+
+    for (id, avc_stream) in &context.avc_streams {
+        if avc_stream.is_empty() {
+            continue;
         }
+        let file_name = format!("{}.mp4", character_file_stem(&context, "", *id));
+        let f = File::create(&file_name)
+            .expect("failed to open MP4 file");
+        avc_stream.write_mp4(context.frame_rate, f)
+            .expect("failed to write MP4 file");
+        context.manifest.add_file(*id, "video", &file_name);
     }
-    if let Some(ssnd) = stream_sound {
-        if ssnd.data.len() > 0 {
-            let file_name = format!("{}stream.{}", filename_prefix, ssnd.extension());
+
+    for (id, flv_stream) in &context.flv_streams {
+        if flv_stream.is_empty() {
+            continue;
+        }
+        let file_stem = character_file_stem(&context, "", *id);
+        let mut streams = vec![(format!("{}.flv", file_stem), flv_stream)];
+        let split_streams = flv_stream.split_alpha();
+        if let Some((color_stream, alpha_stream)) = &split_streams {
+            streams.push((format!("{}_color.flv", file_stem), color_stream));
+            streams.push((format!("{}_alpha.flv", file_stem), alpha_stream));
+        }
+        for (file_name, stream) in streams {
             let f = File::create(&file_name)
-                .expect("failed to open stream file");
-            ssnd.write(f)
-                .expect("failed to write stream file");
+                .expect("failed to open FLV file");
+            stream.write_flv(context.frame_rate, f)
+                .expect("failed to write FLV file");
+            context.manifest.add_file(*id, "video", &file_name);
         }
     }
-    for (i, bitmap) in &id_to_bitmap {
-        let file_name = format!("{}{}.{}", filename_prefix, i, bitmap.extension());
+
+    let mut sound_ids: Vec<&CharacterId> = context.sound_usages.keys().collect();
+    sound_ids.sort_unstable();
+    for id in sound_ids {
+        if !is_extracted(&context, *id) || context.asset_admissions.get(id) == Some(&false) {
+            continue;
+        }
+        let mut obj = JsonValue::new_object();
+        obj.insert("id", *id);
+        obj.insert("usages", JsonValue::Array(context.sound_usages[id].clone()));
+        let file_name = format!("{}.sound.json", character_file_stem(&context, "", *id));
         let f = File::create(&file_name)
-            .expect("failed to open bitmap file");
-        bitmap.write(f)
-            .expect("failed to write bitmap file");
+            .expect("failed to open sound usage file");
+        obj.write(f)
+            .expect("failed to write sound usage file");
+        context.manifest.add_file(*id, "sound", &file_name);
     }
-}
 
+    if let (Some(format), Some(transcript)) = (opts.transcript, &context.transcript) {
+        let file_name = match format {
+            TranscriptFormat::Text => "transcript.txt",
+            TranscriptFormat::Json => "transcript.json",
+        };
+        let f = File::create(file_name)
+            .expect("failed to open transcript file");
+        transcript.write(format, f)
+            .expect("failed to write transcript file");
+    }
 
-fn main() {
-    let opts = Opts::parse();
+    if let Some(format) = opts.reference_graph {
+        let file_name = match format {
+            GraphFormat::Dot => "references.dot",
+            GraphFormat::Json => "references.json",
+        };
+        let f = File::create(file_name)
+            .expect("failed to open reference graph file");
+        context.reference_graph.write(format, f)
+            .expect("failed to write reference graph file");
+    }
 
-    let swf_buf = {
-        let f = File::open(&opts.swf_path)
-            .expect("failed to open SWF file");
-        swf::decompress_swf(f)
-            .expect("failed to decompress SWF file")
-    };
-    let swf = swf::parse_swf(&swf_buf)
-        .expect("failed to parse SWF file");
+    if opts.url_report {
+        let report = UrlReport::scan(&swf.tags, context.swf_version, context.string_encoding);
+        status!("movie references {} external URLs", report.len());
+        let f = File::create("urls.json")
+            .expect("failed to open URL report file");
+        report.write(f)
+            .expect("failed to write URL report file");
+    }
+
+    if let (Some(format), Some(localization)) = (opts.localization, &context.localization) {
+        let file_name = match format {
+            LocalizationFormat::Csv => "strings.csv",
+            LocalizationFormat::Po => "strings.po",
+        };
+        let f = File::create(file_name)
+            .expect("failed to open localization file");
+        localization.write(format, f)
+            .expect("failed to write localization file");
+    }
+
+    if !context.symbol_classes.is_empty() && !opts.render_only {
+        let f = File::create("symbols.json")
+            .expect("failed to open symbol file");
+        symbols_to_json(&context).write(f)
+            .expect("failed to write symbol file");
+    }
+
+    if let Some(index_path) = &duplicate_index_path {
+        find_duplicates(&mut context, index_path, &swf_path, opts.link_duplicates);
+    }
+
+    for (id, location) in &context.tag_locations {
+        if let Some(asset) = context.manifest.find_asset_mut(*id) {
+            asset.insert("source_tag", location.to_json());
+        }
+    }
+    for (id, _) in context.reference_graph.characters() {
+        if let Some(asset) = context.manifest.find_asset_mut(id) {
+            asset.insert("path", context.reference_graph.asset_path(id));
+        }
+    }
+    for (id, name) in context.file_stems.original_names() {
+        if let Some(asset) = context.manifest.find_asset_mut(*id) {
+            asset.insert("name", name.as_str());
+        }
+    }
+
+    if let Some(size) = opts.thumbnails {
+        write_thumbnails(&mut context, size, &movie_name);
+    }
+    if opts.gallery {
+        write_gallery_page(&context, &movie_name);
+    }
+
+    let f = File::create("manifest.json")
+        .expect("failed to open manifest file");
+    context.manifest.write(f)
+        .expect("failed to write manifest file");
+
+    context.report.end_phase();
+    if opts.report {
+        write_report(&context);
+    }
+    if let Some(count) = opts.profile {
+        context.profile.write_summary(context.report.phases(), count, std::io::stdout().lock())
+            .expect("failed to write profile");
+    }
 
-    process_tags("", &swf.tags);
+    let mut written_files = context.manifest.written_files();
+    written_files.push("manifest.json".to_owned());
+    if opts.report {
+        written_files.push("report.json".to_owned());
+    }
+    if opts.gallery {
+        written_files.push("index.html".to_owned());
+    }
+    let bytes_written: u64 = written_files.iter()
+        .filter_map(|file| std::fs::metadata(file).ok())
+        .map(|metadata| metadata.len())
+        .sum();
+    status!("{}", context.report.summary(&asset_outcomes(&context), written_files.len(), bytes_written));
+    if context.timed_out {
+        std::process::exit(TIMED_OUT_EXIT_CODE);
+    }
 }
@@ -0,0 +1,147 @@
+use std::io::{self, Write};
+
+use clap::{Arg, Command};
+
+
+/// Escapes text for roff, so that backslashes, hyphens and leading periods and apostrophes are
+/// output literally.
+fn roff_escape(text: &str) -> String {
+    let escaped = text.replace('\\', "\\e").replace('-', "\\-");
+    if escaped.starts_with(['.', '\'']) {
+        format!("\\&{}", escaped)
+    } else {
+        escaped
+    }
+}
+
+
+/// Writes a paragraph, one sentence per line as is customary in roff sources.
+fn write_paragraph<W: Write>(writer: &mut W, text: &str) -> io::Result<()> {
+    for line in text.lines() {
+        let line = line.trim();
+        if line.is_empty() {
+            writeln!(writer, ".PP")?;
+            continue;
+        }
+        for sentence in line.split_inclusive(". ") {
+            writeln!(writer, "{}", roff_escape(sentence.trim()))?;
+        }
+    }
+    Ok(())
+}
+
+
+fn write_arguments<'a, W: Write>(writer: &mut W, args: impl Iterator<Item = &'a Arg>) -> io::Result<()> {
+    for arg in args {
+        let value_name = arg.get_value_names()
+            .and_then(|names| names.first())
+            .map(|name| name.to_string())
+            .unwrap_or_else(|| arg.get_id().to_string().to_uppercase());
+
+        writeln!(writer, ".TP")?;
+        if arg.is_positional() {
+            writeln!(writer, "\\fI{}\\fR", roff_escape(&value_name))?;
+        } else {
+            let mut names = Vec::new();
+            if let Some(short) = arg.get_short() {
+                names.push(format!("\\fB\\-{}\\fR", short));
+            }
+            if let Some(long) = arg.get_long() {
+                names.push(format!("\\fB\\-\\-{}\\fR", roff_escape(long)));
+            }
+            let mut line = names.join(", ");
+            if arg.get_action().takes_values() {
+                let separator = if arg.is_require_equals_set() { "[=" } else { " " };
+                line.push_str(&format!("{}\\fI{}\\fR", separator, roff_escape(&value_name)));
+                if arg.is_require_equals_set() {
+                    line.push(']');
+                }
+            }
+            writeln!(writer, "{}", line)?;
+        }
+
+        let help = arg.get_long_help().or(arg.get_help())
+            .map(|help| help.to_string())
+            .unwrap_or_default();
+        write_paragraph(writer, &help)?;
+
+        let values: Vec<String> = arg.get_possible_values().iter()
+            .filter(|value| !value.is_hide_set())
+            .map(|value| format!("\\fI{}\\fR", roff_escape(value.get_name())))
+            .collect();
+        if !values.is_empty() {
+            writeln!(writer, ".IP")?;
+            writeln!(writer, "Possible values: {}.", values.join(", "))?;
+        }
+        let defaults: Vec<String> = arg.get_default_values().iter()
+            .map(|value| roff_escape(&value.to_string_lossy()))
+            .collect();
+        if !defaults.is_empty() && arg.get_action().takes_values() {
+            writeln!(writer, ".IP")?;
+            writeln!(writer, "Default: \\fI{}\\fR.", defaults.join(", "))?;
+        }
+    }
+    Ok(())
+}
+
+
+/// Whether the argument is described in the man page; `--help` goes without saying.
+fn is_documented(arg: &Arg) -> bool {
+    !arg.is_hide_set() && arg.get_id() != "help"
+}
+
+
+fn synopsis(command: &Command, prefix: &str) -> String {
+    let mut synopsis = format!("\\fB{}\\fR", roff_escape(prefix));
+    if command.get_arguments().any(|arg| !arg.is_positional() && is_documented(arg)) {
+        synopsis.push_str(" [\\fIOPTIONS\\fR]");
+    }
+    if command.has_subcommands() {
+        synopsis.push_str(" \\fICOMMAND\\fR");
+    }
+    for arg in command.get_positionals() {
+        let name = arg.get_id().to_string().to_uppercase();
+        if arg.is_required_set() {
+            synopsis.push_str(&format!(" \\fI{}\\fR", roff_escape(&name)));
+        } else {
+            synopsis.push_str(&format!(" [\\fI{}\\fR]", roff_escape(&name)));
+        }
+    }
+    synopsis
+}
+
+
+/// Writes a man page in roff format describing the command, its options and its subcommands. The
+/// command must have been built, so that its global options are propagated to the subcommands.
+pub(crate) fn write_man_page<W: Write>(command: &Command, mut writer: W) -> io::Result<()> {
+    let name = command.get_name();
+    let about = command.get_about().map(|about| about.to_string()).unwrap_or_default();
+
+    writeln!(writer, ".TH {} 1 \"\" \"{} {}\"", roff_escape(&name.to_uppercase()), roff_escape(name), env!("CARGO_PKG_VERSION"))?;
+    writeln!(writer, ".SH NAME")?;
+    writeln!(writer, "{} \\- {}", roff_escape(name), roff_escape(about.trim_end_matches('.')))?;
+
+    writeln!(writer, ".SH SYNOPSIS")?;
+    writeln!(writer, "{}", synopsis(command, name))?;
+    for subcommand in command.get_subcommands().filter(|sc| sc.get_name() != "help") {
+        writeln!(writer, ".br")?;
+        writeln!(writer, "{}", synopsis(subcommand, &format!("{} {}", name, subcommand.get_name())))?;
+    }
+
+    writeln!(writer, ".SH OPTIONS")?;
+    write_arguments(&mut writer, command.get_arguments().filter(|arg| is_documented(arg)))?;
+
+    writeln!(writer, ".SH COMMANDS")?;
+    for subcommand in command.get_subcommands().filter(|sc| sc.get_name() != "help") {
+        writeln!(writer, ".SS {}", roff_escape(subcommand.get_name()))?;
+        let about = subcommand.get_long_about().or(subcommand.get_about())
+            .map(|about| about.to_string())
+            .unwrap_or_default();
+        write_paragraph(&mut writer, &about)?;
+        write_arguments(
+            &mut writer,
+            subcommand.get_arguments().filter(|arg| is_documented(arg) && !arg.is_global_set()),
+        )?;
+    }
+    Ok(())
+}
@@ -0,0 +1,176 @@
+use std::collections::HashMap;
+use std::io::Write;
+
+use swf::{CharacterId, Rectangle};
+
+use crate::json::JsonValue;
+
+
+/// Collects information about the movie and the extracted assets, written out as
+/// `manifest.json` once extraction has finished.
+#[derive(Clone, Debug)]
+pub(crate) struct Manifest {
+    /// Information pertaining to the movie as a whole.
+    pub movie: JsonValue,
+
+    assets: Vec<JsonValue>,
+
+    /// The index of the entry of each character in `assets`.
+    asset_indexes: HashMap<CharacterId, usize>,
+
+    streams: Vec<JsonValue>,
+    scripts: Vec<JsonValue>,
+}
+impl Manifest {
+    pub fn new() -> Self {
+        Self {
+            movie: JsonValue::new_object(),
+            assets: Vec::new(),
+            asset_indexes: HashMap::new(),
+            streams: Vec::new(),
+            scripts: Vec::new(),
+        }
+    }
+
+    /// Returns the manifest entry for the character with the given ID, creating it if necessary.
+    pub fn asset(&mut self, id: CharacterId, kind: &str) -> &mut JsonValue {
+        let assets = &mut self.assets;
+        let index = *self.asset_indexes.entry(id).or_insert_with(|| {
+            let mut asset = JsonValue::new_object();
+            asset.insert("id", id);
+            asset.insert("type", kind);
+            asset.insert("files", JsonValue::new_array());
+            assets.push(asset);
+            assets.len() - 1
+        });
+        &mut self.assets[index]
+    }
+
+    /// Returns the manifest entry for the character with the given ID, if one has been created.
+    pub fn find_asset(&self, id: CharacterId) -> Option<&JsonValue> {
+        self.asset_indexes.get(&id)
+            .map(|&index| &self.assets[index])
+    }
+
+    /// Returns the manifest entry for the character with the given ID for modification, if one has
+    /// been created.
+    pub fn find_asset_mut(&mut self, id: CharacterId) -> Option<&mut JsonValue> {
+        self.asset_indexes.get(&id)
+            .map(|&index| &mut self.assets[index])
+    }
+
+    /// Returns the files written for all characters, along with the IDs of the characters.
+    pub fn asset_files(&self) -> Vec<(CharacterId, String)> {
+        let mut files = Vec::new();
+        for asset in &self.assets {
+            let Some(JsonValue::Integer(id)) = asset.get("id") else { continue };
+            let Some(JsonValue::Array(asset_files)) = asset.get("files") else { continue };
+            for file in asset_files {
+                if let (Ok(id), JsonValue::String(file)) = (CharacterId::try_from(*id), file) {
+                    files.push((id, file.clone()));
+                }
+            }
+        }
+        files
+    }
+
+    /// Returns the names of the stream sound files of all timelines.
+    pub fn stream_files(&self) -> Vec<String> {
+        self.streams.iter()
+            .filter_map(|stream| match stream.get("file") {
+                Some(JsonValue::String(file)) => Some(file.clone()),
+                _ => None,
+            })
+            .collect()
+    }
+
+    /// Returns the names of all files recorded in the manifest: those of the characters, the stream
+    /// sounds and the scripts.
+    pub fn written_files(&self) -> Vec<String> {
+        let mut files: Vec<String> = self.asset_files().into_iter()
+            .map(|(_, file)| file)
+            .collect();
+        for entry in self.streams.iter().chain(&self.scripts) {
+            if let Some(JsonValue::String(file)) = entry.get("file") {
+                files.push(file.clone());
+            }
+        }
+        files
+    }
+
+    /// Records that a file has been written for the character with the given ID.
+    pub fn add_file(&mut self, id: CharacterId, kind: &str, file_name: &str) {
+        let asset = self.asset(id, kind);
+        if let Some(files) = asset.get_mut("files") {
+            files.push(file_name);
+        }
+    }
+
+    /// Records the area covered by the character with the given ID, in pixels relative to its
+    /// registration point, along with the position of the registration point relative to the
+    /// top left corner of that area.
+    pub fn set_bounds(&mut self, id: CharacterId, kind: &str, bounds: &Rectangle) {
+        let mut bounds_obj = JsonValue::new_object();
+        bounds_obj.insert("x_min", bounds.x_min.to_pixels());
+        bounds_obj.insert("y_min", bounds.y_min.to_pixels());
+        bounds_obj.insert("x_max", bounds.x_max.to_pixels());
+        bounds_obj.insert("y_max", bounds.y_max.to_pixels());
+        let mut origin = JsonValue::new_object();
+        origin.insert("x", -bounds.x_min.to_pixels());
+        origin.insert("y", -bounds.y_min.to_pixels());
+
+        let asset = self.asset(id, kind);
+        asset.insert("bounds", bounds_obj);
+        asset.insert("origin", origin);
+    }
+
+    /// Records the center area of the 9-slice scaling grid of the character with the given ID, in
+    /// pixels relative to its registration point; only this area is stretched when scaling.
+    pub fn set_scaling_grid(&mut self, id: CharacterId, kind: &str, grid: &Rectangle) {
+        let mut grid_obj = JsonValue::new_object();
+        grid_obj.insert("x_min", grid.x_min.to_pixels());
+        grid_obj.insert("y_min", grid.y_min.to_pixels());
+        grid_obj.insert("x_max", grid.x_max.to_pixels());
+        grid_obj.insert("y_max", grid.y_max.to_pixels());
+        self.asset(id, kind).insert("scaling_grid", grid_obj);
+    }
+
+    /// Records that a stream sound has been written for the given timeline.
+    ///
+    /// `sprite_id` is `None` for the main timeline.
+    pub fn add_stream(&mut self, sprite_id: Option<CharacterId>, file_name: &str) -> &mut JsonValue {
+        let mut stream = JsonValue::new_object();
+        stream.insert("sprite", sprite_id);
+        stream.insert("file", file_name);
+        self.streams.push(stream);
+        self.streams.last_mut().unwrap()
+    }
+
+    /// Records that an ActionScript 3 (DoABC) block has been written for the given timeline.
+    ///
+    /// `sprite_id` is `None` for the main timeline.
+    pub fn add_script(&mut self, sprite_id: Option<CharacterId>, frame: u32, name: &str, file_name: &str) -> &mut JsonValue {
+        let mut script = JsonValue::new_object();
+        script.insert("sprite", sprite_id);
+        script.insert("frame", frame);
+        script.insert("name", name);
+        script.insert("file", file_name);
+        // the scripts are added in the order in which they are executed
+        script.insert("order", self.scripts.len() + 1);
+        self.scripts.push(script);
+        self.scripts.last_mut().unwrap()
+    }
+
+    pub fn to_json(&self) -> JsonValue {
+        let mut obj = JsonValue::new_object();
+        obj.insert("movie", self.movie.clone());
+        obj.insert("assets", JsonValue::Array(self.assets.clone()));
+        obj.insert("streams", JsonValue::Array(self.streams.clone()));
+        obj.insert("scripts", JsonValue::Array(self.scripts.clone()));
+        obj
+    }
+
+    pub fn write<W: Write>(&self, writer: W) -> Result<(), std::io::Error> {
+        self.to_json().write(writer)
+    }
+}
@@ -0,0 +1,241 @@
+use std::time::{Duration, UNIX_EPOCH};
+
+use swf::{Color, FileAttributes, Tag};
+
+use crate::json::JsonValue;
+use crate::sound::utc_date_time;
+
+
+/// The scripting model of a movie, which determines the kind of script tags Flash Player executes.
+#[derive(Clone, Copy, Debug, Eq, Hash, Ord, PartialEq, PartialOrd)]
+pub(crate) enum ScriptKind {
+    /// The movie contains no scripts.
+    None,
+
+    /// ActionScript 1 or 2, run by AVM1 from DoAction and DoInitAction tags.
+    ActionScript2,
+
+    /// ActionScript 3, run by AVM2 from DoABC tags.
+    ActionScript3,
+}
+impl ScriptKind {
+    /// Classifies the movie according to its FileAttributes tag, falling back to the kinds of script
+    /// tags it contains.
+    pub fn classify(tags: &[Tag]) -> Self {
+        let mut has_file_attributes = false;
+        let mut has_avm1 = false;
+        let mut has_avm2 = false;
+        scan_script_tags(tags, &mut has_avm1, &mut has_avm2);
+
+        for tag in tags {
+            if let Tag::FileAttributes(attributes) = tag {
+                if attributes.contains(FileAttributes::IS_ACTION_SCRIPT_3) {
+                    return Self::ActionScript3;
+                }
+                has_file_attributes = true;
+            }
+        }
+
+        if has_file_attributes {
+            // Flash Player ignores DoABC tags unless the movie is marked as ActionScript 3
+            if has_avm1 { Self::ActionScript2 } else { Self::None }
+        } else if has_avm2 {
+            Self::ActionScript3
+        } else if has_avm1 {
+            Self::ActionScript2
+        } else {
+            Self::None
+        }
+    }
+
+    pub fn as_str(&self) -> Option<&'static str> {
+        match self {
+            Self::None => None,
+            Self::ActionScript2 => Some("as2"),
+            Self::ActionScript3 => Some("as3"),
+        }
+    }
+
+    /// Whether DoAction and DoInitAction tags are executed in a movie of this kind.
+    pub fn runs_avm1(&self) -> bool {
+        *self != Self::ActionScript3
+    }
+
+    /// Whether DoABC tags are executed in a movie of this kind.
+    pub fn runs_avm2(&self) -> bool {
+        *self == Self::ActionScript3
+    }
+}
+
+
+fn scan_script_tags(tags: &[Tag], has_avm1: &mut bool, has_avm2: &mut bool) {
+    for tag in tags {
+        match tag {
+            Tag::DoAction(_)|Tag::DoInitAction { .. } => *has_avm1 = true,
+            Tag::DoAbc(_) => *has_avm2 = true,
+            Tag::DefineSprite(sprite) => scan_script_tags(&sprite.tags, has_avm1, has_avm2),
+            _ => {},
+        }
+    }
+}
+
+
+/// Returns the password hash stored in a Protect or EnableDebugger tag, or `None` if it is empty.
+fn password_hash(hash: &swf::SwfStr) -> Option<String> {
+    if hash.is_empty() {
+        None
+    } else {
+        Some(String::from_utf8_lossy(hash.as_bytes()).into_owned())
+    }
+}
+
+
+/// Collects the protection settings of the movie (Protect, EnableDebugger and EnableTelemetry
+/// tags) into a JSON object.
+///
+/// The password hashes are MD5-crypt strings for Protect and EnableDebugger and hex-encoded SHA-256
+/// digests for EnableTelemetry.
+pub(crate) fn protection_to_json(tags: &[Tag]) -> JsonValue {
+    let mut is_protected = false;
+    let mut protect_hash = None;
+    let mut is_debugger_enabled = false;
+    let mut debugger_hash = None;
+    let mut is_telemetry_enabled = false;
+    let mut telemetry_hash = None;
+
+    for tag in tags {
+        match tag {
+            Tag::Protect(hash) => {
+                is_protected = true;
+                protect_hash = hash.and_then(password_hash);
+            },
+            Tag::EnableDebugger(hash) => {
+                is_debugger_enabled = true;
+                debugger_hash = password_hash(hash);
+            },
+            Tag::EnableTelemetry { password_hash } => {
+                is_telemetry_enabled = true;
+                if !password_hash.is_empty() {
+                    telemetry_hash = Some(hex(password_hash));
+                }
+            },
+            _ => {},
+        }
+    }
+
+    let mut obj = JsonValue::new_object();
+    obj.insert("protected", is_protected);
+    obj.insert("protect_password_hash", protect_hash);
+    obj.insert("debugger_enabled", is_debugger_enabled);
+    obj.insert("debugger_password_hash", debugger_hash);
+    obj.insert("telemetry_enabled", is_telemetry_enabled);
+    obj.insert("telemetry_password_hash", telemetry_hash);
+    obj
+}
+
+
+/// Returns the file attributes of the movie as a JSON object, or null if the movie has no
+/// FileAttributes tag.
+pub(crate) fn file_attributes_to_json(tags: &[Tag]) -> JsonValue {
+    let attributes = tags.iter().find_map(|tag| match tag {
+        Tag::FileAttributes(attributes) => Some(*attributes),
+        _ => None,
+    });
+    let Some(attributes) = attributes else { return JsonValue::Null };
+
+    let mut obj = JsonValue::new_object();
+    obj.insert("action_script_3", attributes.contains(FileAttributes::IS_ACTION_SCRIPT_3));
+    obj.insert("use_network_sandbox", attributes.contains(FileAttributes::USE_NETWORK_SANDBOX));
+    obj.insert("has_metadata", attributes.contains(FileAttributes::HAS_METADATA));
+    obj.insert("use_gpu", attributes.contains(FileAttributes::USE_GPU));
+    obj.insert("use_direct_blit", attributes.contains(FileAttributes::USE_DIRECT_BLIT));
+    obj
+}
+
+
+/// Returns the script limits of the movie as a JSON object, or null if the movie has no
+/// ScriptLimits tag.
+pub(crate) fn script_limits_to_json(tags: &[Tag]) -> JsonValue {
+    for tag in tags {
+        if let Tag::ScriptLimits { max_recursion_depth, timeout_in_seconds } = tag {
+            let mut obj = JsonValue::new_object();
+            obj.insert("max_recursion_depth", *max_recursion_depth);
+            obj.insert("timeout_seconds", *timeout_in_seconds);
+            return obj;
+        }
+    }
+    JsonValue::Null
+}
+
+
+/// Returns the compiler information from the ProductInfo tag as a JSON object, or null if the movie
+/// has no ProductInfo tag.
+pub(crate) fn product_info_to_json(tags: &[Tag]) -> JsonValue {
+    let product_info = tags.iter().find_map(|tag| match tag {
+        Tag::ProductInfo(product_info) => Some(product_info),
+        _ => None,
+    });
+    let Some(product_info) = product_info else { return JsonValue::Null };
+
+    let product = match product_info.product_id {
+        1 => Some("Macromedia Flex for J2EE"),
+        2 => Some("Macromedia Flex for .NET"),
+        3 => Some("Adobe Flex"),
+        _ => None,
+    };
+    let edition = match product_info.edition {
+        0 => Some("Developer Edition"),
+        1 => Some("Full Commercial Edition"),
+        2 => Some("Non-Commercial Edition"),
+        3 => Some("Educational Edition"),
+        4 => Some("Not For Resale (NFR) Edition"),
+        5 => Some("Trial Edition"),
+        6 => Some("None"),
+        _ => None,
+    };
+
+    // the compilation date is stored in milliseconds since the Unix epoch
+    let compiled = UNIX_EPOCH + Duration::from_millis(product_info.compilation_date);
+    let (date, time) = utc_date_time(compiled);
+
+    let mut obj = JsonValue::new_object();
+    obj.insert("product_id", product_info.product_id);
+    obj.insert("product", product);
+    obj.insert("edition_id", product_info.edition);
+    obj.insert("edition", edition);
+    obj.insert("version", format!(
+        "{}.{}.{}",
+        product_info.major_version, product_info.minor_version, product_info.build_number,
+    ));
+    obj.insert("compilation_timestamp", product_info.compilation_date);
+    obj.insert("compilation_date", format!("{}T{}Z", date, time));
+    obj
+}
+
+
+/// Returns the background color of the movie as set by the SetBackgroundColor tag, or `None` if the
+/// movie has no SetBackgroundColor tag.
+pub(crate) fn background_color(tags: &[Tag]) -> Option<Color> {
+    tags.iter().find_map(|tag| match tag {
+        Tag::SetBackgroundColor(color) => Some(color.clone()),
+        _ => None,
+    })
+}
+
+
+/// Returns the UUID from the DebugID tag, which links the movie to its SWD debugging information, or
+/// `None` if the movie has no DebugID tag.
+pub(crate) fn debug_id(tags: &[Tag]) -> Option<String> {
+    tags.iter().find_map(|tag| match tag {
+        Tag::DebugId(id) => Some(format!(
+            "{}-{}-{}-{}-{}",
+            hex(&id[0..4]), hex(&id[4..6]), hex(&id[6..8]), hex(&id[8..10]), hex(&id[10..16]),
+        )),
+        _ => None,
+    })
+}
+
+
+fn hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{:02x}", b)).collect()
+}
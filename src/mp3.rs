@@ -0,0 +1,238 @@
+/// Bit rates of MPEG-1 Layer III in kbit/s, by bit rate index.
+const MPEG1_BIT_RATES: [u32; 15] = [0, 32, 40, 48, 56, 64, 80, 96, 112, 128, 160, 192, 224, 256, 320];
+
+/// Bit rates of MPEG-2 and MPEG-2.5 Layer III in kbit/s, by bit rate index.
+const MPEG2_BIT_RATES: [u32; 15] = [0, 8, 16, 24, 32, 40, 48, 56, 64, 80, 96, 112, 128, 144, 160];
+
+
+#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq)]
+pub(crate) enum MpegVersion {
+    Mpeg1,
+    Mpeg2,
+    Mpeg25,
+}
+
+
+/// The information in the header of an MPEG Layer III frame.
+#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq)]
+pub(crate) struct FrameHeader {
+    pub version: MpegVersion,
+    pub has_crc: bool,
+    pub bit_rate: u32,
+    pub sample_rate: u32,
+    pub is_padded: bool,
+    pub is_mono: bool,
+}
+impl FrameHeader {
+    /// Parses the header at the start of the given data.
+    ///
+    /// Returns `None` if the data does not start with a valid Layer III frame header. Free-format
+    /// frames are not supported, as their length cannot be determined from the header.
+    pub fn parse(data: &[u8]) -> Option<Self> {
+        if data.len() < 4 || data[0] != 0xFF || data[1] & 0xE0 != 0xE0 {
+            return None;
+        }
+        let version = match (data[1] >> 3) & 0b11 {
+            0b00 => MpegVersion::Mpeg25,
+            0b10 => MpegVersion::Mpeg2,
+            0b11 => MpegVersion::Mpeg1,
+            _ => return None,
+        };
+        if (data[1] >> 1) & 0b11 != 0b01 {
+            // not Layer III
+            return None;
+        }
+        let has_crc = data[1] & 0b1 == 0;
+
+        let bit_rate_index = usize::from(data[2] >> 4);
+        let bit_rate = match version {
+            MpegVersion::Mpeg1 => MPEG1_BIT_RATES.get(bit_rate_index)?,
+            _ => MPEG2_BIT_RATES.get(bit_rate_index)?,
+        } * 1000;
+        if bit_rate == 0 {
+            return None;
+        }
+
+        let base_sample_rate = match (data[2] >> 2) & 0b11 {
+            0 => 44100,
+            1 => 48000,
+            2 => 32000,
+            _ => return None,
+        };
+        let sample_rate = match version {
+            MpegVersion::Mpeg1 => base_sample_rate,
+            MpegVersion::Mpeg2 => base_sample_rate / 2,
+            MpegVersion::Mpeg25 => base_sample_rate / 4,
+        };
+
+        Some(Self {
+            version,
+            has_crc,
+            bit_rate,
+            sample_rate,
+            is_padded: data[2] & 0b10 != 0,
+            is_mono: data[3] >> 6 == 0b11,
+        })
+    }
+
+    /// Returns the number of samples (per channel) encoded in the frame.
+    pub fn samples(&self) -> u32 {
+        match self.version {
+            MpegVersion::Mpeg1 => 1152,
+            _ => 576,
+        }
+    }
+
+    /// Returns the length of the frame in bytes, including the header.
+    pub fn frame_len(&self) -> usize {
+        let bytes_per_sample_slot = self.samples() / 8;
+        let len = bytes_per_sample_slot * self.bit_rate / self.sample_rate
+            + if self.is_padded { 1 } else { 0 };
+        len as usize
+    }
+}
+
+
+/// Returns a frame with the same format as the frame at the start of `template` that decodes to
+/// silence.
+///
+/// A Layer III frame whose side information is all zeroes contains no audio data.
+pub(crate) fn silent_frame(template: &[u8]) -> Option<Vec<u8>> {
+    let mut header = FrameHeader::parse(template)?;
+    header.is_padded = false;
+    header.has_crc = false;
+
+    let mut frame = vec![0u8; header.frame_len()];
+    frame[0..4].copy_from_slice(&template[0..4]);
+    frame[1] |= 0b1; // no CRC
+    frame[2] &= !0b10; // not padded
+    Some(frame)
+}
+
+
+/// Removes all data that is not part of a valid frame, such as encoder padding or partial frames.
+///
+/// Returns the cleaned data along with the ranges that have been removed, as pairs of start offset
+/// (within the original data) and length.
+///
+/// A frame is confirmed if it is directly followed by another frame (or the end of the data). To
+/// avoid mistaking garbage for a frame header, the first frame must be confirmed and all further
+/// frames must match its version, sample rate and channel mode. Unconfirmed frames are only kept
+/// if no confirmed frame starts within them; otherwise, they are assumed to be truncated.
+pub(crate) fn resync(data: &[u8]) -> (Vec<u8>, Vec<(usize, usize)>) {
+    let mut cleaned = Vec::with_capacity(data.len());
+    let mut removed: Vec<(usize, usize)> = Vec::new();
+    let mut reference: Option<FrameHeader> = None;
+    let mut pos = 0;
+    while pos < data.len() {
+        let frame = frame_at(data, pos, reference.as_ref())
+            .filter(|header| {
+                if is_confirmed_frame_at(data, pos, reference.as_ref()) {
+                    return true;
+                }
+                reference.is_some()
+                    && !(pos + 1..pos + header.frame_len())
+                        .any(|p| is_confirmed_frame_at(data, p, reference.as_ref()))
+            });
+
+        match frame {
+            Some(header) => {
+                reference.get_or_insert(header);
+                let len = header.frame_len();
+                cleaned.extend(&data[pos..pos + len]);
+                pos += len;
+            },
+            None => {
+                match removed.last_mut() {
+                    Some((start, len)) if *start + *len == pos => *len += 1,
+                    _ => removed.push((pos, 1)),
+                }
+                pos += 1;
+            },
+        }
+    }
+    (cleaned, removed)
+}
+
+/// Returns the header of the frame at the given position if it lies entirely within the data and
+/// matches the reference frame.
+fn frame_at(data: &[u8], pos: usize, reference: Option<&FrameHeader>) -> Option<FrameHeader> {
+    FrameHeader::parse(&data[pos..])
+        .filter(|header| pos + header.frame_len() <= data.len())
+        .filter(|header| reference.is_none_or(|r| {
+            header.version == r.version && header.sample_rate == r.sample_rate && header.is_mono == r.is_mono
+        }))
+}
+
+/// Returns whether there is a frame at the given position that is directly followed by another
+/// frame or the end of the data.
+fn is_confirmed_frame_at(data: &[u8], pos: usize, reference: Option<&FrameHeader>) -> bool {
+    let Some(header) = frame_at(data, pos, reference) else { return false };
+    let next = pos + header.frame_len();
+    next == data.len() || frame_at(data, next, Some(reference.unwrap_or(&header))).is_some()
+}
+
+
+/// Builds an Xing (for variable bit rates) or Info (for constant bit rates) frame for the frames
+/// at the start of `data`, allowing players to determine the duration and to seek.
+///
+/// The frame is to be prepended to the data. Returns `None` if the data does not start with a
+/// valid frame.
+pub(crate) fn xing_frame(data: &[u8]) -> Option<Vec<u8>> {
+    let template = FrameHeader::parse(data)?;
+
+    let mut frame_offsets = Vec::new();
+    let mut is_vbr = false;
+    let mut pos = 0;
+    while let Some(header) = FrameHeader::parse(&data[pos..]) {
+        if pos + header.frame_len() > data.len() {
+            break;
+        }
+        frame_offsets.push(pos);
+        is_vbr |= header.bit_rate != template.bit_rate;
+        pos += header.frame_len();
+    }
+
+    // the Xing header follows the side information
+    let side_info_len = match (template.version, template.is_mono) {
+        (MpegVersion::Mpeg1, false) => 32,
+        (MpegVersion::Mpeg1, true) => 17,
+        (_, false) => 17,
+        (_, true) => 9,
+    };
+    let xing_offset = 4 + side_info_len;
+    let needed_len = xing_offset
+        + 4 // "Xing" or "Info"
+        + 4 // flags
+        + 4 // number of frames
+        + 4 // number of bytes
+        + 100 // table of contents
+    ;
+
+    // use the lowest bit rate with frames large enough to contain the header
+    let mut header = [data[0], data[1] | 0b1, data[2] & !0b10, data[3]];
+    let frame_len = (1..15u8).find_map(|bit_rate_index| {
+        header[2] = (header[2] & 0x0F) | (bit_rate_index << 4);
+        FrameHeader::parse(&header)
+            .map(|h| h.frame_len())
+            .filter(|len| *len >= needed_len)
+    })?;
+
+    let total_len = frame_len + data.len();
+    let mut frame = vec![0u8; frame_len];
+    frame[0..4].copy_from_slice(&header);
+    let mut xing = Vec::with_capacity(needed_len - xing_offset);
+    xing.extend(if is_vbr { b"Xing" } else { b"Info" });
+    xing.extend(0b0111u32.to_be_bytes()); // frames, bytes and table of contents present
+    xing.extend(u32::try_from(frame_offsets.len()).unwrap().to_be_bytes());
+    xing.extend(u32::try_from(total_len).unwrap().to_be_bytes());
+    for percent in 0..100 {
+        // the position of each percent of the duration, in 256ths of the file
+        let position = frame_offsets.get(percent * frame_offsets.len() / 100)
+            .map(|offset| frame_len + offset)
+            .unwrap_or(total_len);
+        xing.push((position * 256 / total_len).min(255) as u8);
+    }
+    frame[xing_offset..xing_offset + xing.len()].copy_from_slice(&xing);
+    Some(frame)
+}
@@ -0,0 +1,433 @@
+use std::fmt;
+use std::io::Write;
+
+use swf::Fixed8;
+
+
+/// The AVCPacketType of a packet containing an AVCDecoderConfigurationRecord.
+const AVC_SEQUENCE_HEADER: u8 = 0;
+
+/// The AVCPacketType of a packet containing NAL units.
+const AVC_NALU: u8 = 1;
+
+/// The NAL unit type of a coded slice of an IDR picture.
+const NAL_TYPE_IDR: u8 = 5;
+
+/// The number of time units per frame; the timescale is the frame rate multiplied by this value.
+///
+/// As the SWF frame rate is an 8.8 fixed-point value, this makes all frame durations integral.
+const TICKS_PER_FRAME: u32 = 256;
+
+
+#[derive(Debug)]
+pub(crate) enum Error {
+    Io(std::io::Error),
+    Truncated,
+    MissingDecoderConfiguration,
+    TooLarge,
+    OutOfOrder { frame_num: u16, previous: u16 },
+}
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Io(e) => write!(f, "I/O error: {}", e),
+            Self::Truncated => write!(f, "AVC packet is truncated"),
+            Self::MissingDecoderConfiguration => write!(f, "stream lacks an AVC decoder configuration"),
+            Self::TooLarge => write!(f, "stream too large for an MP4 file with 32-bit offsets"),
+            Self::OutOfOrder { frame_num, previous }
+                => write!(f, "frame {} follows frame {}", frame_num, previous),
+        }
+    }
+}
+impl std::error::Error for Error {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Self::Io(e) => Some(e),
+            Self::Truncated => None,
+            Self::MissingDecoderConfiguration => None,
+            Self::TooLarge => None,
+            Self::OutOfOrder { .. } => None,
+        }
+    }
+}
+impl From<std::io::Error> for Error {
+    fn from(value: std::io::Error) -> Self { Self::Io(value) }
+}
+
+
+#[derive(Clone, Debug)]
+struct AvcSample {
+    frame_num: u16,
+    composition_offset_ms: i32,
+    is_keyframe: bool,
+    data: Vec<u8>,
+}
+
+
+/// Collects the packets of an H.264 video stream and muxes them into an MP4 file.
+#[derive(Clone, Debug)]
+pub(crate) struct AvcStream {
+    width: u16,
+    height: u16,
+
+    /// The AVCDecoderConfigurationRecord containing the SPS and PPS.
+    config: Option<Vec<u8>>,
+
+    samples: Vec<AvcSample>,
+}
+impl AvcStream {
+    pub fn new(width: u16, height: u16) -> Self {
+        Self {
+            width,
+            height,
+            config: None,
+            samples: Vec::new(),
+        }
+    }
+
+    /// Returns whether the stream contains no pictures.
+    pub fn is_empty(&self) -> bool {
+        self.samples.is_empty()
+    }
+
+    /// Adds an AVCVIDEOPACKET (the payload of a VideoFrame tag) to the stream.
+    ///
+    /// Returns whether the packet contained a keyframe (IDR picture). Pictures must follow each other
+    /// in the order of their frames, from which their timestamps are derived.
+    pub fn push_packet(&mut self, frame_num: u16, data: &[u8]) -> Result<bool, Error> {
+        if data.len() < 4 {
+            return Err(Error::Truncated);
+        }
+        let packet_type = data[0];
+        // sign-extend the 24-bit composition time
+        let composition_offset_ms = i32::from_be_bytes([data[1], data[2], data[3], 0]) >> 8;
+        let payload = &data[4..];
+
+        match packet_type {
+            AVC_SEQUENCE_HEADER => {
+                self.config = Some(payload.to_vec());
+                Ok(false)
+            },
+            AVC_NALU => {
+                if let (Some(first), Some(last)) = (self.samples.first(), self.samples.last()) {
+                    // frame numbers are counted from the first picture, as in write_mp4
+                    if frame_num.wrapping_sub(first.frame_num) <= last.frame_num.wrapping_sub(first.frame_num) {
+                        return Err(Error::OutOfOrder { frame_num, previous: last.frame_num });
+                    }
+                }
+                let is_keyframe = self.nal_unit_types(payload)
+                    .any(|t| t == NAL_TYPE_IDR);
+                self.samples.push(AvcSample {
+                    frame_num,
+                    composition_offset_ms,
+                    is_keyframe,
+                    data: payload.to_vec(),
+                });
+                Ok(is_keyframe)
+            },
+            _ => {
+                // end of sequence; nothing to store
+                Ok(false)
+            },
+        }
+    }
+
+    /// Returns the types of the length-prefixed NAL units in the given sample.
+    fn nal_unit_types<'a>(&self, mut sample: &'a [u8]) -> impl Iterator<Item = u8> + 'a {
+        let length_size = self.config.as_ref()
+            .and_then(|c| c.get(4))
+            .map(|b| usize::from(b & 0b11) + 1)
+            .unwrap_or(4);
+        std::iter::from_fn(move || {
+            if sample.len() <= length_size {
+                return None;
+            }
+            let nal_length = sample[..length_size].iter()
+                .fold(0usize, |acc, b| (acc << 8) | usize::from(*b));
+            let nal_type = sample[length_size] & 0x1F;
+            sample = &sample[(length_size + nal_length).min(sample.len())..];
+            Some(nal_type)
+        })
+    }
+
+    /// Writes the stream as an MP4 file, deriving the timestamps from the given frame rate.
+    pub fn write_mp4<W: Write>(&self, frame_rate: Fixed8, mut writer: W) -> Result<(), Error> {
+        let config = self.config.as_ref().ok_or(Error::MissingDecoderConfiguration)?;
+        let timescale = match frame_rate.get() as u16 {
+            // the timing of a movie without frame rate is undefined; show one frame per second
+            0 => TICKS_PER_FRAME,
+            frame_rate => u32::from(frame_rate),
+        };
+        let first_frame = self.samples.first().map(|s| s.frame_num).unwrap_or(0);
+        let decode_times: Vec<u32> = self.samples.iter()
+            .map(|s| u32::from(s.frame_num.wrapping_sub(first_frame)) * TICKS_PER_FRAME)
+            .collect();
+        let duration = decode_times.last().map(|t| t + TICKS_PER_FRAME).unwrap_or(0);
+
+        let mut ftyp = Vec::new();
+        write_box(&mut ftyp, b"ftyp", |b| {
+            b.extend(b"isom");
+            b.extend(0x200u32.to_be_bytes());
+            b.extend(b"isomiso2avc1mp41");
+        });
+
+        let mdat_payload_len: usize = self.samples.iter().map(|s| s.data.len()).sum();
+        let mdat_header = mdat_payload_len.checked_add(8)
+            .and_then(|len| u32::try_from(len).ok())
+            .ok_or(Error::TooLarge)?
+            .to_be_bytes();
+        let chunk_offset = u32::try_from(ftyp.len() + 8).unwrap();
+
+        let mut moov = Vec::new();
+        write_box(&mut moov, b"moov", |b| {
+            write_full_box(b, b"mvhd", 0, 0, |b| {
+                b.extend(0u32.to_be_bytes()); // creation time
+                b.extend(0u32.to_be_bytes()); // modification time
+                b.extend(timescale.to_be_bytes());
+                b.extend(duration.to_be_bytes());
+                b.extend(0x0001_0000u32.to_be_bytes()); // rate 1.0
+                b.extend(0x0100u16.to_be_bytes()); // volume 1.0
+                b.extend([0u8; 10]); // reserved
+                write_identity_matrix(b);
+                b.extend([0u8; 24]); // pre-defined
+                b.extend(2u32.to_be_bytes()); // next track ID
+            });
+            write_box(b, b"trak", |b| {
+                // flags: track enabled, in movie
+                write_full_box(b, b"tkhd", 0, 0x000003, |b| {
+                    b.extend(0u32.to_be_bytes()); // creation time
+                    b.extend(0u32.to_be_bytes()); // modification time
+                    b.extend(1u32.to_be_bytes()); // track ID
+                    b.extend(0u32.to_be_bytes()); // reserved
+                    b.extend(duration.to_be_bytes());
+                    b.extend([0u8; 8]); // reserved
+                    b.extend(0u16.to_be_bytes()); // layer
+                    b.extend(0u16.to_be_bytes()); // alternate group
+                    b.extend(0u16.to_be_bytes()); // volume
+                    b.extend(0u16.to_be_bytes()); // reserved
+                    write_identity_matrix(b);
+                    b.extend((u32::from(self.width) << 16).to_be_bytes());
+                    b.extend((u32::from(self.height) << 16).to_be_bytes());
+                });
+                write_box(b, b"mdia", |b| {
+                    write_full_box(b, b"mdhd", 0, 0, |b| {
+                        b.extend(0u32.to_be_bytes()); // creation time
+                        b.extend(0u32.to_be_bytes()); // modification time
+                        b.extend(timescale.to_be_bytes());
+                        b.extend(duration.to_be_bytes());
+                        b.extend(0x55C4u16.to_be_bytes()); // language "und"
+                        b.extend(0u16.to_be_bytes()); // pre-defined
+                    });
+                    write_full_box(b, b"hdlr", 0, 0, |b| {
+                        b.extend(0u32.to_be_bytes()); // pre-defined
+                        b.extend(b"vide");
+                        b.extend([0u8; 12]); // reserved
+                        b.extend(b"VideoHandler\0");
+                    });
+                    write_box(b, b"minf", |b| {
+                        write_full_box(b, b"vmhd", 0, 0x000001, |b| {
+                            b.extend([0u8; 8]); // graphics mode and opcolor
+                        });
+                        write_box(b, b"dinf", |b| {
+                            write_full_box(b, b"dref", 0, 0, |b| {
+                                b.extend(1u32.to_be_bytes());
+                                // flag: media data is in the same file
+                                write_full_box(b, b"url ", 0, 0x000001, |_| {});
+                            });
+                        });
+                        write_box(b, b"stbl", |b| {
+                            self.write_sample_tables(b, config, timescale, &decode_times, chunk_offset);
+                        });
+                    });
+                });
+            });
+        });
+
+        writer.write_all(&ftyp)?;
+        writer.write_all(&mdat_header)?;
+        writer.write_all(b"mdat")?;
+        for sample in &self.samples {
+            writer.write_all(&sample.data)?;
+        }
+        writer.write_all(&moov)?;
+        Ok(())
+    }
+
+    fn write_sample_tables(&self, b: &mut Vec<u8>, config: &[u8], timescale: u32, decode_times: &[u32], chunk_offset: u32) {
+        let sample_count = u32::try_from(self.samples.len()).unwrap();
+
+        write_full_box(b, b"stsd", 0, 0, |b| {
+            b.extend(1u32.to_be_bytes());
+            write_box(b, b"avc1", |b| {
+                b.extend([0u8; 6]); // reserved
+                b.extend(1u16.to_be_bytes()); // data reference index
+                b.extend([0u8; 16]); // pre-defined and reserved
+                b.extend(self.width.to_be_bytes());
+                b.extend(self.height.to_be_bytes());
+                b.extend(0x0048_0000u32.to_be_bytes()); // 72 dpi horizontally
+                b.extend(0x0048_0000u32.to_be_bytes()); // 72 dpi vertically
+                b.extend(0u32.to_be_bytes()); // reserved
+                b.extend(1u16.to_be_bytes()); // frame count
+                b.extend([0u8; 32]); // compressor name
+                b.extend(0x0018u16.to_be_bytes()); // depth
+                b.extend((-1i16).to_be_bytes()); // pre-defined
+                write_box(b, b"avcC", |b| b.extend(config));
+            });
+        });
+
+        // decoding time deltas, run-length encoded
+        let mut time_deltas: Vec<(u32, u32)> = Vec::new();
+        for (i, time) in decode_times.iter().enumerate() {
+            let delta = decode_times.get(i + 1)
+                .map(|next| next - time)
+                .unwrap_or(TICKS_PER_FRAME);
+            match time_deltas.last_mut() {
+                Some((count, last_delta)) if *last_delta == delta => *count += 1,
+                _ => time_deltas.push((1, delta)),
+            }
+        }
+        write_full_box(b, b"stts", 0, 0, |b| {
+            b.extend(u32::try_from(time_deltas.len()).unwrap().to_be_bytes());
+            for (count, delta) in &time_deltas {
+                b.extend(count.to_be_bytes());
+                b.extend(delta.to_be_bytes());
+            }
+        });
+
+        let composition_offsets: Vec<i32> = self.samples.iter()
+            .map(|s| (i64::from(s.composition_offset_ms) * i64::from(timescale) / 1000) as i32)
+            .collect();
+        if composition_offsets.iter().any(|o| *o != 0) {
+            // version 1 allows negative offsets
+            let version = if composition_offsets.iter().any(|o| *o < 0) { 1 } else { 0 };
+            write_full_box(b, b"ctts", version, 0, |b| {
+                b.extend(sample_count.to_be_bytes());
+                for offset in &composition_offsets {
+                    b.extend(1u32.to_be_bytes());
+                    b.extend(offset.to_be_bytes());
+                }
+            });
+        }
+
+        if self.samples.iter().any(|s| !s.is_keyframe) {
+            let keyframes: Vec<u32> = self.samples.iter()
+                .enumerate()
+                .filter(|(_, s)| s.is_keyframe)
+                .map(|(i, _)| u32::try_from(i + 1).unwrap())
+                .collect();
+            write_full_box(b, b"stss", 0, 0, |b| {
+                b.extend(u32::try_from(keyframes.len()).unwrap().to_be_bytes());
+                for keyframe in &keyframes {
+                    b.extend(keyframe.to_be_bytes());
+                }
+            });
+        }
+
+        // all samples are stored in a single chunk
+        write_full_box(b, b"stsc", 0, 0, |b| {
+            b.extend(1u32.to_be_bytes());
+            b.extend(1u32.to_be_bytes()); // first chunk
+            b.extend(sample_count.to_be_bytes()); // samples per chunk
+            b.extend(1u32.to_be_bytes()); // sample description index
+        });
+        write_full_box(b, b"stsz", 0, 0, |b| {
+            b.extend(0u32.to_be_bytes()); // samples have different sizes
+            b.extend(sample_count.to_be_bytes());
+            for sample in &self.samples {
+                b.extend(u32::try_from(sample.data.len()).unwrap().to_be_bytes());
+            }
+        });
+        write_full_box(b, b"stco", 0, 0, |b| {
+            b.extend(1u32.to_be_bytes());
+            b.extend(chunk_offset.to_be_bytes());
+        });
+    }
+}
+
+
+fn write_box<F: FnOnce(&mut Vec<u8>)>(out: &mut Vec<u8>, kind: &[u8; 4], content: F) {
+    let start = out.len();
+    out.extend([0u8; 4]);
+    out.extend(kind);
+    content(out);
+    let size = u32::try_from(out.len() - start).expect("MP4 box too large");
+    out[start..start+4].copy_from_slice(&size.to_be_bytes());
+}
+
+fn write_full_box<F: FnOnce(&mut Vec<u8>)>(out: &mut Vec<u8>, kind: &[u8; 4], version: u8, flags: u32, content: F) {
+    write_box(out, kind, |b| {
+        b.extend((u32::from(version) << 24 | flags).to_be_bytes());
+        content(b);
+    });
+}
+
+fn write_identity_matrix(out: &mut Vec<u8>) {
+    for value in [0x0001_0000u32, 0, 0, 0, 0x0001_0000, 0, 0, 0, 0x4000_0000] {
+        out.extend(value.to_be_bytes());
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+
+    /// Returns the contents of the first (full) box of the given kind, after its version and flags.
+    fn full_box<'a>(mp4: &'a [u8], kind: &[u8; 4]) -> &'a [u8] {
+        let start = mp4.windows(4).position(|w| w == kind).expect("box missing") + 4;
+        let size = u32::from_be_bytes(mp4[start-8..start-4].try_into().unwrap());
+        &mp4[start+4..start-8+usize::try_from(size).unwrap()]
+    }
+
+
+    fn u32s(data: &[u8]) -> Vec<u32> {
+        data.chunks_exact(4)
+            .map(|chunk| u32::from_be_bytes(chunk.try_into().unwrap()))
+            .collect()
+    }
+
+
+    fn stream(frames: &[u16]) -> AvcStream {
+        let mut stream = AvcStream::new(16, 16);
+        stream.push_packet(0, &[AVC_SEQUENCE_HEADER, 0, 0, 0, 1, 0x64, 0, 0x1F, 0xFF]).unwrap();
+        for &frame_num in frames {
+            // a single NAL unit of an IDR picture
+            stream.push_packet(frame_num, &[AVC_NALU, 0, 0, 0, 0, 0, 0, 2, 0x65, 0x88]).unwrap();
+        }
+        stream
+    }
+
+
+    #[test]
+    fn sample_durations_follow_frame_numbers() {
+        let mut mp4 = Vec::new();
+        stream(&[3, 4, 5, 7]).write_mp4(Fixed8::from_f32(12.5), &mut mp4).unwrap();
+
+        let mvhd = u32s(full_box(&mp4, b"mvhd"));
+        // timescale and duration
+        assert_eq!(&mvhd[2..4], &[3200, 5 * TICKS_PER_FRAME]);
+        let stts = u32s(full_box(&mp4, b"stts"));
+        assert_eq!(stts, [3, 2, TICKS_PER_FRAME, 1, 2 * TICKS_PER_FRAME, 1, TICKS_PER_FRAME]);
+    }
+
+
+    #[test]
+    fn missing_frame_rate_gives_valid_timescale() {
+        let mut mp4 = Vec::new();
+        stream(&[1, 2]).write_mp4(Fixed8::ZERO, &mut mp4).unwrap();
+        let mdhd = u32s(full_box(&mp4, b"mdhd"));
+        assert_eq!(&mdhd[2..4], &[TICKS_PER_FRAME, 2 * TICKS_PER_FRAME]);
+    }
+
+
+    #[test]
+    fn out_of_order_pictures_are_rejected() {
+        let mut stream = stream(&[3, 5]);
+        let result = stream.push_packet(4, &[AVC_NALU, 0, 0, 0, 0, 0, 0, 2, 0x41, 0x9A]);
+        assert!(matches!(result, Err(Error::OutOfOrder { frame_num: 4, previous: 5 })), "{:?}", result);
+        let result = stream.push_packet(5, &[AVC_NALU, 0, 0, 0, 0, 0, 0, 2, 0x41, 0x9A]);
+        assert!(matches!(result, Err(Error::OutOfOrder { .. })), "{:?}", result);
+        assert!(stream.push_packet(6, &[AVC_NALU, 0, 0, 0, 0, 0, 0, 2, 0x41, 0x9A]).is_ok());
+    }
+}
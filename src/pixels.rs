@@ -0,0 +1,317 @@
+use jpeg_decoder::PixelFormat;
+use png::{BitDepth, ColorType};
+
+
+/// Pixels ready to be encoded into a PNG image.
+pub struct PngPixels {
+    pub color_type: ColorType,
+    pub bit_depth: BitDepth,
+    pub data: Vec<u8>,
+}
+
+
+/// Converts a pixel in CMYK, as decoded by jpeg_decoder, to RGB.
+fn cmyk_to_rgb(c: u8, m: u8, y: u8, k: u8) -> [u8; 3] {
+    let scale = |ink: u8| (u16::from(255 - ink) * u16::from(255 - k) / 255) as u8;
+    [scale(c), scale(m), scale(y)]
+}
+
+
+/// Combines the pixels decoded from a JPEG image with a separate alpha channel of one byte per
+/// pixel.
+///
+/// The pixels keep their bit depth: 16-bit grayscale pixels (decoded in native byte order) become
+/// 16-bit grayscale pixels with alpha, the alpha values being scaled up accordingly. CMYK pixels are
+/// converted to RGB. Pixels beyond the end of the alpha channel are opaque; pixels beyond the end of
+/// the color data are black.
+pub fn add_alpha_to_jpeg_pixels(format: PixelFormat, pixels: &[u8], alpha: &[u8], pixel_count: usize) -> PngPixels {
+    let bytes_per_pixel = format.pixel_bytes();
+    let (color_type, bit_depth, output_bytes_per_pixel) = match format {
+        PixelFormat::L8 => (ColorType::GrayscaleAlpha, BitDepth::Eight, 2),
+        PixelFormat::L16 => (ColorType::GrayscaleAlpha, BitDepth::Sixteen, 4),
+        PixelFormat::RGB24|PixelFormat::CMYK32 => (ColorType::Rgba, BitDepth::Eight, 4),
+    };
+
+    let mut data = Vec::with_capacity(pixel_count * output_bytes_per_pixel);
+    let zero_pixel = [0u8; 4];
+    for i in 0..pixel_count {
+        let pixel = pixels.get(i*bytes_per_pixel..(i+1)*bytes_per_pixel)
+            .unwrap_or(&zero_pixel[..bytes_per_pixel]);
+        let alpha_value = alpha.get(i).copied().unwrap_or(0xFF);
+        match format {
+            PixelFormat::L8 => data.extend([pixel[0], alpha_value]),
+            PixelFormat::L16 => {
+                let value = u16::from_ne_bytes([pixel[0], pixel[1]]);
+                // scaling by 257 maps 0xFF to 0xFFFF
+                let alpha_value = u16::from(alpha_value) * 257;
+                data.extend(value.to_be_bytes());
+                data.extend(alpha_value.to_be_bytes());
+            },
+            PixelFormat::RGB24 => data.extend([pixel[0], pixel[1], pixel[2], alpha_value]),
+            PixelFormat::CMYK32 => {
+                let [r, g, b] = cmyk_to_rgb(pixel[0], pixel[1], pixel[2], pixel[3]);
+                data.extend([r, g, b, alpha_value]);
+            },
+        }
+    }
+
+    PngPixels {
+        color_type,
+        bit_depth,
+        data,
+    }
+}
+
+
+/// Converts the pixels decoded from a JPEG image, along with an optional separate alpha channel of
+/// one byte per pixel, to 8-bit RGBA.
+///
+/// 16-bit grayscale pixels are reduced to 8 bits and CMYK pixels converted to RGB. As with
+/// [`add_alpha_to_jpeg_pixels`], missing alpha values are opaque and missing pixels black.
+pub fn jpeg_pixels_to_rgba(format: PixelFormat, pixels: &[u8], alpha: Option<&[u8]>, pixel_count: usize) -> Vec<u8> {
+    let bytes_per_pixel = format.pixel_bytes();
+    let alpha = alpha.unwrap_or(&[]);
+    let mut rgba = Vec::with_capacity(4 * pixel_count);
+    let zero_pixel = [0u8; 4];
+    for i in 0..pixel_count {
+        let pixel = pixels.get(i*bytes_per_pixel..(i+1)*bytes_per_pixel)
+            .unwrap_or(&zero_pixel[..bytes_per_pixel]);
+        let alpha_value = alpha.get(i).copied().unwrap_or(0xFF);
+        let [r, g, b] = match format {
+            PixelFormat::L8 => [pixel[0]; 3],
+            PixelFormat::L16 => [(u16::from_ne_bytes([pixel[0], pixel[1]]) >> 8) as u8; 3],
+            PixelFormat::RGB24 => [pixel[0], pixel[1], pixel[2]],
+            PixelFormat::CMYK32 => cmyk_to_rgb(pixel[0], pixel[1], pixel[2], pixel[3]),
+        };
+        rgba.extend([r, g, b, alpha_value]);
+    }
+    rgba
+}
+
+
+/// Converts 8-bit pixels decoded from a PNG image (with palettes already expanded) to RGBA.
+pub fn png_pixels_to_rgba(color_type: ColorType, pixels: &[u8]) -> Vec<u8> {
+    match color_type {
+        ColorType::Grayscale => pixels.iter().flat_map(|&l| [l, l, l, 0xFF]).collect(),
+        ColorType::GrayscaleAlpha => pixels.chunks_exact(2).flat_map(|p| [p[0], p[0], p[0], p[1]]).collect(),
+        ColorType::Rgb|ColorType::Indexed => pixels.chunks_exact(3).flat_map(|p| [p[0], p[1], p[2], 0xFF]).collect(),
+        ColorType::Rgba => Vec::from(pixels),
+    }
+}
+
+
+/// How to resize bitmaps on export.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum ImageScale {
+    /// Multiply both dimensions by the given factor.
+    Factor(f64),
+
+    /// Resize to the given width and height.
+    Size(u32, u32),
+}
+impl ImageScale {
+    /// Returns the dimensions to which a bitmap of the given dimensions is resized.
+    pub fn scaled_dimensions(&self, width: u32, height: u32) -> (u32, u32) {
+        match self {
+            Self::Factor(factor) => {
+                let scale = |dimension: u32| ((f64::from(dimension) * factor).round() as u32).max(1);
+                (scale(width), scale(height))
+            },
+            Self::Size(width, height) => (*width, *height),
+        }
+    }
+}
+
+
+/// The number of lobes of the Lanczos filter.
+const LANCZOS_LOBES: f64 = 3.0;
+
+
+fn lanczos(x: f64) -> f64 {
+    if x == 0.0 {
+        1.0
+    } else if x.abs() < LANCZOS_LOBES {
+        let pi_x = std::f64::consts::PI * x;
+        LANCZOS_LOBES * pi_x.sin() * (pi_x / LANCZOS_LOBES).sin() / (pi_x * pi_x)
+    } else {
+        0.0
+    }
+}
+
+
+/// Calculates, for each position along the resized axis, the first source position contributing to
+/// it and the weights of the contributing source positions.
+fn lanczos_weights(source_length: usize, target_length: usize) -> Vec<(usize, Vec<f64>)> {
+    let scale = source_length as f64 / target_length as f64;
+    // when shrinking, widen the filter to cover all source pixels
+    let filter_scale = scale.max(1.0);
+    let radius = LANCZOS_LOBES * filter_scale;
+
+    let mut all_weights = Vec::with_capacity(target_length);
+    for target in 0..target_length {
+        let center = (target as f64 + 0.5) * scale;
+        let first = ((center - radius).floor().max(0.0)) as usize;
+        let end = ((center + radius).ceil() as usize).min(source_length);
+        let mut weights: Vec<f64> = (first..end)
+            .map(|source| lanczos((source as f64 + 0.5 - center) / filter_scale))
+            .collect();
+        let sum: f64 = weights.iter().sum();
+        if sum != 0.0 {
+            for weight in &mut weights {
+                *weight /= sum;
+            }
+        }
+        all_weights.push((first, weights));
+    }
+    all_weights
+}
+
+
+/// Resizes 8-bit RGBA pixels using a Lanczos filter.
+///
+/// The color channels are weighted by alpha while filtering so that the colors of transparent
+/// pixels do not bleed into their surroundings.
+pub fn resize_rgba(pixels: &[u8], width: u32, height: u32, new_width: u32, new_height: u32) -> Vec<u8> {
+    let (width, height) = (width as usize, height as usize);
+    let (new_width, new_height) = (new_width as usize, new_height as usize);
+    if width == 0 || height == 0 {
+        return vec![0; 4 * new_width * new_height];
+    }
+
+    let premultiplied: Vec<f64> = pixels.chunks_exact(4)
+        .flat_map(|p| {
+            let alpha = f64::from(p[3]) / 255.0;
+            [f64::from(p[0]) * alpha, f64::from(p[1]) * alpha, f64::from(p[2]) * alpha, f64::from(p[3])]
+        })
+        .collect();
+
+    // resize horizontally, then vertically
+    let horizontal_weights = lanczos_weights(width, new_width);
+    let mut wide = vec![0.0; 4 * new_width * height];
+    for y in 0..height {
+        for (x, (first, weights)) in horizontal_weights.iter().enumerate() {
+            for (i, weight) in weights.iter().enumerate() {
+                let source = 4 * (y * width + first + i);
+                let target = 4 * (y * new_width + x);
+                for channel in 0..4 {
+                    wide[target + channel] += premultiplied[source + channel] * weight;
+                }
+            }
+        }
+    }
+
+    let vertical_weights = lanczos_weights(height, new_height);
+    let mut resized = vec![0.0; 4 * new_width * new_height];
+    for (y, (first, weights)) in vertical_weights.iter().enumerate() {
+        for (i, weight) in weights.iter().enumerate() {
+            let source_row = &wide[4 * (first + i) * new_width..4 * (first + i + 1) * new_width];
+            let target_row = &mut resized[4 * y * new_width..4 * (y + 1) * new_width];
+            for (target, source) in target_row.iter_mut().zip(source_row) {
+                *target += source * weight;
+            }
+        }
+    }
+
+    resized.chunks_exact(4)
+        .flat_map(|p| {
+            let alpha = p[3].clamp(0.0, 255.0);
+            let unpremultiply = |value: f64| if alpha > 0.0 {
+                (value * 255.0 / alpha).round().clamp(0.0, 255.0) as u8
+            } else {
+                0
+            };
+            [unpremultiply(p[0]), unpremultiply(p[1]), unpremultiply(p[2]), alpha.round() as u8]
+        })
+        .collect()
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+
+    #[test]
+    fn cmyk_conversion() {
+        assert_eq!(cmyk_to_rgb(0, 0, 0, 0), [255, 255, 255]);
+        assert_eq!(cmyk_to_rgb(0, 0, 0, 255), [0, 0, 0]);
+        assert_eq!(cmyk_to_rgb(255, 0, 255, 0), [0, 255, 0]);
+        assert_eq!(cmyk_to_rgb(0, 0, 0, 51), [204, 204, 204]);
+    }
+
+
+    #[test]
+    fn jpeg_pixels_with_short_alpha() {
+        let rgba = jpeg_pixels_to_rgba(PixelFormat::RGB24, &[1, 2, 3, 4, 5, 6], Some(&[0x80]), 2);
+        assert_eq!(rgba, [1, 2, 3, 0x80, 4, 5, 6, 0xFF]);
+    }
+
+
+    #[test]
+    fn jpeg_pixels_missing_are_black() {
+        let rgba = jpeg_pixels_to_rgba(PixelFormat::L8, &[0x40], None, 2);
+        assert_eq!(rgba, [0x40, 0x40, 0x40, 0xFF, 0, 0, 0, 0xFF]);
+    }
+
+
+    #[test]
+    fn jpeg_pixels_16_bit_and_cmyk() {
+        let l16 = 0xABCDu16.to_ne_bytes();
+        assert_eq!(jpeg_pixels_to_rgba(PixelFormat::L16, &l16, None, 1), [0xAB, 0xAB, 0xAB, 0xFF]);
+        assert_eq!(jpeg_pixels_to_rgba(PixelFormat::CMYK32, &[0, 255, 255, 0], Some(&[7]), 1), [255, 0, 0, 7]);
+    }
+
+
+    #[test]
+    fn alpha_added_to_16_bit_grayscale() {
+        let pixels = add_alpha_to_jpeg_pixels(PixelFormat::L16, &0x1234u16.to_ne_bytes(), &[0x80], 1);
+        assert_eq!(pixels.color_type, ColorType::GrayscaleAlpha);
+        assert_eq!(pixels.bit_depth, BitDepth::Sixteen);
+        assert_eq!(pixels.data, [0x12, 0x34, 0x80, 0x80]);
+    }
+
+
+    #[test]
+    fn alpha_added_to_cmyk() {
+        let pixels = add_alpha_to_jpeg_pixels(PixelFormat::CMYK32, &[255, 255, 0, 0], &[], 1);
+        assert_eq!(pixels.color_type, ColorType::Rgba);
+        assert_eq!(pixels.bit_depth, BitDepth::Eight);
+        assert_eq!(pixels.data, [0, 0, 255, 0xFF]);
+    }
+
+
+    #[test]
+    fn png_pixels() {
+        assert_eq!(png_pixels_to_rgba(ColorType::Grayscale, &[9]), [9, 9, 9, 0xFF]);
+        assert_eq!(png_pixels_to_rgba(ColorType::GrayscaleAlpha, &[9, 3]), [9, 9, 9, 3]);
+        assert_eq!(png_pixels_to_rgba(ColorType::Rgb, &[1, 2, 3]), [1, 2, 3, 0xFF]);
+        assert_eq!(png_pixels_to_rgba(ColorType::Rgba, &[1, 2, 3, 4]), [1, 2, 3, 4]);
+    }
+
+
+    #[test]
+    fn scaled_dimensions() {
+        assert_eq!(ImageScale::Factor(0.5).scaled_dimensions(5, 1), (3, 1));
+        assert_eq!(ImageScale::Factor(2.0).scaled_dimensions(5, 3), (10, 6));
+        assert_eq!(ImageScale::Size(7, 8).scaled_dimensions(5, 3), (7, 8));
+    }
+
+
+    #[test]
+    fn resizing_keeps_uniform_color() {
+        let pixels: Vec<u8> = [10, 20, 30, 255].repeat(16);
+        let resized = resize_rgba(&pixels, 4, 4, 2, 3);
+        assert_eq!(resized, [10, 20, 30, 255].repeat(6));
+    }
+
+
+    #[test]
+    fn resizing_does_not_bleed_transparent_colors() {
+        // the transparent pixels are green
+        let pixels = [255, 0, 0, 255, 0, 255, 0, 0, 255, 0, 0, 255, 0, 255, 0, 0];
+        let resized = resize_rgba(&pixels, 4, 1, 2, 1);
+        for pixel in resized.chunks_exact(4) {
+            assert_eq!(&pixel[..3], &[255, 0, 0]);
+        }
+    }
+}
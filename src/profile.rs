@@ -0,0 +1,174 @@
+use std::collections::{BTreeMap, HashMap};
+use std::io::{self, Write};
+use std::time::{Duration, Instant};
+
+use swf::{CharacterId, Tag};
+use swfextract::read::defined_character;
+
+
+/// A stage of extracting an asset whose duration is measured separately.
+#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq)]
+pub(crate) enum Stage {
+    /// Decompressing or decoding the data stored in the movie.
+    Decode,
+
+    /// Converting the data into the output format.
+    Encode,
+
+    /// Writing the output files.
+    Write,
+}
+impl Stage {
+    const ALL: [Stage; 3] = [Stage::Decode, Stage::Encode, Stage::Write];
+
+    fn index(self) -> usize {
+        match self {
+            Self::Decode => 0,
+            Self::Encode => 1,
+            Self::Write => 2,
+        }
+    }
+
+    fn as_str(self) -> &'static str {
+        match self {
+            Self::Decode => "decode",
+            Self::Encode => "encode",
+            Self::Write => "write",
+        }
+    }
+}
+
+
+#[derive(Clone, Debug, Default)]
+struct AssetTiming {
+    kind: &'static str,
+
+    /// The time spent on the asset, excluding the time spent on the assets defined within it.
+    total: Duration,
+
+    /// The time spent in each stage, indexed by `Stage::index`.
+    stages: [Duration; 3],
+}
+
+
+#[derive(Clone, Copy, Debug)]
+struct OpenAsset {
+    id: CharacterId,
+    started: Instant,
+
+    /// The time spent on the assets defined within this one so far.
+    nested: Duration,
+}
+
+
+fn milliseconds(duration: Duration) -> f64 {
+    duration.as_secs_f64() * 1000.0
+}
+
+
+/// Returns the character on whose extraction processing the tag is spent: the character defined by
+/// the tag or, for video frames, the video stream.
+pub(crate) fn profiled_character(tag: &Tag) -> Option<CharacterId> {
+    match tag {
+        Tag::VideoFrame(vf) => Some(vf.stream_id),
+        _ => defined_character(tag),
+    }
+}
+
+
+/// Measures the time spent extracting each asset, broken down into stages.
+///
+/// Timelines nest: the time spent on an asset defined within a sprite is attributed to that asset
+/// alone, while the time spent on the other tags of the sprite (placing characters, writing the
+/// timeline) is attributed to the sprite.
+#[derive(Clone, Debug, Default)]
+pub(crate) struct Profile {
+    assets: HashMap<CharacterId, AssetTiming>,
+
+    /// The asset being extracted on each timeline being processed, innermost last.
+    open: Vec<Option<OpenAsset>>,
+}
+impl Profile {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn begin_timeline(&mut self) {
+        self.open.push(None);
+    }
+
+    /// Ends the asset being extracted on the innermost timeline, if any, and leaves that timeline.
+    pub fn end_timeline(&mut self) {
+        self.end_asset();
+        self.open.pop();
+    }
+
+    /// Starts timing an asset on the innermost timeline, ending the one timed before.
+    pub fn begin_asset(&mut self, id: CharacterId, kind: &'static str) {
+        self.end_asset();
+        let Some(slot) = self.open.last_mut() else { return };
+        *slot = Some(OpenAsset {
+            id,
+            started: Instant::now(),
+            nested: Duration::ZERO,
+        });
+        self.assets.entry(id).or_default().kind = kind;
+    }
+
+    /// Ends timing the asset on the innermost timeline, if any.
+    pub fn end_asset(&mut self) {
+        let Some(asset) = self.open.last_mut().and_then(|slot| slot.take()) else { return };
+        let elapsed = asset.started.elapsed();
+        self.assets.entry(asset.id).or_default().total += elapsed.saturating_sub(asset.nested);
+        if let Some(enclosing) = self.open.iter_mut().rev().find_map(|slot| slot.as_mut()) {
+            enclosing.nested += elapsed;
+        }
+    }
+
+    /// Attributes the time elapsed since `started` to the given stage of the asset being extracted.
+    pub fn record_stage(&mut self, stage: Stage, started: Instant) {
+        let Some(asset) = self.open.iter().rev().find_map(|slot| slot.as_ref()) else { return };
+        self.assets.entry(asset.id).or_default().stages[stage.index()] += started.elapsed();
+    }
+
+    /// Writes the duration of each phase of the run, the time spent on each type of asset and in
+    /// each stage, and the `count` assets that took longest.
+    pub fn write_summary<W: Write>(&self, phases: &[(&'static str, Duration)], count: usize, mut writer: W) -> io::Result<()> {
+        writeln!(writer, "phases:")?;
+        for (name, duration) in phases {
+            writeln!(writer, "  {:<12} {:>10.3} ms", name, milliseconds(*duration))?;
+        }
+
+        let mut by_kind: BTreeMap<&str, (usize, Duration)> = BTreeMap::new();
+        let mut stage_totals = [Duration::ZERO; 3];
+        for timing in self.assets.values() {
+            let entry = by_kind.entry(timing.kind).or_default();
+            entry.0 += 1;
+            entry.1 += timing.total;
+            for (total, duration) in stage_totals.iter_mut().zip(&timing.stages) {
+                *total += *duration;
+            }
+        }
+        writeln!(writer, "assets by type:")?;
+        for (kind, (asset_count, duration)) in &by_kind {
+            writeln!(writer, "  {:<12} {:>10.3} ms in {} assets", kind, milliseconds(*duration), asset_count)?;
+        }
+        writeln!(writer, "assets by stage:")?;
+        for stage in Stage::ALL {
+            writeln!(writer, "  {:<12} {:>10.3} ms", stage.as_str(), milliseconds(stage_totals[stage.index()]))?;
+        }
+
+        let mut slowest: Vec<(&CharacterId, &AssetTiming)> = self.assets.iter().collect();
+        slowest.sort_by(|(a_id, a), (b_id, b)| b.total.cmp(&a.total).then(a_id.cmp(b_id)));
+        writeln!(writer, "slowest assets (in ms):")?;
+        writeln!(writer, "  {:>5}  {:<12} {:>10}  {:>10}  {:>10}  {:>10}", "ID", "TYPE", "TOTAL", "DECODE", "ENCODE", "WRITE")?;
+        for (id, timing) in slowest.into_iter().take(count) {
+            let [decode, encode, write] = timing.stages.map(milliseconds);
+            writeln!(
+                writer, "  {:>5}  {:<12} {:>10.3}  {:>10.3}  {:>10.3}  {:>10.3}",
+                id, timing.kind, milliseconds(timing.total), decode, encode, write,
+            )?;
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,269 @@
+//! Reading movies from untrusted data without panicking or overflowing the stack.
+
+
+use std::collections::HashSet;
+use std::error;
+use std::fmt;
+use std::io::{self, Read};
+
+use flate2::read::ZlibDecoder;
+use swf::{CharacterId, Swf, SwfBuf, Tag, TagCode, VideoCodec};
+
+
+/// The codec ID of H.264 video, which is missing from `swf::VideoCodec`.
+const AVC_CODEC_ID: u8 = 7;
+
+/// The greatest length of a decompressed movie (including its header) that is accepted.
+pub const MAX_MOVIE_LEN: u32 = 1 << 30;
+
+
+#[derive(Debug)]
+pub enum Error {
+    /// The movie nests sprites more deeply than the given limit allows.
+    NestedTooDeeply { limit: usize },
+
+    /// The header of the movie gives a length greater than [`MAX_MOVIE_LEN`].
+    TooLong { length: u32 },
+
+    /// The movie could not be decompressed or parsed.
+    Swf(swf::error::Error),
+}
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::NestedTooDeeply { limit } => write!(f, "the movie nests sprites more than {} levels deep", limit),
+            Self::TooLong { length } => write!(f, "the movie claims to be {} bytes long, more than the limit of {}", length, MAX_MOVIE_LEN),
+            Self::Swf(e) => write!(f, "{}", e),
+        }
+    }
+}
+impl error::Error for Error {
+    fn source(&self) -> Option<&(dyn error::Error + 'static)> {
+        match self {
+            Self::NestedTooDeeply { .. }|Self::TooLong { .. } => None,
+            Self::Swf(e) => Some(e),
+        }
+    }
+}
+impl From<swf::error::Error> for Error {
+    fn from(value: swf::error::Error) -> Self { Self::Swf(value) }
+}
+
+
+/// Returns the code and body of each tag in a tag stream up to the End tag, stopping early at a
+/// truncated tag.
+fn tag_bodies(data: &[u8]) -> Vec<(u16, &[u8])> {
+    let mut tags = Vec::new();
+    let mut pos = 0;
+    while pos + 2 <= data.len() {
+        let code_and_length = u16::from_le_bytes([data[pos], data[pos + 1]]);
+        let mut length = usize::from(code_and_length & 0x3F);
+        pos += 2;
+        if length == 0x3F {
+            let Some(long_length) = data.get(pos..pos+4) else { break };
+            length = usize::try_from(u32::from_le_bytes(long_length.try_into().unwrap())).unwrap();
+            pos += 4;
+        }
+        let Some(body) = data.get(pos..pos.saturating_add(length)) else { break };
+        let code = code_and_length >> 6;
+        if code == 0 {
+            break;
+        }
+        tags.push((code, body));
+        pos += length;
+    }
+    tags
+}
+
+
+/// Determines how deeply DefineSprite tags are nested within a tag stream, up to `limit` + 1.
+///
+/// The tags are walked without recursion, so that movies nesting sprites too deeply to be parsed
+/// safely can be recognized before they are.
+pub fn sprite_nesting_depth(data: &[u8], limit: usize) -> usize {
+    let mut deepest = 0;
+    let mut pending = vec![(data, 0)];
+    while let Some((tag_data, depth)) = pending.pop() {
+        deepest = deepest.max(depth);
+        if depth > limit {
+            continue;
+        }
+        for (code, body) in tag_bodies(tag_data) {
+            // the sprite ID and frame count precede the tags of the sprite
+            if code == TagCode::DefineSprite as u16 && body.len() >= 4 {
+                pending.push((&body[4..], depth + 1));
+            }
+        }
+    }
+    deepest
+}
+
+
+/// Determines how deeply sprites are nested within the first two tags of a SWF file (up to
+/// `limit` + 1), which the swf crate parses along with the header when decompressing the file.
+///
+/// Only as much of the file is decompressed as needed to read these tags, except for LZMA.
+pub fn leading_sprite_depth(swf_data: &[u8], limit: usize) -> usize {
+    let Some(body) = swf_data.get(8..) else { return 0 };
+    let mut reader: Box<dyn Read + '_> = match &swf_data[..3] {
+        b"CWS" => Box::new(ZlibDecoder::new(body)),
+        b"ZWS" => {
+            // compressed length, LZMA properties and the data, whose length is given by the header
+            let uncompressed_len = u32::from_le_bytes(swf_data[4..8].try_into().unwrap()).saturating_sub(8);
+            let options = lzma_rs::decompress::Options {
+                unpacked_size: lzma_rs::decompress::UnpackedSize::UseProvided(Some(uncompressed_len.into())),
+                memlimit: None,
+                allow_incomplete: true,
+            };
+            let mut decompressed = Vec::new();
+            let _ = lzma_rs::lzma_decompress_with_options(&mut swf_data.get(12..).unwrap_or_default(), &mut decompressed, &options);
+            Box::new(io::Cursor::new(decompressed))
+        },
+        _ => Box::new(body),
+    };
+
+    let mut deepest = 0;
+    let mut read_leading_tags = || -> io::Result<()> {
+        // the stage size (whose length depends on the number of bits per field given in the first
+        // five bits), frame rate and frame count
+        let mut first = [0u8; 1];
+        reader.read_exact(&mut first)?;
+        let rect_len = (5 + 4 * usize::from(first[0] >> 3)).div_ceil(8);
+        io::copy(&mut reader.by_ref().take(u64::try_from(rect_len - 1 + 4).unwrap()), &mut io::sink())?;
+
+        for _ in 0..2 {
+            let mut header = [0u8; 2];
+            reader.read_exact(&mut header)?;
+            let code_and_length = u16::from_le_bytes(header);
+            let mut length = u64::from(code_and_length & 0x3F);
+            if length == 0x3F {
+                let mut long_length = [0u8; 4];
+                reader.read_exact(&mut long_length)?;
+                length = u64::from(u32::from_le_bytes(long_length));
+            }
+            let code = code_and_length >> 6;
+            if code != TagCode::DefineSprite as u16 {
+                io::copy(&mut reader.by_ref().take(length), &mut io::sink())?;
+                continue;
+            }
+            let mut sprite = Vec::new();
+            reader.by_ref().take(length).read_to_end(&mut sprite)?;
+            if let Some(tags) = sprite.get(4..) {
+                deepest = deepest.max(1 + sprite_nesting_depth(tags, limit));
+            }
+        }
+        Ok(())
+    };
+    // a damaged file is rejected when decompressing it
+    let _ = read_leading_tags();
+    deepest
+}
+
+
+/// Decompresses a SWF file, refusing to if it is shorter than its header, longer than
+/// [`MAX_MOVIE_LEN`] or if its first tags (which are parsed along with the header) nest sprites more
+/// than `max_sprite_depth` levels deep.
+pub fn decompress_movie(data: &[u8], max_sprite_depth: usize) -> Result<SwfBuf, Error> {
+    // the swf crate subtracts the length of the header from the length of the movie and reserves
+    // as much memory as it claims
+    let movie_len = data.get(4..8).map(|len| u32::from_le_bytes(len.try_into().unwrap()));
+    if movie_len.is_some_and(|len| len < 8) {
+        return Err(Error::Swf(swf::error::Error::invalid_data("movie shorter than its header")));
+    }
+    if let Some(length) = movie_len.filter(|len| *len > MAX_MOVIE_LEN) {
+        return Err(Error::TooLong { length });
+    }
+    if leading_sprite_depth(data, max_sprite_depth) > max_sprite_depth {
+        return Err(Error::NestedTooDeeply { limit: max_sprite_depth });
+    }
+    Ok(swf::decompress_swf(data)?)
+}
+
+
+/// Rewrites the codec of H.264 video streams in a tag stream to one known to the swf crate,
+/// returning the IDs of the affected streams.
+///
+/// The swf crate refuses to parse DefineVideoStream tags declaring the AVC codec. As the codec is
+/// the only difference, it is replaced by H.263 and the affected streams are tracked separately.
+pub fn patch_avc_streams(tag_data: &mut [u8]) -> HashSet<CharacterId> {
+    let mut avc_stream_ids = HashSet::new();
+    let mut codec_offsets = Vec::new();
+    let mut pending = vec![&tag_data[..]];
+    while let Some(tags) = pending.pop() {
+        for (code, body) in tag_bodies(tags) {
+            if code == TagCode::DefineVideoStream as u16 && body.len() >= 10 && body[9] == AVC_CODEC_ID {
+                avc_stream_ids.insert(u16::from_le_bytes([body[0], body[1]]));
+                codec_offsets.push(body.as_ptr() as usize - tag_data.as_ptr() as usize + 9);
+            } else if code == TagCode::DefineSprite as u16 && body.len() >= 4 {
+                pending.push(&body[4..]);
+            }
+        }
+    }
+    for offset in codec_offsets {
+        tag_data[offset] = VideoCodec::H263 as u8;
+    }
+    avc_stream_ids
+}
+
+
+/// Parses a decompressed movie, refusing to if it nests sprites more than `max_sprite_depth` levels
+/// deep, as parsing them (and every pass over the parsed tags) recurses into each level.
+pub fn parse_movie(swf_buf: &SwfBuf, max_sprite_depth: usize) -> Result<Swf<'_>, Error> {
+    if sprite_nesting_depth(&swf_buf.data, max_sprite_depth) > max_sprite_depth {
+        return Err(Error::NestedTooDeeply { limit: max_sprite_depth });
+    }
+    Ok(swf::parse_swf(swf_buf)?)
+}
+
+
+/// Returns the ID of the character defined by the tag, if it defines one.
+pub fn defined_character(tag: &Tag) -> Option<CharacterId> {
+    let id = match tag {
+        Tag::DefineBinaryData(bd) => bd.id,
+        Tag::DefineBits { id, .. }|Tag::DefineBitsJpeg2 { id, .. } => *id,
+        Tag::DefineBitsJpeg3(j3) => j3.id,
+        Tag::DefineBitsLossless(bmap) => bmap.id,
+        Tag::DefineButton(btn)|Tag::DefineButton2(btn) => btn.id,
+        Tag::DefineEditText(et) => et.id,
+        Tag::DefineFont(font) => font.id,
+        Tag::DefineFont2(font) => font.id,
+        Tag::DefineFont4(font) => font.id,
+        Tag::DefineMorphShape(ms) => ms.id,
+        Tag::DefineShape(sh) => sh.id,
+        Tag::DefineSound(snd) => snd.id,
+        Tag::DefineSprite(ds) => ds.id,
+        Tag::DefineText(text) => text.id,
+        Tag::DefineVideoStream(dvs) => dvs.id,
+        _ => return None,
+    };
+    Some(id)
+}
+
+
+/// The tag defining a character, along with the data of other tags needed to decode it.
+#[derive(Clone, Copy, Debug)]
+pub struct CharacterDefinition<'a> {
+    pub tag: &'a Tag<'a>,
+
+    /// The JPEG encoding tables of the movie as stored in its JpegTables tag, for DefineBits tags
+    /// (whose JPEG data lacks them) in movies that have any.
+    pub jpeg_tables: Option<&'a [u8]>,
+}
+
+
+/// Finds the tag defining the character with the given ID among the tags of a movie, without
+/// decoding any other character.
+pub fn find_character<'a>(tags: &'a [Tag<'a>], id: CharacterId) -> Option<CharacterDefinition<'a>> {
+    // characters are always defined on the main timeline
+    let tag = tags.iter()
+        .find(|tag| defined_character(tag) == Some(id))?;
+    let jpeg_tables = match tag {
+        Tag::DefineBits { .. } => tags.iter()
+            .find_map(|tag| match tag {
+                Tag::JpegTables(jt) if !jt.is_empty() => Some(*jt),
+                _ => None,
+            }),
+        _ => None,
+    };
+    Some(CharacterDefinition { tag, jpeg_tables })
+}
@@ -0,0 +1,380 @@
+use std::collections::{HashMap, HashSet};
+
+use swf::{BlendMode, CharacterId, Color, ColorTransform, Filter, Matrix, Rectangle, Shape, Twips};
+use sxd_document::Package;
+use sxd_document::dom::{Document, Element};
+
+use crate::filter::{append_svg_filter, css_blend_mode};
+use crate::font::FontOutlines;
+use crate::shape::{append_shape, tw2px};
+use crate::text::TextDrawing;
+use crate::timeline::{DisplayObject, Timeline};
+
+
+/// The area covered by the characters drawn so far, in pixels.
+#[derive(Clone, Copy, Debug)]
+struct Bounds {
+    x_min: f64,
+    y_min: f64,
+    x_max: f64,
+    y_max: f64,
+}
+impl Bounds {
+    fn include(bounds: &mut Option<Bounds>, x: Twips, y: Twips) {
+        let (x, y) = (tw2px(x), tw2px(y));
+        match bounds {
+            Some(b) => {
+                b.x_min = b.x_min.min(x);
+                b.y_min = b.y_min.min(y);
+                b.x_max = b.x_max.max(x);
+                b.y_max = b.y_max.max(y);
+            },
+            None => *bounds = Some(Bounds { x_min: x, y_min: y, x_max: x, y_max: y }),
+        }
+    }
+}
+
+
+/// Composes frames of timelines into SVG documents.
+///
+/// Shapes, texts and sprites are drawn; other kinds of characters are skipped, as are masks. Sprites are
+/// assumed to start playing from their first frame when they are placed and to loop. Bitmap filters
+/// and blend modes are applied as far as SVG and CSS have equivalents.
+pub(crate) struct FrameRenderer<'a> {
+    pub shapes: &'a HashMap<CharacterId, Shape>,
+    pub texts: &'a HashMap<CharacterId, TextDrawing>,
+
+    /// The fonts with which texts are drawn.
+    pub fonts: &'a HashMap<CharacterId, FontOutlines>,
+    pub timelines: &'a HashMap<CharacterId, Timeline>,
+
+    /// Whether to apply the color transforms of placed characters.
+    pub bake_color_transforms: bool,
+
+    /// The color with which to fill the background; transparent if `None`.
+    pub background_color: Option<&'a Color>,
+
+    /// The factor by which the pixel dimensions of the document exceed those of the rendered area.
+    pub scale: u32,
+
+    /// The most sprites drawn within each other; deeper sprites are left out.
+    pub max_sprite_depth: usize,
+}
+
+/// The state of rendering a single frame.
+struct FrameDocument<'d> {
+    document: Document<'d>,
+    defs: Element<'d>,
+    defined_characters: HashSet<CharacterId>,
+    defined_glyphs: HashSet<(CharacterId, usize)>,
+    filter_count: usize,
+    bounds: Option<Bounds>,
+
+    /// The sprites currently being drawn, to avoid endless and excessive recursion.
+    sprite_stack: Vec<CharacterId>,
+}
+
+impl<'a> FrameRenderer<'a> {
+    /// Renders the given frame (starting at 0) of a timeline into an SVG document.
+    ///
+    /// The view box is set to `stage` if given, otherwise to the area covered by the frame's
+    /// contents. The dimensions of the document are those of the view box in pixels, multiplied by
+    /// the scale.
+    pub fn render(&self, timeline: &Timeline, frame: usize, stage: Option<&Rectangle>) -> String {
+        let package = Package::new();
+        let document = package.as_document();
+
+        let svg = document.create_element("svg");
+        document.root().append_child(svg);
+        svg.set_default_namespace_uri(Some("http://www.w3.org/2000/svg"));
+
+        let defs = document.create_element("defs");
+        svg.append_child(defs);
+
+        // sized once the bounds are known
+        let background = self.background_color.map(|color| {
+            let rect = document.create_element("rect");
+            rect.set_attribute_value("fill", &format!("rgb({},{},{})", color.r, color.g, color.b));
+            svg.append_child(rect);
+            rect
+        });
+
+        let mut state = FrameDocument {
+            document,
+            defs,
+            defined_characters: HashSet::new(),
+            defined_glyphs: HashSet::new(),
+            filter_count: 0,
+            bounds: None,
+            sprite_stack: Vec::new(),
+        };
+        self.append_frame(&mut state, svg, timeline, frame, Matrix::IDENTITY);
+
+        let bounds = match stage {
+            Some(rect) => {
+                let mut bounds = None;
+                Bounds::include(&mut bounds, rect.x_min, rect.y_min);
+                Bounds::include(&mut bounds, rect.x_max, rect.y_max);
+                bounds
+            },
+            None => state.bounds,
+        };
+        let b = bounds.unwrap_or(Bounds { x_min: 0.0, y_min: 0.0, x_max: 0.0, y_max: 0.0 });
+        let (width, height) = (b.x_max - b.x_min, b.y_max - b.y_min);
+        svg.set_attribute_value("viewBox", &format!("{} {} {} {}", b.x_min, b.y_min, width, height));
+        let scale = f64::from(self.scale);
+        svg.set_attribute_value("width", &format!("{}px", width * scale));
+        svg.set_attribute_value("height", &format!("{}px", height * scale));
+        if let Some(rect) = background {
+            rect.set_attribute_value("x", &b.x_min.to_string());
+            rect.set_attribute_value("y", &b.y_min.to_string());
+            rect.set_attribute_value("width", &width.to_string());
+            rect.set_attribute_value("height", &height.to_string());
+        }
+
+        let mut buf = Vec::new();
+        sxd_document::writer::format_document(&document, &mut buf)
+            .expect("failed to write SVG");
+        String::from_utf8(buf)
+            .expect("written SVG is not UTF-8?!")
+    }
+
+    fn append_frame<'d>(&self, state: &mut FrameDocument<'d>, parent: Element<'d>, timeline: &Timeline, frame: usize, transform: Matrix) {
+        for object in timeline.frame(frame) {
+            if object.clip_depth.is_some() {
+                // masks are not drawn themselves
+                continue;
+            }
+            self.append_object(state, parent, object, frame, transform);
+        }
+    }
+
+    fn append_object<'d>(&self, state: &mut FrameDocument<'d>, parent: Element<'d>, object: &DisplayObject, frame: usize, transform: Matrix) {
+        let id = object.character_id;
+        let transform = transform * object.matrix;
+
+        let group = state.document.create_element("g");
+        let m = &object.matrix;
+        group.set_attribute_value("transform", &format!(
+            "matrix({} {} {} {} {} {})",
+            m.a.to_f64(), m.b.to_f64(), m.c.to_f64(), m.d.to_f64(), tw2px(m.tx), tw2px(m.ty),
+        ));
+        if self.bake_color_transforms && object.color_transform != ColorTransform::default() {
+            let filter_id = state.add_color_transform_filter(&object.color_transform);
+            group.set_attribute_value("filter", &format!("url(#{})", filter_id));
+        }
+
+        let character_bounds = if let Some(shape) = self.shapes.get(&id) {
+            state.define_shape(shape);
+            Some(&shape.shape_bounds)
+        } else if let Some(text) = self.texts.get(&id) {
+            state.define_text(id, text, self.fonts);
+            Some(&text.bounds)
+        } else {
+            None
+        };
+        if let Some(b) = character_bounds {
+            let usage = state.document.create_element("use");
+            usage.set_attribute_value("href", &format!("#c{}", id));
+            group.append_child(usage);
+
+            for (x, y) in [(b.x_min, b.y_min), (b.x_max, b.y_min), (b.x_min, b.y_max), (b.x_max, b.y_max)] {
+                let (tx, ty) = transform * (x, y);
+                Bounds::include(&mut state.bounds, tx, ty);
+            }
+        } else if let Some(sprite) = self.timelines.get(&id) {
+            if sprite.frame_count() == 0 || state.sprite_stack.contains(&id) || state.sprite_stack.len() >= self.max_sprite_depth {
+                return;
+            }
+            let elapsed = frame.saturating_sub(usize::try_from(object.placed_frame).unwrap() - 1);
+            state.sprite_stack.push(id);
+            self.append_frame(state, group, sprite, elapsed % sprite.frame_count(), transform);
+            state.sprite_stack.pop();
+        } else {
+            return;
+        }
+
+        // filters and blend modes apply to the character after its color transform
+        let filter_id = state.add_filters(&object.filters);
+        let blend_mode = css_blend_mode(object.blend_mode);
+        if filter_id.is_none() && blend_mode.is_none() && object.blend_mode != BlendMode::Layer {
+            parent.append_child(group);
+            return;
+        }
+        let outer = state.document.create_element("g");
+        if let Some(filter_id) = filter_id {
+            outer.set_attribute_value("filter", &format!("url(#{})", filter_id));
+        }
+        if let Some(mode) = blend_mode {
+            outer.set_attribute_value("style", &format!("mix-blend-mode: {}", mode));
+        } else if object.blend_mode == BlendMode::Layer {
+            outer.set_attribute_value("style", "isolation: isolate");
+        }
+        outer.append_child(group);
+        parent.append_child(outer);
+    }
+}
+
+impl<'d> FrameDocument<'d> {
+    /// Adds the shape to the definitions of the document, unless it is already defined.
+    fn define_shape(&mut self, shape: &Shape) {
+        if !self.defined_characters.insert(shape.id) {
+            return;
+        }
+        let group = self.document.create_element("g");
+        group.set_attribute_value("id", &format!("c{}", shape.id));
+        self.defs.append_child(group);
+        append_shape(shape, self.document, self.defs, group, &format!("c{}-", shape.id));
+    }
+
+    /// Adds the text to the definitions of the document, unless it is already defined, along with the
+    /// glyphs it uses.
+    fn define_text(&mut self, id: CharacterId, text: &TextDrawing, fonts: &HashMap<CharacterId, FontOutlines>) {
+        if !self.defined_characters.insert(id) {
+            return;
+        }
+        let group = self.document.create_element("g");
+        group.set_attribute_value("id", &format!("c{}", id));
+        let m = &text.matrix;
+        group.set_attribute_value("transform", &format!(
+            "matrix({} {} {} {} {} {})",
+            m.a.to_f64(), m.b.to_f64(), m.c.to_f64(), m.d.to_f64(), tw2px(m.tx), tw2px(m.ty),
+        ));
+        self.defs.append_child(group);
+
+        for glyph in &text.glyphs {
+            let Some(font) = fonts.get(&glyph.font_id) else { continue };
+            let Some(path_data) = font.glyph_paths.get(glyph.index).filter(|d| !d.is_empty()) else { continue };
+            let glyph_id = format!("f{}g{}", glyph.font_id, glyph.index);
+            if self.defined_glyphs.insert((glyph.font_id, glyph.index)) {
+                let path = self.document.create_element("path");
+                path.set_attribute_value("id", &glyph_id);
+                path.set_attribute_value("d", path_data);
+                // the outlines of counters may run in either direction
+                path.set_attribute_value("fill-rule", "evenodd");
+                self.defs.append_child(path);
+            }
+
+            // glyphs are defined in font units, of which the EM square is as high as the text
+            let scale = tw2px(glyph.height) / (font.em_size as f64);
+            let usage = self.document.create_element("use");
+            usage.set_attribute_value("href", &format!("#{}", glyph_id));
+            usage.set_attribute_value("transform", &format!(
+                "translate({} {}) scale({})", tw2px(glyph.x), tw2px(glyph.y), scale,
+            ));
+            let c = &glyph.color;
+            usage.set_attribute_value("fill", &format!("rgb({},{},{})", c.r, c.g, c.b));
+            if c.a != 255 {
+                usage.set_attribute_value("fill-opacity", &(f64::from(c.a) / 255.0).to_string());
+            }
+            group.append_child(usage);
+        }
+    }
+
+    /// Adds a filter applying the given bitmap filters in order to the definitions of the document and
+    /// returns its ID, or `None` if none of the bitmap filters has an SVG equivalent.
+    fn add_filters(&mut self, filters: &[Filter]) -> Option<String> {
+        if filters.is_empty() {
+            return None;
+        }
+        let filter_id = format!("fx{}", self.filter_count);
+
+        let filter_elem = self.document.create_element("filter");
+        filter_elem.set_attribute_value("id", &filter_id);
+        filter_elem.set_attribute_value("color-interpolation-filters", "sRGB");
+        // leave room for blurs and shadows
+        filter_elem.set_attribute_value("x", "-50%");
+        filter_elem.set_attribute_value("y", "-50%");
+        filter_elem.set_attribute_value("width", "200%");
+        filter_elem.set_attribute_value("height", "200%");
+
+        let mut input = "SourceGraphic".to_owned();
+        let mut any_applied = false;
+        for (i, filter) in filters.iter().enumerate() {
+            let result = format!("{}-{}", filter_id, i);
+            if let Some(output) = append_svg_filter(self.document, filter_elem, &input, &result, filter) {
+                input = output;
+                any_applied = true;
+            }
+        }
+        if !any_applied {
+            return None;
+        }
+        self.defs.append_child(filter_elem);
+        self.filter_count += 1;
+        Some(filter_id)
+    }
+
+    /// Adds a filter applying the color transform to the definitions of the document and returns
+    /// its ID.
+    fn add_color_transform_filter(&mut self, ct: &ColorTransform) -> String {
+        let filter_id = format!("ct{}", self.filter_count);
+        self.filter_count += 1;
+
+        let filter = self.document.create_element("filter");
+        filter.set_attribute_value("id", &filter_id);
+        // color transforms operate on the stored (sRGB) color values
+        filter.set_attribute_value("color-interpolation-filters", "sRGB");
+        self.defs.append_child(filter);
+
+        let matrix = self.document.create_element("feColorMatrix");
+        matrix.set_attribute_value("type", "matrix");
+        let rows = [
+            (ct.r_multiply, ct.r_add),
+            (ct.g_multiply, ct.g_add),
+            (ct.b_multiply, ct.b_add),
+            (ct.a_multiply, ct.a_add),
+        ];
+        let values: Vec<String> = rows.iter().enumerate()
+            .map(|(i, (multiply, add))| {
+                let mut row = [0.0; 5];
+                row[i] = multiply.to_f64();
+                row[4] = f64::from(*add) / 255.0;
+                row.map(|v| v.to_string()).join(" ")
+            })
+            .collect();
+        matrix.set_attribute_value("values", &values.join(" "));
+        filter.append_child(matrix);
+
+        filter_id
+    }
+}
+
+
+/// Draws a text on its own into an SVG document whose view box is the text's bounds.
+pub(crate) fn text_to_svg(id: CharacterId, text: &TextDrawing, fonts: &HashMap<CharacterId, FontOutlines>) -> String {
+    let package = Package::new();
+    let document = package.as_document();
+
+    let svg = document.create_element("svg");
+    document.root().append_child(svg);
+    svg.set_default_namespace_uri(Some("http://www.w3.org/2000/svg"));
+    let b = &text.bounds;
+    let (width, height) = (b.x_max - b.x_min, b.y_max - b.y_min);
+    svg.set_attribute_value("viewBox", &format!("{} {} {} {}", b.x_min, b.y_min, width, height));
+    svg.set_attribute_value("width", &format!("{}px", tw2px(width)));
+    svg.set_attribute_value("height", &format!("{}px", tw2px(height)));
+
+    let defs = document.create_element("defs");
+    svg.append_child(defs);
+
+    let mut state = FrameDocument {
+        document,
+        defs,
+        defined_characters: HashSet::new(),
+        defined_glyphs: HashSet::new(),
+        filter_count: 0,
+        bounds: None,
+        sprite_stack: Vec::new(),
+    };
+    state.define_text(id, text, fonts);
+    let usage = document.create_element("use");
+    usage.set_attribute_value("href", &format!("#c{}", id));
+    svg.append_child(usage);
+
+    let mut buf = Vec::new();
+    sxd_document::writer::format_document(&document, &mut buf)
+        .expect("failed to write SVG");
+    String::from_utf8(buf)
+        .expect("written SVG is not UTF-8?!")
+}
@@ -0,0 +1,48 @@
+use std::io::Write;
+
+use clap::ValueEnum;
+use swf::{Compression, SwfBuf, Tag};
+
+use crate::tags::read_raw_tags;
+
+
+/// The compression with which a movie is repacked.
+#[derive(Clone, Copy, Debug, Default, Eq, Hash, PartialEq, ValueEnum)]
+pub(crate) enum RepackCompression {
+    /// Uncompressed (`FWS`), readable by all versions of Flash Player.
+    None,
+
+    /// zlib (`CWS`), readable from SWF version 6.
+    #[default]
+    Zlib,
+
+    /// LZMA (`ZWS`), readable from SWF version 13.
+    Lzma,
+}
+impl RepackCompression {
+    fn to_swf(self) -> Compression {
+        match self {
+            Self::None => Compression::None,
+            Self::Zlib => Compression::Zlib,
+            Self::Lzma => Compression::Lzma,
+        }
+    }
+}
+
+
+/// Writes the movie out again with the given compression.
+///
+/// The tags are copied undecoded, so the movie is reproduced exactly apart from the compression and
+/// the headers of short tags, which are shortened to two bytes where they had been stored in the
+/// long form. Anything after the End tag is dropped.
+pub(crate) fn repack<W: Write>(swf_buf: &SwfBuf, compression: RepackCompression, writer: W) -> Result<(), swf::error::Error> {
+    let mut header = swf_buf.header.swf_header().clone();
+    header.compression = compression.to_swf();
+
+    // write_swf appends the End tag itself
+    let tags: Vec<Tag> = read_raw_tags(&swf_buf.data, 0).into_iter()
+        .filter(|tag| tag.code != 0)
+        .map(|tag| Tag::Unknown { tag_code: tag.code, data: tag.body })
+        .collect();
+    swf::write_swf(&header, &tags, writer)
+}
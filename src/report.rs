@@ -0,0 +1,215 @@
+use std::collections::BTreeMap;
+use std::io::Write;
+use std::time::{Duration, Instant};
+
+use swf::CharacterId;
+use swfextract::warning::Warning;
+
+use crate::inspect::human_size;
+use crate::json::JsonValue;
+
+
+/// What became of a character of the movie.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub(crate) enum AssetStatus {
+    Written,
+    Skipped,
+    Failed,
+}
+impl AssetStatus {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::Written => "written",
+            Self::Skipped => "skipped",
+            Self::Failed => "failed",
+        }
+    }
+}
+
+
+/// The outcome of extracting a single character.
+#[derive(Clone, Debug)]
+pub(crate) struct AssetOutcome {
+    pub id: CharacterId,
+    pub kind: &'static str,
+
+    /// The path identifying the character across the movie, such as `sprite/12/shape/40`.
+    pub path: String,
+
+    pub status: AssetStatus,
+
+    /// Why the character was skipped or what went wrong while extracting it.
+    pub message: Option<String>,
+
+    pub files: Vec<String>,
+}
+
+
+/// Collects the duration of each phase of a run and the errors encountered, written out as
+/// `report.json` once extraction has finished.
+#[derive(Clone, Debug)]
+pub(crate) struct RunReport {
+    started: Instant,
+    phases: Vec<(&'static str, Duration)>,
+    current_phase: Option<(&'static str, Instant)>,
+
+    /// Characters that could not be extracted, with the reason.
+    failures: BTreeMap<CharacterId, String>,
+
+    /// Characters that have deliberately not been extracted, with the reason.
+    skips: BTreeMap<CharacterId, String>,
+
+    /// Problems that did not prevent extraction, such as damaged data that has been salvaged.
+    warnings: Vec<(Warning, Option<String>)>,
+
+    /// The error that aborted the run, if any.
+    error: Option<String>,
+}
+impl RunReport {
+    pub fn new() -> Self {
+        Self {
+            started: Instant::now(),
+            phases: Vec::new(),
+            current_phase: None,
+            failures: BTreeMap::new(),
+            skips: BTreeMap::new(),
+            warnings: Vec::new(),
+            error: None,
+        }
+    }
+
+    /// Ends the current phase, if any, and starts timing the next one.
+    pub fn begin_phase(&mut self, name: &'static str) {
+        self.end_phase();
+        self.current_phase = Some((name, Instant::now()));
+    }
+
+    pub fn end_phase(&mut self) {
+        if let Some((name, start)) = self.current_phase.take() {
+            self.phases.push((name, start.elapsed()));
+        }
+    }
+
+    /// Returns the phases that have ended, with their durations.
+    pub fn phases(&self) -> &[(&'static str, Duration)] {
+        &self.phases
+    }
+
+    /// Records that the character with the given ID could not be extracted.
+    pub fn fail(&mut self, id: CharacterId, message: impl Into<String>) {
+        self.failures.insert(id, message.into());
+    }
+
+    pub fn failure(&self, id: CharacterId) -> Option<&str> {
+        self.failures.get(&id).map(|m| m.as_str())
+    }
+
+    /// Records that the character with the given ID has deliberately not been extracted.
+    pub fn skip(&mut self, id: CharacterId, reason: impl Into<String>) {
+        self.skips.insert(id, reason.into());
+    }
+
+    pub fn skip_reason(&self, id: CharacterId) -> Option<&str> {
+        self.skips.get(&id).map(|r| r.as_str())
+    }
+
+    /// Records a problem that did not prevent extraction, along with the path of the character
+    /// concerned, if any.
+    pub fn warn(&mut self, warning: Warning, path: Option<String>) {
+        self.warnings.push((warning, path));
+    }
+
+    /// Records the error that aborted the run.
+    pub fn abort(&mut self, message: impl Into<String>) {
+        self.end_phase();
+        self.error = Some(message.into());
+    }
+
+    pub fn to_json(&self, assets: &[AssetOutcome]) -> JsonValue {
+        let mut phases = JsonValue::new_array();
+        for (name, duration) in &self.phases {
+            let mut obj = JsonValue::new_object();
+            obj.insert("phase", *name);
+            obj.insert("seconds", duration.as_secs_f64());
+            phases.push(obj);
+        }
+
+        let mut assets_json = JsonValue::new_array();
+        let mut counts: BTreeMap<&str, usize> = BTreeMap::new();
+        let mut file_count = 0;
+        for asset in assets {
+            let mut obj = JsonValue::new_object();
+            obj.insert("id", asset.id);
+            obj.insert("type", asset.kind);
+            obj.insert("path", asset.path.as_str());
+            obj.insert("status", asset.status.as_str());
+            obj.insert("message", asset.message.clone());
+            obj.insert("files", asset.files.clone());
+            assets_json.push(obj);
+
+            *counts.entry(asset.status.as_str()).or_default() += 1;
+            file_count += asset.files.len();
+        }
+
+        let mut totals = JsonValue::new_object();
+        for status in [AssetStatus::Written, AssetStatus::Skipped, AssetStatus::Failed] {
+            totals.insert(status.as_str(), counts.get(status.as_str()).copied().unwrap_or(0));
+        }
+        totals.insert("files", file_count);
+        totals.insert("warnings", self.warnings.len());
+        totals.insert("seconds", self.started.elapsed().as_secs_f64());
+
+        let mut warnings = JsonValue::new_array();
+        for (warning, path) in &self.warnings {
+            let mut obj = JsonValue::new_object();
+            obj.insert("code", warning.code.as_str());
+            obj.insert("id", warning.character_id);
+            obj.insert("path", path.clone());
+            obj.insert("message", warning.message.as_str());
+            warnings.push(obj);
+        }
+
+        let mut obj = JsonValue::new_object();
+        obj.insert("status", if self.error.is_some() { "failed" } else { "ok" });
+        obj.insert("error", self.error.clone());
+        obj.insert("warnings", warnings);
+        obj.insert("phases", phases);
+        obj.insert("totals", totals);
+        obj.insert("assets", assets_json);
+        obj
+    }
+
+    /// Summarizes the run for output once it has finished: the number of characters written per
+    /// type, the number and total size of the files written, the elapsed time and the number of
+    /// skipped and failed characters and warnings.
+    pub fn summary(&self, assets: &[AssetOutcome], file_count: usize, bytes_written: u64) -> String {
+        let mut written: BTreeMap<&str, usize> = BTreeMap::new();
+        let mut skipped = 0;
+        let mut failed = 0;
+        for asset in assets {
+            match asset.status {
+                AssetStatus::Written => *written.entry(asset.kind).or_default() += 1,
+                AssetStatus::Skipped => skipped += 1,
+                AssetStatus::Failed => failed += 1,
+            }
+        }
+        let written: Vec<String> = written.into_iter()
+            .map(|(kind, count)| format!("{} ({})", kind, count))
+            .collect();
+        let written = if written.is_empty() { "nothing".to_owned() } else { written.join(", ") };
+
+        let mut summary = format!(
+            "written: {}\n{} files ({}) in {:.2} s; {} skipped, {} failed, {} warnings",
+            written, file_count, human_size(usize::try_from(bytes_written).unwrap_or(usize::MAX)),
+            self.started.elapsed().as_secs_f64(), skipped, failed, self.warnings.len(),
+        );
+        if let Some(error) = &self.error {
+            summary.push_str(&format!("\naborted: {}", error));
+        }
+        summary
+    }
+
+    pub fn write<W: Write>(&self, assets: &[AssetOutcome], writer: W) -> Result<(), std::io::Error> {
+        self.to_json(assets).write(writer)
+    }
+}
@@ -0,0 +1,341 @@
+use std::ffi::OsString;
+use std::fs::{self, File};
+use std::io::{self, BufRead, BufReader, Read, Write};
+use std::net::{TcpListener, TcpStream};
+use std::path::{Path, PathBuf};
+use std::process::Command;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+
+use crate::zip::ZipWriter;
+
+
+/// The largest request header accepted, in bytes.
+const MAX_HEADER_LENGTH: usize = 64 * 1024;
+
+/// The largest SWF file accepted, in bytes.
+const MAX_BODY_LENGTH: u64 = 512 * 1024 * 1024;
+
+/// The exit code with which an extraction stopped by `--timeout` ends, distinct from the 2 with
+/// which clap reports invalid arguments and refused movies.
+pub(crate) const TIMED_OUT_EXIT_CODE: i32 = 3;
+
+/// How long a single movie is extracted for if no `--timeout` is given.
+pub(crate) const DEFAULT_EXTRACTION_TIMEOUT: Duration = Duration::from_secs(5 * 60);
+
+const USAGE: &str = "POST a SWF file to /extract to receive a ZIP archive of the extracted files.\n";
+
+
+/// Numbers the working directories of concurrent requests.
+static REQUEST_COUNTER: AtomicU64 = AtomicU64::new(0);
+
+
+/// How the HTTP API is served.
+pub(crate) struct ServeConfig {
+    /// The number of requests handled at once.
+    pub max_connections: usize,
+
+    /// How long reading from or writing to a connection may block.
+    pub socket_timeout: Duration,
+
+    /// The command line (without the program, the output directory and the movie) with which each
+    /// posted movie is extracted.
+    pub extraction_args: Vec<OsString>,
+}
+
+
+/// The request line and headers of a request, whose body is read separately.
+struct RequestHead {
+    method: String,
+    path: String,
+    content_length: Option<u64>,
+}
+
+
+enum ResponseBody {
+    Bytes(Vec<u8>),
+
+    /// A file (such as the ZIP archive) that is copied to the connection as it is read.
+    File { file: File, length: u64 },
+}
+
+
+struct Response {
+    status: u16,
+    content_type: &'static str,
+    headers: Vec<(&'static str, String)>,
+    body: ResponseBody,
+}
+impl Response {
+    fn text(status: u16, text: impl Into<String>) -> Self {
+        Self {
+            status,
+            content_type: "text/plain; charset=utf-8",
+            headers: Vec::new(),
+            body: ResponseBody::Bytes(text.into().into_bytes()),
+        }
+    }
+
+    fn write<W: Write>(self, mut writer: W) -> io::Result<()> {
+        let length = match &self.body {
+            ResponseBody::Bytes(bytes) => u64::try_from(bytes.len()).unwrap(),
+            ResponseBody::File { length, .. } => *length,
+        };
+        write!(writer, "HTTP/1.1 {} {}\r\n", self.status, reason_phrase(self.status))?;
+        write!(writer, "Content-Type: {}\r\n", self.content_type)?;
+        write!(writer, "Content-Length: {}\r\n", length)?;
+        write!(writer, "Connection: close\r\n")?;
+        for (name, value) in &self.headers {
+            write!(writer, "{}: {}\r\n", name, value)?;
+        }
+        write!(writer, "\r\n")?;
+        match self.body {
+            ResponseBody::Bytes(bytes) => writer.write_all(&bytes)?,
+            ResponseBody::File { file, length } => {
+                io::copy(&mut file.take(length), &mut writer)?;
+            },
+        }
+        writer.flush()
+    }
+}
+
+
+fn reason_phrase(status: u16) -> &'static str {
+    match status {
+        200 => "OK",
+        400 => "Bad Request",
+        404 => "Not Found",
+        405 => "Method Not Allowed",
+        411 => "Length Required",
+        413 => "Payload Too Large",
+        422 => "Unprocessable Content",
+        500 => "Internal Server Error",
+        _ => "Unknown",
+    }
+}
+
+
+/// Reads the request line and headers of a request, or returns the response with which to reject
+/// it.
+fn read_request_head<R: BufRead>(mut reader: R) -> Result<RequestHead, Response> {
+    let mut header_length = 0;
+    let mut lines = Vec::new();
+    loop {
+        let mut line = String::new();
+        let read = (&mut reader).take((MAX_HEADER_LENGTH - header_length) as u64).read_line(&mut line)
+            .map_err(|e| Response::text(400, format!("failed to read request: {}\n", e)))?;
+        header_length += read;
+        if !line.ends_with('\n') {
+            return Err(Response::text(400, "incomplete or overly long request header\n"));
+        }
+        let line = line.trim_end_matches(['\r', '\n']);
+        if line.is_empty() {
+            break;
+        }
+        lines.push(line.to_owned());
+    }
+
+    let request_line = lines.first()
+        .ok_or_else(|| Response::text(400, "empty request\n"))?;
+    let mut pieces = request_line.split(' ');
+    let (Some(method), Some(target), Some(_version)) = (pieces.next(), pieces.next(), pieces.next()) else {
+        return Err(Response::text(400, "invalid request line\n"));
+    };
+    let path = target.split('?').next().unwrap_or(target);
+
+    let mut content_length = None;
+    for line in &lines[1..] {
+        let Some((name, value)) = line.split_once(':') else {
+            return Err(Response::text(400, "invalid header line\n"));
+        };
+        if name.eq_ignore_ascii_case("content-length") {
+            let length: u64 = value.trim().parse()
+                .map_err(|_| Response::text(400, "invalid Content-Length\n"))?;
+            content_length = Some(length);
+        } else if name.eq_ignore_ascii_case("transfer-encoding") {
+            return Err(Response::text(411, "chunked requests are not supported; send a Content-Length\n"));
+        }
+    }
+
+    if content_length.is_some_and(|length| length > MAX_BODY_LENGTH) {
+        return Err(Response::text(413, format!("SWF files are limited to {} bytes\n", MAX_BODY_LENGTH)));
+    }
+    if content_length.is_none() && method == "POST" {
+        return Err(Response::text(411, "send a Content-Length\n"));
+    }
+
+    Ok(RequestHead {
+        method: method.to_owned(),
+        path: path.to_owned(),
+        content_length,
+    })
+}
+
+
+/// Copies the body of a request into a file, returning the response with which to reject the
+/// request if it ends early.
+fn save_body<R: Read>(reader: R, length: u64, path: &Path) -> Result<(), Response> {
+    let mut file = File::create(path)
+        .map_err(|e| Response::text(500, format!("failed to store SWF file: {}\n", e)))?;
+    let copied = io::copy(&mut reader.take(length), &mut file)
+        .map_err(|e| Response::text(400, format!("failed to read request body: {}\n", e)))?;
+    if copied != length {
+        return Err(Response::text(400, "request body shorter than Content-Length\n"));
+    }
+    Ok(())
+}
+
+
+/// Lists the files below the given directory, with their paths relative to it, in sorted order.
+fn collect_files(base: &Path, dir: &Path, files: &mut Vec<(String, PathBuf)>) -> io::Result<()> {
+    let mut entries: Vec<_> = fs::read_dir(dir)?.collect::<Result<_, _>>()?;
+    entries.sort_by_key(|e| e.file_name());
+    for entry in entries {
+        let path = entry.path();
+        if entry.file_type()?.is_dir() {
+            collect_files(base, &path, files)?;
+        } else {
+            let relative = path.strip_prefix(base).unwrap();
+            let name: Vec<String> = relative.components()
+                .map(|c| c.as_os_str().to_string_lossy().into_owned())
+                .collect();
+            files.push((name.join("/"), path));
+        }
+    }
+    Ok(())
+}
+
+
+/// Extracts the SWF file in a child process and packs the output into a ZIP archive, which is
+/// written into the working directory and sent from there.
+fn extract(work_dir: &Path, swf_path: &Path, extraction_args: &[OsString]) -> io::Result<Response> {
+    let output_dir = work_dir.join("output");
+
+    // a separate process keeps failures and memory use of one movie from affecting the others
+    let output = Command::new(std::env::current_exe()?)
+        .args(extraction_args)
+        .arg("--output-dir").arg(&output_dir)
+        .arg(swf_path)
+        .output()?;
+    let timed_out = output.status.code() == Some(TIMED_OUT_EXIT_CODE);
+    if !output.status.success() && !timed_out {
+        let mut message = b"extraction failed\n\n".to_vec();
+        message.extend_from_slice(&output.stdout);
+        message.extend_from_slice(&output.stderr);
+        return Ok(Response::text(422, String::from_utf8_lossy(&message)));
+    }
+
+    let mut files = Vec::new();
+    collect_files(&output_dir, &output_dir, &mut files)?;
+    let zip_path = work_dir.join("extracted.zip");
+    let mut zip = ZipWriter::new(io::BufWriter::new(File::create(&zip_path)?));
+    for (name, path) in &files {
+        zip.add_file(name, &fs::read(path)?)?;
+    }
+    zip.finish()?;
+    let file = File::open(&zip_path)?;
+    let length = file.metadata()?.len();
+    Ok(Response {
+        status: 200,
+        content_type: "application/zip",
+        headers: vec![
+            ("Content-Disposition", "attachment; filename=\"extracted.zip\"".to_owned()),
+            ("X-Extraction-Status", if timed_out { "timed-out" } else { "complete" }.to_owned()),
+        ],
+        body: ResponseBody::File { file, length },
+    })
+}
+
+
+/// Answers a request, using the given working directory (which is created if needed) for the files
+/// of an extraction.
+fn respond<R: Read>(head: &RequestHead, body: R, work_dir: &Path, extraction_args: &[OsString]) -> Response {
+    match (head.method.as_str(), head.path.as_str()) {
+        ("GET", "/") => Response::text(200, USAGE),
+        ("POST", "/extract") => {
+            let length = head.content_length.unwrap_or(0);
+            if length == 0 {
+                return Response::text(400, "no SWF file given\n");
+            }
+            if let Err(e) = fs::create_dir_all(work_dir) {
+                return Response::text(500, format!("failed to create working directory: {}\n", e));
+            }
+            let swf_path = work_dir.join("movie.swf");
+            save_body(body, length, &swf_path)
+                .and_then(|_| extract(work_dir, &swf_path, extraction_args)
+                    .map_err(|e| Response::text(500, format!("failed to extract SWF file: {}\n", e))))
+                .unwrap_or_else(|response| response)
+        },
+        (_, "/"|"/extract") => Response::text(405, "method not allowed\n"),
+        _ => Response::text(404, "not found\n"),
+    }
+}
+
+
+fn handle_connection(stream: TcpStream, config: &ServeConfig) -> io::Result<()> {
+    let peer = stream.peer_addr()?;
+    // slow clients must not hold up a worker forever
+    stream.set_read_timeout(Some(config.socket_timeout))?;
+    stream.set_write_timeout(Some(config.socket_timeout))?;
+    let request_number = REQUEST_COUNTER.fetch_add(1, Ordering::Relaxed);
+    let work_dir = std::env::temp_dir()
+        .join(format!("swfextract-serve-{}-{}", std::process::id(), request_number));
+
+    let mut reader = BufReader::new(&stream);
+    let (description, response) = match read_request_head(&mut reader) {
+        Ok(head) => {
+            let description = format!("{} {} ({} bytes)", head.method, head.path, head.content_length.unwrap_or(0));
+            (description, respond(&head, &mut reader, &work_dir, &config.extraction_args))
+        },
+        Err(response) => ("invalid request".to_owned(), response),
+    };
+    println!("{}: {} -> {}", peer, description, response.status);
+    // the archive is sent from the working directory
+    let result = response.write(&stream);
+    if work_dir.exists() {
+        let _ = fs::remove_dir_all(&work_dir);
+    }
+    result
+}
+
+
+/// Accepts and handles connections one after the other.
+fn run_worker(listener: &TcpListener, config: &ServeConfig) {
+    for stream in listener.incoming() {
+        let stream = match stream {
+            Ok(s) => s,
+            Err(e) => {
+                eprintln!("failed to accept connection: {}", e);
+                continue;
+            },
+        };
+        if let Err(e) = handle_connection(stream, config) {
+            eprintln!("failed to handle connection: {}", e);
+        }
+    }
+}
+
+
+/// Serves the HTTP API on the given address until the process is terminated.
+///
+/// Each SWF file posted to `/extract` is extracted with the configured command-line arguments by a
+/// new instance of this program, and the extracted files (including the manifest) are returned as a
+/// ZIP archive. A fixed number of worker threads accept the connections, so that connections beyond
+/// them wait to be accepted.
+pub(crate) fn serve(address: &str, config: ServeConfig) -> io::Result<()> {
+    let listener = TcpListener::bind(address)?;
+    println!("listening on {}", listener.local_addr()?);
+    let config = Arc::new(config);
+    let mut workers = Vec::with_capacity(config.max_connections);
+    for _ in 0..config.max_connections {
+        let listener = listener.try_clone()?;
+        let config = Arc::clone(&config);
+        workers.push(std::thread::spawn(move || run_worker(&listener, &config)));
+    }
+    for worker in workers {
+        worker.join()
+            .map_err(|_| io::Error::other("worker thread panicked"))?;
+    }
+    Ok(())
+}
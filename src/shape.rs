@@ -1,9 +1,11 @@
 use std::fmt::Write;
 
-use swf::{Color, FillStyle, Gradient, LineJoinStyle, Shape, ShapeRecord, Twips};
+use swf::{Color, FillStyle, Gradient, LineJoinStyle, Matrix, Shape, ShapeRecord, ShapeStyles, Twips};
 use sxd_document::Package;
 use sxd_document::dom::{Document, Element};
 
+use crate::json::JsonValue;
+
 
 fn write_rgba_as_css<W: Write>(color: &Color, mut write: W) {
     write!(
@@ -43,6 +45,7 @@ fn write_fill_as_color<'d, W: Write>(
     fill_style: &FillStyle,
     document: Document<'d>,
     defs: Element<'d>,
+    id_prefix: &str,
     gradient_id: &mut usize,
     mut write: W,
 ) {
@@ -52,22 +55,22 @@ fn write_fill_as_color<'d, W: Write>(
         },
         FillStyle::LinearGradient(lg) => {
             let gradient = document.create_element("linearGradient");
-            gradient.set_attribute_value("id", &format!("grad{}", *gradient_id));
+            gradient.set_attribute_value("id", &format!("{}grad{}", id_prefix, *gradient_id));
             defs.append_child(gradient);
 
             populate_gradient(lg, document, gradient);
 
-            write!(write, "url(#grad{})", gradient_id).unwrap();
+            write!(write, "url(#{}grad{})", id_prefix, gradient_id).unwrap();
             *gradient_id += 1;
         },
         FillStyle::RadialGradient(rg) => {
             let gradient = document.create_element("radialGradient");
-            gradient.set_attribute_value("id", &format!("grad{}", *gradient_id));
+            gradient.set_attribute_value("id", &format!("{}grad{}", id_prefix, *gradient_id));
             defs.append_child(gradient);
 
             populate_gradient(rg, document, gradient);
 
-            write!(write, "url(#grad{})", gradient_id).unwrap();
+            write!(write, "url(#{}grad{})", id_prefix, gradient_id).unwrap();
             *gradient_id += 1;
         },
         _ => {
@@ -86,7 +89,7 @@ fn write_line_join_style_css_attributes<W: Write>(join_style: &LineJoinStyle, mu
 }
 
 /// Twips to pixels.
-fn tw2px(twips: Twips) -> f64 {
+pub(crate) fn tw2px(twips: Twips) -> f64 {
     (twips.get() as f64) / 20.0
 }
 
@@ -98,149 +101,339 @@ pub(crate) fn shape_to_svg(shape: &Shape) -> String {
     let svg = svg_document.create_element("svg");
     svg_document.root().append_child(svg);
     svg.set_default_namespace_uri(Some("http://www.w3.org/2000/svg"));
+    let width = shape.shape_bounds.x_max - shape.shape_bounds.x_min;
+    let height = shape.shape_bounds.y_max - shape.shape_bounds.y_min;
     svg.set_attribute_value("viewBox", &format!(
         "{} {} {} {}",
         shape.shape_bounds.x_min,
         shape.shape_bounds.y_min,
-        shape.shape_bounds.x_max,
-        shape.shape_bounds.y_max,
+        width,
+        height,
     ));
-    let width = shape.shape_bounds.x_max - shape.shape_bounds.x_min;
-    let height = shape.shape_bounds.y_max - shape.shape_bounds.y_min;
     svg.set_attribute_value("width", &format!("{}px", tw2px(width)));
     svg.set_attribute_value("height", &format!("{}px", tw2px(height)));
 
     let defs = svg_document.create_element("defs");
     svg.append_child(defs);
-    let mut gradient_index = 0;
 
-    // assemble styles
-    let mut styles = String::new();
-    for (i, fill_style) in shape.styles.fill_styles.iter().enumerate() {
-        if styles.len() > 0 {
-            styles.push_str("\n");
+    append_shape(shape, svg_document, defs, svg, "");
+
+    let mut buf = Vec::new();
+    sxd_document::writer::format_document(&svg_document, &mut buf)
+        .expect("failed to write SVG");
+    String::from_utf8(buf)
+        .expect("written SVG is not UTF-8?!")
+}
+
+
+/// The consecutive paths of a layer drawn with the same style.
+struct StyleRun {
+    class: String,
+    paths: Vec<String>,
+}
+
+
+/// The paths drawn with one set of styles. Flash draws all fills of a set before its strokes, and
+/// each set above the previous ones.
+#[derive(Default)]
+struct Layer {
+    fills: Vec<StyleRun>,
+    strokes: Vec<StyleRun>,
+}
+impl Layer {
+    fn add_path(runs: &mut Vec<StyleRun>, class: String, path_data: &str) {
+        match runs.last_mut() {
+            Some(run) if run.class == class => run.paths.push(path_data.to_owned()),
+            _ => runs.push(StyleRun { class, paths: vec![path_data.to_owned()] }),
+        }
+    }
+
+    /// Appends one group per style run to `parent`, strokes above fills.
+    fn append_to<'d>(self, svg_document: Document<'d>, parent: Element<'d>) {
+        let fills = self.fills.into_iter().map(|run| (run, false));
+        let strokes = self.strokes.into_iter().map(|run| (run, true));
+        for (run, is_stroke) in fills.chain(strokes) {
+            let group = svg_document.create_element("g");
+            parent.append_child(group);
+            group.set_attribute_value("class", &run.class);
+            if is_stroke {
+                group.set_attribute_value("fill", "none");
+            }
+            for path_data in &run.paths {
+                let path = svg_document.create_element("path");
+                group.append_child(path);
+                path.set_attribute_value("d", path_data);
+            }
         }
-        write!(styles, ".f{} {{ fill: ", i+1).unwrap();
+    }
+}
+
+
+/// The path being assembled from the edges drawn with the current styles.
+#[derive(Default)]
+struct CurrentPath {
+    fill_style_0: u32,
+    fill_style_1: u32,
+    line_style: u32,
+    data: String,
+    has_edges: bool,
+}
+impl CurrentPath {
+    /// Adds the path to the layer with its styles (if it contains any edges) and starts a new one.
+    fn finish(&mut self, layer: &mut Layer, class_prefix: &str) {
+        if self.has_edges {
+            // without reconstructing the regions enclosed by the edges, draw the fill on either side
+            let fill_style = if self.fill_style_0 != 0 { self.fill_style_0 } else { self.fill_style_1 };
+            if fill_style != 0 {
+                Layer::add_path(&mut layer.fills, format!("{}f{}", class_prefix, fill_style), &self.data);
+            }
+            if self.line_style != 0 {
+                Layer::add_path(&mut layer.strokes, format!("{}l{}", class_prefix, self.line_style), &self.data);
+            }
+        }
+        self.data.clear();
+        self.has_edges = false;
+    }
+
+    /// Starts the path at the given position unless it has already been started.
+    fn start_at(&mut self, x: Twips, y: Twips) {
+        if self.data.is_empty() {
+            write!(self.data, "M {} {}", x, y).unwrap();
+        }
+    }
+}
+
+
+/// Writes the CSS rules for a set of styles to `rules`, with the classes `{class_prefix}f{n}` for
+/// fill styles and `{class_prefix}l{n}` for line styles, and adds their gradients to `defs`.
+fn write_style_rules<'d>(
+    styles: &ShapeStyles,
+    svg_document: Document<'d>,
+    defs: Element<'d>,
+    id_prefix: &str,
+    class_prefix: &str,
+    gradient_index: &mut usize,
+    rules: &mut String,
+) {
+    for (i, fill_style) in styles.fill_styles.iter().enumerate() {
+        if !rules.is_empty() {
+            rules.push('\n');
+        }
+        write!(rules, ".{}f{} {{ fill: ", class_prefix, i+1).unwrap();
         write_fill_as_color(
             fill_style,
             svg_document,
             defs,
-            &mut gradient_index,
-            &mut styles,
+            id_prefix,
+            gradient_index,
+            &mut *rules,
         );
-        write!(styles, "; }}").unwrap();
+        write!(rules, "; }}").unwrap();
     }
-    for (i, line_style) in shape.styles.line_styles.iter().enumerate() {
-        if styles.len() > 0 {
-            styles.push_str("\n");
+    for (i, line_style) in styles.line_styles.iter().enumerate() {
+        if !rules.is_empty() {
+            rules.push('\n');
         }
-        write!(styles, ".l{} {{ stroke: ", i+1).unwrap();
+        write!(rules, ".{}l{} {{ stroke: ", class_prefix, i+1).unwrap();
         write_fill_as_color(
             line_style.fill_style(),
             svg_document,
             defs,
-            &mut gradient_index,
-            &mut styles,
+            id_prefix,
+            gradient_index,
+            &mut *rules,
         );
-        write!(styles, ";").unwrap();
+        write!(rules, ";").unwrap();
 
-        write!(styles, " ").unwrap();
-        write_line_join_style_css_attributes(&line_style.join_style(), &mut styles);
-        write!(styles, ";").unwrap();
+        write!(rules, " ").unwrap();
+        write_line_join_style_css_attributes(&line_style.join_style(), &mut *rules);
+        write!(rules, ";").unwrap();
 
-        write!(styles, " stroke-width: {}px;", tw2px(line_style.width())).unwrap();
+        write!(rules, " stroke-width: {}px;", tw2px(line_style.width())).unwrap();
 
-        write!(styles, " }}").unwrap();
+        write!(rules, " }}").unwrap();
     }
+}
 
-    let style = svg_document.create_element("style");
-    defs.append_child(style);
-    style.set_text(&styles);
 
-    let mut path = svg_document.create_element("path");
-    let mut classes = String::new();
-    if shape.styles.fill_styles.len() > 0 {
-        if classes.len() > 0 {
-            classes.push(' ');
-        }
-        classes.push_str("f1");
-    }
-    if shape.styles.line_styles.len() > 0 {
-        if classes.len() > 0 {
-            classes.push(' ');
-        }
-        classes.push_str("l1");
-    }
-    path.set_attribute_value("class", &classes);
+/// Appends the paths of a shape to `parent` and its styles and gradients to `defs`.
+///
+/// The paths are grouped by style run, keeping the order in which Flash draws them: each set of
+/// styles forms a layer above the previous ones, and within a layer, strokes are drawn above fills.
+/// The classes of the styles introduced along the way are additionally prefixed with `s{n}`.
+///
+/// The class names and gradient IDs are prefixed with `id_prefix`, allowing multiple shapes to share
+/// a document.
+pub(crate) fn append_shape<'d>(
+    shape: &Shape,
+    svg_document: Document<'d>,
+    defs: Element<'d>,
+    parent: Element<'d>,
+    id_prefix: &str,
+) {
+    let mut gradient_index = 0;
 
-    let mut current_path_data = String::new();
-    let mut current_coords = (Twips::ZERO, Twips::ZERO);
-    for record in &shape.shape {
-        if current_path_data.len() > 0 {
-            current_path_data.push(' ');
-        }
+    // assemble styles
+    let mut styles = String::new();
+    write_style_rules(&shape.styles, svg_document, defs, id_prefix, id_prefix, &mut gradient_index, &mut styles);
+
+    let mut layer = Layer::default();
+    let mut style_set = 0;
+    let mut class_prefix = id_prefix.to_owned();
+    let mut path = CurrentPath::default();
+    let (mut x, mut y) = (Twips::ZERO, Twips::ZERO);
 
+    for record in &shape.shape {
         match record {
             ShapeRecord::StyleChange(sc) => {
-                // finish current path
-                if current_path_data.len() > 0 {
-                    svg.append_child(path);
-                    path.set_attribute_value("d", &current_path_data);
-                    current_path_data.clear();
-
-                    path = svg_document.create_element("path");
+                let changes_style = sc.new_styles.is_some()
+                    || sc.fill_style_0.is_some()
+                    || sc.fill_style_1.is_some()
+                    || sc.line_style.is_some();
+                if changes_style {
+                    path.finish(&mut layer, &class_prefix);
                 }
-                // otherwise, reuse current path element
 
-                current_coords = (Twips::ZERO, Twips::ZERO);
-                if let Some((x, y)) = sc.move_to {
-                    current_coords.0 += x;
-                    current_coords.1 += y;
+                if let Some(new_styles) = &sc.new_styles {
+                    std::mem::take(&mut layer).append_to(svg_document, parent);
+                    style_set += 1;
+                    class_prefix = format!("{}s{}", id_prefix, style_set);
+                    write_style_rules(new_styles, svg_document, defs, id_prefix, &class_prefix, &mut gradient_index, &mut styles);
+                    path.fill_style_0 = 0;
+                    path.fill_style_1 = 0;
+                    path.line_style = 0;
                 }
-                write!(current_path_data, "M {} {}", current_coords.0, current_coords.1).unwrap();
-
-                let mut classes = String::new();
                 if let Some(fs) = sc.fill_style_0 {
-                    if classes.len() > 0 {
-                        classes.push(' ');
-                    }
-                    write!(classes, "f{}", fs).unwrap();
+                    path.fill_style_0 = fs;
+                }
+                if let Some(fs) = sc.fill_style_1 {
+                    path.fill_style_1 = fs;
                 }
                 if let Some(ls) = sc.line_style {
-                    if classes.len() > 0 {
-                        classes.push(' ');
-                    }
-                    write!(classes, "l{}", ls).unwrap();
+                    path.line_style = ls;
                 }
-                if classes.len() > 0 {
-                    path.set_attribute_value("class", &classes);
+
+                if let Some((move_x, move_y)) = sc.move_to {
+                    (x, y) = (move_x, move_y);
+                    if !path.data.is_empty() {
+                        path.data.push(' ');
+                    }
+                    write!(path.data, "M {} {}", x, y).unwrap();
                 }
             },
             ShapeRecord::CurvedEdge { control_delta_x, control_delta_y, anchor_delta_x, anchor_delta_y } => {
+                path.start_at(x, y);
                 let cx = *control_delta_x;
                 let cy = *control_delta_y;
                 let ax = *control_delta_x + *anchor_delta_x;
                 let ay = *control_delta_y + *anchor_delta_y;
-                write!(current_path_data, "q {} {} {} {}", cx, cy, ax, ay).unwrap();
-                current_coords.0 += ax;
-                current_coords.0 += ay;
+                write!(path.data, " q {} {} {} {}", cx, cy, ax, ay).unwrap();
+                x += ax;
+                y += ay;
+                path.has_edges = true;
             },
             ShapeRecord::StraightEdge { delta_x, delta_y } => {
-                write!(current_path_data, "l {} {}", delta_x, delta_y).unwrap();
-                current_coords.0 += *delta_x;
-                current_coords.1 += *delta_y;
+                path.start_at(x, y);
+                write!(path.data, " l {} {}", delta_x, delta_y).unwrap();
+                x += *delta_x;
+                y += *delta_y;
+                path.has_edges = true;
             },
         }
     }
+    path.finish(&mut layer, &class_prefix);
+    layer.append_to(svg_document, parent);
+
+    let style = svg_document.create_element("style");
+    defs.append_child(style);
+    style.set_text(&styles);
+}
+
+
+/// Returns the outlines of a shape as SVG path data with absolute coordinates in pixels, after
+/// transforming them by the given matrix.
+///
+/// Curves remain quadratic Bézier curves, as transforming their control points transforms the whole
+/// curve.
+pub(crate) fn shape_to_path_data(shape: &Shape, matrix: Matrix) -> String {
+    let point = |x: Twips, y: Twips| {
+        let (tx, ty) = matrix * (x, y);
+        format!("{} {}", tw2px(tx), tw2px(ty))
+    };
 
-    if current_path_data.len() > 0 {
-        svg.append_child(path);
-        path.set_attribute_value("d", &current_path_data);
+    let mut path_data = String::new();
+    let (mut x, mut y) = (Twips::ZERO, Twips::ZERO);
+    let mut has_moved = false;
+    for record in &shape.shape {
+        if let ShapeRecord::StyleChange(sc) = record {
+            if let Some((move_x, move_y)) = sc.move_to {
+                (x, y) = (move_x, move_y);
+                has_moved = false;
+            }
+            continue;
+        }
+
+        if !has_moved {
+            if !path_data.is_empty() {
+                path_data.push(' ');
+            }
+            write!(path_data, "M {}", point(x, y)).unwrap();
+            has_moved = true;
+        }
+        match record {
+            ShapeRecord::StraightEdge { delta_x, delta_y } => {
+                x += *delta_x;
+                y += *delta_y;
+                write!(path_data, " L {}", point(x, y)).unwrap();
+            },
+            ShapeRecord::CurvedEdge { control_delta_x, control_delta_y, anchor_delta_x, anchor_delta_y } => {
+                let (control_x, control_y) = (x + *control_delta_x, y + *control_delta_y);
+                x = control_x + *anchor_delta_x;
+                y = control_y + *anchor_delta_y;
+                write!(path_data, " Q {} {}", point(control_x, control_y), point(x, y)).unwrap();
+            },
+            ShapeRecord::StyleChange(_) => unreachable!(),
+        }
     }
+    path_data
+}
 
-    let mut buf = Vec::new();
-    sxd_document::writer::format_document(&svg_document, &mut buf)
-        .expect("failed to write SVG");
-    String::from_utf8(buf)
-        .expect("written SVG is not UTF-8?!")
+
+/// Lists the bitmap fills of a shape, including those of its lines and of the style sets it
+/// introduces along the way, with the flags determining how Flash drew the bitmaps.
+///
+/// Style set 0 holds the initial styles of the shape; fill and line styles are numbered from 1, as
+/// within the shape records.
+pub(crate) fn bitmap_fills_to_json(shape: &Shape) -> JsonValue {
+    let mut style_sets = vec![&shape.styles];
+    for record in &shape.shape {
+        if let ShapeRecord::StyleChange(change) = record {
+            if let Some(styles) = &change.new_styles {
+                style_sets.push(styles);
+            }
+        }
+    }
+
+    let mut fills = JsonValue::new_array();
+    for (set_index, styles) in style_sets.iter().enumerate() {
+        let fill_styles = styles.fill_styles.iter()
+            .enumerate()
+            .map(|(i, fs)| ("fill_style", i, fs));
+        let line_fill_styles = styles.line_styles.iter()
+            .enumerate()
+            .map(|(i, ls)| ("line_style", i, ls.fill_style()));
+        for (style_kind, style_index, fill_style) in fill_styles.chain(line_fill_styles) {
+            if let FillStyle::Bitmap { id, is_smoothed, is_repeating, .. } = fill_style {
+                let mut fill = JsonValue::new_object();
+                fill.insert("style_set", set_index);
+                fill.insert(style_kind, style_index + 1);
+                // 0xFFFF denotes a missing bitmap
+                fill.insert("bitmap", if *id == 0xFFFF { None } else { Some(*id) });
+                fill.insert("is_smoothed", *is_smoothed);
+                fill.insert("is_repeating", *is_repeating);
+                fills.push(fill);
+            }
+        }
+    }
+    fills
 }
@@ -1,49 +1,433 @@
-use std::io::Write;
+use std::borrow::Cow;
+use std::io::{ErrorKind, Read, Write};
+use std::process::{Command, Stdio};
+use std::time::{SystemTime, UNIX_EPOCH};
 
-use swf::{AudioCompression, SoundFormat};
+use clap::ValueEnum;
+use swf::{AudioCompression, CharacterId, SoundEvent, SoundFormat, SoundInfo};
+use swfextract::adpcm::AdpcmDecoder;
 
-use crate::adpcm::AdpcmDecoder;
+use crate::flac::write_flac;
+use crate::json::JsonValue;
+use crate::mp3::{resync, silent_frame, xing_frame, FrameHeader};
 
 
+/// The length of the RIFF, fmt and data chunk headers output by `write_wav`.
+const WAV_HEADER_LEN: usize = 44;
+
+
+/// The file format in which decoded PCM sounds (uncompressed and ADPCM) are output.
+#[derive(Clone, Copy, Debug, Default, Eq, Hash, PartialEq, ValueEnum)]
+pub(crate) enum AudioFormat {
+    #[default]
+    Wav,
+    Flac,
+
+    /// Ogg Vorbis, encoded using an external `oggenc`.
+    Ogg,
+
+    /// Ogg Opus, encoded using an external `opusenc`.
+    Opus,
+}
+impl AudioFormat {
+    /// Returns the external program that encodes sounds in this format, if one is needed.
+    pub fn encoder(self) -> Option<&'static str> {
+        match self {
+            Self::Wav|Self::Flac => None,
+            Self::Ogg => Some("oggenc"),
+            Self::Opus => Some("opusenc"),
+        }
+    }
+}
+
+
+/// Returns whether the given program can be found in PATH and run.
+pub(crate) fn is_program_available(program: &str) -> bool {
+    Command::new(program)
+        .arg("--version")
+        .stdin(Stdio::null())
+        .stdout(Stdio::null())
+        .stderr(Stdio::null())
+        .status()
+        .is_ok()
+}
+
+
+/// The byte order of 16-bit sounds stored as UncompressedUnknownEndian.
+#[derive(Clone, Copy, Debug, Default, Eq, Hash, PartialEq, ValueEnum)]
+pub(crate) enum PcmEndian {
+    /// Guess the byte order from the sound data.
+    #[default]
+    Auto,
+    Little,
+    Big,
+}
+
+
+/// How decoded PCM sounds are output.
+#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq)]
+pub(crate) struct AudioOptions {
+    pub format: AudioFormat,
+
+    /// The bit rate in kbit/s for lossy formats.
+    pub bit_rate: u32,
+
+    pub pcm_endian: PcmEndian,
+
+    /// Whether to output ADPCM sounds undecoded, along with a JSON descriptor.
+    pub raw_adpcm: bool,
+
+    /// Whether to mix stereo PCM sounds down to mono.
+    pub mono: bool,
+
+    /// The sample rate to which to resample PCM sounds, if any.
+    pub sample_rate: Option<u16>,
+}
+
+
+/// Where an extracted sound comes from, recorded in the metadata of WAV files.
+#[derive(Clone, Debug)]
+pub(crate) struct Provenance {
+    /// The file name of the movie.
+    pub source: String,
+
+    /// The export name of the sound, or a description if it is not exported.
+    pub title: String,
+
+    /// The ID of the sound; `None` for stream sounds.
+    pub character_id: Option<CharacterId>,
+
+    pub extraction_time: SystemTime,
+}
+
+
+/// Collects the playback parameters of a sound (loops, in and out points, volume envelope) into a
+/// JSON object.
+pub(crate) fn sound_info_to_json(info: &SoundInfo) -> JsonValue {
+    let event = match info.event {
+        SoundEvent::Event => "event",
+        SoundEvent::Start => "start",
+        SoundEvent::Stop => "stop",
+    };
+
+    let mut obj = JsonValue::new_object();
+    obj.insert("event", event);
+    obj.insert("in_sample", info.in_sample);
+    obj.insert("out_sample", info.out_sample);
+    obj.insert("num_loops", info.num_loops);
+    let envelope = info.envelope.as_ref().map(|points| {
+        let mut envelope = JsonValue::new_array();
+        for point in points {
+            let mut point_obj = JsonValue::new_object();
+            point_obj.insert("sample", point.sample);
+            point_obj.insert("left_volume", point.left_volume);
+            point_obj.insert("right_volume", point.right_volume);
+            envelope.push(point_obj);
+        }
+        envelope
+    });
+    obj.insert("envelope", envelope);
+    obj
+}
+
+
+#[derive(Clone)]
 pub(crate) struct Sound {
     pub format: SoundFormat,
     pub data: Vec<u8>,
+
+    /// The number of samples (per channel) contained in the sound.
+    pub sample_count: u64,
+
+    /// The number of samples of silence to insert before the next block of stream data.
+    pub pending_silence: u64,
+
+    /// The number of samples yet to be skipped at the start of an MP3 stream; negative values
+    /// denote samples of silence yet to be inserted.
+    pub pending_seek: i64,
+
+    /// Whether ADPCM data is kept as-is instead of being decoded.
+    keep_raw_adpcm: bool,
+
+    /// The lengths of the undecoded ADPCM packets making up the data, each of which starts with
+    /// its own header.
+    adpcm_packet_lengths: Vec<usize>,
+
+    /// The origin of the sound, written into WAV files if set.
+    pub provenance: Option<Provenance>,
 }
 impl Sound {
-    pub fn extension(&self) -> &'static str {
+    /// Creates an empty stream sound, to be filled by SoundStreamBlock tags.
+    pub fn new_stream(format: SoundFormat, keep_raw_adpcm: bool) -> Self {
+        Self {
+            format,
+            data: Vec::new(),
+            sample_count: 0,
+            pending_silence: 0,
+            pending_seek: 0,
+            keep_raw_adpcm,
+            adpcm_packet_lengths: Vec::new(),
+            provenance: None,
+        }
+    }
+
+    /// Creates a sound from a DefineSound tag, decoding ADPCM and trimming the data to the
+    /// declared number of samples.
+    pub fn from_define_sound(snd: &swf::Sound, keep_raw_adpcm: bool) -> Self {
+        let mut sound = Self::new_stream(snd.format.clone(), keep_raw_adpcm);
+        let declared_samples = u64::from(snd.num_samples);
+        match snd.format.compression {
+            AudioCompression::Adpcm => {
+                sound.decode_adpcm(snd.data);
+                if sound.is_raw_adpcm() {
+                    // the decoder also returns the samples padding the last block
+                    sound.sample_count = sound.sample_count.min(declared_samples);
+                    return sound;
+                }
+            },
+            AudioCompression::Mp3 => {
+                // event sounds start with the number of samples to skip
+                if snd.data.len() < 2 {
+                    return sound;
+                }
+                let seek_samples = i16::from_le_bytes([snd.data[0], snd.data[1]]).max(0) as u64;
+                let wanted_samples = seek_samples + declared_samples;
+
+                // keep all frames that contain any wanted samples
+                let mut frames = &snd.data[2..];
+                let mut frame_start = 0;
+                while frame_start < wanted_samples {
+                    let Some(header) = FrameHeader::parse(frames) else { break };
+                    let frame_len = header.frame_len().min(frames.len());
+                    sound.data.extend(&frames[..frame_len]);
+                    frames = &frames[frame_len..];
+                    frame_start += u64::from(header.samples());
+                }
+                if sound.data.is_empty() {
+                    // not parseable; output as-is
+                    sound.data.extend(&snd.data[2..]);
+                }
+                sound.sample_count = declared_samples;
+                return sound;
+            },
+            _ => {
+                sound.data.extend(snd.data);
+            },
+        }
+
+        if let Some(bytes_per_sample) = sound.pcm_bytes_per_sample() {
+            // ADPCM blocks are padded to full length
+            let declared_len = usize::try_from(declared_samples).unwrap() * bytes_per_sample;
+            sound.data.truncate(declared_len);
+            sound.sample_count = (sound.data.len() / bytes_per_sample) as u64;
+        } else {
+            sound.sample_count = declared_samples;
+        }
+        sound
+    }
+
+    pub fn extension(&self, audio_format: AudioFormat) -> &'static str {
+        if self.is_raw_adpcm() {
+            return "adpcm";
+        }
+        let pcm_extension = match audio_format {
+            AudioFormat::Wav => "wav",
+            AudioFormat::Flac => "flac",
+            AudioFormat::Ogg => "ogg",
+            AudioFormat::Opus => "opus",
+        };
         match self.format.compression {
-            AudioCompression::Adpcm => "wav",
-            AudioCompression::Uncompressed => "wav",
-            AudioCompression::UncompressedUnknownEndian => "wav",
+            AudioCompression::Adpcm => pcm_extension,
+            AudioCompression::Uncompressed => pcm_extension,
+            AudioCompression::UncompressedUnknownEndian => pcm_extension,
             AudioCompression::Mp3 => "mp3",
             _other => "bin",
         }
     }
 
+    /// Returns whether the sound is ADPCM-encoded and is being kept that way.
+    fn is_raw_adpcm(&self) -> bool {
+        self.keep_raw_adpcm && self.format.compression == AudioCompression::Adpcm
+    }
+
+    /// Returns the number of bytes per sample (across all channels) of PCM data, or `None` if the
+    /// sound is not stored as PCM.
+    fn pcm_bytes_per_sample(&self) -> Option<usize> {
+        if self.is_raw_adpcm() {
+            return None;
+        }
+        let bytes_per_channel = match self.format.compression {
+            AudioCompression::Adpcm => 2, // always decodes to signed-16 PCM
+            AudioCompression::Uncompressed|AudioCompression::UncompressedUnknownEndian => {
+                if self.format.is_16_bit { 2 } else { 1 }
+            },
+            _ => return None,
+        };
+        Some(bytes_per_channel * if self.format.is_stereo { 2 } else { 1 })
+    }
+
+    /// Schedules silence to be inserted before the next block of stream data.
+    ///
+    /// Only PCM sounds (uncompressed and ADPCM) can be padded; this is a no-op for others.
+    pub fn defer_silence(&mut self, sample_count: u64) {
+        if self.pcm_bytes_per_sample().is_some() {
+            self.pending_silence += sample_count;
+        }
+    }
+
+    /// Returns the number of samples and bytes of sound data, including pending silence.
+    pub fn padded_length(&self) -> (u64, usize) {
+        let pending_bytes = self.pcm_bytes_per_sample().unwrap_or(0) * self.pending_silence as usize;
+        (self.sample_count + self.pending_silence, self.data.len() + pending_bytes)
+    }
+
+    fn insert_pending_silence(&mut self) {
+        let Some(bytes_per_sample) = self.pcm_bytes_per_sample() else { return };
+        // 8-bit PCM is unsigned
+        let silence_byte = if self.format.is_16_bit || self.format.compression == AudioCompression::Adpcm { 0x00 } else { 0x80 };
+        let pending_bytes = bytes_per_sample * self.pending_silence as usize;
+        self.data.resize(self.data.len() + pending_bytes, silence_byte);
+        self.sample_count += self.pending_silence;
+        self.pending_silence = 0;
+    }
+
+    fn decode_adpcm(&mut self, data: &[u8]) {
+        // a block too short for its header holds no samples
+        let Ok(adpcm_reader) = AdpcmDecoder::new(data, self.format.is_stereo) else { return };
+        if self.is_raw_adpcm() {
+            // only count the samples
+            self.sample_count += adpcm_reader.count() as u64;
+            self.data.extend(data);
+            self.adpcm_packet_lengths.push(data.len());
+            return;
+        }
+        let start = self.data.len();
+        // damaged data still yields the samples before the damage
+        let _ = adpcm_reader.into_pcm_reader().read_to_end(&mut self.data);
+        let bytes_per_sample = if self.format.is_stereo { 4 } else { 2 };
+        self.sample_count += ((self.data.len() - start) / bytes_per_sample) as u64;
+    }
+
     pub fn append_data(&mut self, data: &[u8]) {
-        if let AudioCompression::Adpcm = self.format.compression {
-            // this needs decoding first
-            let adpcm_reader = AdpcmDecoder::new(data, self.format.is_stereo)
-                .expect("failed to create ADPCM reader");
-            for samples in adpcm_reader {
-                self.data.extend(samples[0].to_le_bytes());
-                if self.format.is_stereo {
-                    self.data.extend(samples[1].to_le_bytes());
+        self.insert_pending_silence();
+        match self.format.compression {
+            AudioCompression::Adpcm => {
+                self.decode_adpcm(data);
+            },
+            AudioCompression::Uncompressed|AudioCompression::UncompressedUnknownEndian => {
+                self.data.extend(data);
+                let bytes_per_sample = if self.format.is_16_bit { 2 } else { 1 }
+                    * if self.format.is_stereo { 2 } else { 1 };
+                self.sample_count += (data.len() / bytes_per_sample) as u64;
+            },
+            AudioCompression::Mp3 => {
+                // stream blocks start with the number of samples they contain and the seek samples
+                if data.len() < 4 {
+                    return;
                 }
-            }
-        } else {
-            self.data.extend(data);
+                let mut block_samples = i64::from(u16::from_le_bytes([data[0], data[1]]));
+                let mut frames = &data[4..];
+
+                if self.pending_seek < 0 {
+                    // delay the stream by prepending silent frames
+                    if let (Some(header), Some(silence)) = (FrameHeader::parse(frames), silent_frame(frames)) {
+                        let frame_samples = i64::from(header.samples());
+                        let frame_count = (-self.pending_seek + frame_samples - 1) / frame_samples;
+                        for _ in 0..frame_count {
+                            self.data.extend(&silence);
+                        }
+                        block_samples += frame_count * frame_samples;
+                    }
+                    self.pending_seek = 0;
+                }
+                while self.pending_seek > 0 {
+                    // skip whole frames; a remainder smaller than a frame cannot be skipped
+                    let Some(header) = FrameHeader::parse(frames) else {
+                        self.pending_seek = 0;
+                        break;
+                    };
+                    let frame_samples = i64::from(header.samples());
+                    if self.pending_seek < frame_samples || frames.len() < header.frame_len() {
+                        self.pending_seek = 0;
+                        break;
+                    }
+                    frames = &frames[header.frame_len()..];
+                    self.pending_seek -= frame_samples;
+                    block_samples -= frame_samples;
+                }
+
+                self.sample_count += block_samples.max(0) as u64;
+                self.data.extend(frames);
+            },
+            _ => {
+                self.data.extend(data);
+            },
+        }
+    }
+
+    /// Removes the data of an MP3 sound that is not part of a valid frame, returning the ranges
+    /// that have been removed as pairs of start offset and length.
+    ///
+    /// This is a no-op for sounds in other formats.
+    pub fn resync_mp3(&mut self) -> Vec<(usize, usize)> {
+        if self.format.compression != AudioCompression::Mp3 {
+            return Vec::new();
+        }
+        let (cleaned, removed) = resync(&self.data);
+        self.data = cleaned;
+        removed
+    }
+
+    /// Prepends an Xing or Info frame to an MP3 sound, returning its length.
+    ///
+    /// This is a no-op for sounds in other formats.
+    pub fn prepend_xing_frame(&mut self) -> usize {
+        if self.format.compression != AudioCompression::Mp3 {
+            return 0;
+        }
+        let Some(frame) = xing_frame(&self.data) else { return 0 };
+        self.data.splice(0..0, frame.iter().copied());
+        frame.len()
+    }
+
+    /// Returns the offset of the sound data within the file output by `write`, or `None` if the
+    /// output is compressed or converted in a way that does not allow mapping positions to byte
+    /// offsets.
+    pub fn data_offset(&self, audio_options: &AudioOptions) -> Option<usize> {
+        if self.is_raw_adpcm() {
+            return Some(0);
+        }
+        if self.is_post_processed(audio_options) {
+            return None;
+        }
+        match self.format.compression {
+            AudioCompression::Adpcm|AudioCompression::Uncompressed|AudioCompression::UncompressedUnknownEndian => {
+                match audio_options.format {
+                    AudioFormat::Wav => Some(WAV_HEADER_LEN),
+                    AudioFormat::Flac|AudioFormat::Ogg|AudioFormat::Opus => None,
+                }
+            },
+            _ => Some(0),
         }
     }
 
-    pub fn write<W: Write>(&self, mut writer: W) -> Result<(), std::io::Error> {
+    pub fn write<W: Write>(&self, audio_options: &AudioOptions, mut writer: W) -> Result<(), std::io::Error> {
+        if self.is_raw_adpcm() {
+            return writer.write_all(&self.data);
+        }
         match self.format.compression {
             AudioCompression::Mp3 => {
                 // data already contains all necessary headers
                 writer.write_all(&self.data)
             },
             AudioCompression::Adpcm|AudioCompression::Uncompressed|AudioCompression::UncompressedUnknownEndian => {
-                self.write_wav(writer)
+                let data = self.little_endian_data(audio_options.pcm_endian);
+                if self.is_post_processed(audio_options) {
+                    let processed = self.post_process(&data, audio_options);
+                    processed.write_pcm(&processed.data, audio_options, writer)
+                } else {
+                    self.write_pcm(&data, audio_options, writer)
+                }
             },
             _ => {
                 // we do not yet decode these formats
@@ -52,7 +436,197 @@ impl Sound {
         }
     }
 
-    fn write_wav<W: Write>(&self, mut writer: W) -> Result<(), std::io::Error> {
+    fn write_pcm<W: Write>(&self, data: &[u8], audio_options: &AudioOptions, writer: W) -> Result<(), std::io::Error> {
+        let bit_rate = audio_options.bit_rate.to_string();
+        match audio_options.format {
+            AudioFormat::Wav => self.write_wav(data, writer),
+            AudioFormat::Flac => self.write_flac(data, writer),
+            AudioFormat::Ogg => self.write_encoded(data, "oggenc", &["--quiet", "--bitrate", &bit_rate, "--output", "-", "-"], writer),
+            AudioFormat::Opus => self.write_encoded(data, "opusenc", &["--quiet", "--bitrate", &bit_rate, "-", "-"], writer),
+        }
+    }
+
+    /// Returns the sample rate of the file output by `write`.
+    pub fn output_sample_rate(&self, audio_options: &AudioOptions) -> u16 {
+        if self.pcm_bytes_per_sample().is_some() {
+            audio_options.sample_rate.unwrap_or(self.format.sample_rate)
+        } else {
+            self.format.sample_rate
+        }
+    }
+
+    /// Returns whether the PCM data is downmixed or resampled before being output.
+    fn is_post_processed(&self, audio_options: &AudioOptions) -> bool {
+        self.pcm_bytes_per_sample().is_some() && (
+            (audio_options.mono && self.format.is_stereo)
+            || self.output_sample_rate(audio_options) != self.format.sample_rate
+        )
+    }
+
+    /// Returns whether the PCM data has 16 bits per sample (as opposed to 8).
+    fn is_16_bit_pcm(&self) -> bool {
+        self.format.is_16_bit || self.format.compression == AudioCompression::Adpcm
+    }
+
+    /// Splits the given little-endian PCM data into one list of samples per channel.
+    fn pcm_channels(&self, data: &[u8]) -> Vec<Vec<f64>> {
+        let channel_count = if self.format.is_stereo { 2 } else { 1 };
+        let samples: Vec<f64> = if self.is_16_bit_pcm() {
+            data.chunks_exact(2)
+                .map(|b| f64::from(i16::from_le_bytes([b[0], b[1]])))
+                .collect()
+        } else {
+            // 8-bit PCM is unsigned
+            data.iter()
+                .map(|&b| f64::from(b) - 128.0)
+                .collect()
+        };
+        (0..channel_count)
+            .map(|c| samples.iter().skip(c).step_by(channel_count).copied().collect())
+            .collect()
+    }
+
+    /// Creates an uncompressed sound by interleaving the given channels.
+    fn from_pcm_channels(sample_rate: u16, is_16_bit: bool, channels: &[Vec<f64>]) -> Sound {
+        let format = SoundFormat {
+            compression: AudioCompression::Uncompressed,
+            sample_rate,
+            is_stereo: channels.len() == 2,
+            is_16_bit,
+        };
+        let mut sound = Sound::new_stream(format, false);
+        let frame_count = channels[0].len();
+        for i in 0..frame_count {
+            for channel in channels {
+                let sample = channel[i].round();
+                if is_16_bit {
+                    let value = sample.clamp(i16::MIN.into(), i16::MAX.into()) as i16;
+                    sound.data.extend(value.to_le_bytes());
+                } else {
+                    sound.data.push((sample.clamp(-128.0, 127.0) + 128.0) as u8);
+                }
+            }
+        }
+        sound.sample_count = frame_count as u64;
+        sound
+    }
+
+    /// Downmixes and resamples the given little-endian PCM data, returning the result as a new
+    /// (uncompressed) sound.
+    fn post_process(&self, data: &[u8], audio_options: &AudioOptions) -> Sound {
+        let mut channels = self.pcm_channels(data);
+
+        if audio_options.mono && channels.len() == 2 {
+            let mixed = channels[0].iter().zip(&channels[1])
+                .map(|(l, r)| (l + r) / 2.0)
+                .collect();
+            channels = vec![mixed];
+        }
+
+        let sample_rate = self.output_sample_rate(audio_options);
+        if sample_rate != self.format.sample_rate {
+            for channel in &mut channels {
+                *channel = resample(channel, self.format.sample_rate.into(), sample_rate.into());
+            }
+        }
+
+        let mut processed = Sound::from_pcm_channels(sample_rate, self.is_16_bit_pcm(), &channels);
+        processed.provenance = self.provenance.clone();
+        processed
+    }
+
+    /// Mixes another sound into this one, starting at the given sample, after converting it to the
+    /// sample rate, sample size and channel count of this sound.
+    ///
+    /// Returns `false` and leaves this sound unchanged if either sound is not stored as PCM.
+    pub fn mix_in(&mut self, other: &Sound, sample_offset: u64, pcm_endian: PcmEndian) -> bool {
+        if self.pcm_bytes_per_sample().is_none() || other.pcm_bytes_per_sample().is_none() {
+            return false;
+        }
+
+        let mut channels = self.pcm_channels(&self.little_endian_data(pcm_endian));
+        let mut other_channels = other.pcm_channels(&other.little_endian_data(pcm_endian));
+
+        let is_16_bit = self.is_16_bit_pcm();
+        if is_16_bit != other.is_16_bit_pcm() {
+            let factor = if is_16_bit { 256.0 } else { 1.0 / 256.0 };
+            for sample in other_channels.iter_mut().flatten() {
+                *sample *= factor;
+            }
+        }
+        if channels.len() == 1 && other_channels.len() == 2 {
+            let mixed = other_channels[0].iter().zip(&other_channels[1])
+                .map(|(l, r)| (l + r) / 2.0)
+                .collect();
+            other_channels = vec![mixed];
+        } else if channels.len() == 2 && other_channels.len() == 1 {
+            other_channels.push(other_channels[0].clone());
+        }
+        if other.format.sample_rate != self.format.sample_rate {
+            for channel in &mut other_channels {
+                *channel = resample(channel, other.format.sample_rate.into(), self.format.sample_rate.into());
+            }
+        }
+
+        let offset = usize::try_from(sample_offset).unwrap();
+        for (channel, other_channel) in channels.iter_mut().zip(&other_channels) {
+            let end = offset + other_channel.len();
+            if channel.len() < end {
+                channel.resize(end, 0.0);
+            }
+            for (sample, other_sample) in channel[offset..end].iter_mut().zip(other_channel) {
+                *sample += other_sample;
+            }
+        }
+
+        let mixed = Sound::from_pcm_channels(self.format.sample_rate, is_16_bit, &channels);
+        self.format = mixed.format;
+        self.data = mixed.data;
+        self.sample_count = mixed.sample_count;
+        true
+    }
+
+    /// Describes the format of undecoded ADPCM data, or returns `None` if the sound is not being
+    /// kept as undecoded ADPCM.
+    pub fn raw_adpcm_descriptor(&self) -> Option<JsonValue> {
+        if !self.is_raw_adpcm() {
+            return None;
+        }
+        let mut obj = JsonValue::new_object();
+        obj.insert("compression", "adpcm");
+        obj.insert("sample_rate", self.format.sample_rate);
+        obj.insert("channels", if self.format.is_stereo { 2 } else { 1 });
+        obj.insert("bits_per_sample", if self.format.is_16_bit { 16 } else { 8 });
+        // the first two bits of each packet specify the size of the ADPCM codes
+        let code_bits = self.data.first().map(|b| (b >> 6) + 2);
+        obj.insert("code_bits", code_bits);
+        obj.insert("sample_count", self.sample_count);
+        obj.insert("packet_lengths", self.adpcm_packet_lengths.clone());
+        Some(obj)
+    }
+
+    /// Returns the PCM data in little-endian byte order, swapping the bytes of 16-bit samples of
+    /// unknown endianness if they (are guessed to) have been stored big-endian.
+    fn little_endian_data(&self, pcm_endian: PcmEndian) -> Cow<'_, [u8]> {
+        if self.format.compression != AudioCompression::UncompressedUnknownEndian || !self.format.is_16_bit {
+            return Cow::Borrowed(&self.data);
+        }
+        let is_big_endian = match pcm_endian {
+            PcmEndian::Little => false,
+            PcmEndian::Big => true,
+            PcmEndian::Auto => guess_big_endian(&self.data, if self.format.is_stereo { 2 } else { 1 }),
+        };
+        if !is_big_endian {
+            return Cow::Borrowed(&self.data);
+        }
+        let mut swapped = self.data.clone();
+        for sample in swapped.chunks_exact_mut(2) {
+            sample.swap(0, 1);
+        }
+        Cow::Owned(swapped)
+    }
+
+    fn write_wav<W: Write>(&self, data: &[u8], mut writer: W) -> Result<(), std::io::Error> {
         let sample_rate_bytes = u32::from(self.format.sample_rate).to_le_bytes();
         // sample rate * bytes per sample * channels
         let bytes_per_sec_bytes = (
@@ -61,12 +635,11 @@ impl Sound {
             * if self.format.is_stereo { 2 } else { 1 }
         ).to_le_bytes();
         let sample_alignment_bytes = (
-            1u16
-            * if self.format.is_16_bit { 2 } else { 1 }
+            if self.format.is_16_bit { 2u16 } else { 1 }
             * if self.format.is_stereo { 2 } else { 1 }
         ).to_le_bytes();
         let bits_per_sample_bytes = match self.format.compression {
-            AudioCompression::Uncompressed => {
+            AudioCompression::Uncompressed|AudioCompression::UncompressedUnknownEndian => {
                 if self.format.is_16_bit { 16u16 } else { 8 }
             },
             AudioCompression::Adpcm => 16, // always decodes to signed-16 PCM
@@ -85,6 +658,17 @@ impl Sound {
             bits_per_sample_bytes[0], bits_per_sample_bytes[1], // bits per sample (u16)
         ];
 
+        // metadata chunks follow the data so that the offset of the data remains the same
+        let mut metadata_chunks = Vec::new();
+        if let Some(provenance) = &self.provenance {
+            if !data.len().is_multiple_of(2) {
+                // chunks are aligned to 16 bits
+                metadata_chunks.push(0x00);
+            }
+            metadata_chunks.extend(info_chunk(provenance));
+            metadata_chunks.extend(bext_chunk(provenance));
+        }
+
         let riff_data_len =
             4 // "WAVE" type identifier
             + 4 // "fmt " chunk tag
@@ -92,7 +676,8 @@ impl Sound {
             + fmt_data.len() // "fmt " chunk data
             + 4 // "data" chunk tag
             + 4 // "data" chunk length value
-            + self.data.len() // "data" chunk data
+            + data.len() // "data" chunk data
+            + metadata_chunks.len()
         ;
         let riff_data_len_u32: u32 = riff_data_len.try_into().expect("wave data too long for 32 bits");
 
@@ -103,8 +688,217 @@ impl Sound {
         writer.write_all(&u32::try_from(fmt_data.len()).unwrap().to_le_bytes())?;
         writer.write_all(&fmt_data)?;
         writer.write_all(b"data")?;
-        writer.write_all(&u32::try_from(self.data.len()).unwrap().to_le_bytes())?;
-        writer.write_all(&self.data)?;
+        writer.write_all(&u32::try_from(data.len()).unwrap().to_le_bytes())?;
+        writer.write_all(data)?;
+        writer.write_all(&metadata_chunks)?;
+        Ok(())
+    }
+
+    fn write_flac<W: Write>(&self, data: &[u8], writer: W) -> Result<(), std::io::Error> {
+        let channels = if self.format.is_stereo { 2 } else { 1 };
+        let is_16_bit = self.format.is_16_bit || self.format.compression == AudioCompression::Adpcm;
+        let samples: Vec<i32> = if is_16_bit {
+            data.chunks_exact(2)
+                .map(|b| i32::from(i16::from_le_bytes([b[0], b[1]])))
+                .collect()
+        } else {
+            // 8-bit PCM is unsigned
+            data.iter()
+                .map(|&b| i32::from(b) - 0x80)
+                .collect()
+        };
+        let bits_per_sample = if is_16_bit { 16 } else { 8 };
+        write_flac(writer, self.format.sample_rate.into(), channels, bits_per_sample, &samples)
+    }
+
+    /// Pipes the sound as WAV through an external encoder, writing the encoder's output.
+    fn write_encoded<W: Write>(&self, data: &[u8], program: &str, args: &[&str], mut writer: W) -> Result<(), std::io::Error> {
+        let mut wav = Vec::new();
+        self.write_wav(data, &mut wav)?;
+
+        let mut child = Command::new(program)
+            .args(args)
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .spawn()
+            .map_err(|e| if e.kind() == ErrorKind::NotFound {
+                std::io::Error::new(ErrorKind::NotFound, format!("encoder {:?} not found in PATH", program))
+            } else {
+                e
+            })?;
+
+        // feed the encoder from another thread so that neither pipe fills up
+        let mut stdin = child.stdin.take().unwrap();
+        let feeder = std::thread::spawn(move || stdin.write_all(&wav));
+        let mut stdout = child.stdout.take().unwrap();
+        std::io::copy(&mut stdout, &mut writer)?;
+        feeder.join().expect("encoder feeder panicked")?;
+
+        let status = child.wait()?;
+        if !status.success() {
+            return Err(std::io::Error::other(format!("encoder {:?} failed: {}", program, status)));
+        }
         Ok(())
     }
 }
+
+
+/// Guesses whether 16-bit PCM data is stored big-endian.
+///
+/// Audio signals change little from one sample to the next; reading the data in the wrong byte
+/// order turns the low byte into the most significant one, which makes the signal jump around
+/// wildly. The byte order with the smaller total difference between consecutive samples wins.
+fn guess_big_endian(data: &[u8], channels: usize) -> bool {
+    let mut little_endian_jumps: u64 = 0;
+    let mut big_endian_jumps: u64 = 0;
+    let frames: Vec<&[u8]> = data.chunks_exact(2 * channels).collect();
+    for pair in frames.windows(2) {
+        for channel in 0..channels {
+            let prev = &pair[0][2*channel..2*channel + 2];
+            let cur = &pair[1][2*channel..2*channel + 2];
+            let le_diff = i32::from(i16::from_le_bytes([cur[0], cur[1]])) - i32::from(i16::from_le_bytes([prev[0], prev[1]]));
+            let be_diff = i32::from(i16::from_be_bytes([cur[0], cur[1]])) - i32::from(i16::from_be_bytes([prev[0], prev[1]]));
+            little_endian_jumps += u64::from(le_diff.unsigned_abs());
+            big_endian_jumps += u64::from(be_diff.unsigned_abs());
+        }
+    }
+    big_endian_jumps < little_endian_jumps
+}
+
+
+/// Builds a LIST chunk of type INFO describing the origin of a sound.
+fn info_chunk(provenance: &Provenance) -> Vec<u8> {
+    let (date, _) = utc_date_time(provenance.extraction_time);
+    let comment = match provenance.character_id {
+        Some(id) => format!("extracted from character {} of {}", id, provenance.source),
+        None => format!("extracted from {}", provenance.source),
+    };
+    let fields: [(&[u8; 4], &str); 5] = [
+        (b"INAM", &provenance.title),
+        (b"ISRC", &provenance.source),
+        (b"ICMT", &comment),
+        (b"ICRD", &date),
+        (b"ISFT", "swfextract"),
+    ];
+
+    let mut list_data = Vec::new();
+    list_data.extend(b"INFO");
+    for (id, value) in fields {
+        // zero-terminated, padded to an even length
+        let mut value_bytes = value.as_bytes().to_vec();
+        value_bytes.push(0x00);
+        list_data.extend(id);
+        list_data.extend(u32::try_from(value_bytes.len()).unwrap().to_le_bytes());
+        if !value_bytes.len().is_multiple_of(2) {
+            value_bytes.push(0x00);
+        }
+        list_data.extend(value_bytes);
+    }
+
+    let mut chunk = Vec::with_capacity(8 + list_data.len());
+    chunk.extend(b"LIST");
+    chunk.extend(u32::try_from(list_data.len()).unwrap().to_le_bytes());
+    chunk.extend(list_data);
+    chunk
+}
+
+
+/// Builds a Broadcast Wave Format extension chunk (version 1) describing the origin of a sound.
+fn bext_chunk(provenance: &Provenance) -> Vec<u8> {
+    fn fixed_field(value: &str, len: usize) -> Vec<u8> {
+        let mut bytes: Vec<u8> = value.bytes().take(len).collect();
+        bytes.resize(len, 0x00);
+        bytes
+    }
+
+    let (date, time) = utc_date_time(provenance.extraction_time);
+    let reference = match provenance.character_id {
+        Some(id) => format!("{}#{}", provenance.source, id),
+        None => provenance.source.clone(),
+    };
+
+    let mut data = Vec::new();
+    data.extend(fixed_field(&provenance.title, 256)); // description
+    data.extend(fixed_field("swfextract", 32)); // originator
+    data.extend(fixed_field(&reference, 32)); // originator reference
+    data.extend(fixed_field(&date, 10)); // origination date
+    data.extend(fixed_field(&time, 8)); // origination time
+    data.extend(0u64.to_le_bytes()); // time reference
+    data.extend(1u16.to_le_bytes()); // version
+    data.extend([0x00; 64]); // UMID
+    data.extend([0x00; 10]); // loudness values (unknown)
+    data.extend([0x00; 180]); // reserved
+
+    let mut chunk = Vec::with_capacity(8 + data.len());
+    chunk.extend(b"bext");
+    chunk.extend(u32::try_from(data.len()).unwrap().to_le_bytes());
+    chunk.extend(data);
+    chunk
+}
+
+
+/// Formats a point in time as a UTC date (`YYYY-MM-DD`) and time (`HH:MM:SS`).
+pub(crate) fn utc_date_time(time: SystemTime) -> (String, String) {
+    let seconds = time.duration_since(UNIX_EPOCH).map(|d| d.as_secs()).unwrap_or(0);
+    let days = (seconds / 86400) as i64;
+    let second_of_day = seconds % 86400;
+
+    // convert days since 1970-01-01 to a civil date (proleptic Gregorian calendar)
+    let z = days + 719468;
+    let era = z.div_euclid(146097);
+    let day_of_era = z.rem_euclid(146097);
+    let year_of_era = (day_of_era - day_of_era / 1460 + day_of_era / 36524 - day_of_era / 146096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let shifted_month = (5 * day_of_year + 2) / 153;
+    let day = day_of_year - (153 * shifted_month + 2) / 5 + 1;
+    let month = if shifted_month < 10 { shifted_month + 3 } else { shifted_month - 9 };
+    let year = year_of_era + era * 400 + if month <= 2 { 1 } else { 0 };
+
+    (
+        format!("{:04}-{:02}-{:02}", year, month, day),
+        format!("{:02}:{:02}:{:02}", second_of_day / 3600, (second_of_day / 60) % 60, second_of_day % 60),
+    )
+}
+
+
+/// Resamples a single channel using windowed-sinc interpolation.
+fn resample(input: &[f64], from_rate: u32, to_rate: u32) -> Vec<f64> {
+    // number of zero crossings of the sinc function on either side of each output sample
+    const ZERO_CROSSINGS: f64 = 16.0;
+
+    if input.is_empty() {
+        return Vec::new();
+    }
+
+    // when downsampling, the cutoff frequency must be lowered to avoid aliasing
+    let step = f64::from(from_rate) / f64::from(to_rate);
+    let cutoff = (1.0 / step).min(1.0);
+    let half_width = ZERO_CROSSINGS / cutoff;
+
+    let output_len = (input.len() as u64 * u64::from(to_rate)).div_ceil(u64::from(from_rate));
+    let mut output = Vec::with_capacity(output_len as usize);
+    for n in 0..output_len {
+        let t = n as f64 * step;
+        let first = (t - half_width).ceil().max(0.0) as usize;
+        let last = ((t + half_width).floor() as usize).min(input.len() - 1);
+        let mut value = 0.0;
+        for (k, sample) in input.iter().enumerate().take(last + 1).skip(first) {
+            let x = t - k as f64;
+            // Hann window
+            let window = 0.5 + 0.5 * (std::f64::consts::PI * x / half_width).cos();
+            value += sample * cutoff * sinc(cutoff * x) * window;
+        }
+        output.push(value);
+    }
+    output
+}
+
+
+fn sinc(x: f64) -> f64 {
+    if x == 0.0 {
+        1.0
+    } else {
+        let pi_x = std::f64::consts::PI * x;
+        pi_x.sin() / pi_x
+    }
+}
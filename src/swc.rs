@@ -0,0 +1,60 @@
+use std::collections::HashMap;
+
+use sxd_document::dom::Element;
+
+
+/// The path of the catalog within a SWC file.
+pub(crate) const CATALOG_PATH: &str = "catalog.xml";
+
+
+/// Returns the child elements of the element with the given (local) name.
+fn child_elements<'d>(element: Element<'d>, name: &'static str) -> impl Iterator<Item = Element<'d>> {
+    element.children()
+        .into_iter()
+        .filter_map(|child| child.element())
+        .filter(move |child| child.name().local_part() == name)
+}
+
+
+/// Turns a class name as written in the catalog (`package:Class`) into a fully qualified class name
+/// as used by SymbolClass (`package.Class`).
+fn qualified_class_name(name: &str) -> String {
+    name.replace(':', ".")
+}
+
+
+/// The catalog (`catalog.xml`) of a SWC component library, which is a ZIP archive of the catalog
+/// and one or more SWF files.
+#[derive(Clone, Debug, Default)]
+pub(crate) struct SwcCatalog {
+    /// The paths of the SWF files within the SWC file, usually just `library.swf`.
+    pub libraries: Vec<String>,
+
+    /// The names under which components are shown to authors, by fully qualified class name.
+    pub component_names: HashMap<String, String>,
+}
+impl SwcCatalog {
+    pub fn parse(xml: &str) -> Result<Self, sxd_document::parser::Error> {
+        let package = sxd_document::parser::parse(xml)?;
+        let document = package.as_document();
+        let mut catalog = Self::default();
+        let Some(swc) = document.root().children().into_iter().find_map(|child| child.element()) else {
+            return Ok(catalog);
+        };
+
+        for library in child_elements(swc, "libraries").flat_map(|libs| child_elements(libs, "library")) {
+            if let Some(path) = library.attribute_value("path") {
+                catalog.libraries.push(path.to_owned());
+            }
+        }
+
+        for component in child_elements(swc, "components").flat_map(|comps| child_elements(comps, "component")) {
+            let (Some(class_name), Some(name)) = (component.attribute_value("className"), component.attribute_value("name")) else {
+                continue;
+            };
+            catalog.component_names.insert(qualified_class_name(class_name), name.to_owned());
+        }
+
+        Ok(catalog)
+    }
+}
@@ -0,0 +1,198 @@
+use std::fmt::Write;
+
+use swf::{CharacterId, Fixed8};
+
+use crate::json::JsonValue;
+
+
+/// A position within the stream sound.
+#[derive(Clone, Copy, Debug, Default)]
+pub(crate) struct StreamPosition {
+    /// The number of samples (per channel) output before this position.
+    pub sample_offset: u64,
+
+    /// The number of bytes of sound data output before this position.
+    pub byte_offset: usize,
+}
+
+
+/// Tracks the timing of a stream sound and the video frames on a timeline.
+#[derive(Clone, Debug, Default)]
+pub(crate) struct TimelineSync {
+    /// The frame at which the stream sound starts.
+    first_frame: u32,
+
+    /// The stream position at the end of each frame, starting with the first frame.
+    frame_end_positions: Vec<StreamPosition>,
+
+    /// The video frames shown on this timeline as (movie frame, stream ID, video frame number).
+    video_frames: Vec<(u32, CharacterId, u16)>,
+
+    /// The labels of the frames on this timeline.
+    labels: Vec<(u32, String)>,
+}
+impl TimelineSync {
+    pub fn new(first_frame: u32) -> Self {
+        Self {
+            first_frame,
+            ..Self::default()
+        }
+    }
+
+    pub fn first_frame(&self) -> u32 {
+        self.first_frame
+    }
+
+    /// Records the stream position at the end of the current frame.
+    pub fn end_frame(&mut self, position: StreamPosition) {
+        self.frame_end_positions.push(position);
+    }
+
+    /// Returns the number of samples of the stream sound output before the given frame, or `None`
+    /// if the stream does not cover the frame.
+    pub fn frame_start_sample(&self, frame: u32) -> Option<u64> {
+        if frame == self.first_frame {
+            return Some(0);
+        }
+        let index = frame.checked_sub(self.first_frame + 1)?;
+        self.frame_end_positions.get(usize::try_from(index).unwrap())
+            .map(|position| position.sample_offset)
+    }
+
+    pub fn add_video_frame(&mut self, frame: u32, stream_id: CharacterId, frame_num: u16) {
+        self.video_frames.push((frame, stream_id, frame_num));
+    }
+
+    pub fn add_label(&mut self, frame: u32, label: String) {
+        self.labels.push((frame, label));
+    }
+
+    pub fn has_video(&self) -> bool {
+        !self.video_frames.is_empty()
+    }
+
+    /// Adjusts the byte offsets after ranges (pairs of start offset and length, in ascending order)
+    /// have been removed from the stream sound data.
+    pub fn remove_byte_ranges(&mut self, removed: &[(usize, usize)]) {
+        for position in &mut self.frame_end_positions {
+            let removed_before: usize = removed.iter()
+                .take_while(|(start, _)| *start < position.byte_offset)
+                .map(|(start, len)| (*len).min(position.byte_offset - start))
+                .sum();
+            position.byte_offset -= removed_before;
+        }
+    }
+
+    /// Converts the sample offsets to those of the stream sound resampled to another rate.
+    ///
+    /// Byte offsets are left alone and become meaningless.
+    pub fn resample(&mut self, from_rate: u16, to_rate: u16) {
+        for position in &mut self.frame_end_positions {
+            position.sample_offset = position.sample_offset * u64::from(to_rate) / u64::from(from_rate.max(1));
+        }
+    }
+
+    /// Outputs the mapping of each frame to its time, the offset of the stream sound and the video
+    /// frames shown.
+    pub fn to_json(&self, frame_rate: Fixed8, audio_file: &str, sample_rate: u16) -> JsonValue {
+        let frame_rate = frame_rate.to_f64();
+
+        let mut frames = JsonValue::new_array();
+        let mut position = StreamPosition::default();
+        for (i, end_position) in self.frame_end_positions.iter().enumerate() {
+            let frame = self.first_frame + u32::try_from(i).unwrap();
+            let mut frame_obj = JsonValue::new_object();
+            frame_obj.insert("frame", frame);
+            frame_obj.insert("time", if frame_rate > 0.0 { f64::from(frame - 1) / frame_rate } else { 0.0 });
+            frame_obj.insert("audio_sample", position.sample_offset);
+            let mut video = JsonValue::new_array();
+            for (_, stream_id, frame_num) in self.video_frames.iter().filter(|(f, _, _)| *f == frame) {
+                let mut video_obj = JsonValue::new_object();
+                video_obj.insert("stream", *stream_id);
+                video_obj.insert("frame_num", *frame_num);
+                video.push(video_obj);
+            }
+            frame_obj.insert("video", video);
+            frames.push(frame_obj);
+            position = *end_position;
+        }
+
+        let mut obj = JsonValue::new_object();
+        obj.insert("frame_rate", frame_rate);
+        obj.insert("audio", audio_file);
+        obj.insert("sample_rate", sample_rate);
+        obj.insert("frames", frames);
+        obj
+    }
+
+    /// Returns the stream position at the start of each frame.
+    fn frame_start_positions(&self) -> impl Iterator<Item = (u32, StreamPosition)> + '_ {
+        std::iter::once(StreamPosition::default())
+            .chain(self.frame_end_positions.iter().copied())
+            .take(self.frame_end_positions.len())
+            .enumerate()
+            .map(|(i, position)| (self.first_frame + u32::try_from(i).unwrap(), position))
+    }
+
+    /// Outputs the offsets within the extracted stream file at which each frame starts.
+    ///
+    /// `data_offset` is the offset of the sound data within the extracted file; if it is `None`,
+    /// the byte offsets are output as null.
+    pub fn to_cue_json(&self, sample_rate: u16, data_offset: Option<usize>) -> JsonValue {
+        let mut frames = JsonValue::new_array();
+        for (frame, position) in self.frame_start_positions() {
+            let mut frame_obj = JsonValue::new_object();
+            frame_obj.insert("frame", frame);
+            let labels: Vec<&str> = self.labels.iter()
+                .filter(|(f, _)| *f == frame)
+                .map(|(_, l)| l.as_str())
+                .collect();
+            frame_obj.insert("labels", labels);
+            frame_obj.insert("sample_offset", position.sample_offset);
+            frame_obj.insert("byte_offset", data_offset.map(|o| o + position.byte_offset));
+            frame_obj.insert("time", position.sample_offset as f64 / f64::from(sample_rate));
+            frames.push(frame_obj);
+        }
+
+        let mut obj = JsonValue::new_object();
+        obj.insert("sample_rate", sample_rate);
+        obj.insert("frames", frames);
+        obj
+    }
+
+    /// Outputs a cue sheet with one track per frame label, or `None` if there are no labels.
+    pub fn to_cue_sheet(&self, audio_file: &str, file_type: &str, sample_rate: u16) -> Option<String> {
+        if self.labels.is_empty() {
+            return None;
+        }
+
+        let mut tracks: Vec<(&str, u64)> = Vec::new();
+        for (frame, label) in &self.labels {
+            let position = self.frame_start_positions()
+                .find(|(f, _)| f == frame)
+                .map(|(_, p)| p)
+                .unwrap_or_default();
+            tracks.push((label.as_str(), position.sample_offset));
+        }
+        if tracks[0].1 > 0 {
+            // make sure the beginning of the stream is covered
+            tracks.insert(0, ("", 0));
+        }
+
+        let mut cue = String::new();
+        writeln!(cue, "FILE \"{}\" {}", audio_file, file_type).unwrap();
+        for (i, (title, sample_offset)) in tracks.iter().enumerate() {
+            // cue sheet times are given in minutes, seconds and frames of 1/75 seconds
+            let cd_frames = sample_offset * 75 / u64::from(sample_rate.max(1));
+            writeln!(cue, "  TRACK {:02} AUDIO", i + 1).unwrap();
+            if !title.is_empty() {
+                writeln!(cue, "    TITLE \"{}\"", title.replace('"', "'")).unwrap();
+            }
+            writeln!(
+                cue, "    INDEX 01 {:02}:{:02}:{:02}",
+                cd_frames / (75 * 60), (cd_frames / 75) % 60, cd_frames % 75,
+            ).unwrap();
+        }
+        Some(cue)
+    }
+}
@@ -0,0 +1,185 @@
+use std::collections::HashMap;
+use std::fs::{create_dir_all, File};
+use std::io::{self, Write};
+use std::path::Path;
+
+use swf::{CharacterId, TagCode};
+
+use crate::json::JsonValue;
+
+
+/// A tag as stored in the SWF file, before any decoding.
+#[derive(Clone, Copy, Debug)]
+pub(crate) struct RawTag<'a> {
+    pub code: u16,
+
+    /// The offset of the tag header within the decompressed SWF file.
+    pub offset: usize,
+
+    /// The length of the tag header (2 bytes, or 6 bytes for the long form).
+    pub header_length: usize,
+
+    pub body: &'a [u8],
+}
+impl<'a> RawTag<'a> {
+    /// The name of the tag type, or `Unknown` followed by the tag code if the type is not known.
+    pub fn name(&self) -> String {
+        match TagCode::from_u16(self.code) {
+            Some(code) => format!("{:?}", code),
+            None => match legacy_tag_name(self.code) {
+                Some(name) => name.to_owned(),
+                None => format!("Unknown{}", self.code),
+            },
+        }
+    }
+
+    /// Whether the tag defines a new character, whose ID is then stored in the first two bytes of
+    /// the body.
+    pub fn defines_character(&self) -> bool {
+        let Some(code) = TagCode::from_u16(self.code) else { return false };
+        matches!(
+            code,
+            TagCode::DefineBinaryData
+                |TagCode::DefineBits|TagCode::DefineBitsJpeg2|TagCode::DefineBitsJpeg3|TagCode::DefineBitsJpeg4
+                |TagCode::DefineBitsLossless|TagCode::DefineBitsLossless2
+                |TagCode::DefineButton|TagCode::DefineButton2
+                |TagCode::DefineEditText
+                |TagCode::DefineFont|TagCode::DefineFont2|TagCode::DefineFont3|TagCode::DefineFont4
+                |TagCode::DefineMorphShape|TagCode::DefineMorphShape2
+                |TagCode::DefineShape|TagCode::DefineShape2|TagCode::DefineShape3|TagCode::DefineShape4
+                |TagCode::DefineSound
+                |TagCode::DefineSprite
+                |TagCode::DefineText|TagCode::DefineText2
+                |TagCode::DefineVideoStream
+        )
+    }
+}
+
+
+/// Returns the name of a tag type that is not decoded by the swf crate because it is obsolete or was
+/// only used by authoring tools, or `None` if the tag code is not known at all.
+pub(crate) fn legacy_tag_name(code: u16) -> Option<&'static str> {
+    let name = match code {
+        3 => "FreeCharacter",
+        16 => "StopSound",
+        25 => "PathsArePostScript",
+        29 => "SyncFrame",
+        31 => "FreeAll",
+        38 => "DefineVideo",
+        42 => "DefineTextFormat",
+        44 => "DefineBehavior",
+        47 => "FrameTag",
+        49 => "GenCommand",
+        50 => "DefineCommandObject",
+        51 => "CharacterSet",
+        52 => "ExternalFont",
+        1023 => "DefineBitsPtr",
+        _ => return None,
+    };
+    Some(name)
+}
+
+
+/// Splits the top-level tag stream of a movie into its tags.
+///
+/// `stream_offset` is the offset of the tag stream within the decompressed SWF file and is added to
+/// the offsets of the tags. A truncated final tag is returned with as much of its body as is
+/// available; reading stops after the End tag.
+pub(crate) fn read_raw_tags(data: &[u8], stream_offset: usize) -> Vec<RawTag<'_>> {
+    let mut tags = Vec::new();
+    let mut pos = 0;
+    while pos + 2 <= data.len() {
+        let code_and_length = u16::from_le_bytes([data[pos], data[pos + 1]]);
+        let code = code_and_length >> 6;
+        let mut length = usize::from(code_and_length & 0x3F);
+        let mut header_length = 2;
+        if length == 0x3F {
+            if pos + 6 > data.len() {
+                break;
+            }
+            let long_length = u32::from_le_bytes(data[pos+2..pos+6].try_into().unwrap());
+            length = usize::try_from(long_length).unwrap();
+            header_length = 6;
+        }
+
+        let body_start = pos + header_length;
+        let body_end = body_start.saturating_add(length).min(data.len());
+        tags.push(RawTag {
+            code,
+            offset: stream_offset + pos,
+            header_length,
+            body: &data[body_start..body_end],
+        });
+
+        if code == 0 {
+            break;
+        }
+        pos = body_end;
+    }
+    tags
+}
+
+
+/// Writes the body of each tag to `NNNN_<name>.bin` in the given directory, along with an
+/// `index.json` listing the offsets and lengths of the tags.
+pub(crate) fn dump_raw_tags(tags: &[RawTag], directory: &Path) -> io::Result<()> {
+    create_dir_all(directory)?;
+
+    let mut index = JsonValue::new_array();
+    for (i, tag) in tags.iter().enumerate() {
+        let file_name = format!("{:04}_{}.bin", i, tag.name());
+        let mut f = File::create(directory.join(&file_name))?;
+        f.write_all(tag.body)?;
+
+        let mut entry = JsonValue::new_object();
+        entry.insert("index", i);
+        entry.insert("code", tag.code);
+        entry.insert("name", tag.name());
+        entry.insert("offset", tag.offset);
+        entry.insert("header_length", tag.header_length);
+        entry.insert("length", tag.body.len());
+        entry.insert("file", file_name);
+        index.push(entry);
+    }
+
+    let f = File::create(directory.join("index.json"))?;
+    index.write(f)
+}
+
+
+/// The position of a tag within the decompressed SWF file.
+#[derive(Clone, Debug)]
+pub(crate) struct TagLocation {
+    pub name: String,
+    pub offset: usize,
+    pub header_length: usize,
+
+    /// The length of the tag body.
+    pub length: usize,
+}
+impl TagLocation {
+    pub fn to_json(&self) -> JsonValue {
+        let mut obj = JsonValue::new_object();
+        obj.insert("tag", self.name.as_str());
+        obj.insert("offset", self.offset);
+        obj.insert("header_length", self.header_length);
+        obj.insert("length", self.length);
+        obj
+    }
+}
+
+
+/// Collects the locations of the tags defining each character.
+pub(crate) fn character_tag_locations(tags: &[RawTag]) -> HashMap<CharacterId, TagLocation> {
+    let mut locations = HashMap::new();
+    for tag in tags.iter().filter(|t| t.defines_character() && t.body.len() >= 2) {
+        let id = u16::from_le_bytes([tag.body[0], tag.body[1]]);
+        locations.insert(id, TagLocation {
+            name: tag.name(),
+            offset: tag.offset,
+            header_length: tag.header_length,
+            length: tag.body.len(),
+        });
+    }
+    locations
+}
@@ -0,0 +1,406 @@
+use std::collections::HashMap;
+use std::fmt::Write;
+use std::ops::Range;
+
+use encoding_rs::Encoding;
+use swf::{CharacterId, Color, CsmTextSettings, EditText, Matrix, Rectangle, Text, TextAlign, TextGridFit, Twips};
+
+use crate::encoding::decode_string;
+use crate::font::FontOutlines;
+use crate::json::JsonValue;
+
+
+/// Converts an HTML-formatted DefineEditText field to a standalone HTML document.
+///
+/// The field's markup is output verbatim; the properties of the field itself (font, size, color,
+/// alignment, bounds) are reproduced as CSS.
+pub(crate) fn edit_text_to_html(et: &EditText, font_name: Option<&str>, encoding: &'static Encoding) -> String {
+    let mut css = String::new();
+    writeln!(css, "#edittext{} {{", et.id).unwrap();
+    writeln!(css, "  position: absolute;").unwrap();
+    writeln!(css, "  left: {}px;", et.bounds.x_min.to_pixels()).unwrap();
+    writeln!(css, "  top: {}px;", et.bounds.y_min.to_pixels()).unwrap();
+    writeln!(css, "  width: {}px;", (et.bounds.x_max - et.bounds.x_min).to_pixels()).unwrap();
+    writeln!(css, "  height: {}px;", (et.bounds.y_max - et.bounds.y_min).to_pixels()).unwrap();
+    writeln!(css, "  overflow: hidden;").unwrap();
+    let font_class_name = et.font_class_name
+        .map(|n| decode_string(n, encoding));
+    if let Some(name) = font_name.or(font_class_name.as_deref()) {
+        writeln!(css, "  font-family: \"{}\";", name.replace('\\', "\\\\").replace('"', "\\\"")).unwrap();
+    }
+    if let Some(height) = et.height {
+        writeln!(css, "  font-size: {}px;", height.to_pixels()).unwrap();
+    }
+    if let Some(color) = &et.color {
+        writeln!(
+            css, "  color: rgba({},{},{},{});",
+            color.r, color.g, color.b, (color.a as f64) / 255.0,
+        ).unwrap();
+    }
+    if let Some(layout) = &et.layout {
+        let align = match layout.align {
+            TextAlign::Left => "left",
+            TextAlign::Right => "right",
+            TextAlign::Center => "center",
+            TextAlign::Justify => "justify",
+        };
+        writeln!(css, "  text-align: {};", align).unwrap();
+        writeln!(css, "  padding-left: {}px;", layout.left_margin.to_pixels()).unwrap();
+        writeln!(css, "  padding-right: {}px;", layout.right_margin.to_pixels()).unwrap();
+        writeln!(css, "  text-indent: {}px;", layout.indent.to_pixels()).unwrap();
+        if let Some(height) = et.height {
+            writeln!(css, "  line-height: {}px;", (height + layout.leading).to_pixels()).unwrap();
+        }
+    }
+    if !et.is_word_wrap {
+        writeln!(css, "  white-space: nowrap;").unwrap();
+    }
+    if et.has_border {
+        writeln!(css, "  border: 1px solid black;").unwrap();
+    }
+    writeln!(css, "}}").unwrap();
+    // Flash does not add margins around paragraphs
+    writeln!(css, "#edittext{} p {{ margin: 0; }}", et.id).unwrap();
+
+    let markup = et.initial_text
+        .map(|t| decode_string(t, encoding))
+        .unwrap_or_default();
+
+    let mut html = String::new();
+    writeln!(html, "<!DOCTYPE html>").unwrap();
+    writeln!(html, "<html>").unwrap();
+    writeln!(html, "<head>").unwrap();
+    writeln!(html, "<meta charset=\"utf-8\">").unwrap();
+    writeln!(html, "<style>").unwrap();
+    write!(html, "{}", css).unwrap();
+    writeln!(html, "</style>").unwrap();
+    writeln!(html, "</head>").unwrap();
+    writeln!(html, "<body>").unwrap();
+    writeln!(html, "<div id=\"edittext{}\">{}</div>", et.id, markup).unwrap();
+    writeln!(html, "</body>").unwrap();
+    writeln!(html, "</html>").unwrap();
+    html
+}
+
+
+/// Reconstructs the string displayed by a DefineText tag using the code tables of its fonts.
+///
+/// Glyphs whose font or code is unknown are replaced by U+FFFD. A new line is started whenever a
+/// text record moves the pen vertically.
+pub(crate) fn static_text_to_string(text: &Text, code_tables: &HashMap<CharacterId, Vec<u16>>) -> String {
+    let mut ret = String::new();
+    let mut current_font = None;
+    let mut current_y = None;
+    for record in &text.records {
+        if record.font_id.is_some() {
+            current_font = record.font_id;
+        }
+        if let Some(y) = record.y_offset {
+            if current_y.is_some() && current_y != Some(y) {
+                ret.push('\n');
+            }
+            current_y = Some(y);
+        }
+
+        let code_table = current_font.and_then(|f| code_tables.get(&f));
+        let codes: Vec<u16> = record.glyphs.iter()
+            .map(|g| code_table
+                .and_then(|ct| ct.get(g.index as usize))
+                .copied()
+                .unwrap_or(0xFFFD)
+            )
+            .collect();
+        ret.extend(char::decode_utf16(codes).map(|c| c.unwrap_or(char::REPLACEMENT_CHARACTER)));
+    }
+    ret
+}
+
+/// A glyph of a static text, positioned in the coordinate space of the text.
+#[derive(Clone, Debug)]
+pub(crate) struct PositionedGlyph {
+    pub font_id: CharacterId,
+    pub index: usize,
+
+    /// The position of the glyph's origin on the baseline.
+    pub x: Twips,
+    pub y: Twips,
+
+    /// The height of the EM square.
+    pub height: Twips,
+
+    pub color: Color,
+}
+
+
+/// A text laid out for drawing.
+#[derive(Clone, Debug)]
+pub(crate) struct TextDrawing {
+    pub bounds: Rectangle,
+
+    /// The transformation from the coordinate space of the glyphs to that of the character.
+    pub matrix: Matrix,
+
+    pub glyphs: Vec<PositionedGlyph>,
+}
+
+
+/// Lays out the glyphs of a DefineText tag as Flash Player does.
+///
+/// Each text record may move the pen to a new position; each glyph then moves it on by the advance
+/// stored with it, which already includes the kerning applied by the authoring tool. Glyphs stored
+/// without an advance are advanced by the font's own advance, adjusted by its kerning pair with the
+/// following glyph, rather than drawn on top of each other. Glyphs of unknown fonts are skipped but
+/// still advance the pen.
+pub(crate) fn layout_static_text(text: &Text, fonts: &HashMap<CharacterId, FontOutlines>) -> Vec<PositionedGlyph> {
+    let mut glyphs = Vec::new();
+    let mut font_id = None;
+    let mut color = Color { r: 0, g: 0, b: 0, a: 255 };
+    let mut height = Twips::ZERO;
+    let (mut x, mut y) = (Twips::ZERO, Twips::ZERO);
+    for record in &text.records {
+        font_id = record.font_id.or(font_id);
+        color = record.color.clone().unwrap_or(color);
+        height = record.height.unwrap_or(height);
+        x = record.x_offset.unwrap_or(x);
+        y = record.y_offset.unwrap_or(y);
+
+        let font = font_id.and_then(|id| fonts.get(&id).map(|font| (id, font)));
+        for (i, entry) in record.glyphs.iter().enumerate() {
+            let index = entry.index as usize;
+            if let Some((id, _)) = font {
+                glyphs.push(PositionedGlyph {
+                    font_id: id,
+                    index,
+                    x,
+                    y,
+                    height,
+                    color: color.clone(),
+                });
+            }
+
+            let mut advance = entry.advance;
+            if let (0, Some((_, font))) = (advance, font) {
+                let next = record.glyphs.get(i + 1).map(|next| next.index as usize);
+                if let Some(font_advance) = font.advance(index, next) {
+                    let scaled = i64::from(font_advance) * i64::from(height.get()) / font.em_size;
+                    advance = i32::try_from(scaled).unwrap_or(0);
+                }
+            }
+            x += Twips::new(advance);
+        }
+    }
+    glyphs
+}
+
+
+/// A character of an edit field being laid out.
+struct LaidOutChar {
+    glyph: Option<usize>,
+    advance: i32,
+    is_space: bool,
+}
+
+
+/// Lays out the text of a DefineEditText field in the given font, breaking it into lines at line
+/// breaks and, if the field wraps words, before words that would run past its edge. Justified text
+/// is aligned to the left; characters for which the font has no glyph are left out.
+pub(crate) fn layout_edit_text(et: &EditText, text: &str, font_id: CharacterId, font: &mut FontOutlines) -> Vec<PositionedGlyph> {
+    // Flash Player keeps text two pixels away from the edges of a field
+    const GUTTER: i32 = 40;
+
+    // 12 points unless given
+    let height = et.height.unwrap_or(Twips::new(240));
+    let color = et.color.clone().unwrap_or(Color { r: 0, g: 0, b: 0, a: 255 });
+    let em_size = font.em_size;
+    let scale = |units: i32| i32::try_from(i64::from(units) * i64::from(height.get()) / em_size).unwrap_or(0);
+    let ascent = font.ascent.map_or(height.get() * 4 / 5, scale);
+    let descent = font.descent.map_or(height.get() / 5, scale);
+    let (align, left_margin, right_margin, indent, leading) = match &et.layout {
+        Some(layout) => (layout.align, layout.left_margin.get(), layout.right_margin.get(), layout.indent.get(), layout.leading.get()),
+        None => (TextAlign::Left, 0, 0, 0, 0),
+    };
+    let left = et.bounds.x_min.get() + GUTTER + left_margin;
+    let right = et.bounds.x_max.get() - GUTTER - right_margin;
+
+    let text = text.replace("\r\n", "\n").replace('\r', "\n");
+    let mut glyphs = Vec::new();
+    let mut y = et.bounds.y_min.get() + GUTTER + ascent;
+    for paragraph in text.split('\n') {
+        let indices: Vec<Option<usize>> = paragraph.chars()
+            .map(|c| font.glyph_for_char(if et.is_password { '*' } else { c }))
+            .collect();
+        let chars: Vec<LaidOutChar> = paragraph.chars().enumerate()
+            .map(|(i, c)| {
+                let next = indices.get(i + 1).copied().flatten();
+                LaidOutChar {
+                    glyph: indices[i],
+                    advance: indices[i].and_then(|index| font.advance(index, next)).map_or(0, scale),
+                    is_space: c == ' ',
+                }
+            })
+            .collect();
+
+        let mut lines: Vec<Range<usize>> = Vec::new();
+        let (mut line_start, mut width, mut last_space) = (0, 0, None);
+        let mut i = 0;
+        while i < chars.len() {
+            let available = right - left - if lines.is_empty() { indent } else { 0 };
+            if et.is_word_wrap && i > line_start && !chars[i].is_space && width + chars[i].advance > available {
+                // break after the last space, or within the word if it does not fit on a line at all
+                let end = last_space.unwrap_or(i);
+                lines.push(line_start..end);
+                line_start = if last_space.is_some() { end + 1 } else { end };
+                (i, width, last_space) = (line_start, 0, None);
+                continue;
+            }
+            if chars[i].is_space {
+                last_space = Some(i);
+            }
+            width += chars[i].advance;
+            i += 1;
+        }
+        lines.push(line_start..chars.len());
+
+        for (line_index, line) in lines.into_iter().enumerate() {
+            let line_chars = &chars[line];
+            let width: i32 = line_chars.iter().map(|c| c.advance).sum();
+            let mut x = match align {
+                TextAlign::Left|TextAlign::Justify => left + if line_index == 0 { indent } else { 0 },
+                TextAlign::Right => right - width,
+                TextAlign::Center => left + (right - left - width) / 2,
+            };
+            for c in line_chars {
+                if let Some(index) = c.glyph {
+                    glyphs.push(PositionedGlyph {
+                        font_id,
+                        index,
+                        x: Twips::new(x),
+                        y: Twips::new(y),
+                        height,
+                        color: color.clone(),
+                    });
+                }
+                x += c.advance;
+            }
+            y += ascent + descent + leading;
+        }
+    }
+    glyphs
+}
+
+
+/// Strips the markup from Flash HTML text, retaining line breaks.
+pub(crate) fn html_to_plain_text(html: &str) -> String {
+    let mut ret = String::new();
+    let mut tag: Option<String> = None;
+    let mut entity: Option<String> = None;
+    for c in html.chars() {
+        if let Some(t) = &mut tag {
+            if c == '>' {
+                let tag_name = t.trim_start_matches('/')
+                    .split(|c: char| c.is_whitespace() || c == '/')
+                    .next()
+                    .unwrap_or("")
+                    .to_ascii_lowercase();
+                let is_closing = t.starts_with('/');
+                if tag_name == "br" || (is_closing && (tag_name == "p" || tag_name == "li")) {
+                    ret.push('\n');
+                }
+                tag = None;
+            } else {
+                t.push(c);
+            }
+        } else if let Some(e) = &mut entity {
+            if c == ';' {
+                match e.as_str() {
+                    "amp" => ret.push('&'),
+                    "lt" => ret.push('<'),
+                    "gt" => ret.push('>'),
+                    "quot" => ret.push('"'),
+                    "apos" => ret.push('\''),
+                    "nbsp" => ret.push('\u{A0}'),
+                    other => {
+                        let decoded = other.strip_prefix("#x")
+                            .and_then(|hex| u32::from_str_radix(hex, 16).ok())
+                            .or_else(|| other.strip_prefix('#').and_then(|dec| dec.parse().ok()))
+                            .and_then(char::from_u32);
+                        match decoded {
+                            Some(d) => ret.push(d),
+                            None => {
+                                // not an entity we know; output verbatim
+                                ret.push('&');
+                                ret.push_str(other);
+                                ret.push(';');
+                            },
+                        }
+                    },
+                }
+                entity = None;
+            } else {
+                e.push(c);
+            }
+        } else if c == '<' {
+            tag = Some(String::new());
+        } else if c == '&' {
+            entity = Some(String::new());
+        } else {
+            ret.push(c);
+        }
+    }
+    if let Some(e) = entity {
+        ret.push('&');
+        ret.push_str(&e);
+    }
+    ret.trim_end_matches('\n').to_owned()
+}
+
+/// Returns the text initially shown by an edit field, without any markup.
+pub(crate) fn edit_text_to_string(et: &EditText, encoding: &'static Encoding) -> Option<String> {
+    let text = decode_string(et.initial_text?, encoding);
+    if et.is_html {
+        Some(html_to_plain_text(&text))
+    } else {
+        Some(text)
+    }
+}
+
+/// Collects the properties of a DefineEditText field into a JSON object.
+///
+/// Most interesting is the name of the ActionScript variable the field is bound to, if any.
+pub(crate) fn edit_text_to_json(et: &EditText, font_name: Option<&str>, encoding: &'static Encoding) -> JsonValue {
+    let mut obj = JsonValue::new_object();
+    let variable_name = decode_string(et.variable_name, encoding);
+    obj.insert("variable_name", if variable_name.is_empty() { None } else { Some(variable_name) });
+    obj.insert("font_id", et.font_id);
+    obj.insert("font_name", font_name);
+    obj.insert("font_class_name", et.font_class_name.map(|n| decode_string(n, encoding)));
+    obj.insert("height", et.height.map(|h| h.get()));
+    obj.insert("max_length", et.max_length);
+    obj.insert("is_password", et.is_password);
+    obj.insert("is_multiline", et.is_multiline);
+    obj.insert("is_word_wrap", et.is_word_wrap);
+    obj.insert("is_read_only", et.is_read_only);
+    obj.insert("is_selectable", et.is_selectable);
+    obj.insert("is_html", et.is_html);
+    obj.insert("is_auto_size", et.is_auto_size);
+    obj.insert("is_device_font", et.is_device_font);
+    obj.insert("has_border", et.has_border);
+    obj
+}
+
+
+/// Collects the advanced anti-aliasing settings of a text (from CSMTextSettings) into a JSON object.
+pub(crate) fn csm_text_settings_to_json(settings: &CsmTextSettings) -> JsonValue {
+    let grid_fit = match settings.grid_fit {
+        TextGridFit::None => "none",
+        TextGridFit::Pixel => "pixel",
+        TextGridFit::SubPixel => "subpixel",
+    };
+
+    let mut obj = JsonValue::new_object();
+    obj.insert("use_advanced_rendering", settings.use_advanced_rendering);
+    obj.insert("grid_fit", grid_fit);
+    obj.insert("thickness", settings.thickness);
+    obj.insert("sharpness", settings.sharpness);
+    obj
+}
@@ -0,0 +1,124 @@
+use std::io::{self, Write};
+
+use swf::CharacterId;
+use swfextract::bitmap::{Bitmap, BitmapData, Error as BitmapError};
+use swfextract::pixels::resize_rgba;
+
+use crate::html::{escape_html, escape_url_path};
+
+
+/// The directory into which thumbnails are written, along with the page listing them.
+pub(crate) const THUMBNAIL_DIR: &str = "thumbnails";
+
+
+/// A preview of a file written for an asset.
+#[derive(Clone, Debug)]
+pub(crate) struct Thumbnail {
+    pub id: CharacterId,
+    pub kind: String,
+
+    /// The path of the character through the sprites defining it.
+    pub path: String,
+
+    /// The file previewed.
+    pub asset_file: String,
+
+    /// The name of the thumbnail within [`THUMBNAIL_DIR`].
+    pub file_name: String,
+}
+
+
+/// Returns the dimensions of the thumbnail of an image with the given dimensions, which fits into a
+/// square of the given size. Images fitting into it already keep their dimensions.
+pub(crate) fn thumbnail_dimensions(width: f64, height: f64, size: u32) -> (f64, f64) {
+    let factor = (f64::from(size) / width.max(height)).min(1.0);
+    if !factor.is_finite() || factor <= 0.0 {
+        return (width, height);
+    }
+    (width * factor, height * factor)
+}
+
+
+/// Scales a PNG, JPEG or GIF image down to the given thumbnail size, returning it as RGBA pixels.
+pub(crate) fn bitmap_thumbnail(image_data: &[u8], size: u32) -> Result<Bitmap, BitmapError> {
+    let (width, height, pixels) = Bitmap::from_bytes(image_data, None)?.decode_rgba()?;
+    let (new_width, new_height) = thumbnail_dimensions(f64::from(width), f64::from(height), size);
+    let new_width = (new_width.round() as u32).max(1);
+    let new_height = (new_height.round() as u32).max(1);
+    let image_data = resize_rgba(&pixels, width, height, new_width, new_height);
+    Ok(Bitmap::new(new_width, new_height, BitmapData::Rgba32 { image_data }))
+}
+
+
+/// Returns the position of the value of an attribute within an XML start tag.
+fn attribute_value_range(tag: &str, name: &str) -> Option<(usize, usize)> {
+    let name_pos = tag.find(&format!(" {}=", name))?;
+    let quote_pos = name_pos + name.len() + 2;
+    let quote = tag[quote_pos..].chars().next().filter(|c| *c == '\'' || *c == '"')?;
+    let value_start = quote_pos + 1;
+    let value_end = value_start + tag[value_start..].find(quote)?;
+    Some((value_start, value_end))
+}
+
+
+/// Makes an SVG document (as written for shapes, texts and rendered frames) display at the given
+/// thumbnail size by changing the width and height of its root element; its view box is kept.
+///
+/// Returns `None` if the root element has no width or height in pixels.
+pub(crate) fn svg_thumbnail(svg: &str, size: u32) -> Option<String> {
+    let tag_start = svg.find("<svg")?;
+    let tag_end = tag_start + svg[tag_start..].find('>')?;
+    let tag = &svg[tag_start..tag_end];
+    let (width_start, width_end) = attribute_value_range(tag, "width")?;
+    let (height_start, height_end) = attribute_value_range(tag, "height")?;
+    let width: f64 = tag[width_start..width_end].trim_end_matches("px").parse().ok()?;
+    let height: f64 = tag[height_start..height_end].trim_end_matches("px").parse().ok()?;
+    let (new_width, new_height) = thumbnail_dimensions(width, height, size);
+
+    // replace the later value first, keeping the position of the earlier one
+    let mut replacements = [
+        (width_start, width_end, format!("{}px", round_to_hundredths(new_width))),
+        (height_start, height_end, format!("{}px", round_to_hundredths(new_height))),
+    ];
+    replacements.sort_by_key(|(start, _, _)| std::cmp::Reverse(*start));
+    let mut thumbnail = svg.to_owned();
+    for (start, end, value) in replacements {
+        thumbnail.replace_range(tag_start+start..tag_start+end, &value);
+    }
+    Some(thumbnail)
+}
+
+
+fn round_to_hundredths(value: f64) -> f64 {
+    (value * 100.0).round() / 100.0
+}
+
+
+/// Writes a page showing the thumbnails, each linking to the file it previews. The page is meant to
+/// be written into [`THUMBNAIL_DIR`].
+pub(crate) fn write_thumbnail_index<W: Write>(thumbnails: &[Thumbnail], movie_name: &str, mut write: W) -> io::Result<()> {
+    writeln!(write, "<!DOCTYPE html>")?;
+    writeln!(write, "<html>")?;
+    writeln!(write, "<head>")?;
+    writeln!(write, "<meta charset=\"utf-8\">")?;
+    writeln!(write, "<title>{}</title>", escape_html(movie_name))?;
+    writeln!(write, "<style>")?;
+    writeln!(write, "body {{ font-family: sans-serif; }}")?;
+    writeln!(write, "figure {{ display: inline-block; margin: 8px; text-align: center; vertical-align: top; }}")?;
+    writeln!(write, "figcaption {{ font-size: small; }}")?;
+    writeln!(write, "</style>")?;
+    writeln!(write, "</head>")?;
+    writeln!(write, "<body>")?;
+    writeln!(write, "<h1>{}</h1>", escape_html(movie_name))?;
+    for thumbnail in thumbnails {
+        writeln!(
+            write,
+            "<figure><a href=\"../{}\"><img src=\"{}\" alt=\"{} {}\"></a><figcaption>{}</figcaption></figure>",
+            escape_url_path(&thumbnail.asset_file), escape_url_path(&thumbnail.file_name),
+            escape_html(&thumbnail.kind), thumbnail.id, escape_html(&thumbnail.path),
+        )?;
+    }
+    writeln!(write, "</body>")?;
+    writeln!(write, "</html>")?;
+    Ok(())
+}
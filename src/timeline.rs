@@ -0,0 +1,281 @@
+use std::collections::{BTreeMap, HashMap};
+use std::ops::Range;
+
+use encoding_rs::Encoding;
+use swf::{BlendMode, CharacterId, ColorTransform, Depth, Filter, Matrix, PlaceObject, PlaceObjectAction, Rectangle, Tag};
+
+use crate::encoding::decode_string;
+use crate::filter::{blend_mode_name, filter_to_json};
+use crate::graph::ReferenceGraph;
+use crate::json::JsonValue;
+
+
+/// A character placed on a timeline.
+#[derive(Clone, Debug)]
+pub(crate) struct DisplayObject {
+    pub character_id: CharacterId,
+    pub depth: Depth,
+    pub matrix: Matrix,
+    pub color_transform: ColorTransform,
+    pub ratio: Option<u16>,
+    pub name: Option<String>,
+
+    /// The highest depth of the characters masked by this one, if it is a mask.
+    pub clip_depth: Option<Depth>,
+
+    pub blend_mode: BlendMode,
+    pub filters: Vec<Filter>,
+
+    /// The frame at which the character was placed, which determines the frame shown by sprites.
+    pub placed_frame: u32,
+}
+
+
+/// Collects the multipliers and additions of a color transform into a JSON object.
+pub(crate) fn color_transform_to_json(ct: &ColorTransform) -> JsonValue {
+    let mut obj = JsonValue::new_object();
+    obj.insert("r_multiply", ct.r_multiply.to_f64());
+    obj.insert("g_multiply", ct.g_multiply.to_f64());
+    obj.insert("b_multiply", ct.b_multiply.to_f64());
+    obj.insert("a_multiply", ct.a_multiply.to_f64());
+    obj.insert("r_add", ct.r_add);
+    obj.insert("g_add", ct.g_add);
+    obj.insert("b_add", ct.b_add);
+    obj.insert("a_add", ct.a_add);
+    obj
+}
+
+
+fn matrix_to_json(matrix: &Matrix) -> JsonValue {
+    let mut obj = JsonValue::new_object();
+    obj.insert("a", matrix.a.to_f64());
+    obj.insert("b", matrix.b.to_f64());
+    obj.insert("c", matrix.c.to_f64());
+    obj.insert("d", matrix.d.to_f64());
+    obj.insert("tx", matrix.tx.get());
+    obj.insert("ty", matrix.ty.get());
+    obj
+}
+
+
+/// Tracks the display list of a timeline and records its contents at every frame.
+#[derive(Clone, Debug, Default)]
+pub(crate) struct Timeline {
+    display_list: BTreeMap<Depth, DisplayObject>,
+    frames: Vec<Vec<DisplayObject>>,
+}
+impl Timeline {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// The number of the frame currently being assembled, starting at 1.
+    fn current_frame(&self) -> u32 {
+        u32::try_from(self.frames.len()).unwrap() + 1
+    }
+
+    pub fn place(&mut self, po: &PlaceObject, encoding: &'static Encoding) {
+        let name = po.name.map(|n| decode_string(n, encoding));
+        match po.action {
+            PlaceObjectAction::Place(id)|PlaceObjectAction::Replace(id) => {
+                let previous = self.display_list.remove(&po.depth);
+                let is_replace = po.action == PlaceObjectAction::Replace(id);
+
+                // replacing a character keeps the properties not specified anew
+                let keep = previous.filter(|_| is_replace);
+                let object = DisplayObject {
+                    character_id: id,
+                    depth: po.depth,
+                    matrix: po.matrix
+                        .or_else(|| keep.as_ref().map(|o| o.matrix))
+                        .unwrap_or(Matrix::IDENTITY),
+                    color_transform: po.color_transform.clone()
+                        .or_else(|| keep.as_ref().map(|o| o.color_transform.clone()))
+                        .unwrap_or_default(),
+                    ratio: po.ratio.or_else(|| keep.as_ref().and_then(|o| o.ratio)),
+                    name: name.or_else(|| keep.as_ref().and_then(|o| o.name.clone())),
+                    clip_depth: po.clip_depth.or_else(|| keep.as_ref().and_then(|o| o.clip_depth)),
+                    blend_mode: po.blend_mode
+                        .or_else(|| keep.as_ref().map(|o| o.blend_mode))
+                        .unwrap_or_default(),
+                    filters: po.filters.clone()
+                        .or_else(|| keep.as_ref().map(|o| o.filters.clone()))
+                        .unwrap_or_default(),
+                    placed_frame: self.current_frame(),
+                };
+                self.display_list.insert(po.depth, object);
+            },
+            PlaceObjectAction::Modify => {
+                let Some(object) = self.display_list.get_mut(&po.depth) else { return };
+                if let Some(matrix) = po.matrix {
+                    object.matrix = matrix;
+                }
+                if let Some(color_transform) = &po.color_transform {
+                    object.color_transform = color_transform.clone();
+                }
+                if po.ratio.is_some() {
+                    object.ratio = po.ratio;
+                }
+                if name.is_some() {
+                    object.name = name;
+                }
+                if po.clip_depth.is_some() {
+                    object.clip_depth = po.clip_depth;
+                }
+                if let Some(blend_mode) = po.blend_mode {
+                    object.blend_mode = blend_mode;
+                }
+                if let Some(filters) = &po.filters {
+                    object.filters = filters.clone();
+                }
+            },
+        }
+    }
+
+    pub fn remove(&mut self, depth: Depth) {
+        self.display_list.remove(&depth);
+    }
+
+    /// Records the current contents of the display list as a frame.
+    pub fn show_frame(&mut self) {
+        self.frames.push(self.display_list.values().cloned().collect());
+    }
+
+    pub fn frame_count(&self) -> usize {
+        self.frames.len()
+    }
+
+    /// Returns the characters shown in the given frame (starting at 0), ordered by depth.
+    pub fn frame(&self, index: usize) -> &[DisplayObject] {
+        &self.frames[index]
+    }
+
+    /// Calculates the area covered by the characters shown in any frame, relative to the
+    /// registration point of the timeline, from the areas covered by the characters themselves.
+    ///
+    /// Characters whose areas are unknown are left out, as are masks.
+    pub fn bounds(&self, character_bounds: &HashMap<CharacterId, Rectangle>) -> Option<Rectangle> {
+        let mut bounds: Option<Rectangle> = None;
+        for object in self.frames.iter().flatten() {
+            if object.clip_depth.is_some() {
+                continue;
+            }
+            let Some(b) = character_bounds.get(&object.character_id) else { continue };
+            for corner in [(b.x_min, b.y_min), (b.x_max, b.y_min), (b.x_min, b.y_max), (b.x_max, b.y_max)] {
+                let (x, y) = object.matrix * corner;
+                match &mut bounds {
+                    Some(r) => {
+                        r.x_min = r.x_min.min(x);
+                        r.y_min = r.y_min.min(y);
+                        r.x_max = r.x_max.max(x);
+                        r.y_max = r.y_max.max(y);
+                    },
+                    None => bounds = Some(Rectangle { x_min: x, x_max: x, y_min: y, y_max: y }),
+                }
+            }
+        }
+        bounds
+    }
+
+    /// Collects the contents of every frame into a JSON array, limited to the given range of frames
+    /// (starting at 1) if one is given. The characters placed are identified by ID and by their path
+    /// in the reference graph.
+    ///
+    /// Color transforms are recorded as specified, regardless of whether they are applied when
+    /// rendering frames.
+    pub fn to_json(&self, frame_range: Option<&Range<u32>>, graph: &ReferenceGraph) -> JsonValue {
+        let mut frames = JsonValue::new_array();
+        for (i, objects) in self.frames.iter().enumerate() {
+            let frame_number = u32::try_from(i).unwrap() + 1;
+            if frame_range.is_some_and(|r| !r.contains(&frame_number)) {
+                continue;
+            }
+            let mut objects_json = JsonValue::new_array();
+            for object in objects {
+                let mut obj = JsonValue::new_object();
+                obj.insert("depth", object.depth);
+                obj.insert("character", object.character_id);
+                obj.insert("path", graph.asset_path(object.character_id));
+                obj.insert("name", object.name.clone());
+                obj.insert("placed_frame", object.placed_frame);
+                obj.insert("matrix", matrix_to_json(&object.matrix));
+                obj.insert("color_transform", color_transform_to_json(&object.color_transform));
+                obj.insert("ratio", object.ratio);
+                obj.insert("clip_depth", object.clip_depth);
+                obj.insert("blend_mode", blend_mode_name(object.blend_mode));
+                obj.insert("filters", object.filters.iter().map(filter_to_json).collect::<Vec<JsonValue>>());
+                objects_json.push(obj);
+            }
+
+            let mut frame = JsonValue::new_object();
+            frame.insert("frame", i + 1);
+            frame.insert("objects", objects_json);
+            frames.push(frame);
+        }
+        frames
+    }
+}
+
+
+/// The labels of the frames of a timeline, used to select ranges of frames.
+#[derive(Clone, Debug, Default)]
+pub(crate) struct FrameLabels {
+    /// The labels with the frames (starting at 1) they are attached to, in timeline order.
+    labels: Vec<(u32, String)>,
+
+    frame_count: u32,
+}
+impl FrameLabels {
+    pub fn scan(tags: &[Tag], encoding: &'static Encoding) -> Self {
+        let mut frame_labels = Self::default();
+        for tag in tags {
+            match tag {
+                Tag::FrameLabel(fl) => {
+                    let label = decode_string(fl.label, encoding);
+                    frame_labels.labels.push((frame_labels.frame_count + 1, label));
+                },
+                Tag::ShowFrame => frame_labels.frame_count += 1,
+                _ => {},
+            }
+        }
+        frame_labels
+    }
+
+    pub fn labels(&self) -> &[(u32, String)] {
+        &self.labels
+    }
+
+    pub fn frame_count(&self) -> u32 {
+        self.frame_count
+    }
+
+    /// Returns the frame with the given label or number.
+    fn frame(&self, label_or_number: &str) -> Option<u32> {
+        if let Some((frame, _)) = self.labels.iter().find(|(_, label)| label == label_or_number) {
+            return Some(*frame);
+        }
+        label_or_number.parse().ok()
+            .filter(|frame| (1..=self.frame_count).contains(frame))
+    }
+
+    /// Returns the frames from the given label up to the next label or the end of the timeline.
+    pub fn section(&self, label: &str) -> Option<Range<u32>> {
+        let start = self.frame(label)?;
+        let end = self.labels.iter()
+            .map(|(frame, _)| *frame)
+            .find(|frame| *frame > start)
+            .unwrap_or(self.frame_count + 1);
+        Some(start..end)
+    }
+
+    /// Returns the frames selected by a specification of the form `start..end`, where `start` and
+    /// `end` are labels or frame numbers; the end is exclusive and either may be left out to select
+    /// from the start or to the end of the timeline. A specification without `..` selects the
+    /// section starting at the given label.
+    pub fn select(&self, spec: &str) -> Option<Range<u32>> {
+        let Some((start, end)) = spec.split_once("..") else { return self.section(spec) };
+        let start = if start.is_empty() { 1 } else { self.frame(start)? };
+        let end = if end.is_empty() { self.frame_count + 1 } else { self.frame(end)? };
+        if start < end { Some(start..end) } else { None }
+    }
+}
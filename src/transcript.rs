@@ -0,0 +1,121 @@
+use std::collections::HashMap;
+use std::io::Write;
+
+use clap::ValueEnum;
+use swf::CharacterId;
+
+use crate::describe_location;
+use crate::json::JsonValue;
+
+
+#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq, ValueEnum)]
+pub(crate) enum TranscriptFormat {
+    Text,
+    Json,
+}
+
+
+#[derive(Clone, Debug)]
+struct TranscriptEntry {
+    sprite_id: Option<CharacterId>,
+    frame: u32,
+    character_id: CharacterId,
+    kind: &'static str,
+    text: String,
+}
+
+
+/// Collects all text shown in the movie in timeline order.
+#[derive(Clone, Debug, Default)]
+pub(crate) struct Transcript {
+    /// Texts defined so far, with the kind of character defining them.
+    texts: HashMap<CharacterId, (&'static str, String)>,
+
+    /// The characters displayed by each button defined so far.
+    buttons: HashMap<CharacterId, Vec<CharacterId>>,
+
+    entries: Vec<TranscriptEntry>,
+}
+impl Transcript {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn define_text(&mut self, id: CharacterId, kind: &'static str, text: String) {
+        self.texts.insert(id, (kind, text));
+    }
+
+    pub fn define_button(&mut self, id: CharacterId, character_ids: Vec<CharacterId>) {
+        self.buttons.insert(id, character_ids);
+    }
+
+    /// Records the placement of a character on a timeline, adding its text (or the text of its
+    /// labels, if it is a button) to the transcript.
+    pub fn place(&mut self, sprite_id: Option<CharacterId>, frame: u32, character_id: CharacterId) {
+        if let Some((kind, text)) = self.texts.get(&character_id) {
+            self.entries.push(TranscriptEntry {
+                sprite_id,
+                frame,
+                character_id,
+                kind,
+                text: text.clone(),
+            });
+        } else if let Some(label_ids) = self.buttons.get(&character_id) {
+            let mut seen = Vec::new();
+            for label_id in label_ids {
+                if seen.contains(label_id) {
+                    // the same label is commonly shown in multiple button states
+                    continue;
+                }
+                seen.push(*label_id);
+
+                if let Some((_, text)) = self.texts.get(label_id) {
+                    self.entries.push(TranscriptEntry {
+                        sprite_id,
+                        frame,
+                        character_id,
+                        kind: "button",
+                        text: text.clone(),
+                    });
+                }
+            }
+        }
+    }
+
+    /// Returns the entries with those of the main timeline first, followed by those of the sprites
+    /// in the order of their definition.
+    fn ordered_entries(&self) -> Vec<&TranscriptEntry> {
+        let mut entries: Vec<&TranscriptEntry> = self.entries.iter().collect();
+        entries.sort_by_key(|e| e.sprite_id.is_some());
+        entries
+    }
+
+    pub fn write<W: Write>(&self, format: TranscriptFormat, mut writer: W) -> Result<(), std::io::Error> {
+        match format {
+            TranscriptFormat::Text => {
+                for entry in self.ordered_entries() {
+                    writeln!(
+                        writer, "[{}, {} {}]",
+                        describe_location(entry.sprite_id, entry.frame), entry.kind, entry.character_id,
+                    )?;
+                    writeln!(writer, "{}", entry.text)?;
+                    writeln!(writer)?;
+                }
+                Ok(())
+            },
+            TranscriptFormat::Json => {
+                let mut entries = JsonValue::new_array();
+                for entry in self.ordered_entries() {
+                    let mut obj = JsonValue::new_object();
+                    obj.insert("sprite", entry.sprite_id);
+                    obj.insert("frame", entry.frame);
+                    obj.insert("id", entry.character_id);
+                    obj.insert("type", entry.kind);
+                    obj.insert("text", entry.text.as_str());
+                    entries.push(obj);
+                }
+                entries.write(writer)
+            },
+        }
+    }
+}
@@ -0,0 +1,498 @@
+use std::collections::HashMap;
+use std::fmt::Write;
+use std::path::{Path, PathBuf};
+use std::rc::Rc;
+
+
+/// How deeply composite glyphs may nest.
+const MAX_COMPONENT_DEPTH: usize = 8;
+
+/// The names of the device fonts that stand for a kind of font rather than a specific one, with the
+/// families tried for each, in order.
+const GENERIC_FAMILIES: [(&str, &[&str]); 3] = [
+    ("_sans", &["Arial", "Helvetica", "Liberation Sans", "DejaVu Sans"]),
+    ("_serif", &["Times New Roman", "Times", "Liberation Serif", "DejaVu Serif"]),
+    ("_typewriter", &["Courier New", "Courier", "Liberation Mono", "DejaVu Sans Mono"]),
+];
+
+
+fn u16_at(data: &[u8], offset: usize) -> Option<u16> {
+    data.get(offset..offset+2).map(|bytes| u16::from_be_bytes(bytes.try_into().unwrap()))
+}
+
+
+fn i16_at(data: &[u8], offset: usize) -> Option<i16> {
+    data.get(offset..offset+2).map(|bytes| i16::from_be_bytes(bytes.try_into().unwrap()))
+}
+
+
+fn u32_at(data: &[u8], offset: usize) -> Option<u32> {
+    data.get(offset..offset+4).map(|bytes| u32::from_be_bytes(bytes.try_into().unwrap()))
+}
+
+
+/// A point of a glyph outline, in font units with the y axis pointing up.
+#[derive(Clone, Copy, Debug)]
+struct OutlinePoint {
+    x: f64,
+    y: f64,
+    on_curve: bool,
+}
+
+
+/// Appends a closed contour of quadratic curves to SVG path data, mirroring it vertically.
+fn append_contour(path_data: &mut String, points: &[OutlinePoint]) {
+    let Some(first) = points.first() else { return };
+    let midpoint = |a: &OutlinePoint, b: &OutlinePoint| OutlinePoint {
+        x: (a.x + b.x) / 2.0,
+        y: (a.y + b.y) / 2.0,
+        on_curve: true,
+    };
+    // start on the curve, between two control points if need be
+    let start_index = points.iter().position(|p| p.on_curve);
+    let start = match start_index {
+        Some(i) => points[i],
+        None => midpoint(first, &points[1 % points.len()]),
+    };
+    let offset = start_index.map_or(0, |i| i + 1);
+
+    if !path_data.is_empty() {
+        path_data.push(' ');
+    }
+    write!(path_data, "M {} {}", start.x, -start.y).unwrap();
+    let mut control: Option<OutlinePoint> = None;
+    for i in 0..points.len() {
+        let point = points[(offset + i) % points.len()];
+        match (point.on_curve, control) {
+            (true, None) => write!(path_data, " L {} {}", point.x, -point.y).unwrap(),
+            (true, Some(c)) => {
+                write!(path_data, " Q {} {} {} {}", c.x, -c.y, point.x, -point.y).unwrap();
+                control = None;
+            },
+            (false, None) => control = Some(point),
+            (false, Some(c)) => {
+                let implied = midpoint(&c, &point);
+                write!(path_data, " Q {} {} {} {}", c.x, -c.y, implied.x, -implied.y).unwrap();
+                control = Some(point);
+            },
+        }
+    }
+    if let Some(c) = control {
+        write!(path_data, " Q {} {} {} {}", c.x, -c.y, start.x, -start.y).unwrap();
+    }
+    path_data.push_str(" Z");
+}
+
+
+/// A TrueType font (with `glyf` outlines; CFF-based OpenType fonts are not supported) loaded from a
+/// file, from which texts in device fonts are drawn.
+#[derive(Clone, Debug)]
+pub(crate) struct TrueTypeFont {
+    data: Vec<u8>,
+    pub units_per_em: u16,
+    pub ascent: i16,
+    pub descent: i16,
+    glyph_count: u16,
+    long_offsets: bool,
+    advance_count: u16,
+    cmap: usize,
+    cmap_format: u16,
+    hmtx: usize,
+    loca: usize,
+    glyf: usize,
+}
+impl TrueTypeFont {
+    /// Parses a TrueType font or, for a collection, the first font in it.
+    pub fn parse(data: Vec<u8>) -> Option<Self> {
+        let mut directory = 0;
+        if data.starts_with(b"ttcf") {
+            directory = usize::try_from(u32_at(&data, 12)?).ok()?;
+        }
+        let table_count = u16_at(&data, directory + 4)?;
+        let mut tables = HashMap::new();
+        for i in 0..usize::from(table_count) {
+            let record = directory + 12 + 16 * i;
+            let tag = data.get(record..record+4)?;
+            tables.insert(<[u8; 4]>::try_from(tag).unwrap(), usize::try_from(u32_at(&data, record + 8)?).ok()?);
+        }
+        let table = |tag: &[u8; 4]| tables.get(tag).copied();
+        let (head, hhea, maxp) = (table(b"head")?, table(b"hhea")?, table(b"maxp")?);
+
+        // prefer the full Unicode mapping over the one restricted to the Basic Multilingual Plane
+        let cmap = table(b"cmap")?;
+        let mut subtable = None;
+        for i in 0..usize::from(u16_at(&data, cmap + 2)?) {
+            let record = cmap + 4 + 8 * i;
+            let (platform, encoding) = (u16_at(&data, record)?, u16_at(&data, record + 2)?);
+            let offset = cmap + usize::try_from(u32_at(&data, record + 4)?).ok()?;
+            let format = u16_at(&data, offset)?;
+            let unicode = platform == 0 || (platform == 3 && (encoding == 1 || encoding == 10));
+            if unicode && (format == 12 || (format == 4 && subtable.is_none())) {
+                subtable = Some((offset, format));
+            }
+        }
+        let (cmap, cmap_format) = subtable?;
+
+        Some(Self {
+            units_per_em: u16_at(&data, head + 18)?,
+            long_offsets: i16_at(&data, head + 50)? != 0,
+            ascent: i16_at(&data, hhea + 4)?,
+            descent: i16_at(&data, hhea + 6)?,
+            advance_count: u16_at(&data, hhea + 34)?,
+            glyph_count: u16_at(&data, maxp + 4)?,
+            cmap,
+            cmap_format,
+            hmtx: table(b"hmtx")?,
+            loca: table(b"loca")?,
+            glyf: table(b"glyf")?,
+            data,
+        })
+    }
+
+    /// Returns the glyph for the given character, if the font has one.
+    pub fn glyph_index(&self, c: char) -> Option<u16> {
+        let code = u32::from(c);
+        let data = &self.data;
+        let glyph = if self.cmap_format == 12 {
+            let group_count = u32_at(data, self.cmap + 12)?;
+            (0..usize::try_from(group_count).ok()?)
+                .map(|i| self.cmap + 16 + 12 * i)
+                .find_map(|group| {
+                    let (start, end) = (u32_at(data, group)?, u32_at(data, group + 4)?);
+                    (start..=end).contains(&code).then(|| u32_at(data, group + 8).and_then(|first| first.checked_add(code - start)))?
+                })
+                .and_then(|glyph| u16::try_from(glyph).ok())?
+        } else {
+            let code = u16::try_from(code).ok()?;
+            let segment_count = usize::from(u16_at(data, self.cmap + 6)? / 2);
+            let ends = self.cmap + 14;
+            let starts = ends + 2 * segment_count + 2;
+            let deltas = starts + 2 * segment_count;
+            let range_offsets = deltas + 2 * segment_count;
+            let segment = (0..segment_count).find(|&i| u16_at(data, ends + 2 * i).is_some_and(|end| end >= code))?;
+            let start = u16_at(data, starts + 2 * segment)?;
+            if code < start {
+                return None;
+            }
+            let delta = u16_at(data, deltas + 2 * segment)?;
+            let range_offset = u16_at(data, range_offsets + 2 * segment)?;
+            if range_offset == 0 {
+                code.wrapping_add(delta)
+            } else {
+                let address = range_offsets + 2 * segment + usize::from(range_offset) + 2 * usize::from(code - start);
+                match u16_at(data, address)? {
+                    0 => 0,
+                    glyph => glyph.wrapping_add(delta),
+                }
+            }
+        };
+        (glyph != 0 && glyph < self.glyph_count).then_some(glyph)
+    }
+
+    /// Returns the advance of the given glyph in font units.
+    pub fn advance(&self, glyph: u16) -> Option<u16> {
+        let metric = glyph.min(self.advance_count.checked_sub(1)?);
+        u16_at(&self.data, self.hmtx + 4 * usize::from(metric))
+    }
+
+    fn glyph_range(&self, glyph: u16) -> Option<(usize, usize)> {
+        let glyph = usize::from(glyph);
+        let (start, end) = if self.long_offsets {
+            (u32_at(&self.data, self.loca + 4 * glyph)?, u32_at(&self.data, self.loca + 4 * glyph + 4)?)
+        } else {
+            (
+                u32::from(u16_at(&self.data, self.loca + 2 * glyph)?) * 2,
+                u32::from(u16_at(&self.data, self.loca + 2 * glyph + 2)?) * 2,
+            )
+        };
+        let start = self.glyf + usize::try_from(start).ok()?;
+        let end = self.glyf + usize::try_from(end).ok()?;
+        Some((start, end))
+    }
+
+    /// Collects the contours of the given glyph, transformed by `transform` (`[a, b, c, d, dx, dy]`).
+    fn collect_contours(&self, glyph: u16, transform: [f64; 6], depth: usize, contours: &mut Vec<Vec<OutlinePoint>>) -> Option<()> {
+        let data = &self.data;
+        let (start, end) = self.glyph_range(glyph)?;
+        if start >= end {
+            // a glyph without outline, such as a space
+            return Some(());
+        }
+        let contour_count = i16_at(data, start)?;
+        let mut pos = start + 10;
+
+        if contour_count < 0 {
+            if depth >= MAX_COMPONENT_DEPTH {
+                return None;
+            }
+            loop {
+                let flags = u16_at(data, pos)?;
+                let component = u16_at(data, pos + 2)?;
+                pos += 4;
+                let (dx, dy) = if flags & 0x0001 != 0 {
+                    pos += 4;
+                    (f64::from(i16_at(data, pos - 4)?), f64::from(i16_at(data, pos - 2)?))
+                } else {
+                    pos += 2;
+                    (f64::from(*data.get(pos - 2)? as i8), f64::from(*data.get(pos - 1)? as i8))
+                };
+                let f2dot14 = |offset: usize| i16_at(data, offset).map(|v| f64::from(v) / 16384.0);
+                let [mut a, mut b, mut c, mut d] = [1.0, 0.0, 0.0, 1.0];
+                if flags & 0x0008 != 0 {
+                    a = f2dot14(pos)?;
+                    d = a;
+                    pos += 2;
+                } else if flags & 0x0040 != 0 {
+                    (a, d) = (f2dot14(pos)?, f2dot14(pos + 2)?);
+                    pos += 4;
+                } else if flags & 0x0080 != 0 {
+                    (a, b, c, d) = (f2dot14(pos)?, f2dot14(pos + 2)?, f2dot14(pos + 4)?, f2dot14(pos + 6)?);
+                    pos += 8;
+                }
+                // matched points (ARGS_ARE_XY_VALUES unset) are not supported; the offset is ignored
+                let (dx, dy) = if flags & 0x0002 != 0 { (dx, dy) } else { (0.0, 0.0) };
+                let [ta, tb, tc, td, tx, ty] = transform;
+                let combined = [
+                    a * ta + b * tc, a * tb + b * td,
+                    c * ta + d * tc, c * tb + d * td,
+                    dx * ta + dy * tc + tx, dx * tb + dy * td + ty,
+                ];
+                self.collect_contours(component, combined, depth + 1, contours)?;
+                if flags & 0x0020 == 0 {
+                    return Some(());
+                }
+            }
+        }
+
+        let contour_count = usize::try_from(contour_count).unwrap();
+        let mut ends = Vec::with_capacity(contour_count);
+        for i in 0..contour_count {
+            ends.push(usize::from(u16_at(data, pos + 2 * i)?));
+        }
+        pos += 2 * contour_count;
+        let point_count = ends.last().map_or(0, |&last| last + 1);
+        let instruction_length = usize::from(u16_at(data, pos)?);
+        pos += 2 + instruction_length;
+
+        let mut flags = Vec::with_capacity(point_count);
+        while flags.len() < point_count {
+            let flag = *data.get(pos)?;
+            pos += 1;
+            let mut repeat = 1;
+            if flag & 0x08 != 0 {
+                repeat += usize::from(*data.get(pos)?);
+                pos += 1;
+            }
+            flags.extend(std::iter::repeat_n(flag, repeat));
+        }
+        flags.truncate(point_count);
+
+        // coordinates are given as deltas: short ones as a byte with the sign in the flags, long ones
+        // as 16-bit values, or left out if they repeat the previous one
+        let mut read_coordinates = |short_bit: u8, same_bit: u8| -> Option<Vec<i32>> {
+            let mut value = 0i32;
+            let mut values = Vec::with_capacity(point_count);
+            for flag in &flags {
+                if flag & short_bit != 0 {
+                    let delta = i32::from(*data.get(pos)?);
+                    pos += 1;
+                    value += if flag & same_bit != 0 { delta } else { -delta };
+                } else if flag & same_bit == 0 {
+                    value += i32::from(i16_at(data, pos)?);
+                    pos += 2;
+                }
+                values.push(value);
+            }
+            Some(values)
+        };
+        let xs = read_coordinates(0x02, 0x10)?;
+        let ys = read_coordinates(0x04, 0x20)?;
+
+        let [a, b, c, d, tx, ty] = transform;
+        let mut contour_start = 0;
+        for end in ends {
+            let contour = (contour_start..=end.min(point_count.saturating_sub(1)))
+                .map(|i| {
+                    let (x, y) = (f64::from(xs[i]), f64::from(ys[i]));
+                    OutlinePoint { x: a * x + c * y + tx, y: b * x + d * y + ty, on_curve: flags[i] & 0x01 != 0 }
+                })
+                .collect();
+            contours.push(contour);
+            contour_start = end + 1;
+        }
+        Some(())
+    }
+
+    /// Returns the outline of the given glyph as SVG path data, in font units with the y axis
+    /// pointing down as in SWF fonts.
+    pub fn glyph_path(&self, glyph: u16) -> Option<String> {
+        let mut contours = Vec::new();
+        self.collect_contours(glyph, [1.0, 0.0, 0.0, 1.0, 0.0, 0.0], 0, &mut contours)?;
+        let mut path_data = String::new();
+        for contour in &contours {
+            append_contour(&mut path_data, contour);
+        }
+        Some(path_data)
+    }
+}
+
+
+/// Reads the family name and style (bold, italic) of a TrueType font from its `name` and `head`
+/// tables.
+fn read_face_info(data: &[u8]) -> Option<(String, bool, bool)> {
+    let mut directory = 0;
+    if data.starts_with(b"ttcf") {
+        directory = usize::try_from(u32_at(data, 12)?).ok()?;
+    }
+    let mut name_table = None;
+    let mut head = None;
+    for i in 0..usize::from(u16_at(data, directory + 4)?) {
+        let record = directory + 12 + 16 * i;
+        let offset = usize::try_from(u32_at(data, record + 8)?).ok()?;
+        match data.get(record..record+4)? {
+            b"name" => name_table = Some(offset),
+            b"head" => head = Some(offset),
+            _ => {},
+        }
+    }
+    let (name_table, head) = (name_table?, head?);
+    let mac_style = u16_at(data, head + 44)?;
+
+    let count = usize::from(u16_at(data, name_table + 2)?);
+    let strings = name_table + usize::from(u16_at(data, name_table + 4)?);
+    let mut family = None;
+    for i in 0..count {
+        let record = name_table + 6 + 12 * i;
+        let (platform, name_id) = (u16_at(data, record)?, u16_at(data, record + 6)?);
+        if name_id != 1 {
+            continue;
+        }
+        let length = usize::from(u16_at(data, record + 8)?);
+        let offset = strings + usize::from(u16_at(data, record + 10)?);
+        let bytes = data.get(offset..offset+length)?;
+        let name = if platform == 3 || platform == 0 {
+            let units: Vec<u16> = bytes.chunks_exact(2).map(|pair| u16::from_be_bytes([pair[0], pair[1]])).collect();
+            String::from_utf16_lossy(&units)
+        } else {
+            String::from_utf8_lossy(bytes).into_owned()
+        };
+        // prefer the Windows name, which is in Unicode
+        if family.is_none() || platform == 3 {
+            family = Some(name);
+        }
+    }
+    Some((family?, mac_style & 0x01 != 0, mac_style & 0x02 != 0))
+}
+
+
+/// The TrueType fonts in a directory (and its subdirectories), looked up by family name and style.
+#[derive(Debug, Default)]
+pub(crate) struct FontDirectory {
+    /// The files of each family and their styles (bold, italic), by lowercased family name.
+    faces: HashMap<String, Vec<(PathBuf, bool, bool)>>,
+
+    loaded: HashMap<PathBuf, Option<Rc<TrueTypeFont>>>,
+}
+impl FontDirectory {
+    pub fn scan(dir: &Path) -> std::io::Result<Self> {
+        let mut font_dir = Self::default();
+        // the fonts are loaded once needed, after changing into the output directory
+        let mut pending = vec![std::path::absolute(dir)?];
+        while let Some(dir) = pending.pop() {
+            for entry in std::fs::read_dir(&dir)? {
+                let path = entry?.path();
+                if path.is_dir() {
+                    pending.push(path);
+                    continue;
+                }
+                let is_font = path.extension()
+                    .and_then(|ext| ext.to_str())
+                    .is_some_and(|ext| ["ttf", "ttc", "otf"].iter().any(|font_ext| ext.eq_ignore_ascii_case(font_ext)));
+                if !is_font {
+                    continue;
+                }
+                let Ok(data) = std::fs::read(&path) else { continue };
+                if let Some((family, bold, italic)) = read_face_info(&data) {
+                    font_dir.faces.entry(family.to_lowercase()).or_default().push((path, bold, italic));
+                }
+            }
+        }
+        for faces in font_dir.faces.values_mut() {
+            faces.sort();
+        }
+        Ok(font_dir)
+    }
+
+    /// Returns the font of the given family that best matches the given style, loading it if
+    /// necessary. The generic device fonts (`_sans`, `_serif` and `_typewriter`) are looked up as
+    /// common fonts of their kind.
+    pub fn find(&mut self, name: &str, bold: bool, italic: bool) -> Option<Rc<TrueTypeFont>> {
+        let families: Vec<String> = match GENERIC_FAMILIES.iter().find(|(generic, _)| *generic == name) {
+            Some((_, families)) => families.iter().map(|family| family.to_lowercase()).collect(),
+            None => vec![name.to_lowercase()],
+        };
+        let path = families.iter()
+            .filter_map(|family| self.faces.get(family))
+            .find_map(|faces| faces.iter()
+                .min_by_key(|(_, face_bold, face_italic)| u8::from(*face_bold != bold) + u8::from(*face_italic != italic))
+                .map(|(path, _, _)| path.clone()))?;
+        self.loaded.entry(path.clone())
+            .or_insert_with(|| std::fs::read(&path).ok().and_then(TrueTypeFont::parse).map(Rc::new))
+            .clone()
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+
+    /// A font consisting of the given `cmap` subtable and the `glyf` data of glyph 0 alone.
+    fn font(cmap_format: u16, cmap: &[u8], glyph: &[u8]) -> TrueTypeFont {
+        let glyf = cmap.len() + 4;
+        let mut data = cmap.to_vec();
+        let glyph_end = u16::try_from(glyph.len().div_ceil(2)).unwrap();
+        data.extend([0, 0]);
+        data.extend(glyph_end.to_be_bytes());
+        data.extend(glyph);
+        TrueTypeFont {
+            data,
+            units_per_em: 1024,
+            ascent: 0,
+            descent: 0,
+            glyph_count: 2,
+            long_offsets: false,
+            advance_count: 0,
+            cmap: 0,
+            cmap_format,
+            hmtx: 0,
+            loca: cmap.len(),
+            glyf,
+        }
+    }
+
+
+    #[test]
+    fn overflowing_cmap_group_maps_nothing() {
+        let mut cmap = vec![0; 12];
+        cmap.extend(1u32.to_be_bytes());
+        for value in [0x41, 0x5A, 0xFFFF_FFFF] {
+            cmap.extend(u32::to_be_bytes(value));
+        }
+        let font = font(12, &cmap, &[]);
+        assert_eq!(font.glyph_index('A'), None);
+        assert_eq!(font.glyph_index('B'), None);
+    }
+
+
+    #[test]
+    fn truncated_composite_glyph_has_no_outline() {
+        // one component with byte offsets (ARGS_ARE_XY_VALUES), which are cut off
+        let mut glyph = vec![0xFF, 0xFF];
+        glyph.extend([0; 8]);
+        glyph.extend([0x00, 0x02, 0x00, 0x00]);
+        let font = font(4, &[], &glyph);
+        assert_eq!(font.glyph_path(0), None);
+    }
+}
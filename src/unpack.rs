@@ -0,0 +1,208 @@
+use std::io::Read;
+
+use flate2::read::ZlibDecoder;
+use swf::{CharacterId, Tag};
+
+use crate::json::JsonValue;
+
+
+/// The signatures of uncompressed, zlib-compressed and LZMA-compressed SWF files.
+const SWF_SIGNATURES: [&[u8; 3]; 3] = [b"FWS", b"CWS", b"ZWS"];
+
+/// The most data a compressed payload is inflated to.
+const MAX_PAYLOAD_LEN: u64 = 512 * 1024 * 1024;
+
+/// The most loaders a movie is unpacked from, each containing the next.
+pub(crate) const MAX_UNPACK_DEPTH: usize = 8;
+
+/// Names of ActionScript 3 classes and methods with which loaders turn their payload into a movie.
+const LOADER_NAMES: [&str; 5] = ["loadBytes", "uncompress", "inflate", "decompress", "ByteArray"];
+
+
+/// How a movie has been stored within its loader.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub(crate) enum PackingMethod {
+    /// Stored as is.
+    Plain,
+
+    /// Compressed with zlib (`ByteArray.compress()`).
+    Zlib,
+
+    /// Compressed with LZMA (`ByteArray.compress(CompressionAlgorithm.LZMA)`).
+    Lzma,
+
+    /// Each byte XORed with the given key.
+    Xor(u8),
+}
+impl PackingMethod {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::Plain => "plain",
+            Self::Zlib => "zlib",
+            Self::Lzma => "lzma",
+            Self::Xor(_) => "xor",
+        }
+    }
+}
+
+
+/// Checks whether the data starts with a plausible SWF header.
+fn looks_like_swf(data: &[u8]) -> bool {
+    if data.len() < 8 || !SWF_SIGNATURES.iter().any(|sig| data.starts_with(*sig)) {
+        return false;
+    }
+    let version = data[3];
+    let uncompressed_len = u32::from_le_bytes(data[4..8].try_into().unwrap());
+    (1..=64).contains(&version) && uncompressed_len >= 8
+}
+
+
+pub(crate) fn is_zlib(data: &[u8]) -> bool {
+    // deflate with a window of at most 32 KiB and a valid header checksum
+    data.len() >= 2 && data[0] & 0x0F == 8 && data[0] >> 4 <= 7
+        && (u16::from(data[0]) << 8 | u16::from(data[1])) % 31 == 0
+}
+
+
+pub(crate) fn inflate(data: &[u8]) -> Option<Vec<u8>> {
+    let mut inflated = Vec::new();
+    ZlibDecoder::new(data).take(MAX_PAYLOAD_LEN).read_to_end(&mut inflated).ok()?;
+    Some(inflated)
+}
+
+
+fn decompress_lzma(data: &[u8]) -> Option<Vec<u8>> {
+    // LZMA properties, dictionary size and unpacked size (all ones if unknown)
+    if data.len() < 13 || data[0] >= 9 * 5 * 5 {
+        return None;
+    }
+    let unpacked_len = u64::from_le_bytes(data[5..13].try_into().unwrap());
+    if unpacked_len != u64::MAX && unpacked_len > MAX_PAYLOAD_LEN {
+        return None;
+    }
+    let mut decompressed = Vec::new();
+    lzma_rs::lzma_decompress(&mut &data[..], &mut decompressed).ok()?;
+    Some(decompressed)
+}
+
+
+/// Tries to recover a SWF file from binary data stored by a loader.
+pub(crate) fn unpack_payload(data: &[u8]) -> Option<(PackingMethod, Vec<u8>)> {
+    if looks_like_swf(data) {
+        return Some((PackingMethod::Plain, data.to_vec()));
+    }
+    if is_zlib(data) {
+        if let Some(inflated) = inflate(data).filter(|d| looks_like_swf(d)) {
+            return Some((PackingMethod::Zlib, inflated));
+        }
+    }
+    if data.first() == Some(&0x5D) {
+        // the properties written by practically every LZMA encoder
+        if let Some(decompressed) = decompress_lzma(data).filter(|d| looks_like_swf(d)) {
+            return Some((PackingMethod::Lzma, decompressed));
+        }
+    }
+    if data.len() >= 8 {
+        for signature in SWF_SIGNATURES {
+            let key = data[0] ^ signature[0];
+            if key == 0 || data[1] ^ key != signature[1] || data[2] ^ key != signature[2] {
+                continue;
+            }
+            let decoded: Vec<u8> = data.iter().map(|b| b ^ key).collect();
+            if looks_like_swf(&decoded) {
+                return Some((PackingMethod::Xor(key), decoded));
+            }
+        }
+    }
+    None
+}
+
+
+/// A movie found within the binary data of its loader.
+#[derive(Clone, Debug)]
+pub(crate) struct Payload {
+    /// The ID of the binary data character.
+    pub id: CharacterId,
+
+    pub method: PackingMethod,
+
+    /// The SWF file, as recovered.
+    pub data: Vec<u8>,
+}
+
+
+/// The signs that a movie is merely a loader for another movie stored within it.
+#[derive(Clone, Debug, Default)]
+pub(crate) struct LoaderAnalysis {
+    /// The names of the loader classes and methods referenced by the ActionScript 3 code.
+    pub loader_names: Vec<&'static str>,
+
+    /// The ID and length of the largest binary data character.
+    pub largest_blob: Option<(CharacterId, usize)>,
+
+    /// The movie recovered from the binary data, if any.
+    pub payload: Option<Payload>,
+}
+impl LoaderAnalysis {
+    /// Looks for a movie stored within the binary data of another, trying the largest binary
+    /// data first.
+    pub fn analyze(tags: &[Tag]) -> Self {
+        let mut loader_names = Vec::new();
+        for tag in tags {
+            let Tag::DoAbc(abc) = tag else { continue };
+            let Ok(abc) = swf::avm2::read::Reader::new(abc.data).read() else { continue };
+            for name in LOADER_NAMES {
+                if !loader_names.contains(&name) && abc.constant_pool.strings.iter().any(|s| s == name) {
+                    loader_names.push(name);
+                }
+            }
+        }
+
+        let mut blobs: Vec<(CharacterId, &[u8])> = tags.iter()
+            .filter_map(|tag| match tag {
+                Tag::DefineBinaryData(bd) => Some((bd.id, bd.data)),
+                _ => None,
+            })
+            .collect();
+        blobs.sort_by(|(a_id, a), (b_id, b)| b.len().cmp(&a.len()).then(a_id.cmp(b_id)));
+        let largest_blob = blobs.first().map(|(id, data)| (*id, data.len()));
+        let payload = blobs.iter()
+            .find_map(|(id, data)| unpack_payload(data).map(|(method, data)| Payload { id: *id, method, data }));
+
+        Self {
+            loader_names,
+            largest_blob,
+            payload,
+        }
+    }
+
+    /// Whether the movie looks like a loader: code that loads movies from memory or binary data
+    /// making up most of the movie, together with a movie recovered from binary data or (if the
+    /// payload is presumably encrypted) with each other.
+    pub fn is_loader(&self, movie_len: usize) -> bool {
+        let loads_bytes = self.loader_names.contains(&"loadBytes");
+        let is_dominant = self.largest_blob.is_some_and(|(_, len)| len >= movie_len / 2);
+        if self.payload.is_some() {
+            loads_bytes || is_dominant
+        } else {
+            loads_bytes && is_dominant
+        }
+    }
+
+    pub fn to_json(&self) -> JsonValue {
+        let mut obj = JsonValue::new_object();
+        obj.insert("loader_code", self.loader_names.clone());
+        obj.insert("largest_binary_data", self.largest_blob.map(|(id, _)| id));
+        if let Some(payload) = &self.payload {
+            obj.insert("payload", payload.id);
+            obj.insert("method", payload.method.as_str());
+            if let PackingMethod::Xor(key) = payload.method {
+                obj.insert("key", key);
+            }
+            obj.insert("payload_length", payload.data.len());
+        } else {
+            obj.insert("payload", JsonValue::Null);
+        }
+        obj
+    }
+}
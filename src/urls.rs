@@ -0,0 +1,229 @@
+use std::collections::BTreeMap;
+use std::io::Write;
+
+use encoding_rs::Encoding;
+use swf::{CharacterId, PlaceObjectAction, Tag};
+use swf::avm1::types::{Action, Value};
+
+use crate::avm1::walk_actions;
+use crate::describe_location;
+use crate::encoding::decode_string;
+use crate::json::JsonValue;
+
+
+/// Extensions of files commonly loaded by movies or linked from them.
+const RESOURCE_EXTENSIONS: &[&str] = &[
+    "asp", "aspx", "cgi", "css", "f4v", "flv", "gif", "htm", "html", "jpeg", "jpg", "js", "json",
+    "jsp", "mp3", "mp4", "php", "pl", "png", "swf", "txt", "xml",
+];
+
+
+/// Whether a string found in a script is probably the URL of an external resource.
+fn looks_like_url(s: &str) -> bool {
+    if s.is_empty() || s.chars().any(|c| c.is_whitespace() || c.is_control()) {
+        return false;
+    }
+    if s.contains("://") || s.starts_with("mailto:") {
+        return true;
+    }
+    let path = s.split(['?', '#']).next().unwrap_or(s);
+    match path.rsplit_once('.') {
+        Some((stem, extension)) => {
+            !stem.is_empty() && RESOURCE_EXTENSIONS.contains(&extension.to_ascii_lowercase().as_str())
+        },
+        None => false,
+    }
+}
+
+
+/// The external resources referenced by a movie, from ImportAssets tags and from the arguments of
+/// `getURL`, `loadMovie` and similar calls in scripts.
+///
+/// Arguments are only found if they are constant; additionally, every string constant in a script
+/// that looks like a URL or the name of a resource file is reported.
+#[derive(Clone, Debug, Default)]
+pub(crate) struct UrlReport {
+    /// The references to each URL.
+    urls: BTreeMap<String, Vec<JsonValue>>,
+}
+impl UrlReport {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Collects the URLs referenced by a movie from its top-level tags.
+    pub fn scan(tags: &[Tag], swf_version: u8, encoding: &'static Encoding) -> Self {
+        let mut report = Self::new();
+        report.scan_timeline(None, tags, swf_version, encoding);
+        report
+    }
+
+    pub fn len(&self) -> usize {
+        self.urls.len()
+    }
+
+    fn scan_timeline(
+        &mut self,
+        sprite_id: Option<CharacterId>,
+        tags: &[Tag],
+        swf_version: u8,
+        encoding: &'static Encoding,
+    ) {
+        let mut frame: u32 = 1;
+        for tag in tags {
+            match tag {
+                Tag::DefineButton(btn)|Tag::DefineButton2(btn) => {
+                    let location = format!("button {}", btn.id);
+                    for action in &btn.actions {
+                        self.scan_avm1(&location, action.action_data, swf_version, encoding);
+                    }
+                },
+                Tag::DefineSprite(ds) => self.scan_timeline(Some(ds.id), &ds.tags, swf_version, encoding),
+                Tag::DoAbc(abc) => {
+                    let location = describe_location(sprite_id, frame);
+                    self.scan_abc(&location, abc.data);
+                },
+                Tag::DoAction(action_data) => {
+                    let location = describe_location(sprite_id, frame);
+                    self.scan_avm1(&location, action_data, swf_version, encoding);
+                },
+                Tag::DoInitAction { id, action_data } => {
+                    let location = format!("initialization of sprite {}", id);
+                    self.scan_avm1(&location, action_data, swf_version, encoding);
+                },
+                Tag::ImportAssets { url, imports } => {
+                    let url = decode_string(url, encoding);
+                    let names: Vec<String> = imports.iter()
+                        .map(|asset| decode_string(asset.name, encoding))
+                        .collect();
+                    let reference = self.add(url, "import_assets", describe_location(sprite_id, frame), None);
+                    if let Some(reference) = reference {
+                        reference.insert("names", names);
+                    }
+                },
+                Tag::PlaceObject(po) => {
+                    let Some(clip_actions) = &po.clip_actions else { continue };
+                    let location = match po.action {
+                        PlaceObjectAction::Place(id)|PlaceObjectAction::Replace(id) => {
+                            format!("{}, clip events of character {}", describe_location(sprite_id, frame), id)
+                        },
+                        PlaceObjectAction::Modify => {
+                            format!("{}, clip events at depth {}", describe_location(sprite_id, frame), po.depth)
+                        },
+                    };
+                    for clip_action in clip_actions {
+                        self.scan_avm1(&location, clip_action.action_data, swf_version, encoding);
+                    }
+                },
+                Tag::ShowFrame => frame += 1,
+                _ => {},
+            }
+        }
+    }
+
+    fn scan_avm1(&mut self, location: &str, action_data: &[u8], swf_version: u8, encoding: &'static Encoding) {
+        let mut calls = Vec::new();
+        let mut strings = Vec::new();
+        let mut constant_pool: Vec<String> = Vec::new();
+        // the constant strings pushed since the last other action; the arguments of GetUrl2
+        let mut pushed: Vec<Option<String>> = Vec::new();
+
+        walk_actions(action_data, swf_version, &mut |action| {
+            match action {
+                Action::ConstantPool(cp) => {
+                    constant_pool = cp.strings.iter()
+                        .map(|s| decode_string(s, encoding))
+                        .collect();
+                    strings.extend(constant_pool.iter().cloned());
+                },
+                Action::Push(push) => {
+                    for value in &push.values {
+                        let string = match value {
+                            Value::Str(s) => Some(decode_string(s, encoding)),
+                            Value::ConstantPool(i) => constant_pool.get(usize::from(*i)).cloned(),
+                            _ => None,
+                        };
+                        if let Some(s) = &string {
+                            strings.push(s.clone());
+                        }
+                        pushed.push(string);
+                    }
+                },
+                Action::GetUrl(gu) => {
+                    let url = decode_string(gu.url, encoding);
+                    let target = decode_string(gu.target, encoding);
+                    let kind = if target.starts_with("_level") { "load_movie" } else { "get_url" };
+                    calls.push((url, kind, target));
+                    pushed.clear();
+                },
+                Action::GetUrl2(gu) => {
+                    let target = pushed.pop().flatten();
+                    let url = pushed.pop().flatten();
+                    if let (Some(url), Some(target)) = (url, target) {
+                        let kind = if gu.is_load_vars() {
+                            "load_variables"
+                        } else if gu.is_target_sprite() {
+                            "load_movie"
+                        } else {
+                            "get_url"
+                        };
+                        calls.push((url, kind, target));
+                    }
+                    pushed.clear();
+                },
+                _ => pushed.clear(),
+            }
+        });
+
+        for (url, kind, target) in calls {
+            // FSCommands are passed to the host application rather than loaded
+            if url.is_empty() || url.starts_with("FSCommand:") {
+                continue;
+            }
+            self.add(url, kind, location.to_owned(), Some(target));
+        }
+        for string in strings.into_iter().filter(|s| looks_like_url(s)) {
+            self.add(string, "string", location.to_owned(), None);
+        }
+    }
+
+    fn scan_abc(&mut self, location: &str, abc_data: &[u8]) {
+        let Ok(abc) = swf::avm2::read::Reader::new(abc_data).read() else { return };
+        for string in abc.constant_pool.strings.into_iter().filter(|s| looks_like_url(s)) {
+            self.add(string, "string", location.to_owned(), None);
+        }
+    }
+
+    /// Records a reference to a URL and returns it, unless the URL has already been found at the
+    /// same location.
+    fn add(&mut self, url: String, kind: &str, location: String, target: Option<String>) -> Option<&mut JsonValue> {
+        let references = self.urls.entry(url).or_default();
+        let location_value = JsonValue::from(location.as_str());
+        if references.iter().any(|r| r.get("location") == Some(&location_value)) {
+            return None;
+        }
+        let mut reference = JsonValue::new_object();
+        reference.insert("kind", kind);
+        reference.insert("location", location);
+        if let Some(target) = target {
+            reference.insert("target", target);
+        }
+        references.push(reference);
+        references.last_mut()
+    }
+
+    pub fn to_json(&self) -> JsonValue {
+        let mut urls = JsonValue::new_array();
+        for (url, references) in &self.urls {
+            let mut obj = JsonValue::new_object();
+            obj.insert("url", url.as_str());
+            obj.insert("references", JsonValue::Array(references.clone()));
+            urls.push(obj);
+        }
+        urls
+    }
+
+    pub fn write<W: Write>(&self, writer: W) -> Result<(), std::io::Error> {
+        self.to_json().write(writer)
+    }
+}
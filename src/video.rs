@@ -0,0 +1,304 @@
+use std::fmt;
+use std::io::{Read, Write};
+
+use png::{BitDepth, ColorType};
+use swf::{DefineVideoStream, VideoCodec, VideoDeblocking};
+use swfextract::cancel::{Cancellation, Cancelled};
+
+use crate::json::JsonValue;
+
+
+#[derive(Debug)]
+pub(crate) enum Error {
+    Truncated,
+    EmptyImage,
+    ZlibDecoding(std::io::Error),
+    BlockSize { expected: usize, obtained: usize },
+    UnsupportedColorDepth(u8),
+    UnsupportedZlibPriming,
+    PngEncoding(png::EncodingError),
+    Cancelled,
+}
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Truncated => write!(f, "video packet is truncated"),
+            Self::EmptyImage => write!(f, "video packet declares an image without pixels"),
+            Self::ZlibDecoding(e) => write!(f, "zlib decoding error: {}", e),
+            Self::BlockSize { expected, obtained }
+                => write!(f, "block decompressed to {} bytes, expected {}", obtained, expected),
+            Self::UnsupportedColorDepth(d) => write!(f, "unsupported color depth {}", d),
+            Self::UnsupportedZlibPriming => write!(f, "zlib-primed blocks are not supported"),
+            Self::PngEncoding(e) => write!(f, "PNG encoding error: {}", e),
+            Self::Cancelled => write!(f, "{}", Cancelled),
+        }
+    }
+}
+impl std::error::Error for Error {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Self::Truncated => None,
+            Self::EmptyImage => None,
+            Self::ZlibDecoding(e) => Some(e),
+            Self::BlockSize { .. } => None,
+            Self::UnsupportedColorDepth(_) => None,
+            Self::UnsupportedZlibPriming => None,
+            Self::PngEncoding(e) => Some(e),
+            Self::Cancelled => None,
+        }
+    }
+}
+impl From<png::EncodingError> for Error {
+    fn from(value: png::EncodingError) -> Self { Self::PngEncoding(value) }
+}
+impl From<Cancelled> for Error {
+    fn from(_: Cancelled) -> Self { Self::Cancelled }
+}
+
+
+/// Reads consecutive fields from a video packet.
+struct PacketReader<'a> {
+    data: &'a [u8],
+}
+impl<'a> PacketReader<'a> {
+    fn read_u8(&mut self) -> Result<u8, Error> {
+        let (&value, rest) = self.data.split_first().ok_or(Error::Truncated)?;
+        self.data = rest;
+        Ok(value)
+    }
+
+    fn read_u16_be(&mut self) -> Result<u16, Error> {
+        let bytes = self.read_slice(2)?;
+        Ok(u16::from_be_bytes([bytes[0], bytes[1]]))
+    }
+
+    fn read_slice(&mut self, len: usize) -> Result<&'a [u8], Error> {
+        if self.data.len() < len {
+            return Err(Error::Truncated);
+        }
+        let (slice, rest) = self.data.split_at(len);
+        self.data = rest;
+        Ok(slice)
+    }
+}
+
+
+/// Decodes Screen Video (version 1 and 2) packets into a sequence of RGB images.
+///
+/// Screen Video splits the image into blocks, each of which is zlib-compressed separately;
+/// blocks that have not changed since the previous frame are omitted from the packet. The
+/// decoder therefore keeps the previous image around and updates it with each packet.
+pub(crate) struct ScreenVideoDecoder {
+    is_v2: bool,
+    width: usize,
+    height: usize,
+
+    /// RGB pixels, top row first.
+    pixels: Vec<u8>,
+}
+impl ScreenVideoDecoder {
+    pub fn new(width: u16, height: u16, is_v2: bool) -> Self {
+        let width = usize::from(width);
+        let height = usize::from(height);
+        Self {
+            is_v2,
+            width,
+            height,
+            pixels: vec![0; 3 * width * height],
+        }
+    }
+
+    /// Applies the given packet (the payload of a VideoFrame tag) to the current image.
+    ///
+    /// Returns whether the packet updated every block of the image, i.e. was a keyframe. The
+    /// cancellation is checked before each block.
+    pub fn decode_frame(&mut self, data: &[u8], cancellation: &Cancellation) -> Result<bool, Error> {
+        let mut reader = PacketReader { data };
+
+        let width_field = reader.read_u16_be()?;
+        let height_field = reader.read_u16_be()?;
+        let block_width = (usize::from(width_field >> 12) + 1) * 16;
+        let image_width = usize::from(width_field & 0x0FFF);
+        let block_height = (usize::from(height_field >> 12) + 1) * 16;
+        let image_height = usize::from(height_field & 0x0FFF);
+        if image_width == 0 || image_height == 0 {
+            return Err(Error::EmptyImage);
+        }
+
+        if image_width != self.width || image_height != self.height {
+            // the dimensions in the packet are authoritative
+            self.width = image_width;
+            self.height = image_height;
+            self.pixels = vec![0; 3 * image_width * image_height];
+        }
+
+        if self.is_v2 {
+            let flags = reader.read_u8()?;
+            let has_palette_info = flags & 0b01 != 0;
+            if has_palette_info {
+                // we only decode 24-bit blocks, which do not use the palette
+                let palette_size = reader.read_u16_be()?;
+                reader.read_slice(palette_size.into())?;
+            }
+        }
+
+        let block_columns = image_width.div_ceil(block_width);
+        let block_rows = image_height.div_ceil(block_height);
+
+        let mut is_keyframe = true;
+
+        // blocks are stored starting at the bottom left of the image, row by row
+        for block_row in 0..block_rows {
+            for block_column in 0..block_columns {
+                cancellation.check()?;
+                let mut data_size = usize::from(reader.read_u16_be()?);
+                if data_size == 0 {
+                    // unchanged since the previous frame
+                    is_keyframe = false;
+                    continue;
+                }
+
+                let x = block_column * block_width;
+                let bottom = image_height - block_row * block_height;
+                let this_width = block_width.min(image_width - x);
+                let this_height = block_height.min(bottom);
+
+                // range of rows (counted from the bottom of the block) contained in the data
+                let mut row_start = 0;
+                let mut row_count = this_height;
+                if self.is_v2 {
+                    let format = reader.read_u8()?;
+                    data_size -= 1;
+                    let color_depth = (format >> 3) & 0b11;
+                    let has_diff_blocks = format & 0b100 != 0;
+                    let zlib_prime_current = format & 0b010 != 0;
+                    let zlib_prime_previous = format & 0b001 != 0;
+                    if color_depth != 0 {
+                        return Err(Error::UnsupportedColorDepth(color_depth));
+                    }
+                    if zlib_prime_current || zlib_prime_previous {
+                        return Err(Error::UnsupportedZlibPriming);
+                    }
+                    if has_diff_blocks {
+                        is_keyframe = false;
+                        row_start = usize::from(reader.read_u8()?);
+                        row_count = usize::from(reader.read_u8()?);
+                        data_size = data_size.checked_sub(2).ok_or(Error::Truncated)?;
+                    }
+                }
+
+                let compressed = reader.read_slice(data_size)?;
+                let expected = 3 * this_width * row_count;
+                // the data may expand far beyond the block; anything past it is of no use
+                let mut block_pixels = Vec::new();
+                flate2::read::ZlibDecoder::new(compressed)
+                    .take(expected as u64 + 1)
+                    .read_to_end(&mut block_pixels)
+                    .map_err(Error::ZlibDecoding)?;
+                if block_pixels.len() < expected || row_start + row_count > this_height {
+                    return Err(Error::BlockSize { expected, obtained: block_pixels.len() });
+                }
+
+                // block rows are stored bottom-up, pixels as BGR
+                for (i, block_line) in block_pixels.chunks_exact(3 * this_width).take(row_count).enumerate() {
+                    let y = bottom - 1 - (row_start + i);
+                    let line_start = 3 * (y * image_width + x);
+                    let line = &mut self.pixels[line_start..line_start + 3 * this_width];
+                    for (pixel, bgr) in line.chunks_exact_mut(3).zip(block_line.chunks_exact(3)) {
+                        pixel[0] = bgr[2];
+                        pixel[1] = bgr[1];
+                        pixel[2] = bgr[0];
+                    }
+                }
+            }
+        }
+
+        Ok(is_keyframe)
+    }
+
+    /// Writes the current image as a PNG file.
+    pub fn write_png<W: Write>(&self, write: W) -> Result<(), Error> {
+        let mut png = png::Encoder::new(
+            write,
+            self.width.try_into().unwrap(),
+            self.height.try_into().unwrap(),
+        );
+        png.set_color(ColorType::Rgb);
+        png.set_depth(BitDepth::Eight);
+        let mut writer = png.write_header()?;
+        writer.write_image_data(&self.pixels)?;
+        Ok(())
+    }
+}
+
+
+/// Collects the properties of a video stream into a JSON object.
+///
+/// The list of keyframes is left empty; it is filled in as the frames are processed.
+pub(crate) fn video_stream_to_json(dvs: &DefineVideoStream, is_avc: bool) -> JsonValue {
+    let codec = if is_avc {
+        "H.264"
+    } else {
+        match dvs.codec {
+            VideoCodec::H263 => "H.263",
+            VideoCodec::ScreenVideo => "Screen Video",
+            VideoCodec::Vp6 => "VP6",
+            VideoCodec::Vp6WithAlpha => "VP6 with alpha",
+            VideoCodec::ScreenVideoV2 => "Screen Video V2",
+        }
+    };
+    let deblocking = match dvs.deblocking {
+        VideoDeblocking::UseVideoPacketValue => "packet",
+        VideoDeblocking::None => "none",
+        VideoDeblocking::Level1 => "level1",
+        VideoDeblocking::Level2 => "level2",
+        VideoDeblocking::Level3 => "level3",
+        VideoDeblocking::Level4 => "level4",
+    };
+
+    let mut obj = JsonValue::new_object();
+    obj.insert("codec", codec);
+    obj.insert("width", dvs.width);
+    obj.insert("height", dvs.height);
+    obj.insert("num_frames", dvs.num_frames);
+    obj.insert("is_smoothed", dvs.is_smoothed);
+    obj.insert("deblocking", deblocking);
+    obj.insert("keyframes", JsonValue::new_array());
+    obj
+}
+
+
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+
+    #[test]
+    fn empty_image_is_rejected() {
+        let mut decoder = ScreenVideoDecoder::new(16, 16, false);
+        let result = decoder.decode_frame(&[0x00, 0x00, 0x00, 0x10], &Cancellation::new());
+        assert!(matches!(result, Err(Error::EmptyImage)), "{:?}", result);
+    }
+
+
+    #[test]
+    fn oversized_block_is_not_inflated_in_full() {
+        use flate2::write::ZlibEncoder;
+
+        let mut encoder = ZlibEncoder::new(Vec::new(), flate2::Compression::best());
+        encoder.write_all(&vec![0; 1 << 20]).unwrap();
+        let compressed = encoder.finish().unwrap();
+        // a single 16x16 block
+        let mut packet = vec![0x00, 0x10, 0x00, 0x10];
+        packet.extend(u16::try_from(compressed.len()).unwrap().to_be_bytes());
+        packet.extend(&compressed);
+
+        let mut decoder = ScreenVideoDecoder::new(16, 16, false);
+        assert!(decoder.decode_frame(&packet, &Cancellation::new()).unwrap());
+        let mut png = Vec::new();
+        decoder.write_png(&mut png).unwrap();
+        assert!(png.starts_with(b"\x89PNG"));
+    }
+}
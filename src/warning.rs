@@ -0,0 +1,106 @@
+//! Problems that do not prevent extraction, such as damaged data that has been salvaged.
+
+
+use std::fmt;
+
+
+/// The kind of a [`Warning`]. The codes returned by [`WarningCode::as_str`] are stable and meant to
+/// be matched on by tools processing the report of a run; new kinds may be added.
+#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq)]
+#[non_exhaustive]
+pub enum WarningCode {
+    /// Invalid data has been cut out of an MP3 sound.
+    TrimmedAudio,
+
+    /// A font is not embedded and no font standing in for it has been found.
+    MissingFont,
+
+    /// JPEG data refers to the shared JPEG tables, but the movie has none.
+    MissingJpegTables,
+
+    /// An ActionScript 3 (DoABC) block could not be decoded.
+    InvalidAbc,
+
+    /// A tag has been skipped because it is unknown or not supported.
+    SkippedTag,
+
+    /// A bitmap could not be scaled or converted and has been written as is.
+    UnconvertedBitmap,
+
+    /// A damaged bitmap has been salvaged, at least in part.
+    RecoveredBitmap,
+
+    /// A movie stored within a loader could not be decompressed.
+    FailedUnpacking,
+
+    /// The stream sound of a sprite could not be mixed into that of the main timeline.
+    UnmixedStream,
+
+    /// A character has been skipped because it exceeds a resource limit.
+    ExceededLimit,
+
+    /// A video stream could not be decoded and its remaining frames have been skipped.
+    UndecodableVideo,
+
+    /// The rendered frames could not be encoded into a video.
+    FailedEncoding,
+}
+impl WarningCode {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::TrimmedAudio => "trimmed_audio",
+            Self::MissingFont => "missing_font",
+            Self::MissingJpegTables => "missing_jpeg_tables",
+            Self::InvalidAbc => "invalid_abc",
+            Self::SkippedTag => "skipped_tag",
+            Self::UnconvertedBitmap => "unconverted_bitmap",
+            Self::RecoveredBitmap => "recovered_bitmap",
+            Self::FailedUnpacking => "failed_unpacking",
+            Self::UnmixedStream => "unmixed_stream",
+            Self::ExceededLimit => "exceeded_limit",
+            Self::UndecodableVideo => "undecodable_video",
+            Self::FailedEncoding => "failed_encoding",
+        }
+    }
+}
+impl fmt::Display for WarningCode {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+
+/// A problem that did not prevent extraction, as opposed to an error, which did.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct Warning {
+    pub code: WarningCode,
+
+    /// The ID of the character concerned, if any.
+    pub character_id: Option<u16>,
+
+    /// A description of the problem for humans.
+    pub message: String,
+}
+impl Warning {
+    pub fn new(code: WarningCode, message: impl Into<String>) -> Self {
+        Self {
+            code,
+            character_id: None,
+            message: message.into(),
+        }
+    }
+
+    /// Creates a warning concerning the character with the given ID.
+    pub fn about(code: WarningCode, character_id: u16, message: impl Into<String>) -> Self {
+        Self {
+            code,
+            character_id: Some(character_id),
+            message: message.into(),
+        }
+    }
+}
+impl fmt::Display for Warning {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.message)
+    }
+}
@@ -0,0 +1,373 @@
+use std::io::{self, Read, Write};
+
+use flate2::Compression;
+use flate2::Crc;
+use flate2::read::DeflateDecoder;
+use flate2::write::DeflateEncoder;
+use swfextract::read::MAX_MOVIE_LEN;
+
+
+const LOCAL_FILE_HEADER_SIGNATURE: u32 = 0x04034B50;
+const CENTRAL_DIRECTORY_HEADER_SIGNATURE: u32 = 0x02014B50;
+const END_OF_CENTRAL_DIRECTORY_SIGNATURE: u32 = 0x06054B50;
+
+/// Version 2.0, the first to support deflate compression.
+const VERSION_NEEDED: u16 = 20;
+
+/// The file is encrypted.
+const FLAG_ENCRYPTED: u16 = 1 << 0;
+
+/// The names of the files are encoded in UTF-8.
+const FLAG_UTF8: u16 = 1 << 11;
+
+const METHOD_STORED: u16 = 0;
+const METHOD_DEFLATE: u16 = 8;
+
+/// The length of the end of central directory record without the comment following it.
+const END_OF_CENTRAL_DIRECTORY_LENGTH: usize = 22;
+
+/// 1980-01-01 00:00, the earliest time that can be stored; the files are not dated.
+const DOS_TIME: u16 = 0;
+const DOS_DATE: u16 = (1 << 5) | 1;
+
+
+struct CentralDirectoryEntry {
+    name: String,
+    crc: u32,
+    compressed_size: u32,
+    uncompressed_size: u32,
+    offset: u32,
+}
+
+
+fn too_large() -> io::Error {
+    io::Error::other("ZIP archive too large (ZIP64 is not supported)")
+}
+
+
+fn malformed(what: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, format!("malformed ZIP archive: {}", what))
+}
+
+
+fn u16_at(data: &[u8], offset: usize) -> io::Result<u16> {
+    offset.checked_add(2)
+        .and_then(|end| data.get(offset..end))
+        .map(|bytes| u16::from_le_bytes(bytes.try_into().unwrap()))
+        .ok_or_else(|| malformed("truncated"))
+}
+
+
+fn u32_at(data: &[u8], offset: usize) -> io::Result<u32> {
+    offset.checked_add(4)
+        .and_then(|end| data.get(offset..end))
+        .map(|bytes| u32::from_le_bytes(bytes.try_into().unwrap()))
+        .ok_or_else(|| malformed("truncated"))
+}
+
+
+/// Whether the data starts like a ZIP archive (including an empty one).
+pub(crate) fn is_zip(data: &[u8]) -> bool {
+    data.starts_with(&LOCAL_FILE_HEADER_SIGNATURE.to_le_bytes())
+        || data.starts_with(&END_OF_CENTRAL_DIRECTORY_SIGNATURE.to_le_bytes())
+}
+
+
+struct ArchivedFile {
+    name: String,
+    flags: u16,
+    method: u16,
+    crc: u32,
+    compressed_size: u32,
+    uncompressed_size: u32,
+    offset: u32,
+}
+
+
+/// Reads stored and deflate-compressed files from a ZIP archive (or a JAR, which is one) held in
+/// memory.
+pub(crate) struct ZipReader<'a> {
+    data: &'a [u8],
+    files: Vec<ArchivedFile>,
+}
+impl<'a> ZipReader<'a> {
+    pub fn new(data: &'a [u8]) -> io::Result<Self> {
+        // the end of central directory record is followed by a comment of up to 64 KiB
+        let last_start = data.len().checked_sub(END_OF_CENTRAL_DIRECTORY_LENGTH)
+            .ok_or_else(|| malformed("no end of central directory"))?;
+        let first_start = last_start.saturating_sub(usize::from(u16::MAX));
+        let signature = END_OF_CENTRAL_DIRECTORY_SIGNATURE.to_le_bytes();
+        let end = (first_start..=last_start).rev()
+            .find(|&start| data[start..].starts_with(&signature))
+            .ok_or_else(|| malformed("no end of central directory"))?;
+
+        let entry_count = u16_at(data, end + 10)?;
+        let directory_offset = u32_at(data, end + 16)?;
+        if entry_count == u16::MAX || directory_offset == u32::MAX {
+            return Err(too_large());
+        }
+
+        let mut files = Vec::with_capacity(usize::from(entry_count));
+        let mut pos = usize::try_from(directory_offset).unwrap();
+        for _ in 0..entry_count {
+            if u32_at(data, pos)? != CENTRAL_DIRECTORY_HEADER_SIGNATURE {
+                return Err(malformed("bad central directory header"));
+            }
+            let flags = u16_at(data, pos + 8)?;
+            let name_length = usize::from(u16_at(data, pos + 28)?);
+            let extra_length = usize::from(u16_at(data, pos + 30)?);
+            let comment_length = usize::from(u16_at(data, pos + 32)?);
+            let name_start = pos.checked_add(46).ok_or_else(|| malformed("truncated"))?;
+            let name_bytes = name_start.checked_add(name_length)
+                .and_then(|name_end| data.get(name_start..name_end))
+                .ok_or_else(|| malformed("truncated"))?;
+            // names not flagged as UTF-8 are in code page 437, which agrees with UTF-8 on ASCII
+            let name = String::from_utf8_lossy(name_bytes).into_owned();
+            files.push(ArchivedFile {
+                name,
+                flags,
+                method: u16_at(data, pos + 10)?,
+                crc: u32_at(data, pos + 16)?,
+                compressed_size: u32_at(data, pos + 20)?,
+                uncompressed_size: u32_at(data, pos + 24)?,
+                offset: u32_at(data, pos + 42)?,
+            });
+            pos = (name_start + name_length).checked_add(extra_length + comment_length)
+                .ok_or_else(|| malformed("truncated"))?;
+        }
+
+        Ok(Self {
+            data,
+            files,
+        })
+    }
+
+    /// Returns the paths of the files in the archive, in the order in which they are stored,
+    /// skipping directories.
+    pub fn file_names(&self) -> impl Iterator<Item = &str> {
+        self.files.iter()
+            .map(|file| file.name.as_str())
+            .filter(|name| !name.ends_with('/'))
+    }
+
+    /// Reads and decompresses the file with the given path.
+    pub fn read_file(&self, name: &str) -> io::Result<Vec<u8>> {
+        let file = self.files.iter()
+            .find(|file| file.name == name)
+            .ok_or_else(|| io::Error::new(io::ErrorKind::NotFound, format!("no file {:?} in ZIP archive", name)))?;
+        if file.flags & FLAG_ENCRYPTED != 0 {
+            return Err(io::Error::other(format!("{:?} is encrypted", name)));
+        }
+
+        let header = usize::try_from(file.offset).unwrap();
+        if u32_at(self.data, header)? != LOCAL_FILE_HEADER_SIGNATURE {
+            return Err(malformed("bad local file header"));
+        }
+        // the lengths in the local header may differ from those in the central directory
+        let name_length = usize::from(u16_at(self.data, header + 26)?);
+        let extra_length = usize::from(u16_at(self.data, header + 28)?);
+        let compressed = header.checked_add(30 + name_length + extra_length)
+            .and_then(|start| Some(start..start.checked_add(usize::try_from(file.compressed_size).ok()?)?))
+            .and_then(|range| self.data.get(range))
+            .ok_or_else(|| malformed("truncated"))?;
+
+        // the files are read into memory as a whole; no movie may be larger
+        if file.uncompressed_size > MAX_MOVIE_LEN {
+            return Err(io::Error::new(io::ErrorKind::InvalidData, format!(
+                "{:?} claims to be {} bytes long, more than the limit of {}", name, file.uncompressed_size, MAX_MOVIE_LEN,
+            )));
+        }
+
+        let data = match file.method {
+            METHOD_STORED => compressed.to_vec(),
+            METHOD_DEFLATE => {
+                // the size in the header is not trusted for reserving memory; decompressing more
+                // than it gives is a mismatch caught below
+                let mut data = Vec::new();
+                DeflateDecoder::new(compressed)
+                    .take(u64::from(file.uncompressed_size) + 1)
+                    .read_to_end(&mut data)?;
+                data
+            },
+            other => return Err(io::Error::other(format!("{:?} uses unsupported compression method {}", name, other))),
+        };
+
+        let mut crc = Crc::new();
+        crc.update(&data);
+        if u64::try_from(data.len()).unwrap() != u64::from(file.uncompressed_size) || crc.sum() != file.crc {
+            return Err(malformed(&format!("checksum mismatch in {:?}", name)));
+        }
+        Ok(data)
+    }
+}
+
+
+/// Writes a ZIP archive of deflate-compressed files.
+pub(crate) struct ZipWriter<W: Write> {
+    writer: W,
+    offset: u64,
+    entries: Vec<CentralDirectoryEntry>,
+}
+impl<W: Write> ZipWriter<W> {
+    pub fn new(writer: W) -> Self {
+        Self {
+            writer,
+            offset: 0,
+            entries: Vec::new(),
+        }
+    }
+
+    fn write_all(&mut self, data: &[u8]) -> io::Result<()> {
+        self.writer.write_all(data)?;
+        self.offset += u64::try_from(data.len()).unwrap();
+        Ok(())
+    }
+
+    fn write_u16(&mut self, value: u16) -> io::Result<()> {
+        self.write_all(&value.to_le_bytes())
+    }
+
+    fn write_u32(&mut self, value: u32) -> io::Result<()> {
+        self.write_all(&value.to_le_bytes())
+    }
+
+    /// Adds a file with the given path (using forward slashes) and contents to the archive.
+    pub fn add_file(&mut self, name: &str, data: &[u8]) -> io::Result<()> {
+        let mut crc = Crc::new();
+        crc.update(data);
+        let mut encoder = DeflateEncoder::new(Vec::new(), Compression::default());
+        encoder.write_all(data)?;
+        let compressed = encoder.finish()?;
+
+        let name_length = u16::try_from(name.len()).map_err(|_| too_large())?;
+        let entry = CentralDirectoryEntry {
+            name: name.to_owned(),
+            crc: crc.sum(),
+            compressed_size: u32::try_from(compressed.len()).map_err(|_| too_large())?,
+            uncompressed_size: u32::try_from(data.len()).map_err(|_| too_large())?,
+            offset: u32::try_from(self.offset).map_err(|_| too_large())?,
+        };
+
+        self.write_u32(LOCAL_FILE_HEADER_SIGNATURE)?;
+        self.write_u16(VERSION_NEEDED)?;
+        self.write_u16(FLAG_UTF8)?;
+        self.write_u16(METHOD_DEFLATE)?;
+        self.write_u16(DOS_TIME)?;
+        self.write_u16(DOS_DATE)?;
+        self.write_u32(entry.crc)?;
+        self.write_u32(entry.compressed_size)?;
+        self.write_u32(entry.uncompressed_size)?;
+        self.write_u16(name_length)?;
+        self.write_u16(0)?; // extra field length
+        self.write_all(name.as_bytes())?;
+        self.write_all(&compressed)?;
+
+        self.entries.push(entry);
+        Ok(())
+    }
+
+    /// Writes the central directory and returns the underlying writer.
+    pub fn finish(mut self) -> io::Result<W> {
+        let directory_offset = u32::try_from(self.offset).map_err(|_| too_large())?;
+        let entry_count = u16::try_from(self.entries.len()).map_err(|_| too_large())?;
+
+        let entries = std::mem::take(&mut self.entries);
+        for entry in &entries {
+            self.write_u32(CENTRAL_DIRECTORY_HEADER_SIGNATURE)?;
+            self.write_u16(VERSION_NEEDED)?; // version made by
+            self.write_u16(VERSION_NEEDED)?;
+            self.write_u16(FLAG_UTF8)?;
+            self.write_u16(METHOD_DEFLATE)?;
+            self.write_u16(DOS_TIME)?;
+            self.write_u16(DOS_DATE)?;
+            self.write_u32(entry.crc)?;
+            self.write_u32(entry.compressed_size)?;
+            self.write_u32(entry.uncompressed_size)?;
+            self.write_u16(u16::try_from(entry.name.len()).unwrap())?;
+            self.write_u16(0)?; // extra field length
+            self.write_u16(0)?; // comment length
+            self.write_u16(0)?; // disk number
+            self.write_u16(0)?; // internal attributes
+            self.write_u32(0)?; // external attributes
+            self.write_u32(entry.offset)?;
+            self.write_all(entry.name.as_bytes())?;
+        }
+        let directory_size = u32::try_from(self.offset - u64::from(directory_offset)).map_err(|_| too_large())?;
+
+        self.write_u32(END_OF_CENTRAL_DIRECTORY_SIGNATURE)?;
+        self.write_u16(0)?; // this disk
+        self.write_u16(0)?; // disk with the central directory
+        self.write_u16(entry_count)?;
+        self.write_u16(entry_count)?;
+        self.write_u32(directory_size)?;
+        self.write_u32(directory_offset)?;
+        self.write_u16(0)?; // comment length
+        self.writer.flush()?;
+        Ok(self.writer)
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+
+    fn archive(files: &[(&str, &[u8])]) -> Vec<u8> {
+        let mut zip = ZipWriter::new(Vec::new());
+        for (name, data) in files {
+            zip.add_file(name, data).unwrap();
+        }
+        zip.finish().unwrap()
+    }
+
+
+    #[test]
+    fn round_trip() {
+        let data = archive(&[("a.swf", b"FWS"), ("dir/b.txt", &[7; 1000])]);
+        assert!(is_zip(&data));
+        let reader = ZipReader::new(&data).unwrap();
+        assert_eq!(reader.file_names().collect::<Vec<_>>(), ["a.swf", "dir/b.txt"]);
+        assert_eq!(reader.read_file("a.swf").unwrap(), b"FWS");
+        assert_eq!(reader.read_file("dir/b.txt").unwrap(), [7; 1000]);
+        assert_eq!(reader.read_file("c").unwrap_err().kind(), io::ErrorKind::NotFound);
+    }
+
+
+    #[test]
+    fn understated_size_stops_decompression() {
+        let mut data = archive(&[("bomb", &[0; 100_000])]);
+        // the uncompressed size in the central directory, which follows the local header and data
+        let directory = data.len() - END_OF_CENTRAL_DIRECTORY_LENGTH - 46 - 4;
+        data[directory + 24..directory + 28].copy_from_slice(&10u32.to_le_bytes());
+        let reader = ZipReader::new(&data).unwrap();
+        assert_eq!(reader.read_file("bomb").unwrap_err().kind(), io::ErrorKind::InvalidData);
+    }
+
+
+    #[test]
+    fn overstated_size_is_refused() {
+        let mut data = archive(&[("bomb", &[0; 100_000])]);
+        let directory = data.len() - END_OF_CENTRAL_DIRECTORY_LENGTH - 46 - 4;
+        data[directory + 24..directory + 28].copy_from_slice(&(MAX_MOVIE_LEN + 1).to_le_bytes());
+        let reader = ZipReader::new(&data).unwrap();
+        let error = reader.read_file("bomb").unwrap_err();
+        assert_eq!(error.kind(), io::ErrorKind::InvalidData);
+        assert!(error.to_string().contains("claims"), "{}", error);
+    }
+
+
+    #[test]
+    fn lengths_beyond_the_archive() {
+        let mut data = archive(&[("a", b"abc")]);
+        let directory = data.len() - END_OF_CENTRAL_DIRECTORY_LENGTH - 46 - 1;
+        // the compressed size
+        data[directory + 20..directory + 24].copy_from_slice(&u32::MAX.to_le_bytes());
+        let reader = ZipReader::new(&data).unwrap();
+        assert_eq!(reader.read_file("a").unwrap_err().kind(), io::ErrorKind::InvalidData);
+
+        // the length of the name
+        data[directory + 28..directory + 30].copy_from_slice(&u16::MAX.to_le_bytes());
+        assert!(ZipReader::new(&data).is_err());
+    }
+}
@@ -0,0 +1,92 @@
+//! Extracting the fixtures on a background thread.
+
+
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::task::{Context, Poll, Wake, Waker};
+use std::thread::{self, Thread};
+
+use swfextract::background::{BackgroundExtraction, Error, Outcome};
+use swfextract::cancel::Cancellation;
+use swfextract::extract::{extract_from_bytes, Decoded, ExtractOptions};
+
+
+fn fixture(name: &str) -> PathBuf {
+    Path::new(env!("CARGO_MANIFEST_DIR")).join("tests").join("fixtures").join(name)
+}
+
+
+/// Wakes a thread parked while waiting for the extraction.
+struct ThreadWaker(Thread);
+impl Wake for ThreadWaker {
+    fn wake(self: Arc<Self>) {
+        self.0.unpark();
+    }
+}
+
+
+#[test]
+fn items_match_extraction_in_memory() {
+    let data = std::fs::read(fixture("lossless.swf")).expect("failed to read fixture");
+    let expected = extract_from_bytes(&data).expect("failed to extract fixture");
+
+    let mut extraction = BackgroundExtraction::spawn(data, ExtractOptions::default(), Cancellation::new());
+    let (mut assets, mut warnings) = (Vec::new(), Vec::new());
+    for item in extraction.by_ref() {
+        match item {
+            Decoded::Asset(asset) => assets.push(asset),
+            Decoded::Warning(warning) => warnings.push(warning),
+        }
+    }
+    assert_eq!(assets, expected.assets);
+    assert_eq!(warnings, expected.warnings);
+    assert!(matches!(extraction.take_outcome(), Some(Outcome::Finished)));
+    assert_eq!(extraction.try_next(), Poll::Ready(None));
+}
+
+
+#[test]
+fn polling_from_a_task() {
+    let mut extraction = BackgroundExtraction::spawn_file(
+        fixture("jpeg_tables.swf"), ExtractOptions::default(), Cancellation::new(),
+    );
+    let waker = Waker::from(Arc::new(ThreadWaker(thread::current())));
+    let mut cx = Context::from_waker(&waker);
+    let mut ids = Vec::new();
+    loop {
+        match extraction.poll_next(&mut cx) {
+            Poll::Ready(Some(Decoded::Asset(asset))) => ids.push(asset.id),
+            Poll::Ready(Some(Decoded::Warning(warning))) => panic!("unexpected warning {:?}", warning),
+            Poll::Ready(None) => break,
+            Poll::Pending => thread::park(),
+        }
+    }
+    assert_eq!(ids, [1]);
+    assert!(matches!(extraction.take_outcome(), Some(Outcome::Finished)));
+}
+
+
+#[test]
+fn cancelled_extraction_yields_nothing() {
+    let cancellation = Cancellation::new();
+    cancellation.cancel();
+    let mut extraction = BackgroundExtraction::spawn_file(fixture("lossless.swf"), ExtractOptions::default(), cancellation);
+    assert_eq!(extraction.by_ref().count(), 0);
+    assert!(matches!(extraction.take_outcome(), Some(Outcome::Cancelled)));
+}
+
+
+#[test]
+fn unreadable_movies_fail() {
+    let mut extraction = BackgroundExtraction::spawn_file(
+        fixture("missing.swf"), ExtractOptions::default(), Cancellation::new(),
+    );
+    assert_eq!(extraction.next(), None);
+    assert!(matches!(extraction.take_outcome(), Some(Outcome::Failed(Error::Io(_)))));
+
+    let mut extraction = BackgroundExtraction::spawn(
+        b"FWS\x0A\x08\x00\x00\x00".to_vec(), ExtractOptions::default(), Cancellation::new(),
+    );
+    assert_eq!(extraction.next(), None);
+    assert!(matches!(extraction.take_outcome(), Some(Outcome::Failed(Error::Read(_)))));
+}
@@ -0,0 +1,165 @@
+//! Extracting the small legacy movies in `tests/fixtures`, both through the library and the
+//! command-line tool.
+
+
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+use swf::AudioCompression;
+use swfextract::extract::{extract_from_bytes, Asset, AssetData, Extraction};
+use swfextract::warning::WarningCode;
+
+
+fn fixture(name: &str) -> PathBuf {
+    Path::new(env!("CARGO_MANIFEST_DIR")).join("tests").join("fixtures").join(name)
+}
+
+
+fn extract_fixture(name: &str) -> Extraction {
+    let data = fs::read(fixture(name)).expect("failed to read fixture");
+    extract_from_bytes(&data).expect("failed to extract fixture")
+}
+
+
+fn asset(extraction: &Extraction, id: u16) -> &Asset {
+    extraction.assets.iter()
+        .find(|asset| asset.id == id)
+        .unwrap_or_else(|| panic!("asset {} missing", id))
+}
+
+
+fn bitmap_pixels(asset: &Asset) -> (u32, u32, Vec<[u8; 4]>) {
+    match &asset.data {
+        AssetData::Bitmap { width, height, rgba } => {
+            let pixels = rgba.chunks_exact(4)
+                .map(|pixel| pixel.try_into().unwrap())
+                .collect();
+            (*width, *height, pixels)
+        },
+        other => panic!("asset {} is not a bitmap: {:?}", asset.id, other),
+    }
+}
+
+
+/// Runs the command-line tool on a fixture, returning the directory the files were written into.
+fn run_extract(name: &str) -> PathBuf {
+    let output_dir = std::env::temp_dir()
+        .join(format!("swfextract-test-{}-{}", std::process::id(), name));
+    let _ = fs::remove_dir_all(&output_dir);
+    let output = Command::new(env!("CARGO_BIN_EXE_swfextract"))
+        .arg("extract")
+        .arg("--output-dir").arg(&output_dir)
+        .arg(fixture(name))
+        .output()
+        .expect("failed to run swfextract");
+    assert!(
+        output.status.success(),
+        "swfextract failed on {}: {}\n{}", name, output.status, String::from_utf8_lossy(&output.stderr),
+    );
+    output_dir
+}
+
+
+#[test]
+fn jpeg_with_separate_tables() {
+    let extraction = extract_fixture("jpeg_tables.swf");
+    assert!(extraction.warnings.is_empty(), "{:?}", extraction.warnings);
+    assert!(!extraction.cancelled);
+
+    let (width, height, pixels) = bitmap_pixels(asset(&extraction, 1));
+    assert_eq!((width, height), (6, 5));
+    assert_eq!(pixels.len(), 30);
+    assert!(pixels.iter().all(|p| p[0] > 200 && p[1] < 60 && p[2] < 60 && p[3] == 255), "{:?}", pixels);
+}
+
+
+#[test]
+fn lossless_formats() {
+    let extraction = extract_fixture("lossless.swf");
+
+    // colormapped, with rows padded to 32 bits
+    let (width, height, pixels) = bitmap_pixels(asset(&extraction, 1));
+    assert_eq!((width, height), (5, 4));
+    assert_eq!(&pixels[..4], &[[255, 0, 0, 255], [0, 255, 0, 255], [0, 0, 255, 255], [255, 255, 0, 255]]);
+
+    // RGB15
+    let (_, _, pixels) = bitmap_pixels(asset(&extraction, 2));
+    assert_eq!(&pixels[..4], &[[255, 0, 0, 255], [255, 0, 0, 255], [0, 255, 0, 255], [0, 255, 0, 255]]);
+
+    // RGB24 stored as XRGB
+    let (_, _, pixels) = bitmap_pixels(asset(&extraction, 3));
+    assert_eq!(&pixels[..4], &[[0, 0, 0, 255], [0, 50, 0, 255], [0, 100, 0, 255], [0, 150, 0, 255]]);
+
+    // premultiplied ARGB
+    let (_, _, pixels) = bitmap_pixels(asset(&extraction, 4));
+    assert!(pixels.iter().all(|p| *p == [255, 0, 127, 128]), "{:?}", pixels);
+}
+
+
+#[test]
+fn truncated_lossless_keeps_complete_rows() {
+    let extraction = extract_fixture("lossless.swf");
+    for id in [11, 12, 13] {
+        assert!(
+            extraction.warnings.iter().any(|w| w.code == WarningCode::RecoveredBitmap && w.character_id == Some(id)),
+            "no recovery warning for {}: {:?}", id, extraction.warnings,
+        );
+    }
+
+    // the rows that could be decompressed match the intact copy
+    let (_, _, intact) = bitmap_pixels(asset(&extraction, 1));
+    let (width, height, recovered) = bitmap_pixels(asset(&extraction, 11));
+    assert_eq!((width, height), (5, 4));
+    assert_eq!(&recovered[..10], &intact[..10]);
+    assert!(recovered[10..].iter().all(|p| p[3] == 0));
+}
+
+
+#[test]
+fn adpcm_sound_is_decoded() {
+    let extraction = extract_fixture("adpcm_sound.swf");
+    assert!(extraction.warnings.is_empty(), "{:?}", extraction.warnings);
+    match &asset(&extraction, 1).data {
+        AssetData::Sound { format, sample_count, data } => {
+            assert_eq!(format.compression, AudioCompression::Uncompressed);
+            assert!(format.is_16_bit);
+            assert!(!format.is_stereo);
+            assert_eq!(format.sample_rate, 11025);
+            assert_eq!(*sample_count, 1000);
+            assert_eq!(data.len(), 2000);
+        },
+        other => panic!("not a sound: {:?}", other),
+    }
+}
+
+
+#[test]
+fn command_line_writes_assets_and_manifest() {
+    let output_dir = run_extract("jpeg_tables.swf");
+    let jpeg = fs::read(output_dir.join("1.jpeg")).expect("failed to read JPEG");
+    assert!(jpeg.starts_with(b"\xFF\xD8"));
+    assert!(output_dir.join("2.svg").is_file());
+    let manifest = fs::read_to_string(output_dir.join("manifest.json")).expect("failed to read manifest");
+    assert!(manifest.contains("\"tag\": \"DefineBits\""), "{}", manifest);
+    assert!(manifest.contains("\"bitmap\": 1"), "{}", manifest);
+    fs::remove_dir_all(&output_dir).expect("failed to remove output");
+
+    let output_dir = run_extract("exported_sound.swf");
+    let wav = fs::read(output_dir.join("1_boing.wav")).expect("failed to read sound");
+    assert!(wav.starts_with(b"RIFF") && wav[8..12] == *b"WAVE");
+    let manifest = fs::read_to_string(output_dir.join("manifest.json")).expect("failed to read manifest");
+    assert!(manifest.contains("\"name\": \"boing\""), "{}", manifest);
+    fs::remove_dir_all(&output_dir).expect("failed to remove output");
+}
+
+
+#[test]
+fn command_line_writes_lossless_bitmaps() {
+    let output_dir = run_extract("lossless.swf");
+    for id in [1, 2, 3, 4, 11, 12, 13, 14] {
+        let png = fs::read(output_dir.join(format!("{}.png", id))).expect("failed to read PNG");
+        assert!(png.starts_with(b"\x89PNG"), "{} is not a PNG", id);
+    }
+    fs::remove_dir_all(&output_dir).expect("failed to remove output");
+}
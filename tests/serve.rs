@@ -0,0 +1,82 @@
+//! Posting the fixtures to the HTTP API of the `serve` subcommand.
+
+
+use std::io::{BufRead, BufReader, Read, Write};
+use std::net::{Shutdown, TcpStream};
+use std::path::Path;
+use std::process::{Child, Command, Stdio};
+
+
+/// The server process, which is killed when the test ends.
+struct Server {
+    child: Child,
+    address: String,
+}
+impl Server {
+    fn start() -> Self {
+        let mut child = Command::new(env!("CARGO_BIN_EXE_swfextract"))
+            .args(["serve", "--max-connections", "2", "--socket-timeout", "5s", "127.0.0.1:0"])
+            .stdout(Stdio::piped())
+            .spawn()
+            .expect("failed to start server");
+        let mut line = String::new();
+        BufReader::new(child.stdout.as_mut().unwrap()).read_line(&mut line)
+            .expect("failed to read server address");
+        let address = line.trim().strip_prefix("listening on ")
+            .unwrap_or_else(|| panic!("unexpected output {:?}", line))
+            .to_owned();
+        Self { child, address }
+    }
+
+    /// Sends a request, returning the status code, the headers and the body of the response.
+    fn request(&self, request: &[u8]) -> (u16, String, Vec<u8>) {
+        let mut stream = TcpStream::connect(&self.address).expect("failed to connect to server");
+        stream.write_all(request).expect("failed to send request");
+        stream.shutdown(Shutdown::Write).expect("failed to end request");
+        let mut response = Vec::new();
+        stream.read_to_end(&mut response).expect("failed to read response");
+        let header_end = response.windows(4).position(|w| w == b"\r\n\r\n").expect("no end of header");
+        let head = String::from_utf8(response[..header_end].to_vec()).expect("header not UTF-8");
+        let status = head[9..12].parse().expect("invalid status");
+        (status, head, response[header_end+4..].to_vec())
+    }
+}
+impl Drop for Server {
+    fn drop(&mut self) {
+        let _ = self.child.kill();
+        let _ = self.child.wait();
+    }
+}
+
+
+#[test]
+fn posted_movie_is_returned_as_zip() {
+    let server = Server::start();
+    let swf = std::fs::read(Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/fixtures/exported_sound.swf"))
+        .expect("failed to read fixture");
+    let mut request = format!("POST /extract HTTP/1.1\r\nContent-Length: {}\r\n\r\n", swf.len()).into_bytes();
+    request.extend(&swf);
+
+    let (status, head, body) = server.request(&request);
+    assert_eq!(status, 200, "{}", String::from_utf8_lossy(&body));
+    assert!(head.contains("X-Extraction-Status: complete"), "{}", head);
+    assert!(head.contains(&format!("Content-Length: {}", body.len())), "{}", head);
+    assert!(body.starts_with(b"PK\x03\x04"));
+    for name in [&b"1_boing.wav"[..], b"manifest.json"] {
+        assert!(body.windows(name.len()).any(|w| w == name), "{} missing", String::from_utf8_lossy(name));
+    }
+}
+
+
+#[test]
+fn invalid_requests_are_rejected() {
+    let server = Server::start();
+    let (status, _, _) = server.request(b"POST /extract HTTP/1.1\r\n\r\n");
+    assert_eq!(status, 411);
+    let (status, _, _) = server.request(b"POST /extract HTTP/1.1\r\nContent-Length: 8\r\n\r\nFWS");
+    assert_eq!(status, 400);
+    let (status, _, body) = server.request(b"POST /extract HTTP/1.1\r\nContent-Length: 8\r\n\r\nnot a sw");
+    assert_eq!(status, 422, "{}", String::from_utf8_lossy(&body));
+    let (status, _, _) = server.request(b"GET /missing HTTP/1.1\r\n\r\n");
+    assert_eq!(status, 404);
+}